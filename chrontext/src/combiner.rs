@@ -9,6 +9,7 @@ pub(crate) mod time_series_queries;
 use crate::query_context::Context;
 
 use crate::combiner::solution_mapping::SolutionMappings;
+use crate::external_ids::ExternalIdError;
 use crate::preparing::TimeSeriesQueryPrepper;
 use crate::pushdown_setting::PushdownSetting;
 use crate::static_sparql::QueryExecutionError;
@@ -25,7 +26,10 @@ pub enum CombinerError {
     TimeSeriesQueryError(Box<dyn Error>),
     StaticQueryExecutionError(QueryExecutionError),
     InconsistentDatatype(String, String, String),
-    TimeSeriesValidationError(TimeSeriesValidationError)
+    InconsistentTable(String, String, String),
+    InvalidExternalId(ExternalIdError),
+    TimeSeriesValidationError(TimeSeriesValidationError),
+    UnsupportedAggregate(String),
 }
 
 impl Display for CombinerError {
@@ -38,6 +42,12 @@ impl Display for CombinerError {
                     s1, s2, s3
                 )
             }
+            CombinerError::InconsistentTable(s1, s2, s3) => {
+                write!(f, "Inconsistent tables {} and {} for identifier {}", s1, s2, s3)
+            }
+            CombinerError::InvalidExternalId(e) => {
+                write!(f, "Invalid external id: {}", e)
+            }
             CombinerError::TimeSeriesQueryError(tsqe) => {
                 write!(f, "Time series query error {}", tsqe)
             }
@@ -47,6 +57,9 @@ impl Display for CombinerError {
             CombinerError::TimeSeriesValidationError(v) => {
                 write!(f, "Time series validation error {}", v)
             }
+            CombinerError::UnsupportedAggregate(a) => {
+                write!(f, "Unsupported custom aggregate {}", a)
+            }
         }
     }
 }
@@ -68,8 +81,11 @@ impl Combiner {
         basic_time_series_queries: Vec<BasicTimeSeriesQuery>,
         rewritten_filters: HashMap<Context, Expression>,
     ) -> Combiner {
+        let capabilities = time_series_database
+            .capabilities()
+            .restrict_to_pushdown_settings(&pushdown_settings);
         let prepper = TimeSeriesQueryPrepper::new(
-            pushdown_settings,
+            capabilities,
             basic_time_series_queries,
             rewritten_filters,
         );
@@ -97,9 +113,11 @@ impl Combiner {
             let time_series_queries;
             if let Some(static_query) = static_query_map.remove(&context) {
                 let mut new_solution_mappings = self.execute_static_query(&static_query, None).await?;
-                let new_time_series_queries = self
-                .prepper
-                .prepare(&query, &mut new_solution_mappings);
+                let new_time_series_queries = self.prepare_time_series_queries(
+                    pattern,
+                    &mut new_solution_mappings,
+                    &context,
+                )?;
                 solution_mappings = Some(new_solution_mappings);
                 time_series_queries = Some(new_time_series_queries);
             } else {
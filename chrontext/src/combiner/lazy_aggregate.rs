@@ -1,7 +1,8 @@
 use crate::constants::NEST;
+use crate::custom_aggregates::CustomAggregate;
 use crate::query_context::{Context, PathEntry};
 use oxrdf::Variable;
-use polars::prelude::{col, DataType, Expr, GetOutput, IntoSeries};
+use polars::prelude::{col, lit, DataType, Expr, GetOutput, IntoSeries, QuantileInterpolOptions};
 use spargebra::algebra::AggregateExpression;
 use crate::combiner::CombinerError;
 use crate::combiner::solution_mapping::SolutionMappings;
@@ -170,7 +171,7 @@ impl Combiner {
             AggregateExpression::Custom {
                 name,
                 expr,
-                distinct: _,
+                distinct,
             } => {
                 let iri = name.as_str();
                 if iri == NEST {
@@ -184,12 +185,114 @@ impl Combiner {
                         column_context.as_ref().unwrap(),
                     ).await?;
                     out_expr = col(column_context.as_ref().unwrap().as_str());
+                } else if let Some(custom) = CustomAggregate::from_iri(iri) {
+                    column_context = Some(context.extension_with(PathEntry::AggregationOperation));
+
+                    output_solution_mappings = self.lazy_expression(
+                        expr,
+                        solution_mappings,
+                        None,
+                        None,
+                        column_context.as_ref().unwrap(),
+                    ).await?;
+                    let mut values = col(column_context.as_ref().unwrap().as_str());
+                    if *distinct {
+                        values = values.unique();
+                    }
+                    out_expr = match custom {
+                        CustomAggregate::StdDev => values.std(1),
+                        CustomAggregate::Variance => values.var(1),
+                        CustomAggregate::Median => values.median(),
+                        CustomAggregate::Percentile(p) => {
+                            values.quantile(lit(p), QuantileInterpolOptions::Linear)
+                        }
+                    };
                 } else {
-                    panic!("Custom aggregation not supported")
+                    return Err(CombinerError::UnsupportedAggregate(iri.to_string()));
                 }
             }
         }
         out_expr = out_expr.alias(variable.as_str());
         Ok((output_solution_mappings, out_expr, column_context))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::combiner::solution_mapping::SolutionMappings;
+    use crate::combiner::Combiner;
+    use crate::constants::{MEDIAN, PERCENTILE_PREFIX, STDDEV, VARIANCE};
+    use crate::pushdown_setting::all_pushdowns;
+    use crate::query_context::{Context, PathEntry};
+    use crate::timeseries_database::simple_in_memory_timeseries::InMemoryTimeseriesDatabase;
+    use oxrdf::{NamedNode, Variable};
+    use polars::prelude::{col, df, IntoLazy};
+    use spargebra::algebra::{AggregateExpression, Expression};
+    use std::collections::{HashMap, HashSet};
+
+    fn evaluate_custom_aggregate(iri: &str, distinct: bool) -> f64 {
+        let df =
+            df!("g" => [0i64, 0, 0, 0, 0, 0], "v" => [1.0f64, 2.0, 3.0, 4.0, 10.0, 10.0]).unwrap();
+        let columns = HashSet::from(["g".to_string(), "v".to_string()]);
+        let solution_mappings = SolutionMappings::new(df.lazy(), columns, HashMap::new());
+        let mut combiner = Combiner::new(
+            "".to_string(),
+            all_pushdowns(),
+            Box::new(InMemoryTimeseriesDatabase {
+                frames: Default::default(),
+            }),
+            vec![],
+            Default::default(),
+        );
+        let aggregate_expression = AggregateExpression::Custom {
+            name: NamedNode::new_unchecked(iri),
+            expr: Box::new(Expression::Variable(Variable::new_unchecked("v"))),
+            distinct,
+        };
+        let context = Context::new().extension_with(PathEntry::GroupAggregation(0));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let (solution_mappings, agg_expr, _) = runtime
+            .block_on(
+                combiner.sparql_aggregate_expression_as_lazy_column_and_expression(
+                    &Variable::new_unchecked("agg"),
+                    &aggregate_expression,
+                    solution_mappings,
+                    &context,
+                ),
+            )
+            .unwrap();
+        let out_df = solution_mappings
+            .mappings
+            .groupby([col("g")])
+            .agg([agg_expr])
+            .collect()
+            .unwrap();
+        out_df.column("agg").unwrap().f64().unwrap().get(0).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_custom_aggregates_without_pushdown() {
+        assert_close(evaluate_custom_aggregate(STDDEV, false), 4.0);
+        assert_close(evaluate_custom_aggregate(VARIANCE, false), 16.0);
+        assert_close(evaluate_custom_aggregate(MEDIAN, false), 3.5);
+        let percentile_25 = format!("{}25", PERCENTILE_PREFIX);
+        assert_close(evaluate_custom_aggregate(&percentile_25, false), 2.25);
+    }
+
+    #[test]
+    fn test_distinct_custom_aggregates_without_pushdown() {
+        assert_close(evaluate_custom_aggregate(VARIANCE, true), 12.5);
+        assert_close(evaluate_custom_aggregate(MEDIAN, true), 3.0);
+    }
+}
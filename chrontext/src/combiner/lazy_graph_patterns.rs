@@ -7,12 +7,13 @@ mod left_join;
 mod minus;
 mod order_by;
 mod project;
+mod reduced;
+mod slice;
 mod union;
 
 use super::Combiner;
 use crate::combiner::solution_mapping::SolutionMappings;
 use crate::combiner::CombinerError;
-use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::query_context::{Context, PathEntry};
 use crate::timeseries_query::TimeSeriesQuery;
 use async_recursion::async_recursion;
@@ -57,15 +58,11 @@ impl Combiner {
                 .await?;
             debug!("Finished executing static query");
             debug!("Start preparing time series queries");
-            let GPPrepReturn {
-                time_series_queries,
-                ..
-            } = self.prepper.prepare_graph_pattern(
+            let time_series_queries = self.prepare_time_series_queries(
                 graph_pattern,
-                false,
                 &mut new_solution_mappings,
                 &context,
-            );
+            )?;
             debug!("Finshed preparing time series queries, {} were created", time_series_queries.len());
             updated_solution_mappings = Some(new_solution_mappings);
             new_prepared_time_series_queries = Some(time_series_queries);
@@ -222,11 +219,31 @@ impl Combiner {
                 )
                 .await
             }
-            GraphPattern::Reduced { .. } => {
-                todo!()
+            GraphPattern::Reduced { inner } => {
+                self.lazy_reduced(
+                    inner,
+                    updated_solution_mappings,
+                    static_query_map,
+                    new_prepared_time_series_queries,
+                    context,
+                )
+                .await
             }
-            GraphPattern::Slice { .. } => {
-                todo!()
+            GraphPattern::Slice {
+                inner,
+                start,
+                length,
+            } => {
+                self.lazy_slice(
+                    inner,
+                    start,
+                    length,
+                    updated_solution_mappings,
+                    static_query_map,
+                    new_prepared_time_series_queries,
+                    context,
+                )
+                .await
             }
             GraphPattern::Group {
                 inner,
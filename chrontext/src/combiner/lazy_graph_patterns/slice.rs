@@ -0,0 +1,50 @@
+use super::Combiner;
+use crate::combiner::solution_mapping::SolutionMappings;
+use crate::combiner::CombinerError;
+use crate::query_context::{Context, PathEntry};
+use crate::timeseries_query::TimeSeriesQuery;
+use async_recursion::async_recursion;
+use log::debug;
+use polars_core::prelude::IdxSize;
+use spargebra::algebra::GraphPattern;
+use spargebra::Query;
+use std::collections::HashMap;
+
+impl Combiner {
+    #[async_recursion]
+    pub(crate) async fn lazy_slice(
+        &mut self,
+        inner: &GraphPattern,
+        start: &usize,
+        length: &Option<usize>,
+        solution_mappings: Option<SolutionMappings>,
+        static_query_map: HashMap<Context, Query>,
+        prepared_time_series_queries: Option<HashMap<Context, Vec<TimeSeriesQuery>>>,
+        context: &Context,
+    ) -> Result<SolutionMappings, CombinerError> {
+        debug!("Processing slice graph pattern");
+        let SolutionMappings {
+            mappings,
+            columns,
+            datatypes,
+        } = self
+            .lazy_graph_pattern(
+                inner,
+                solution_mappings,
+                static_query_map,
+                prepared_time_series_queries,
+                &context.extension_with(PathEntry::SliceInner),
+            )
+            .await?;
+        let len = if let Some(length) = length {
+            *length as IdxSize
+        } else {
+            IdxSize::MAX
+        };
+        Ok(SolutionMappings::new(
+            mappings.slice(*start as i64, len),
+            columns,
+            datatypes,
+        ))
+    }
+}
@@ -2,6 +2,7 @@ use super::Combiner;
 use crate::combiner::solution_mapping::SolutionMappings;
 use crate::combiner::time_series_queries::complete_basic_time_series_queries;
 use crate::combiner::CombinerError;
+use crate::constants::COMPOSITE_ID;
 use crate::query_context::Context;
 use crate::sparql_result_to_polars::create_static_query_dataframe;
 use crate::static_sparql::execute_sparql_query;
@@ -216,6 +217,11 @@ fn any_to_ground_term(any: AnyValue, datatype: &NamedNode, datatype_nnref:&Named
         AnyValue::Utf8(s) => {
             if datatype_nnref == &xsd::STRING {
                 Some(GroundTerm::Literal(Literal::new_simple_literal(s)))
+            } else if datatype.as_str() == COMPOSITE_ID {
+                Some(GroundTerm::Literal(Literal::new_typed_literal(
+                    s,
+                    datatype.to_owned(),
+                )))
             } else {
                 Some(GroundTerm::NamedNode(NamedNode::new_unchecked(s)))
             }
@@ -257,6 +263,11 @@ fn any_to_ground_term(any: AnyValue, datatype: &NamedNode, datatype_nnref:&Named
         AnyValue::Utf8Owned(s) => {
             if datatype_nnref == &xsd::STRING {
                 Some(GroundTerm::Literal(Literal::new_simple_literal(s)))
+            } else if datatype.as_str() == COMPOSITE_ID {
+                Some(GroundTerm::Literal(Literal::new_typed_literal(
+                    s,
+                    datatype.to_owned(),
+                )))
             } else {
                 Some(GroundTerm::NamedNode(NamedNode::new_unchecked(s)))
             }
@@ -1,18 +1,63 @@
 use super::Combiner;
 use crate::combiner::solution_mapping::SolutionMappings;
 use crate::combiner::CombinerError;
+use crate::external_ids::ExternalId;
+use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::query_context::Context;
 use crate::timeseries_query::{BasicTimeSeriesQuery, TimeSeriesQuery};
 use log::debug;
-use oxrdf::vocab::xsd;
 use oxrdf::Term;
 use polars::prelude::{col, Expr, IntoLazy};
 use polars_core::prelude::{DataType, JoinArgs, JoinType};
 use polars::enable_string_cache;
 use sparesults::QuerySolution;
+use spargebra::algebra::GraphPattern;
 use std::collections::{HashMap, HashSet};
 
 impl Combiner {
+    pub(crate) fn prepare_time_series_queries(
+        &mut self,
+        graph_pattern: &GraphPattern,
+        solution_mappings: &mut SolutionMappings,
+        context: &Context,
+    ) -> Result<HashMap<Context, Vec<TimeSeriesQuery>>, CombinerError> {
+        let original_solution_mappings = solution_mappings.clone();
+        loop {
+            let GPPrepReturn {
+                time_series_queries,
+                ..
+            } = self.prepper.prepare_graph_pattern(
+                graph_pattern,
+                false,
+                solution_mappings,
+                context,
+            );
+            //Only the grouped queries that the database rejects are aggregated by the combiner,
+            // the remaining queries are still pushed down, and other rejected queries are errors
+            let mut declined = false;
+            for tsq in time_series_queries.values().flatten() {
+                if let Err(e) = self.time_series_database.validate_pushdown(tsq) {
+                    let grouped_context = match grouped_context(tsq) {
+                        Some(grouped_context) => grouped_context,
+                        None => return Err(CombinerError::TimeSeriesQueryError(e)),
+                    };
+                    debug!(
+                        "Time series database does not support query {:?}: {}, retrying without group by pushdown at context {:?}",
+                        tsq, e, grouped_context
+                    );
+                    declined |= self
+                        .prepper
+                        .declined_grouped_contexts
+                        .insert(grouped_context.clone());
+                }
+            }
+            if !declined {
+                return Ok(time_series_queries);
+            }
+            *solution_mappings = original_solution_mappings.clone();
+        }
+    }
+
     pub async fn execute_attach_time_series_query(
         &mut self,
         tsq: &TimeSeriesQuery,
@@ -55,6 +100,12 @@ impl Combiner {
                     .map(|x| x.as_str().to_string())
                     .collect::<Vec<String>>(),
             );
+            drop_cols.extend(
+                tsq.get_table_variables()
+                    .iter()
+                    .map(|x| x.as_str().to_string())
+                    .collect::<Vec<String>>(),
+            );
         }
         let datatypes = tsq.get_datatype_map();
         for (k, v) in datatypes {
@@ -78,10 +129,17 @@ impl Combiner {
         solution_mappings.mappings = solution_mappings.mappings.collect().unwrap().lazy();
         let mut ts_lf = ts_df.lazy();
         if let Some(cat_col) = &to_cat_col {
-            ts_lf = ts_lf.with_column(col(cat_col).cast(DataType::Categorical(None)));
-            solution_mappings.mappings = solution_mappings
-                .mappings
-                .with_column(col(cat_col).cast(DataType::Categorical(None)));
+            //Integer external ids are strings in the time series query results
+            ts_lf = ts_lf.with_column(
+                col(cat_col)
+                    .cast(DataType::Utf8)
+                    .cast(DataType::Categorical(None)),
+            );
+            solution_mappings.mappings = solution_mappings.mappings.with_column(
+                col(cat_col)
+                    .cast(DataType::Utf8)
+                    .cast(DataType::Categorical(None)),
+            );
         }
 
         let on_reverse_false = vec![false].repeat(on_cols.len());
@@ -141,11 +199,9 @@ pub(crate) fn complete_basic_time_series_queries(
             if let Some(Term::Literal(lit)) =
                 sqs.get(basic_query.identifier_variable.as_ref().unwrap())
             {
-                if lit.datatype() == xsd::STRING {
-                    ids.insert(lit.value().to_string());
-                } else {
-                    todo!()
-                }
+                ids.insert(
+                    ExternalId::from_literal(lit).map_err(CombinerError::InvalidExternalId)?,
+                );
             }
         }
 
@@ -171,9 +227,49 @@ pub(crate) fn complete_basic_time_series_queries(
                 }
             }
         }
-        let mut ids_vec: Vec<String> = ids.into_iter().collect();
+        if let Some(table_var) = &basic_query.table_variable {
+            let mut tables = HashMap::new();
+            for sqs in static_query_solutions {
+                let table = match sqs.get(table_var) {
+                    Some(Term::NamedNode(nn)) => Some(nn.as_str().to_string()),
+                    Some(Term::Literal(lit)) => Some(lit.value().to_string()),
+                    _ => None,
+                };
+                if let (Some(table), Some(Term::Literal(id))) = (
+                    table,
+                    sqs.get(basic_query.identifier_variable.as_ref().unwrap()),
+                ) {
+                    let id = ExternalId::from_literal(id)
+                        .map_err(CombinerError::InvalidExternalId)?
+                        .to_string();
+                    if let Some(existing) = tables.get(&id) {
+                        if existing != &table {
+                            return Err(CombinerError::InconsistentTable(
+                                table,
+                                existing.to_string(),
+                                id,
+                            ));
+                        }
+                    } else {
+                        tables.insert(id, table);
+                    }
+                }
+            }
+            if !tables.is_empty() {
+                basic_query.tables = Some(tables);
+            }
+        }
+        let mut ids_vec: Vec<ExternalId> = ids.into_iter().collect();
         ids_vec.sort();
         basic_query.ids = Some(ids_vec);
     }
     Ok(())
 }
+
+fn grouped_context(tsq: &TimeSeriesQuery) -> Option<&Context> {
+    match tsq {
+        TimeSeriesQuery::Grouped(grouped) => Some(&grouped.context),
+        TimeSeriesQuery::Limited(inner, _) => grouped_context(inner),
+        _ => None,
+    }
+}
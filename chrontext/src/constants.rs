@@ -2,11 +2,19 @@ pub const HAS_TIMESTAMP: &str = "https://github.com/magbak/chrontext#hasTimestam
 pub const HAS_TIMESERIES: &str = "https://github.com/magbak/chrontext#hasTimeseries";
 pub const HAS_DATA_POINT: &str = "https://github.com/magbak/chrontext#hasDataPoint";
 pub const HAS_VALUE: &str = "https://github.com/magbak/chrontext#hasValue";
+pub const HAS_QUALITY: &str = "https://github.com/magbak/chrontext#hasQuality";
 pub const HAS_DATATYPE: &str = "https://github.com/magbak/chrontext#hasDatatype";
 pub const HAS_EXTERNAL_ID: &str = "https://github.com/magbak/chrontext#hasExternalId";
+pub const HAS_TABLE: &str = "https://github.com/magbak/chrontext#hasTable";
+pub const COMPOSITE_ID: &str = "https://github.com/magbak/chrontext#CompositeId";
 pub const DATETIME_AS_NANOS: &str = "https://github.com/magbak/chrontext#DateTimeAsNanos";
 pub const NANOS_AS_DATETIME: &str = "https://github.com/magbak/chrontext#NanosAsDateTime";
 pub const DATETIME_AS_SECONDS: &str = "https://github.com/magbak/chrontext#DateTimeAsSeconds";
 pub const SECONDS_AS_DATETIME: &str = "https://github.com/magbak/chrontext#SecondsAsDateTime";
 pub const NEST: &str = "https://github.com/magbak/chrontext#nestAggregation";
 pub const GROUPING_COL: &str = "grouping_col";
+pub const STDDEV: &str = "https://github.com/magbak/chrontext#stddev";
+pub const VARIANCE: &str = "https://github.com/magbak/chrontext#variance";
+pub const MEDIAN: &str = "https://github.com/magbak/chrontext#median";
+//Followed by the percentile, e.g. chrontext:percentile95
+pub const PERCENTILE_PREFIX: &str = "https://github.com/magbak/chrontext#percentile";
@@ -6,6 +6,7 @@ pub enum Constraint {
     ExternalTimeseries,
     ExternalDataPoint,
     ExternalDataValue,
+    ExternalDataQuality,
     ExternalTimestamp,
     ExternallyDerived,
 }
@@ -0,0 +1,30 @@
+use crate::constants::{MEDIAN, PERCENTILE_PREFIX, STDDEV, VARIANCE};
+
+//Statistical aggregates that are expressed in SPARQL as custom aggregate functions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CustomAggregate {
+    //Sample standard deviation and variance
+    StdDev,
+    Variance,
+    Median,
+    //Continuous percentile as a fraction between 0 and 1
+    Percentile(f64),
+}
+
+impl CustomAggregate {
+    pub fn from_iri(iri: &str) -> Option<CustomAggregate> {
+        match iri {
+            STDDEV => Some(CustomAggregate::StdDev),
+            VARIANCE => Some(CustomAggregate::Variance),
+            MEDIAN => Some(CustomAggregate::Median),
+            _ => {
+                let percentile = iri.strip_prefix(PERCENTILE_PREFIX)?.parse::<u8>().ok()?;
+                if percentile <= 100 {
+                    Some(CustomAggregate::Percentile(percentile as f64 / 100.0))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::combiner::{Combiner};
 use crate::preprocessing::Preprocessor;
-use crate::pushdown_setting::PushdownSetting;
+use crate::pushdown_setting::{all_pushdowns, PushdownSetting};
 use crate::rewriting::StaticQueryRewriter;
 use crate::splitter::parse_sparql_select_query;
 use crate::timeseries_database::TimeSeriesQueryable;
@@ -17,6 +17,14 @@ pub struct Engine {
 
 impl Engine {
     pub fn new(
+        time_series_database: Box<dyn TimeSeriesQueryable>,
+        endpoint: String,
+    ) -> Engine {
+        Engine::new_with_pushdown_settings(all_pushdowns(), time_series_database, endpoint)
+    }
+
+    //The pushdown settings can turn off pushdowns that the database supports, e.g. for debugging
+    pub fn new_with_pushdown_settings(
         pushdown_settings: HashSet<PushdownSetting>,
         time_series_database: Box<dyn TimeSeriesQueryable>,
         endpoint: String,
@@ -37,7 +45,16 @@ impl Engine {
         let mut preprocessor = Preprocessor::new();
         let (preprocessed_query, variable_constraints) = preprocessor.preprocess(&parsed_query);
         debug!("Constraints: {:?}", variable_constraints);
-        let rewriter = StaticQueryRewriter::new(&variable_constraints);
+        let table_annotations = self
+            .time_series_database
+            .as_ref()
+            .unwrap()
+            .capabilities()
+            .table_annotations;
+        let rewriter = StaticQueryRewriter::new_with_table_annotations(
+            &variable_constraints,
+            table_annotations,
+        );
         let (static_queries_map, basic_time_series_queries, rewritten_filters) =
             rewriter.rewrite_query(preprocessed_query);
         debug!("Produced static rewrite: {:?}", static_queries_map);
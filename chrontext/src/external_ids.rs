@@ -0,0 +1,244 @@
+use crate::constants::COMPOSITE_ID;
+use oxrdf::vocab::xsd;
+use oxrdf::Literal;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+
+//External ids of time series, as given by chrontext:hasExternalId.
+//Composite ids are literals of datatype chrontext:CompositeId with a lexical form such as ["asset_1", 101],
+// where the parts correspond to the identifier columns of the table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ExternalId {
+    String(String),
+    Integer(i64),
+    Composite(Vec<ExternalId>),
+}
+
+#[derive(Debug)]
+pub enum ExternalIdError {
+    UnsupportedDatatype(String, String),
+    InvalidInteger(String),
+    InvalidComposite(String),
+}
+
+impl Display for ExternalIdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExternalIdError::UnsupportedDatatype(value, datatype) => {
+                write!(
+                    f,
+                    "External id {} has unsupported datatype {}",
+                    value, datatype
+                )
+            }
+            ExternalIdError::InvalidInteger(value) => {
+                write!(f, "External id {} is not a valid integer", value)
+            }
+            ExternalIdError::InvalidComposite(value) => {
+                write!(f, "External id {} is not a valid composite id", value)
+            }
+        }
+    }
+}
+
+impl Error for ExternalIdError {}
+
+impl ExternalId {
+    pub fn from_literal(lit: &Literal) -> Result<ExternalId, ExternalIdError> {
+        let datatype = lit.datatype();
+        let value = lit.value();
+        if datatype == xsd::STRING {
+            Ok(ExternalId::String(value.to_string()))
+        } else if is_integer_datatype(datatype.as_str()) {
+            value
+                .trim()
+                .parse()
+                .map(ExternalId::Integer)
+                .map_err(|_| ExternalIdError::InvalidInteger(value.to_string()))
+        } else if datatype.as_str() == COMPOSITE_ID {
+            ExternalId::parse_composite(value)
+        } else {
+            Err(ExternalIdError::UnsupportedDatatype(
+                value.to_string(),
+                datatype.as_str().to_string(),
+            ))
+        }
+    }
+
+    pub fn parse_composite(s: &str) -> Result<ExternalId, ExternalIdError> {
+        let invalid = || ExternalIdError::InvalidComposite(s.to_string());
+        let mut chars = s.chars().peekable();
+        skip_whitespace(&mut chars);
+        if chars.next() != Some('[') {
+            return Err(invalid());
+        }
+        let mut parts = vec![];
+        loop {
+            skip_whitespace(&mut chars);
+            match chars.peek() {
+                Some('"') => {
+                    chars.next();
+                    parts.push(ExternalId::String(
+                        parse_string(&mut chars).ok_or_else(invalid)?,
+                    ));
+                }
+                Some(c) if *c == '-' || c.is_ascii_digit() => {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek() {
+                        if *c == '-' || c.is_ascii_digit() {
+                            digits.push(*c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    parts.push(ExternalId::Integer(digits.parse().map_err(|_| invalid())?));
+                }
+                _ => return Err(invalid()),
+            }
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => {}
+                Some(']') => break,
+                _ => return Err(invalid()),
+            }
+        }
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() || parts.len() < 2 {
+            return Err(invalid());
+        }
+        Ok(ExternalId::Composite(parts))
+    }
+
+    pub fn parts(&self) -> Vec<&ExternalId> {
+        match self {
+            ExternalId::Composite(parts) => parts.iter().collect(),
+            _ => vec![self],
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, ExternalId::String(_))
+    }
+}
+
+//The canonical form is used as the identifier in the results of time series queries
+impl Display for ExternalId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExternalId::String(s) => write!(f, "{}", s),
+            ExternalId::Integer(i) => write!(f, "{}", i),
+            ExternalId::Composite(parts) => {
+                write!(f, "[")?;
+                for (i, p) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    if let ExternalId::String(s) = p {
+                        write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))?;
+                    } else {
+                        write!(f, "{}", p)?;
+                    }
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+fn is_integer_datatype(datatype: &str) -> bool {
+    [
+        xsd::INTEGER,
+        xsd::LONG,
+        xsd::INT,
+        xsd::UNSIGNED_LONG,
+        xsd::UNSIGNED_INT,
+    ]
+    .iter()
+    .any(|x| x.as_str() == datatype)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |x| x.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => s.push(chars.next()?),
+            c => s.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::COMPOSITE_ID;
+    use crate::external_ids::ExternalId;
+    use oxrdf::{Literal, NamedNode};
+
+    #[test]
+    fn test_parse_composite() {
+        assert_eq!(
+            ExternalId::parse_composite(r#" [ "asset_1" , -101,"TT \"A\" \\ 1" ] "#).unwrap(),
+            ExternalId::Composite(vec![
+                ExternalId::String("asset_1".to_string()),
+                ExternalId::Integer(-101),
+                ExternalId::String(r#"TT "A" \ 1"#.to_string()),
+            ])
+        );
+        for invalid in [
+            "",
+            r#""a", 1"#,
+            r#"["a"]"#,
+            r#"["a", 1"#,
+            r#"["a", 1] x"#,
+            r#"["a", 1-1]"#,
+            r#"["a",, 1]"#,
+            r#"["a, 1]"#,
+            r#"[a, 1]"#,
+        ] {
+            assert!(
+                ExternalId::parse_composite(invalid).is_err(),
+                "{} was parsed",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_canonical_form_round_trip() {
+        let ids = [
+            ExternalId::Composite(vec![
+                ExternalId::String("asset_1".to_string()),
+                ExternalId::Integer(7),
+            ]),
+            ExternalId::Composite(vec![
+                ExternalId::String(r#"say "hi" \ bye"#.to_string()),
+                ExternalId::String("7".to_string()),
+                ExternalId::Integer(-7),
+            ]),
+        ];
+        for id in ids {
+            assert_eq!(ExternalId::parse_composite(&id.to_string()).unwrap(), id);
+            let lit =
+                Literal::new_typed_literal(id.to_string(), NamedNode::new_unchecked(COMPOSITE_ID));
+            assert_eq!(ExternalId::from_literal(&lit).unwrap(), id);
+        }
+        assert_eq!(
+            ExternalId::from_literal(&Literal::from(12i64)).unwrap(),
+            ExternalId::Integer(12)
+        );
+        assert_eq!(
+            ExternalId::from_literal(&Literal::new_simple_literal("12")).unwrap(),
+            ExternalId::String("12".to_string())
+        );
+        assert!(ExternalId::from_literal(&Literal::from(1.5f64)).is_err());
+    }
+}
@@ -7,7 +7,9 @@ pub mod change_types;
 pub mod combiner;
 pub mod constants;
 pub mod constraints;
+pub mod custom_aggregates;
 pub mod engine;
+pub mod external_ids;
 mod find_query_variables;
 mod preparing;
 pub mod preprocessing;
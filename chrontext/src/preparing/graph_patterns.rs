@@ -125,8 +125,8 @@ impl TimeSeriesQueryPrepper {
             GraphPattern::Reduced { inner } => {
                 self.prepare_reduced(inner, try_groupby_complex_query , solution_mappings, context)
             }
-            GraphPattern::Slice { inner, .. } => {
-                self.prepare_slice(inner, try_groupby_complex_query , solution_mappings, context)
+            GraphPattern::Slice { inner, start, length } => {
+                self.prepare_slice(inner, start, length, try_groupby_complex_query , solution_mappings, context)
             }
             GraphPattern::Group {
                 inner,
@@ -3,6 +3,8 @@ use super::TimeSeriesQueryPrepper;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::preparing::synchronization::create_identity_synchronized_queries;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryKind;
+use log::debug;
 use crate::timeseries_query::TimeSeriesQuery;
 
 impl TimeSeriesQueryPrepper {
@@ -22,6 +24,16 @@ impl TimeSeriesQueryPrepper {
         }
         if try_groupby_complex_query {
             local_tsqs = create_identity_synchronized_queries(local_tsqs);
+            if !self
+                .capabilities
+                .supports_query_kind(&TimeSeriesQueryKind::InnerSynchronized)
+                && local_tsqs
+                    .iter()
+                    .any(|t| matches!(t, TimeSeriesQuery::InnerSynchronized(..)))
+            {
+                debug!("Synchronized time series queries are not supported");
+                return GPPrepReturn::fail_groupby_complex_query();
+            }
         }
         let mut tsqs_map = HashMap::new();
         if !local_tsqs.is_empty() {
@@ -2,7 +2,9 @@ use super::TimeSeriesQueryPrepper;
 use crate::find_query_variables::find_all_used_variables_in_expression;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryKind;
 use crate::timeseries_query::TimeSeriesQuery;
+use log::debug;
 use oxrdf::Variable;
 use spargebra::algebra::{Expression, GraphPattern};
 use std::collections::HashSet;
@@ -26,6 +28,14 @@ impl TimeSeriesQueryPrepper {
             &inner_context,
         );
         if try_groupby_complex_query {
+            if !self
+                .capabilities
+                .supports_query_kind(&TimeSeriesQueryKind::ExpressionAs)
+                || !self.capabilities.supports_expression(expr)
+            {
+                debug!("Expression {} can not be pushed down", expr);
+                return GPPrepReturn::fail_groupby_complex_query();
+            }
             let mut expression_vars = HashSet::new();
             find_all_used_variables_in_expression(expr, &mut expression_vars);
             let mut found_i = None;
@@ -1,10 +1,9 @@
 use crate::change_types::ChangeType;
-use crate::pushdown_setting::PushdownSetting;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
 use crate::timeseries_query::TimeSeriesQuery;
 use oxrdf::Literal;
 use spargebra::algebra::Expression;
-use std::collections::HashSet;
 
 pub(crate) struct RecursiveRewriteReturn {
     pub expression: Option<Expression>,
@@ -39,7 +38,7 @@ pub(crate) fn rewrite_filter_expression(
     required_change_direction: &ChangeType,
     context: &Context,
     static_rewrite_conjunction: &Option<Vec<&Expression>>,
-    pushdown_settings: &HashSet<PushdownSetting>,
+    capabilities: &TimeSeriesQueryCapabilities,
 ) -> (Option<Expression>, bool) {
     let mut rewrite = try_recursive_rewrite_expression(
         tsq,
@@ -47,7 +46,7 @@ pub(crate) fn rewrite_filter_expression(
         expression,
         required_change_direction,
         context,
-        pushdown_settings,
+        capabilities,
     );
     return (rewrite.expression.take(), rewrite.lost_value);
 }
@@ -58,7 +57,7 @@ pub(crate) fn try_recursive_rewrite_expression(
     expression: &Expression,
     required_change_direction: &ChangeType,
     context: &Context,
-    pushdown_settings: &HashSet<PushdownSetting>,
+    capabilities: &TimeSeriesQueryCapabilities,
 ) -> RecursiveRewriteReturn {
     if static_rewrite_conjunction.is_some()
         && static_rewrite_conjunction
@@ -73,6 +72,10 @@ pub(crate) fn try_recursive_rewrite_expression(
         );
     }
 
+    if !capabilities.supports_expression_kind(expression) {
+        return RecursiveRewriteReturn::none(false);
+    }
+
     match &expression {
         Expression::Literal(lit) => {
             return RecursiveRewriteReturn::new(
@@ -83,18 +86,23 @@ pub(crate) fn try_recursive_rewrite_expression(
         }
         Expression::Variable(v) => {
             if tsq.has_equivalent_timestamp_variable(v, context) {
-                return RecursiveRewriteReturn::new(
-                    Some(Expression::Variable(v.clone())),
-                    Some(ChangeType::NoChange),
-                    false,
-                );
+                if capabilities.timestamp_conditions {
+                    return RecursiveRewriteReturn::new(
+                        Some(Expression::Variable(v.clone())),
+                        Some(ChangeType::NoChange),
+                        false,
+                    );
+                } else {
+                    return RecursiveRewriteReturn::new(None, None, true);
+                }
             } else if tsq
                 .get_value_variables()
                 .into_iter()
+                .chain(tsq.get_quality_variables().into_iter())
                 .find(|x| &x.variable == v)
                 .is_some()
             {
-                if pushdown_settings.contains(&PushdownSetting::ValueConditions) {
+                if capabilities.value_conditions {
                     return RecursiveRewriteReturn::new(
                         Some(Expression::Variable(v.clone())),
                         Some(ChangeType::NoChange),
@@ -114,7 +122,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::OrLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -122,7 +130,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::OrRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
             match required_change_direction {
@@ -243,7 +251,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::AndLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -251,7 +259,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::AndRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -371,7 +379,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::EqualLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -379,7 +387,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::EqualRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -406,7 +414,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::GreaterLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -414,7 +422,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::GreaterRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -441,7 +449,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::GreaterOrEqualLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -449,7 +457,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::GreaterOrEqualRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -476,7 +484,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::LessLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -484,7 +492,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::LessRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -511,7 +519,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::LessOrEqualLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -519,7 +527,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::LessOrEqualRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -546,7 +554,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 &ChangeType::NoChange,
                 &context.extension_with(PathEntry::InLeft),
-                pushdown_settings,
+                capabilities,
             );
 
             let mut right_rewrites = right
@@ -559,7 +567,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                         e,
                         required_change_direction,
                         &context.extension_with(PathEntry::InRight(i as u16)),
-                        pushdown_settings,
+                        capabilities,
                     )
                 })
                 .collect::<Vec<RecursiveRewriteReturn>>();
@@ -620,7 +628,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::AddLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -628,7 +636,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::AddRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -655,7 +663,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::SubtractLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -663,7 +671,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::SubtractRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -690,7 +698,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::MultiplyLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -698,7 +706,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::MultiplyRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -725,7 +733,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::DivideLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -733,7 +741,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::DivideRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value = or_lost_value(vec![&left_rewrite, &right_rewrite]);
 
@@ -760,7 +768,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 inner,
                 required_change_direction,
                 &context.extension_with(PathEntry::UnaryPlus),
-                pushdown_settings,
+                capabilities,
             );
             if inner_rewrite.change_type.is_some()
                 && inner_rewrite.change_type.as_ref().unwrap() == &ChangeType::NoChange
@@ -782,7 +790,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 inner,
                 required_change_direction,
                 &context.extension_with(PathEntry::UnaryMinus),
-                pushdown_settings,
+                capabilities,
             );
             if inner_rewrite.expression.is_some()
                 && inner_rewrite.change_type.as_ref().unwrap() == &ChangeType::NoChange
@@ -810,7 +818,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 inner,
                 &use_direction,
                 &context.extension_with(PathEntry::Not),
-                pushdown_settings,
+                capabilities,
             );
             if inner_rewrite.expression.is_some() {
                 match inner_rewrite.change_type.as_ref().unwrap() {
@@ -852,7 +860,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 left,
                 required_change_direction,
                 &context.extension_with(PathEntry::IfLeft),
-                pushdown_settings,
+                capabilities,
             );
             let mut middle_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -860,7 +868,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 middle,
                 required_change_direction,
                 &context.extension_with(PathEntry::IfMiddle),
-                pushdown_settings,
+                capabilities,
             );
             let mut right_rewrite = try_recursive_rewrite_expression(
                 tsq,
@@ -868,7 +876,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                 right,
                 required_change_direction,
                 &context.extension_with(PathEntry::IfRight),
-                pushdown_settings,
+                capabilities,
             );
             let use_lost_value =
                 or_lost_value(vec![&left_rewrite, &middle_rewrite, &right_rewrite]);
@@ -903,7 +911,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                         e,
                         required_change_direction,
                         &context.extension_with(PathEntry::Coalesce(i as u16)),
-                        pushdown_settings,
+                        capabilities,
                     )
                 })
                 .collect::<Vec<RecursiveRewriteReturn>>();
@@ -938,7 +946,7 @@ pub(crate) fn try_recursive_rewrite_expression(
                         e,
                         required_change_direction,
                         &context.extension_with(PathEntry::FunctionCall(i as u16)),
-                        pushdown_settings,
+                        capabilities,
                     )
                 })
                 .collect::<Vec<RecursiveRewriteReturn>>();
@@ -4,6 +4,7 @@ use crate::change_types::ChangeType;
 use crate::preparing::graph_patterns::filter_expression_rewrites::rewrite_filter_expression;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryKind;
 use crate::timeseries_query::TimeSeriesQuery;
 use spargebra::algebra::{Expression, GraphPattern};
 use crate::combiner::solution_mapping::SolutionMappings;
@@ -52,8 +53,16 @@ impl TimeSeriesQueryPrepper {
                     &use_change_type,
                     context,
                     &conj_vec,
-                    &self.pushdown_settings,
+                    &self.capabilities,
                 );
+                let time_series_condition = if self
+                    .capabilities
+                    .supports_query_kind(&TimeSeriesQueryKind::Filtered)
+                {
+                    time_series_condition
+                } else {
+                    None
+                };
                 if try_groupby_complex_query && (lost_value || time_series_condition.is_none()) {
                     return GPPrepReturn::fail_groupby_complex_query();
                 }
@@ -6,11 +6,11 @@ use super::TimeSeriesQueryPrepper;
 use crate::constants::GROUPING_COL;
 use crate::find_query_variables::find_all_used_variables_in_aggregate_expression;
 use crate::preparing::graph_patterns::GPPrepReturn;
-use crate::pushdown_setting::PushdownSetting;
+use crate::timeseries_database::capabilities::TimeSeriesQueryKind;
 use crate::timeseries_query::{GroupedTimeSeriesQuery, TimeSeriesQuery};
 use oxrdf::Variable;
 use polars::prelude::{IntoLazy};
-use polars_core::prelude::{JoinArgs, JoinType, UniqueKeepStrategy};
+use polars_core::prelude::{DataType, JoinArgs, JoinType, UniqueKeepStrategy};
 use polars_core::series::Series;
 use polars::prelude::DataFrameJoinOps;
 use spargebra::algebra::{AggregateExpression, GraphPattern};
@@ -30,11 +30,22 @@ impl TimeSeriesQueryPrepper {
         if try_groupby_complex_query {
             return GPPrepReturn::fail_groupby_complex_query();
         }
+        if self.declined_grouped_contexts.contains(context) {
+            debug!("Group by pushdown was declined at context {:?}", context);
+            return self.prepare_graph_pattern(
+                graph_pattern,
+                false,
+                solution_mappings,
+                &context.extension_with(PathEntry::GroupInner),
+            );
+        }
         let inner_context = &context.extension_with(PathEntry::GroupInner);
         let mut try_graph_pattern_prepare =
             self.prepare_graph_pattern(graph_pattern, true, solution_mappings, &inner_context);
         if !try_graph_pattern_prepare.fail_groupby_complex_query
-            && self.pushdown_settings.contains(&PushdownSetting::GroupBy)
+            && self
+                .capabilities
+                .supports_query_kind(&TimeSeriesQueryKind::Grouped)
         {
             if try_graph_pattern_prepare.time_series_queries.len() == 1 {
                 let (_c, mut tsqs) = try_graph_pattern_prepare.time_series_queries.drain().next().unwrap();
@@ -146,7 +157,15 @@ fn add_basic_groupby_mapping_values(
                 b.identifier_variable.as_ref().unwrap().as_str(),
             ];
             solution_mappings.mappings = solution_mappings.mappings.clone().collect().unwrap().lazy();
-            let mapping_values = solution_mappings.mappings.clone().collect().unwrap().select(by_vec).unwrap();
+            let mut mapping_values = solution_mappings.mappings.clone().collect().unwrap().select(by_vec).unwrap();
+            //Time series queries identify the time series by the string form of the external id
+            let identifier_colname = b.identifier_variable.as_ref().unwrap().as_str();
+            let identifier_series = mapping_values
+                .column(identifier_colname)
+                .unwrap()
+                .cast(&DataType::Utf8)
+                .unwrap();
+            mapping_values.with_column(identifier_series).unwrap();
             TimeSeriesQuery::GroupedBasic(b, mapping_values, grouping_col.to_string())
         }
         TimeSeriesQuery::Filtered(tsq, f) => TimeSeriesQuery::Filtered(
@@ -183,5 +202,13 @@ fn add_basic_groupby_mapping_values(
         TimeSeriesQuery::GroupedBasic(_, _, _) => {
             panic!("Should never happen")
         }
+        TimeSeriesQuery::Limited(tsq, limit) => TimeSeriesQuery::Limited(
+            Box::new(add_basic_groupby_mapping_values(
+                *tsq,
+                solution_mappings,
+                grouping_col,
+            )),
+            limit,
+        ),
     }
 }
@@ -1,6 +1,8 @@
 use super::TimeSeriesQueryPrepper;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryKind;
+use crate::timeseries_query::TimeSeriesQuery;
 use log::debug;
 use spargebra::algebra::GraphPattern;
 use crate::combiner::solution_mapping::SolutionMappings;
@@ -9,6 +11,8 @@ impl TimeSeriesQueryPrepper {
     pub fn prepare_slice(
         &mut self,
         inner: &GraphPattern,
+        start: &usize,
+        length: &Option<usize>,
         try_groupby_complex_query: bool,
         solution_mappings: &mut SolutionMappings,
         context: &Context,
@@ -17,13 +21,40 @@ impl TimeSeriesQueryPrepper {
             debug!("Encountered graph inside slice, not supported for complex groupby pushdown");
             return GPPrepReturn::fail_groupby_complex_query();
         } else {
-            let inner_prepare = self.prepare_graph_pattern(
+            let mut inner_prepare = self.prepare_graph_pattern(
                 inner,
                 try_groupby_complex_query,
                 solution_mappings,
-                &context.extension_with(PathEntry::ReducedInner),
+                &context.extension_with(PathEntry::SliceInner),
             );
+            if let Some(length) = length {
+                if slice_can_be_pushed_down(inner)
+                    && self
+                        .capabilities
+                        .supports_query_kind(&TimeSeriesQueryKind::Limited)
+                    && inner_prepare.time_series_queries.len() == 1
+                {
+                    let (c, mut tsqs) = inner_prepare.time_series_queries.drain().next().unwrap();
+                    if tsqs.len() == 1 {
+                        debug!("Pushing down slice into time series query at context {}", c.as_str());
+                        let tsq = tsqs.remove(0);
+                        //The offset is applied by the combiner, so we need the first start + length rows
+                        tsqs.push(TimeSeriesQuery::Limited(Box::new(tsq), start + length));
+                    }
+                    inner_prepare.time_series_queries.insert(c, tsqs);
+                }
+            }
             inner_prepare
         }
     }
 }
+
+//Each row from the time series database joins with at least one static solution,
+// so the slice can only be pushed down when no pattern in between can remove rows.
+fn slice_can_be_pushed_down(graph_pattern: &GraphPattern) -> bool {
+    match graph_pattern {
+        GraphPattern::Bgp { .. } => true,
+        GraphPattern::Project { inner, .. } => slice_can_be_pushed_down(inner),
+        _ => false,
+    }
+}
@@ -1,4 +1,4 @@
-use crate::constants::{HAS_DATA_POINT, HAS_TIMESERIES, HAS_TIMESTAMP, HAS_VALUE};
+use crate::constants::{HAS_DATA_POINT, HAS_QUALITY, HAS_TIMESERIES, HAS_TIMESTAMP, HAS_VALUE};
 use crate::constraints::{Constraint, VariableConstraints};
 use crate::find_query_variables::{
     find_all_used_variables_in_aggregate_expression, find_all_used_variables_in_expression,
@@ -159,6 +159,7 @@ impl Preprocessor {
                 for v in used_vars.drain() {
                     if let Some(ctr) = self.variable_constraints.get_constraint(&v, context) {
                         if ctr == &Constraint::ExternalDataValue
+                            || ctr == &Constraint::ExternalDataQuality
                             || ctr == &Constraint::ExternalTimestamp
                             || ctr == &Constraint::ExternallyDerived
                         {
@@ -280,6 +281,7 @@ impl Preprocessor {
                     for v in used_vars.drain() {
                         if let Some(ctr) = self.variable_constraints.get_constraint(&v, context) {
                             if ctr == &Constraint::ExternalDataValue
+                                || ctr == &Constraint::ExternalDataQuality
                                 || ctr == &Constraint::ExternalTimestamp
                                 || ctr == &Constraint::ExternallyDerived
                             {
@@ -370,6 +372,18 @@ impl Preprocessor {
                         Constraint::ExternalDataPoint,
                     );
                 }
+                if named_predicate_node == &HAS_QUALITY {
+                    self.variable_constraints.insert(
+                        new_object_variable.clone(),
+                        context.clone(),
+                        Constraint::ExternalDataQuality,
+                    );
+                    self.variable_constraints.insert(
+                        new_subject_variable.clone(),
+                        context.clone(),
+                        Constraint::ExternalDataPoint,
+                    );
+                }
                 if named_predicate_node == &HAS_DATA_POINT {
                     self.variable_constraints.insert(
                         new_object_variable.clone(),
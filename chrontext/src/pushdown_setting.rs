@@ -4,6 +4,7 @@ pub fn all_pushdowns() -> HashSet<PushdownSetting> {
     [PushdownSetting::GroupBy, PushdownSetting::ValueConditions].into()
 }
 
+//Restricts what is pushed down to the database, in addition to its capabilities
 #[derive(Hash, Clone, Eq, PartialEq, Debug)]
 pub enum PushdownSetting {
     ValueConditions,
@@ -22,10 +22,19 @@ pub struct StaticQueryRewriter {
     basic_time_series_queries: Vec<BasicTimeSeriesQuery>,
     static_subqueries: HashMap<Context, Query>,
     rewritten_filters: HashMap<Context, Expression>,
+    table_annotations: bool,
 }
 
 impl StaticQueryRewriter {
     pub fn new(variable_constraints: &VariableConstraints) -> StaticQueryRewriter {
+        StaticQueryRewriter::new_with_table_annotations(variable_constraints, false)
+    }
+
+    //With table annotations, the static queries also fetch the optional chrontext:hasTable of each time series
+    pub fn new_with_table_annotations(
+        variable_constraints: &VariableConstraints,
+        table_annotations: bool,
+    ) -> StaticQueryRewriter {
         StaticQueryRewriter {
             variable_counter: 0,
             additional_projections: Default::default(),
@@ -33,6 +42,7 @@ impl StaticQueryRewriter {
             basic_time_series_queries: vec![],
             static_subqueries: HashMap::new(),
             rewritten_filters: HashMap::new(),
+            table_annotations,
         }
     }
 
@@ -72,6 +82,7 @@ impl StaticQueryRewriter {
         if let Some(ctr) = self.variable_constraints.get_constraint(v, context) {
             if !(ctr == &Constraint::ExternalDataPoint
                 || ctr == &Constraint::ExternalDataValue
+                || ctr == &Constraint::ExternalDataQuality
                 || ctr == &Constraint::ExternalTimestamp
                 || ctr == &Constraint::ExternallyDerived)
             {
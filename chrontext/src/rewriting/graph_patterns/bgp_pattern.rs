@@ -1,5 +1,7 @@
 use super::StaticQueryRewriter;
-use crate::constants::{HAS_DATATYPE, HAS_DATA_POINT, HAS_EXTERNAL_ID, HAS_TIMESTAMP, HAS_VALUE};
+use crate::constants::{
+    HAS_DATATYPE, HAS_DATA_POINT, HAS_EXTERNAL_ID, HAS_QUALITY, HAS_TABLE, HAS_TIMESTAMP, HAS_VALUE,
+};
 use crate::constraints::{Constraint, VariableConstraints};
 use crate::query_context::{Context, PathEntry, VariableInContext};
 use crate::rewriting::graph_patterns::GPReturn;
@@ -19,7 +21,9 @@ impl StaticQueryRewriter {
         let mut new_triples = vec![];
         let mut dynamic_triples = vec![];
         let mut datatypes_in_scope = HashMap::new();
+        let mut tables_in_scope = HashMap::new();
         let mut external_ids_in_scope = HashMap::new();
+        let mut optional_table_triples = vec![];
         let mut new_basic_tsqs = vec![];
         for t in patterns {
             //If the object is an external timeseries, we need to do get the external id
@@ -42,11 +46,23 @@ impl StaticQueryRewriter {
                                     + self.variable_counter.to_string().as_str(),
                             )
                             .unwrap();
+                            let table_var = if self.table_annotations {
+                                Some(
+                                    Variable::new(
+                                        "ts_table_".to_string()
+                                            + self.variable_counter.to_string().as_str(),
+                                    )
+                                    .unwrap(),
+                                )
+                            } else {
+                                None
+                            };
                             self.variable_counter += 1;
                             let btsq = self.create_basic_time_series_query(
                                 &object_var,
                                 &external_id_var,
                                 &datatype_var,
+                                table_var.as_ref(),
                                 &context,
                             );
                             new_basic_tsqs.push(btsq);
@@ -70,6 +86,18 @@ impl StaticQueryRewriter {
                                 .insert(object_var.clone(), vec![external_id_var.clone()]);
                             datatypes_in_scope
                                 .insert(object_var.clone(), vec![datatype_var.clone()]);
+                            //The table annotation is optional, routing falls back to the table configuration
+                            if let Some(table_var) = table_var {
+                                let new_table_triple = TriplePattern {
+                                    subject: t.object.clone(),
+                                    predicate: NamedNodePattern::NamedNode(
+                                        NamedNode::new(HAS_TABLE).unwrap(),
+                                    ),
+                                    object: TermPattern::Variable(table_var.clone()),
+                                };
+                                optional_table_triples.push(new_table_triple);
+                                tables_in_scope.insert(object_var.clone(), vec![table_var]);
+                            }
                         }
                     }
                 }
@@ -85,6 +113,7 @@ impl StaticQueryRewriter {
                         if ctr == &Constraint::ExternalDataPoint
                             || ctr == &Constraint::ExternalTimestamp
                             || ctr == &Constraint::ExternalDataValue
+                            || ctr == &Constraint::ExternalDataQuality
                         {
                             return true;
                         }
@@ -122,7 +151,8 @@ impl StaticQueryRewriter {
                 Default::default(),
                 Default::default(),
                 Default::default(),
-                false
+                Default::default(),
+                false,
             )
         } else {
             let mut variables_in_scope = HashSet::new();
@@ -134,14 +164,31 @@ impl StaticQueryRewriter {
                     variables_in_scope.insert(v.clone());
                 }
             }
+            for t in &optional_table_triples {
+                if let TermPattern::Variable(v) = &t.object {
+                    variables_in_scope.insert(v.clone());
+                }
+            }
+
+            let mut graph_pattern = GraphPattern::Bgp {
+                patterns: new_triples,
+            };
+            for table_triple in optional_table_triples {
+                graph_pattern = GraphPattern::LeftJoin {
+                    left: Box::new(graph_pattern),
+                    right: Box::new(GraphPattern::Bgp {
+                        patterns: vec![table_triple],
+                    }),
+                    expression: None,
+                };
+            }
 
             let gpr = GPReturn::new(
-                GraphPattern::Bgp {
-                    patterns: new_triples,
-                },
+                graph_pattern,
                 rewritten,
                 variables_in_scope,
                 datatypes_in_scope,
+                tables_in_scope,
                 external_ids_in_scope,
                 false,
             );
@@ -154,11 +201,13 @@ impl StaticQueryRewriter {
         time_series_variable: &Variable,
         time_series_id_variable: &Variable,
         datatype_variable: &Variable,
+        table_variable: Option<&Variable>,
         context: &Context,
     ) -> BasicTimeSeriesQuery {
         let mut ts_query = BasicTimeSeriesQuery::new_empty();
         ts_query.identifier_variable = Some(time_series_id_variable.clone());
         ts_query.datatype_variable = Some(datatype_variable.clone());
+        ts_query.table_variable = table_variable.cloned();
         ts_query.timeseries_variable = Some(VariableInContext::new(
             time_series_variable.clone(),
             context.clone(),
@@ -212,6 +261,25 @@ fn process_dynamic_triples(
                         }
                     }
                 }
+            } else if named_predicate_node == HAS_QUALITY {
+                for q in local_basic_tsqs.iter_mut() {
+                    if q.quality_variable.is_none() {
+                        if let (
+                            Some(q_data_point_variable),
+                            TermPattern::Variable(subject_variable),
+                        ) = (&q.data_point_variable, &t.subject)
+                        {
+                            if q_data_point_variable.partial(subject_variable, context) {
+                                if let TermPattern::Variable(quality_var) = &t.object {
+                                    q.quality_variable = Some(VariableInContext::new(
+                                        quality_var.clone(),
+                                        context.clone(),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
             } else if named_predicate_node == HAS_TIMESTAMP {
                 for q in local_basic_tsqs.iter_mut() {
                     if q.timestamp_variable.is_none() {
@@ -50,6 +50,22 @@ impl StaticQueryRewriter {
                 }
             }
         }
+        let mut table_keys_sorted = gpreturn
+            .tables_in_scope
+            .keys()
+            .collect::<Vec<&Variable>>();
+        table_keys_sorted.sort_by_key(|v| v.to_string());
+        for k in table_keys_sorted {
+            let vs = gpreturn.tables_in_scope.get(k).unwrap();
+            let mut vars = vs.iter().collect::<Vec<&Variable>>();
+            //Sort to make rewrites deterministic
+            vars.sort_by_key(|v| v.to_string());
+            for v in vars {
+                if !variables_rewrite.contains(v) {
+                    variables_rewrite.push(v.clone());
+                }
+            }
+        }
         let mut id_keys_sorted = gpreturn
             .external_ids_in_scope
             .keys()
@@ -16,7 +16,8 @@ impl StaticQueryRewriter {
             inner,
             &context.extension_with(PathEntry::SliceInner),
         );
-        if !inner_rewrite.is_subquery {
+        //Slicing the static query is only correct when no time series data is attached afterwards
+        if !inner_rewrite.is_subquery && !inner_rewrite.rewritten {
             let inner_graph_pattern = inner_rewrite.graph_pattern.take().unwrap();
             inner_rewrite.with_graph_pattern(GraphPattern::Slice {
                 inner: Box::new(inner_graph_pattern),
@@ -1,3 +1,5 @@
+use crate::constants::COMPOSITE_ID;
+use crate::external_ids::ExternalId;
 use std::collections::HashMap;
 use oxrdf::vocab::xsd;
 use oxrdf::{Literal, NamedNode, Term};
@@ -126,6 +128,12 @@ pub(crate) fn sparql_literal_to_polars_literal_value(lit: &Literal) -> LiteralVa
     } else if datatype == xsd::DECIMAL {
         let d = f64::from_str(value).expect("Decimal parsing error");
         LiteralValue::Float64(d)
+    } else if datatype.as_str() == COMPOSITE_ID {
+        //Composite ids are joined with time series query results on their canonical form
+        let id = ExternalId::parse_composite(value)
+            .map(|x| x.to_string())
+            .unwrap_or(value.to_string());
+        LiteralValue::Utf8(id)
     } else {
         todo!("Not implemented!")
     };
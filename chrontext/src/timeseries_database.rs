@@ -1,8 +1,17 @@
 pub mod arrow_flight_sql_database;
+pub mod capabilities;
+pub mod dataset_database;
+#[cfg(feature = "embedded")]
+pub mod embedded_sql_database;
 pub mod opcua_history_read;
+#[cfg(feature = "postgres")]
+pub mod postgres_database;
 pub mod simple_in_memory_timeseries;
+#[cfg(any(feature = "embedded", feature = "postgres"))]
+pub mod sql_result_to_polars;
 pub mod timeseries_sql_rewrite;
 
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
 use crate::timeseries_query::TimeSeriesQuery;
 use async_trait::async_trait;
 use polars::frame::DataFrame;
@@ -11,5 +20,9 @@ use std::error::Error;
 #[async_trait]
 pub trait TimeSeriesQueryable: Send {
     async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>>;
-    fn allow_compound_timeseries_queries(&self) -> bool;
+    //Errors here make the combiner fall back to processing aggregations client side
+    fn validate_pushdown(&self, _tsq: &TimeSeriesQuery) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities;
 }
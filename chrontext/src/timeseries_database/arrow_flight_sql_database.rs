@@ -12,33 +12,53 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod flight_data_decoder;
+pub mod flight_sql;
+
+use crate::timeseries_database::capabilities::{ExpressionKind, TimeSeriesQueryCapabilities};
 use crate::timeseries_database::TimeSeriesQueryable;
 use crate::timeseries_query::TimeSeriesQuery;
+use arrow2::array::Array;
+use arrow2::chunk::Chunk;
 use arrow2::io::flight as flight2;
-use arrow_format::flight::data::{FlightDescriptor, FlightInfo, HandshakeRequest};
+use arrow2::io::ipc::write::{default_ipc_fields, WriteOptions};
+use arrow_format::flight::data::{
+    Action, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest,
+};
 use async_trait::async_trait;
+use flight_data_decoder::FlightDataDecoder;
+use flight_sql::{
+    ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
+    ActionCreatePreparedStatementResult, CommandGetSqlInfo, CommandPreparedStatementQuery,
+    CommandStatementQuery, FlightSqlInfo, FlightSqlMessage, CLOSE_PREPARED_STATEMENT,
+    CREATE_PREPARED_STATEMENT, FLIGHT_SQL_SERVER_NAME, FLIGHT_SQL_SERVER_READ_ONLY,
+    FLIGHT_SQL_SERVER_VERSION, SQL_DATETIME_FUNCTIONS,
+};
 
 use polars::frame::DataFrame;
-use polars_core::utils::accumulate_dataframes_vertical;
 
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::has_table_iris;
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::normalize_timestamps;
 use crate::timeseries_database::timeseries_sql_rewrite::{
     TimeSeriesQueryToSQLError, TimeSeriesQueryToSQLTransformer, TimeSeriesTable,
 };
 use arrow_format::flight::service::flight_service_client::FlightServiceClient;
-use arrow_format::ipc::planus::ReadAsRoot;
-use arrow_format::ipc::MessageHeaderRef;
 use log::{debug, warn};
 use polars_core::error::ArrowError;
 use polars_core::prelude::PolarsError;
-use sea_query::PostgresQueryBuilder;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::sleep;
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tonic::metadata::MetadataValue;
-use tonic::transport::Channel;
-use tonic::{IntoRequest, Request, Response, Status};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use tonic::{Code, IntoRequest, Request, Response, Status};
 
 #[derive(Error, Debug)]
 pub enum ArrowFlightSQLError {
@@ -47,6 +67,10 @@ pub enum ArrowFlightSQLError {
     TranslationError(#[from] TimeSeriesQueryToSQLError),
     ArrowError(#[from] ArrowError),
     PolarsError(#[from] PolarsError),
+    DecodeError(#[from] prost::DecodeError),
+    UnexpectedResponse(String),
+    ReadCertificateError(String, std::io::Error),
+    EndpointTaskError(String),
 }
 
 impl Display for ArrowFlightSQLError {
@@ -67,17 +91,81 @@ impl Display for ArrowFlightSQLError {
             ArrowFlightSQLError::PolarsError(err) => {
                 write!(f, "Problem creating dataframe from arrow: {:?}", err)
             }
+            ArrowFlightSQLError::DecodeError(err) => {
+                write!(f, "Problem decoding Flight SQL message: {}", err)
+            }
+            ArrowFlightSQLError::UnexpectedResponse(s) => {
+                write!(f, "Unexpected response from Flight SQL server: {}", s)
+            }
+            ArrowFlightSQLError::ReadCertificateError(path, err) => {
+                write!(f, "Could not read certificate {}: {}", path, err)
+            }
+            ArrowFlightSQLError::EndpointTaskError(err) => {
+                write!(f, "Fetching endpoint failed: {}", err)
+            }
         }
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct ArrowFlightSQLConfig {
+    //TLS is also used when the endpoint starts with https://
+    pub use_tls: bool,
+    //PEM-file with the CA certificate used to verify the server, the system roots are used if not set
+    pub root_certificate_path: Option<String>,
+    //Overrides the domain name the server certificate is verified against
+    pub tls_domain_name: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    //Retries of requests failing with Unavailable, DeadlineExceeded or a transport error
+    pub max_retries: u32,
+    //Doubled for each retry
+    pub retry_backoff: Duration,
+    //Endpoints of a FlightInfo that are fetched at the same time
+    pub max_parallel_endpoints: usize,
+    //Record batches that can be waiting to be consumed from a result stream
+    pub stream_buffer_size: usize,
+}
+
+impl Default for ArrowFlightSQLConfig {
+    fn default() -> Self {
+        ArrowFlightSQLConfig {
+            use_tls: false,
+            root_certificate_path: None,
+            tls_domain_name: None,
+            connect_timeout: Some(Duration::from_secs(30)),
+            request_timeout: None,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(500),
+            max_parallel_endpoints: 8,
+            stream_buffer_size: 16,
+        }
+    }
+}
+
+#[derive(Default)]
+struct RetryState {
+    attempts: u32,
+    reauthenticated: bool,
+}
+
+//Record batches as they arrive from the endpoints of a query, in no particular order
+pub type DataFrameStream = ReceiverStream<Result<DataFrame, ArrowFlightSQLError>>;
+
 pub struct ArrowFlightSQLDatabase {
     endpoint: String,
+    config: ArrowFlightSQLConfig,
+    //Created once, tonic reconnects the channel when the connection is lost
+    channel: Option<Channel>,
     username: String,
     password: String,
     token: Option<String>,
     cookies: Option<Vec<String>>,
     time_series_tables: Vec<TimeSeriesTable>,
+    sql_dialect: SqlDialect,
+    //Only set when the server understands Flight SQL commands,
+    // otherwise the SQL is sent as the raw command as Dremio's legacy Flight endpoint expects
+    sql_info: Option<FlightSqlInfo>,
 }
 
 impl ArrowFlightSQLDatabase {
@@ -86,124 +174,404 @@ impl ArrowFlightSQLDatabase {
         username: &str,
         password: &str,
         time_series_tables: Vec<TimeSeriesTable>,
+    ) -> Result<ArrowFlightSQLDatabase, ArrowFlightSQLError> {
+        ArrowFlightSQLDatabase::new_with_config(
+            endpoint,
+            username,
+            password,
+            time_series_tables,
+            ArrowFlightSQLConfig::default(),
+        )
+        .await
+    }
+
+    pub async fn new_with_config(
+        endpoint: &str,
+        username: &str,
+        password: &str,
+        time_series_tables: Vec<TimeSeriesTable>,
+        config: ArrowFlightSQLConfig,
     ) -> Result<ArrowFlightSQLDatabase, ArrowFlightSQLError> {
         let mut db = ArrowFlightSQLDatabase {
             endpoint: endpoint.into(),
+            config,
+            channel: None,
             username: username.into(),
             password: password.into(),
             token: None,
             cookies: None,
             time_series_tables,
+            sql_dialect: SqlDialect::Dremio,
+            sql_info: None,
         };
         db.init().await?;
         Ok(db)
     }
 
+    pub fn set_sql_dialect(&mut self, sql_dialect: SqlDialect) {
+        self.sql_dialect = sql_dialect;
+    }
+
     async fn init(&mut self) -> Result<(), ArrowFlightSQLError> {
-        let token = self.get_token().await?;
+        self.channel = Some(connect(self.endpoint.clone(), &self.config).await?);
+        self.refresh_token().await?;
+        match self.get_sql_info().await {
+            Ok(sql_info) => {
+                debug!(
+                    "Connected to Flight SQL server {:?} version {:?}",
+                    sql_info.get_string(FLIGHT_SQL_SERVER_NAME),
+                    sql_info.get_string(FLIGHT_SQL_SERVER_VERSION)
+                );
+                self.sql_info = Some(sql_info);
+            }
+            Err(err) => {
+                debug!(
+                    "GetSqlInfo failed, sending queries as raw SQL commands: {}",
+                    err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sql_info(&self) -> Option<&FlightSqlInfo> {
+        self.sql_info.as_ref()
+    }
+
+    //Also used to get a new token when the old one has expired
+    async fn refresh_token(&mut self) -> Result<(), ArrowFlightSQLError> {
+        let token = authenticate(self.get_channel(), &self.username, &self.password).await?;
         self.token = Some(token);
+        //Cookies belong to the session of the old token
+        self.cookies = None;
         Ok(())
     }
 
-    async fn get_token(&self) -> Result<String, ArrowFlightSQLError> {
-        let channel = self.get_channel().await?;
-        let token = authenticate(channel, &self.username, &self.password).await?;
-        Ok(token)
+    fn get_channel(&self) -> Channel {
+        self.channel.as_ref().unwrap().clone()
     }
 
-    async fn get_channel(&self) -> Result<Channel, ArrowFlightSQLError> {
-        let channel = tonic::transport::Endpoint::new(self.endpoint.clone())?
-            .connect()
-            .await?;
-        Ok(channel)
+    //Refreshes the token the first time a request is unauthenticated and backs off before
+    // retrying transient failures, returns the error if the request should not be retried
+    async fn prepare_retry(
+        &mut self,
+        err: ArrowFlightSQLError,
+        retry_state: &mut RetryState,
+    ) -> Result<(), ArrowFlightSQLError> {
+        let code = match &err {
+            ArrowFlightSQLError::TonicStatus(status) => Some(status.code()),
+            ArrowFlightSQLError::TransportError(_) => Some(Code::Unavailable),
+            _ => None,
+        };
+        match code {
+            Some(Code::Unauthenticated) if !retry_state.reauthenticated => {
+                debug!("Request was unauthenticated, authenticating again");
+                retry_state.reauthenticated = true;
+                self.refresh_token().await
+            }
+            Some(Code::Unavailable) | Some(Code::DeadlineExceeded)
+                if retry_state.attempts < self.config.max_retries =>
+            {
+                let backoff = self.config.retry_backoff * 2u32.pow(retry_state.attempts);
+                retry_state.attempts += 1;
+                warn!(
+                    "Request failed with {}, retry {} of {} in {} seconds",
+                    err,
+                    retry_state.attempts,
+                    self.config.max_retries,
+                    backoff.as_secs_f32()
+                );
+                sleep(backoff).await;
+                Ok(())
+            }
+            _ => Err(err),
+        }
     }
 
     pub async fn execute_sql_query(
         &mut self,
         query: String,
     ) -> Result<DataFrame, ArrowFlightSQLError> {
-        let instant = Instant::now();
-        let channel = self.get_channel().await?;
-        let elapsed = instant.elapsed();
-        debug!("Connecting took {} seconds", elapsed.as_secs_f32());
-        let mut dfs = vec![];
+        let mut retry_state = RetryState::default();
+        loop {
+            match self.try_execute_sql_query(query.clone()).await {
+                Ok(df) => return Ok(df),
+                Err(err) => self.prepare_retry(err, &mut retry_state).await?,
+            }
+        }
+    }
+
+    async fn try_execute_sql_query(
+        &mut self,
+        query: String,
+    ) -> Result<DataFrame, ArrowFlightSQLError> {
+        let stream = self.try_execute_sql_query_stream(query).await?;
+        collect_dataframes(stream).await
+    }
+
+    //Errors while reading the stream are not retried, as batches may already have been consumed
+    pub async fn execute_sql_query_stream(
+        &mut self,
+        query: String,
+    ) -> Result<DataFrameStream, ArrowFlightSQLError> {
+        let mut retry_state = RetryState::default();
+        loop {
+            match self.try_execute_sql_query_stream(query.clone()).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => self.prepare_retry(err, &mut retry_state).await?,
+            }
+        }
+    }
+
+    async fn try_execute_sql_query_stream(
+        &mut self,
+        query: String,
+    ) -> Result<DataFrameStream, ArrowFlightSQLError> {
+        let cmd = if self.sql_info.is_some() {
+            CommandStatementQuery {
+                query,
+                transaction_id: None,
+            }
+            .as_any()
+        } else {
+            query.into_bytes()
+        };
+        let mut client = self.get_client();
+        let flight_info = self.get_flight_info(&mut client, cmd).await?;
+        Ok(self.stream_endpoints(flight_info))
+    }
+
+    //Parameters are bound from the rows of the dataframe, in the order of its columns
+    pub async fn execute_prepared_sql_query(
+        &mut self,
+        query: String,
+        parameters: Option<DataFrame>,
+    ) -> Result<DataFrame, ArrowFlightSQLError> {
+        let mut retry_state = RetryState::default();
+        loop {
+            match self
+                .try_execute_prepared_sql_query(query.clone(), parameters.clone())
+                .await
+            {
+                Ok(df) => return Ok(df),
+                Err(err) => self.prepare_retry(err, &mut retry_state).await?,
+            }
+        }
+    }
+
+    async fn try_execute_prepared_sql_query(
+        &mut self,
+        query: String,
+        parameters: Option<DataFrame>,
+    ) -> Result<DataFrame, ArrowFlightSQLError> {
+        let mut client = self.get_client();
+        let prepared = self.create_prepared_statement(&mut client, query).await?;
+        let result = self
+            .execute_prepared_statement(
+                &mut client,
+                &prepared.prepared_statement_handle,
+                parameters,
+            )
+            .await;
+        self.close_prepared_statement(&mut client, prepared.prepared_statement_handle)
+            .await?;
+        result
+    }
+
+    async fn get_sql_info(&mut self) -> Result<FlightSqlInfo, ArrowFlightSQLError> {
+        let mut retry_state = RetryState::default();
+        loop {
+            match self.try_get_sql_info().await {
+                Ok(sql_info) => return Ok(sql_info),
+                Err(err) => self.prepare_retry(err, &mut retry_state).await?,
+            }
+        }
+    }
+
+    async fn try_get_sql_info(&mut self) -> Result<FlightSqlInfo, ArrowFlightSQLError> {
+        let cmd = CommandGetSqlInfo {
+            info: vec![
+                FLIGHT_SQL_SERVER_NAME,
+                FLIGHT_SQL_SERVER_VERSION,
+                FLIGHT_SQL_SERVER_READ_ONLY,
+                SQL_DATETIME_FUNCTIONS,
+            ],
+        }
+        .as_any();
+        let mut client = self.get_client();
+        let flight_info = self.get_flight_info(&mut client, cmd).await?;
+        let chunks = self.fetch_chunks(&mut client, flight_info).await?;
+        FlightSqlInfo::from_chunks(&chunks).map_err(ArrowFlightSQLError::UnexpectedResponse)
+    }
+
+    async fn create_prepared_statement(
+        &mut self,
+        client: &mut FlightServiceClient<Channel>,
+        query: String,
+    ) -> Result<ActionCreatePreparedStatementResult, ArrowFlightSQLError> {
+        let body = ActionCreatePreparedStatementRequest {
+            query,
+            transaction_id: None,
+        }
+        .as_any();
+        let results = self
+            .do_action(client, CREATE_PREPARED_STATEMENT, body)
+            .await?;
+        for r in results {
+            if let Some(prepared) = ActionCreatePreparedStatementResult::from_any(&r)? {
+                return Ok(prepared);
+            }
+        }
+        Err(ArrowFlightSQLError::UnexpectedResponse(
+            "no prepared statement was created".to_string(),
+        ))
+    }
+
+    async fn execute_prepared_statement(
+        &mut self,
+        client: &mut FlightServiceClient<Channel>,
+        handle: &Vec<u8>,
+        parameters: Option<DataFrame>,
+    ) -> Result<DataFrame, ArrowFlightSQLError> {
+        let cmd = CommandPreparedStatementQuery {
+            prepared_statement_handle: handle.clone(),
+        }
+        .as_any();
+        if let Some(parameters) = parameters {
+            let descriptor = FlightDescriptor {
+                r#type: 2, //CMD
+                cmd: cmd.clone(),
+                path: vec![],
+            };
+            let flight_data = dataframe_to_flight_data(parameters, descriptor)?;
+            let mut request = tokio_stream::iter(flight_data).into_request();
+            self.add_headers(&mut request);
+            let mut put_results = client.do_put(request).await?.into_inner();
+            while let Some(put_result) = put_results.next().await {
+                put_result?;
+            }
+        }
+        let flight_info = self.get_flight_info(client, cmd).await?;
+        collect_dataframes(self.stream_endpoints(flight_info)).await
+    }
+
+    async fn close_prepared_statement(
+        &mut self,
+        client: &mut FlightServiceClient<Channel>,
+        handle: Vec<u8>,
+    ) -> Result<(), ArrowFlightSQLError> {
+        let body = ActionClosePreparedStatementRequest {
+            prepared_statement_handle: handle,
+        }
+        .as_any();
+        self.do_action(client, CLOSE_PREPARED_STATEMENT, body)
+            .await?;
+        Ok(())
+    }
+
+    async fn do_action(
+        &mut self,
+        client: &mut FlightServiceClient<Channel>,
+        action_type: &str,
+        body: Vec<u8>,
+    ) -> Result<Vec<Vec<u8>>, ArrowFlightSQLError> {
+        let mut request = Action {
+            r#type: action_type.to_string(),
+            body,
+        }
+        .into_request();
+        self.add_headers(&mut request);
+        let mut results = client.do_action(request).await?.into_inner();
+        let mut bodies = vec![];
+        while let Some(result) = results.next().await {
+            bodies.push(result?.body);
+        }
+        Ok(bodies)
+    }
+
+    fn get_client(&self) -> FlightServiceClient<Channel> {
+        FlightServiceClient::new(self.get_channel())
+    }
+
+    async fn get_flight_info(
+        &mut self,
+        client: &mut FlightServiceClient<Channel>,
+        cmd: Vec<u8>,
+    ) -> Result<FlightInfo, ArrowFlightSQLError> {
         let mut request = FlightDescriptor {
             r#type: 2, //CMD
-            cmd: query.into_bytes(),
-            //TODO: For some reason, encoding the CommandStatementQuery-struct
-            // gives me a parsing error with an extra character at the start of the decoded query.
+            cmd,
             path: vec![], // Should be empty when CMD
         }
         .into_request();
         add_auth_header(&mut request, self.token.as_ref().unwrap());
-
-        let mut client = FlightServiceClient::new(channel);
         let response = client.get_flight_info(request).await?;
         //We expect some new cookies here since we did not add cookies to the get flight info.
         //See: https://docs.dremio.com/software/developing-client-apps/arrow-flight/
         self.find_set_cookies(&response);
         debug!("Got flight info response");
-        let mut schema_opt = None;
-        let mut ipc_schema_opt = None;
-        for endpoint in response.into_inner().endpoint {
+        Ok(response.into_inner())
+    }
+
+    //Reads the endpoints one at a time, for small results that are not converted to dataframes
+    async fn fetch_chunks(
+        &self,
+        client: &mut FlightServiceClient<Channel>,
+        flight_info: FlightInfo,
+    ) -> Result<Vec<Chunk<Box<dyn Array>>>, ArrowFlightSQLError> {
+        let mut chunks = vec![];
+        for endpoint in flight_info.endpoint {
             if let Some(ticket) = endpoint.ticket.clone() {
                 let mut ticket = ticket.into_request();
-                add_auth_header(&mut ticket, self.token.as_ref().unwrap());
-                add_cookies(&mut ticket, self.cookies.as_ref().unwrap());
-                let stream = client
-                    .do_get(ticket)
-                    .await
-                    .map_err(ArrowFlightSQLError::from)?;
-                let mut streaming_flight_data = stream.into_inner();
-                while let Some(flight_data_result) = streaming_flight_data.next().await {
-                    if let Ok(flight_data) = flight_data_result {
-                        let message =
-                            arrow_format::ipc::MessageRef::read_as_root(&flight_data.data_header)
-                                .unwrap();
-                        let header = message.header().unwrap().unwrap();
-                        match header {
-                            MessageHeaderRef::Schema(_) => {
-                                if schema_opt.is_some() || ipc_schema_opt.is_some() {
-                                    warn!("Received multiple schema messages, keeping last");
-                                }
-                                let (schema, ipc_schema) =
-                                    flight2::deserialize_schemas(&flight_data.data_header)
-                                        .expect("Schema deserialization problem");
-                                schema_opt = Some(schema);
-                                ipc_schema_opt = Some(ipc_schema);
-                            }
-                            MessageHeaderRef::DictionaryBatch(_) => {
-                                unimplemented!("Dictionary batch not implemented")
-                            }
-                            MessageHeaderRef::RecordBatch(_) => {
-                                let chunk = flight2::deserialize_batch(
-                                    &flight_data,
-                                    schema_opt.as_ref().unwrap().fields.as_slice(),
-                                    &ipc_schema_opt.as_ref().unwrap(),
-                                    &Default::default(),
-                                )
-                                .map_err(ArrowFlightSQLError::from)?;
-
-                                let df = DataFrame::try_from((
-                                    chunk,
-                                    schema_opt.as_ref().unwrap().fields.as_slice(),
-                                ))
-                                .map_err(ArrowFlightSQLError::from)?;
-                                dfs.push(df);
-                            }
-                            MessageHeaderRef::Tensor(_) => {
-                                unimplemented!("Tensor message not implemented");
-                            }
-                            MessageHeaderRef::SparseTensor(_) => {
-                                unimplemented!("Sparse tensor message not implemented");
-                            }
-                        }
+                self.add_headers(&mut ticket);
+                let mut streaming_flight_data = client.do_get(ticket).await?.into_inner();
+                let mut decoder = FlightDataDecoder::default();
+                while let Some(flight_data) = streaming_flight_data.next().await {
+                    if let Some(chunk) = decoder.decode(&flight_data?)? {
+                        chunks.push(chunk);
                     }
                 }
             }
         }
-        Ok(accumulate_dataframes_vertical(dfs).expect("Problem stacking dataframes"))
+        Ok(chunks)
     }
+
+    //Fetches the endpoints concurrently, sending the record batches on as they are decoded
+    fn stream_endpoints(&self, flight_info: FlightInfo) -> DataFrameStream {
+        let (sender, receiver) = mpsc::channel(self.config.stream_buffer_size);
+        let semaphore = Arc::new(Semaphore::new(self.config.max_parallel_endpoints.max(1)));
+        for endpoint in flight_info.endpoint {
+            let fetcher = EndpointFetcher {
+                channel: self.get_channel(),
+                config: self.config.clone(),
+                token: self.token.clone().unwrap(),
+                cookies: self.cookies.clone(),
+                sender: sender.clone(),
+            };
+            let semaphore = semaphore.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let sender = fetcher.sender.clone();
+                //Fetching in its own task lets a panic be reported as an error on the stream
+                let result = match tokio::spawn(fetcher.fetch(endpoint)).await {
+                    Ok(result) => result,
+                    Err(join_error) => Err(ArrowFlightSQLError::EndpointTaskError(
+                        join_error.to_string(),
+                    )),
+                };
+                if let Err(err) = result {
+                    //The receiver may have been dropped, then nobody needs the error
+                    let _ = sender.send(Err(err)).await;
+                }
+            });
+        }
+        ReceiverStream::new(receiver)
+    }
+
+    fn add_headers<T>(&self, request: &mut Request<T>) {
+        add_session_headers(request, self.token.as_ref().unwrap(), &self.cookies);
+    }
+
     fn find_set_cookies(&mut self, response: &Response<FlightInfo>) {
         let mut cookies: Vec<String> = response
             .metadata()
@@ -225,19 +593,173 @@ impl TimeSeriesQueryable for ArrowFlightSQLDatabase {
     async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
         let query_string;
         {
-            let transformer = TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables);
+            let transformer =
+                TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
             let (query, _) = transformer.create_query(tsq, false)?;
-            query_string = query.to_string(PostgresQueryBuilder);
+            query_string = self.sql_dialect.build_query(&query);
             debug!("SQL: {}", query_string);
         }
-        Ok(self.execute_sql_query(query_string).await?)
+        let df = self.execute_sql_query(query_string).await?;
+        Ok(normalize_timestamps(df, tsq, &self.time_series_tables)?)
     }
 
-    fn allow_compound_timeseries_queries(&self) -> bool {
-        true
+    fn validate_pushdown(&self, tsq: &TimeSeriesQuery) -> Result<(), Box<dyn Error>> {
+        let transformer =
+            TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
+        transformer.validate_aggregations(tsq)?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        let mut capabilities = self.sql_dialect.capabilities();
+        if let Some(sql_info) = &self.sql_info {
+            if !sql_info.supports_datetime_functions(&self.sql_dialect.date_functions()) {
+                capabilities
+                    .expression_kinds
+                    .remove(&ExpressionKind::DateFunction);
+            }
+        }
+        capabilities.table_annotations = has_table_iris(&self.time_series_tables);
+        capabilities
+    }
+}
+
+struct EndpointFetcher {
+    channel: Channel,
+    config: ArrowFlightSQLConfig,
+    token: String,
+    cookies: Option<Vec<String>>,
+    sender: mpsc::Sender<Result<DataFrame, ArrowFlightSQLError>>,
+}
+
+impl EndpointFetcher {
+    async fn fetch(self, endpoint: FlightEndpoint) -> Result<(), ArrowFlightSQLError> {
+        let ticket = if let Some(ticket) = endpoint.ticket {
+            ticket
+        } else {
+            return Ok(());
+        };
+        //Endpoints without a location are fetched from the server that returned the FlightInfo
+        let channel = if let Some(uri) = endpoint
+            .location
+            .iter()
+            .find_map(|l| location_to_endpoint(&l.uri))
+        {
+            debug!("Fetching endpoint from {}", uri);
+            connect(uri, &self.config).await?
+        } else {
+            self.channel.clone()
+        };
+        let mut client = FlightServiceClient::new(channel);
+        let mut request = ticket.into_request();
+        add_session_headers(&mut request, &self.token, &self.cookies);
+        let mut streaming_flight_data = client.do_get(request).await?.into_inner();
+        let mut decoder = FlightDataDecoder::default();
+        let mut any_batches = false;
+        while let Some(flight_data) = streaming_flight_data.next().await {
+            if let Some(chunk) = decoder.decode(&flight_data?)? {
+                any_batches = true;
+                if self.sender.send(decoder.to_dataframe(chunk)).await.is_err() {
+                    debug!("Result stream was dropped, stopping fetching endpoint");
+                    return Ok(());
+                }
+            }
+        }
+        if !any_batches {
+            if let Some(df) = decoder.empty_dataframe() {
+                let _ = self.sender.send(Ok(df)).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Flight locations use grpc-schemes, the reuse-scheme means the original server
+fn location_to_endpoint(uri: &str) -> Option<String> {
+    if let Some(rest) = uri.strip_prefix("grpc+tls://") {
+        Some(format!("https://{}", rest))
+    } else if let Some(rest) = uri
+        .strip_prefix("grpc+tcp://")
+        .or(uri.strip_prefix("grpc://"))
+    {
+        Some(format!("http://{}", rest))
+    } else {
+        None
     }
 }
 
+async fn connect(
+    endpoint_uri: String,
+    config: &ArrowFlightSQLConfig,
+) -> Result<Channel, ArrowFlightSQLError> {
+    let use_tls = config.use_tls || endpoint_uri.starts_with("https://");
+    let mut endpoint = tonic::transport::Endpoint::new(endpoint_uri)?;
+    if use_tls {
+        let mut tls_config = ClientTlsConfig::new();
+        if let Some(path) = &config.root_certificate_path {
+            let pem = std::fs::read(path)
+                .map_err(|e| ArrowFlightSQLError::ReadCertificateError(path.clone(), e))?;
+            tls_config = tls_config.ca_certificate(Certificate::from_pem(pem));
+        }
+        if let Some(domain_name) = &config.tls_domain_name {
+            tls_config = tls_config.domain_name(domain_name);
+        }
+        endpoint = endpoint.tls_config(tls_config)?;
+    }
+    if let Some(connect_timeout) = config.connect_timeout {
+        endpoint = endpoint.connect_timeout(connect_timeout);
+    }
+    if let Some(request_timeout) = config.request_timeout {
+        endpoint = endpoint.timeout(request_timeout);
+    }
+    let instant = Instant::now();
+    let channel = endpoint.connect().await?;
+    debug!(
+        "Connecting took {} seconds",
+        instant.elapsed().as_secs_f32()
+    );
+    Ok(channel)
+}
+
+async fn collect_dataframes(mut stream: DataFrameStream) -> Result<DataFrame, ArrowFlightSQLError> {
+    let mut out: Option<DataFrame> = None;
+    while let Some(df) = stream.next().await {
+        let df = df?;
+        match &mut out {
+            //Empty frames only tell us the schema, which may be less precise than that of a batch
+            Some(out_df) if df.height() == 0 => {
+                debug!("Skipping empty frame with {} columns", out_df.width());
+            }
+            Some(out_df) if out_df.height() > 0 => {
+                out_df.vstack_mut(&df)?;
+            }
+            _ => out = Some(df),
+        }
+    }
+    out.ok_or(ArrowFlightSQLError::UnexpectedResponse(
+        "no schema or record batches in result".to_string(),
+    ))
+}
+
+fn dataframe_to_flight_data(
+    mut df: DataFrame,
+    descriptor: FlightDescriptor,
+) -> Result<Vec<FlightData>, ArrowFlightSQLError> {
+    df.rechunk();
+    let schema = df.schema().to_arrow();
+    let ipc_fields = default_ipc_fields(&schema.fields);
+    let mut schema_data = flight2::serialize_schema(&schema, Some(&ipc_fields));
+    schema_data.flight_descriptor = Some(descriptor);
+    let mut flight_data = vec![schema_data];
+    for chunk in df.iter_chunks() {
+        let (dictionaries, batch) =
+            flight2::serialize_batch(&chunk, &ipc_fields, &WriteOptions { compression: None })?;
+        flight_data.extend(dictionaries);
+        flight_data.push(batch);
+    }
+    Ok(flight_data)
+}
+
 //Adapted from: https://github.com/apache/arrow-rs/blob/master/integration-testing/src/flight_client_scenarios/auth_basic_proto.rs
 // Licensed to the Apache Software Foundation (ASF) under one
 // or more contributor license agreements.  See the NOTICE file
@@ -281,13 +803,34 @@ async fn authenticate(
     let bearer_token = rx
         .metadata()
         .get("authorization")
-        .unwrap()
+        .ok_or_else(|| {
+            ArrowFlightSQLError::UnexpectedResponse(
+                "Handshake response has no authorization header".to_string(),
+            )
+        })?
         .to_str()
-        .unwrap()
+        .map_err(|_| {
+            ArrowFlightSQLError::UnexpectedResponse(
+                "Handshake response has an authorization header that is not ASCII".to_string(),
+            )
+        })?
         .to_string();
     Ok(bearer_token)
 }
 
+fn add_session_headers<T>(
+    request: &mut Request<T>,
+    bearer_token: &str,
+    cookies: &Option<Vec<String>>,
+) {
+    add_auth_header(request, bearer_token);
+    if let Some(cookies) = cookies {
+        if !cookies.is_empty() {
+            add_cookies(request, cookies);
+        }
+    }
+}
+
 fn add_auth_header<T>(request: &mut Request<T>, bearer_token: &str) {
     let token_value: MetadataValue<_> = bearer_token.parse().unwrap();
     request.metadata_mut().insert("authorization", token_value);
@@ -0,0 +1,150 @@
+use super::ArrowFlightSQLError;
+use arrow2::array::Array;
+use arrow2::chunk::Chunk;
+use arrow2::compute::cast::{cast, CastOptions};
+use arrow2::datatypes::{DataType as ArrowDataType, Field, Schema};
+use arrow2::io::flight as flight2;
+use arrow2::io::ipc::read::Dictionaries;
+use arrow2::io::ipc::IpcSchema;
+use arrow_format::flight::data::FlightData;
+use arrow_format::ipc::planus::ReadAsRoot;
+use arrow_format::ipc::MessageHeaderRef;
+use log::warn;
+use polars::enable_string_cache;
+use polars::frame::DataFrame;
+use polars_core::prelude::Schema as PolarsSchema;
+
+//Decodes the messages of a single do_get stream, each stream starts with its own schema
+// and the dictionaries it sends apply to the record batches that follow them
+#[derive(Default)]
+pub(crate) struct FlightDataDecoder {
+    schema: Option<Schema>,
+    ipc_schema: Option<IpcSchema>,
+    dictionaries: Dictionaries,
+}
+
+impl FlightDataDecoder {
+    //Returns a chunk for record batches, None for other messages
+    pub(crate) fn decode(
+        &mut self,
+        flight_data: &FlightData,
+    ) -> Result<Option<Chunk<Box<dyn Array>>>, ArrowFlightSQLError> {
+        let message = arrow_format::ipc::MessageRef::read_as_root(&flight_data.data_header)
+            .map_err(|x| ArrowFlightSQLError::UnexpectedResponse(x.to_string()))?;
+        let header = message
+            .header()
+            .map_err(|x| ArrowFlightSQLError::UnexpectedResponse(x.to_string()))?
+            .ok_or(ArrowFlightSQLError::UnexpectedResponse(
+                "IPC message without header".to_string(),
+            ))?;
+        match header {
+            MessageHeaderRef::Schema(_) => {
+                if self.schema.is_some() || self.ipc_schema.is_some() {
+                    warn!("Received multiple schema messages, keeping last");
+                }
+                let (schema, ipc_schema) = flight2::deserialize_schemas(&flight_data.data_header)?;
+                self.schema = Some(schema);
+                self.ipc_schema = Some(ipc_schema);
+                self.dictionaries.clear();
+                Ok(None)
+            }
+            MessageHeaderRef::DictionaryBatch(_) => {
+                let ipc_schema =
+                    self.ipc_schema
+                        .as_ref()
+                        .ok_or(ArrowFlightSQLError::UnexpectedResponse(
+                            "Dictionary batch before schema".to_string(),
+                        ))?;
+                flight2::deserialize_dictionary(
+                    flight_data,
+                    self.fields(),
+                    ipc_schema,
+                    &mut self.dictionaries,
+                )?;
+                Ok(None)
+            }
+            MessageHeaderRef::RecordBatch(_) => {
+                let chunk = flight2::deserialize_batch(
+                    flight_data,
+                    self.fields(),
+                    self.ipc_schema
+                        .as_ref()
+                        .ok_or(ArrowFlightSQLError::UnexpectedResponse(
+                            "Record batch before schema".to_string(),
+                        ))?,
+                    &self.dictionaries,
+                )?;
+                Ok(Some(chunk))
+            }
+            MessageHeaderRef::Tensor(_) => Err(ArrowFlightSQLError::UnexpectedResponse(
+                "Tensor messages are not supported".to_string(),
+            )),
+            MessageHeaderRef::SparseTensor(_) => Err(ArrowFlightSQLError::UnexpectedResponse(
+                "Sparse tensor messages are not supported".to_string(),
+            )),
+        }
+    }
+
+    pub(crate) fn fields(&self) -> &[Field] {
+        if let Some(schema) = &self.schema {
+            schema.fields.as_slice()
+        } else {
+            &[]
+        }
+    }
+
+    //Dictionary encoded strings become categoricals, other dictionaries are unpacked to their values
+    pub(crate) fn to_dataframe(
+        &self,
+        chunk: Chunk<Box<dyn Array>>,
+    ) -> Result<DataFrame, ArrowFlightSQLError> {
+        let fields = self.fields();
+        if !fields.iter().any(|f| is_dictionary(f.data_type())) {
+            return Ok(DataFrame::try_from((chunk, fields))?);
+        }
+        //Categoricals from different batches and endpoints are stacked and joined with the
+        // solution mappings, which requires that they share the global string cache
+        enable_string_cache(true);
+        let mut arrays = vec![];
+        let mut out_fields = vec![];
+        for (array, field) in chunk.into_arrays().into_iter().zip(fields) {
+            match field.data_type() {
+                ArrowDataType::Dictionary(_, value_type, _) if !is_string(value_type) => {
+                    let unpacked = cast(array.as_ref(), value_type, CastOptions::default())?;
+                    out_fields.push(Field::new(
+                        &field.name,
+                        value_type.as_ref().clone(),
+                        field.is_nullable,
+                    ));
+                    arrays.push(unpacked);
+                }
+                _ => {
+                    out_fields.push(field.clone());
+                    arrays.push(array);
+                }
+            }
+        }
+        Ok(DataFrame::try_from((
+            Chunk::new(arrays),
+            out_fields.as_slice(),
+        ))?)
+    }
+
+    //Used when a stream has a schema but no record batches
+    pub(crate) fn empty_dataframe(&self) -> Option<DataFrame> {
+        self.schema.as_ref().map(|schema| {
+            let polars_schema = PolarsSchema::from_iter(
+                schema.fields.iter().map(polars_core::prelude::Field::from),
+            );
+            DataFrame::from(&polars_schema)
+        })
+    }
+}
+
+fn is_dictionary(data_type: &ArrowDataType) -> bool {
+    matches!(data_type, ArrowDataType::Dictionary(..))
+}
+
+fn is_string(data_type: &ArrowDataType) -> bool {
+    matches!(data_type, ArrowDataType::Utf8 | ArrowDataType::LargeUtf8)
+}
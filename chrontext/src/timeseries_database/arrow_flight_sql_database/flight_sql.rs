@@ -0,0 +1,218 @@
+//Messages from the Flight SQL protocol definition, see:
+// https://github.com/apache/arrow/blob/main/format/FlightSql.proto
+// Flight SQL commands and action bodies are sent as protobuf Any-messages.
+
+use arrow2::array::{Array, BooleanArray, ListArray, PrimitiveArray, UnionArray, Utf8Array};
+use arrow2::chunk::Chunk;
+use prost::Message;
+use std::collections::HashMap;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/arrow.flight.protocol.sql.";
+
+pub const CREATE_PREPARED_STATEMENT: &str = "CreatePreparedStatement";
+pub const CLOSE_PREPARED_STATEMENT: &str = "ClosePreparedStatement";
+
+//Ids of the SqlInfo-values we ask for
+pub const FLIGHT_SQL_SERVER_NAME: u32 = 0;
+pub const FLIGHT_SQL_SERVER_VERSION: u32 = 1;
+pub const FLIGHT_SQL_SERVER_READ_ONLY: u32 = 3;
+pub const SQL_DATETIME_FUNCTIONS: u32 = 512;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Any {
+    #[prost(string, tag = "1")]
+    pub type_url: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CommandStatementQuery {
+    #[prost(string, tag = "1")]
+    pub query: String,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub transaction_id: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CommandGetSqlInfo {
+    #[prost(uint32, repeated, tag = "1")]
+    pub info: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CommandPreparedStatementQuery {
+    #[prost(bytes = "vec", tag = "1")]
+    pub prepared_statement_handle: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ActionCreatePreparedStatementRequest {
+    #[prost(string, tag = "1")]
+    pub query: String,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub transaction_id: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ActionCreatePreparedStatementResult {
+    #[prost(bytes = "vec", tag = "1")]
+    pub prepared_statement_handle: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub dataset_schema: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub parameter_schema: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ActionClosePreparedStatementRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub prepared_statement_handle: Vec<u8>,
+}
+
+pub trait FlightSqlMessage: Message + Default + Sized {
+    const TYPE_NAME: &'static str;
+
+    fn as_any(&self) -> Vec<u8> {
+        Any {
+            type_url: format!("{}{}", TYPE_URL_PREFIX, Self::TYPE_NAME),
+            value: self.encode_to_vec(),
+        }
+        .encode_to_vec()
+    }
+
+    //Returns None if the Any-message contains a different message type
+    fn from_any(bytes: &[u8]) -> Result<Option<Self>, prost::DecodeError> {
+        let any = Any::decode(bytes)?;
+        if any.type_url != format!("{}{}", TYPE_URL_PREFIX, Self::TYPE_NAME) {
+            return Ok(None);
+        }
+        Ok(Some(Self::decode(any.value.as_slice())?))
+    }
+}
+
+impl FlightSqlMessage for CommandStatementQuery {
+    const TYPE_NAME: &'static str = "CommandStatementQuery";
+}
+
+impl FlightSqlMessage for CommandGetSqlInfo {
+    const TYPE_NAME: &'static str = "CommandGetSqlInfo";
+}
+
+impl FlightSqlMessage for CommandPreparedStatementQuery {
+    const TYPE_NAME: &'static str = "CommandPreparedStatementQuery";
+}
+
+impl FlightSqlMessage for ActionCreatePreparedStatementRequest {
+    const TYPE_NAME: &'static str = "ActionCreatePreparedStatementRequest";
+}
+
+impl FlightSqlMessage for ActionCreatePreparedStatementResult {
+    const TYPE_NAME: &'static str = "ActionCreatePreparedStatementResult";
+}
+
+impl FlightSqlMessage for ActionClosePreparedStatementRequest {
+    const TYPE_NAME: &'static str = "ActionClosePreparedStatementRequest";
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlInfoValue {
+    String(String),
+    Boolean(bool),
+    BigInt(i64),
+    Bitmask(i32),
+    StringList(Vec<String>),
+    //The map-variant of the union is not used for anything we ask for
+    Unsupported,
+}
+
+//The SqlInfo-values reported by the server, used to detect what it supports
+#[derive(Clone, Debug, Default)]
+pub struct FlightSqlInfo {
+    pub values: HashMap<u32, SqlInfoValue>,
+}
+
+impl FlightSqlInfo {
+    //Reads the (info_name: uint32, value: dense_union) batches returned for CommandGetSqlInfo
+    pub fn from_chunks(chunks: &Vec<Chunk<Box<dyn Array>>>) -> Result<FlightSqlInfo, String> {
+        let mut values = HashMap::new();
+        for chunk in chunks {
+            if chunk.arrays().len() != 2 {
+                return Err(format!(
+                    "Expected two SqlInfo columns, got {}",
+                    chunk.arrays().len()
+                ));
+            }
+            let names = chunk.arrays()[0]
+                .as_any()
+                .downcast_ref::<PrimitiveArray<u32>>()
+                .ok_or("SqlInfo names should be uint32")?;
+            let union = chunk.arrays()[1]
+                .as_any()
+                .downcast_ref::<UnionArray>()
+                .ok_or("SqlInfo values should be a union")?;
+            for i in 0..names.len() {
+                if let Some(name) = names.get(i) {
+                    let (field, offset) = union.index(i);
+                    values.insert(
+                        name,
+                        union_value(union.fields().get(field).unwrap(), offset),
+                    );
+                }
+            }
+        }
+        Ok(FlightSqlInfo { values })
+    }
+
+    pub fn get_string(&self, info: u32) -> Option<&String> {
+        if let Some(SqlInfoValue::String(s)) = self.values.get(&info) {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_string_list(&self, info: u32) -> Option<&Vec<String>> {
+        if let Some(SqlInfoValue::StringList(l)) = self.values.get(&info) {
+            Some(l)
+        } else {
+            None
+        }
+    }
+
+    //Servers that do not report their datetime functions are assumed to support them
+    pub fn supports_datetime_functions(&self, functions: &Vec<&str>) -> bool {
+        if let Some(reported) = self.get_string_list(SQL_DATETIME_FUNCTIONS) {
+            functions
+                .iter()
+                .all(|f| reported.iter().any(|r| r.eq_ignore_ascii_case(f)))
+        } else {
+            true
+        }
+    }
+}
+
+fn union_value(array: &Box<dyn Array>, offset: usize) -> SqlInfoValue {
+    if array.is_null(offset) {
+        return SqlInfoValue::Unsupported;
+    }
+    let any = array.as_any();
+    if let Some(a) = any.downcast_ref::<Utf8Array<i32>>() {
+        SqlInfoValue::String(a.value(offset).to_string())
+    } else if let Some(a) = any.downcast_ref::<BooleanArray>() {
+        SqlInfoValue::Boolean(a.value(offset))
+    } else if let Some(a) = any.downcast_ref::<PrimitiveArray<i64>>() {
+        SqlInfoValue::BigInt(a.value(offset))
+    } else if let Some(a) = any.downcast_ref::<PrimitiveArray<i32>>() {
+        SqlInfoValue::Bitmask(a.value(offset))
+    } else if let Some(a) = any.downcast_ref::<ListArray<i32>>() {
+        let list = a.value(offset);
+        if let Some(strings) = list.as_any().downcast_ref::<Utf8Array<i32>>() {
+            SqlInfoValue::StringList(strings.iter().flatten().map(|x| x.to_string()).collect())
+        } else {
+            SqlInfoValue::Unsupported
+        }
+    } else {
+        SqlInfoValue::Unsupported
+    }
+}
@@ -0,0 +1,199 @@
+use crate::constants::DATETIME_AS_SECONDS;
+use crate::pushdown_setting::PushdownSetting;
+use spargebra::algebra::{Expression, Function};
+use std::collections::HashSet;
+
+#[derive(Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TimeSeriesQueryKind {
+    Filtered,
+    InnerSynchronized,
+    ExpressionAs,
+    Grouped,
+    Limited,
+}
+
+#[derive(Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ExpressionKind {
+    And,
+    Or,
+    Not,
+    Comparison,
+    Arithmetic,
+    In,
+    If,
+    Coalesce,
+    //Extraction of date parts and conversion of timestamps to epoch seconds
+    DateFunction,
+    FunctionCall,
+}
+
+//Describes the parts of time series queries that a database can execute natively,
+// the remaining parts are evaluated by the combiner.
+//Filters are split by conjunct, so the supported conjuncts are still pushed down.
+//Each group by is either pushed down with all of its expressions and aggregations,
+// or aggregated by the combiner on top of the basic queries it contains.
+//Ordering is not pushed down, so it has no capability: order by is always evaluated
+// by the combiner, and limits are never pushed below it.
+#[derive(Clone, Debug)]
+pub struct TimeSeriesQueryCapabilities {
+    pub query_kinds: HashSet<TimeSeriesQueryKind>,
+    pub expression_kinds: HashSet<ExpressionKind>,
+    //Function calls that can be evaluated, None if all of them can
+    pub functions: Option<HashSet<Function>>,
+    pub timestamp_conditions: bool,
+    pub value_conditions: bool,
+    //Whether time series are routed to tables by their chrontext:hasTable annotations
+    pub table_annotations: bool,
+}
+
+impl TimeSeriesQueryCapabilities {
+    pub fn all() -> TimeSeriesQueryCapabilities {
+        TimeSeriesQueryCapabilities {
+            query_kinds: [
+                TimeSeriesQueryKind::Filtered,
+                TimeSeriesQueryKind::InnerSynchronized,
+                TimeSeriesQueryKind::ExpressionAs,
+                TimeSeriesQueryKind::Grouped,
+                TimeSeriesQueryKind::Limited,
+            ]
+            .into(),
+            expression_kinds: [
+                ExpressionKind::And,
+                ExpressionKind::Or,
+                ExpressionKind::Not,
+                ExpressionKind::Comparison,
+                ExpressionKind::Arithmetic,
+                ExpressionKind::In,
+                ExpressionKind::If,
+                ExpressionKind::Coalesce,
+                ExpressionKind::DateFunction,
+                ExpressionKind::FunctionCall,
+            ]
+            .into(),
+            functions: None,
+            timestamp_conditions: true,
+            value_conditions: true,
+            table_annotations: false,
+        }
+    }
+
+    //Pushdowns that are turned off by the user are not used even if the database supports them
+    pub fn restrict_to_pushdown_settings(
+        mut self,
+        pushdown_settings: &HashSet<PushdownSetting>,
+    ) -> TimeSeriesQueryCapabilities {
+        if !pushdown_settings.contains(&PushdownSetting::GroupBy) {
+            self.query_kinds.remove(&TimeSeriesQueryKind::Grouped);
+        }
+        if !pushdown_settings.contains(&PushdownSetting::ValueConditions) {
+            self.value_conditions = false;
+        }
+        self
+    }
+
+    pub fn supports_query_kind(&self, kind: &TimeSeriesQueryKind) -> bool {
+        self.query_kinds.contains(kind)
+    }
+
+    //Only considers the outermost operator of the expression
+    pub fn supports_expression_kind(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::NamedNode(_) | Expression::Literal(_) | Expression::Variable(_) => true,
+            Expression::Exists(_) | Expression::Bound(_) => false,
+            Expression::FunctionCall(f, _) if !self.supports_function(f) => false,
+            _ => self
+                .expression_kinds
+                .contains(&expression_kind(expression).unwrap()),
+        }
+    }
+
+    pub fn supports_function(&self, function: &Function) -> bool {
+        if let Some(functions) = &self.functions {
+            functions.contains(function)
+        } else {
+            true
+        }
+    }
+
+    pub fn supports_expression(&self, expression: &Expression) -> bool {
+        self.supports_expression_kind(expression)
+            && expression_arguments(expression)
+                .into_iter()
+                .all(|a| self.supports_expression(a))
+    }
+}
+
+fn expression_kind(expression: &Expression) -> Option<ExpressionKind> {
+    let kind = match expression {
+        Expression::NamedNode(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Exists(_)
+        | Expression::Bound(_) => return None,
+        Expression::Or(..) => ExpressionKind::Or,
+        Expression::And(..) => ExpressionKind::And,
+        Expression::Equal(..)
+        | Expression::SameTerm(..)
+        | Expression::Greater(..)
+        | Expression::GreaterOrEqual(..)
+        | Expression::Less(..)
+        | Expression::LessOrEqual(..) => ExpressionKind::Comparison,
+        Expression::Add(..)
+        | Expression::Subtract(..)
+        | Expression::Multiply(..)
+        | Expression::Divide(..)
+        | Expression::UnaryPlus(..)
+        | Expression::UnaryMinus(..) => ExpressionKind::Arithmetic,
+        Expression::Not(..) => ExpressionKind::Not,
+        Expression::In(..) => ExpressionKind::In,
+        Expression::If(..) => ExpressionKind::If,
+        Expression::Coalesce(..) => ExpressionKind::Coalesce,
+        Expression::FunctionCall(f, _) => match f {
+            Function::Year
+            | Function::Month
+            | Function::Day
+            | Function::Hours
+            | Function::Minutes
+            | Function::Seconds => ExpressionKind::DateFunction,
+            Function::Custom(nn) if nn.as_str() == DATETIME_AS_SECONDS => {
+                ExpressionKind::DateFunction
+            }
+            _ => ExpressionKind::FunctionCall,
+        },
+    };
+    Some(kind)
+}
+
+fn expression_arguments(expression: &Expression) -> Vec<&Expression> {
+    match expression {
+        Expression::NamedNode(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Exists(_)
+        | Expression::Bound(_) => vec![],
+        Expression::Or(left, right)
+        | Expression::And(left, right)
+        | Expression::Equal(left, right)
+        | Expression::SameTerm(left, right)
+        | Expression::Greater(left, right)
+        | Expression::GreaterOrEqual(left, right)
+        | Expression::Less(left, right)
+        | Expression::LessOrEqual(left, right)
+        | Expression::Add(left, right)
+        | Expression::Subtract(left, right)
+        | Expression::Multiply(left, right)
+        | Expression::Divide(left, right) => vec![left.as_ref(), right.as_ref()],
+        Expression::UnaryPlus(inner) | Expression::UnaryMinus(inner) | Expression::Not(inner) => {
+            vec![inner.as_ref()]
+        }
+        Expression::In(left, right) => {
+            let mut args = vec![left.as_ref()];
+            args.extend(right.iter());
+            args
+        }
+        Expression::If(left, middle, right) => {
+            vec![left.as_ref(), middle.as_ref(), right.as_ref()]
+        }
+        Expression::Coalesce(inner) | Expression::FunctionCall(_, inner) => inner.iter().collect(),
+    }
+}
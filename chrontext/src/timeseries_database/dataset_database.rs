@@ -0,0 +1,732 @@
+use crate::combiner::solution_mapping::SolutionMappings;
+use crate::combiner::{Combiner, CombinerError};
+use crate::constants::GROUPING_COL;
+use crate::external_ids::ExternalId;
+use crate::pushdown_setting::all_pushdowns;
+use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
+use crate::timeseries_database::simple_in_memory_timeseries::InMemoryTimeseriesDatabase;
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::{
+    has_table_iris, route_basic_query,
+};
+use crate::timeseries_database::timeseries_sql_rewrite::{
+    TimeSeriesQueryToSQLError, TimeSeriesTable,
+};
+use crate::timeseries_database::TimeSeriesQueryable;
+use crate::timeseries_query::{
+    BasicTimeSeriesQuery, GroupedTimeSeriesQuery, Synchronizer, TimeSeriesQuery,
+};
+use async_recursion::async_recursion;
+use async_trait::async_trait;
+use log::debug;
+use oxrdf::vocab::xsd;
+use polars::export::chrono::{DateTime, NaiveDateTime, Utc};
+use polars::frame::DataFrame;
+use polars::prelude::{
+    col, concat, lit, when, Expr, IdxSize, IntoLazy, LazyFrame, PolarsError, ScanArgsIpc,
+    ScanArgsParquet, Series, UnionArgs,
+};
+use polars_core::prelude::{DataType, JoinArgs, JoinType};
+use spargebra::algebra::Expression;
+use spargebra::term::Variable;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum DatasetDatabaseError {
+    IOError(PathBuf, std::io::Error),
+    PolarsError(PolarsError),
+    TableRoutingError(TimeSeriesQueryToSQLError),
+    NoFilesFound(PathBuf),
+    EvaluationError(CombinerError),
+    MissingIdentifierVariable,
+    MissingIds,
+    MissingGroupingColumn,
+    UnsupportedSynchronizers(Vec<Synchronizer>),
+}
+
+impl Display for DatasetDatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatasetDatabaseError::IOError(path, err) => {
+                write!(f, "Error reading {}: {}", path.display(), err)
+            }
+            DatasetDatabaseError::PolarsError(err) => {
+                write!(f, "Polars error: {}", err)
+            }
+            DatasetDatabaseError::TableRoutingError(err) => {
+                write!(f, "Could not find the dataset table: {}", err)
+            }
+            DatasetDatabaseError::NoFilesFound(path) => {
+                write!(f, "No dataset files found in {}", path.display())
+            }
+            DatasetDatabaseError::EvaluationError(err) => {
+                write!(f, "Error evaluating time series query: {}", err)
+            }
+            DatasetDatabaseError::MissingIdentifierVariable => {
+                write!(
+                    f,
+                    "Basic time series query is missing the identifier variable"
+                )
+            }
+            DatasetDatabaseError::MissingIds => {
+                write!(f, "Basic time series query is missing the time series ids")
+            }
+            DatasetDatabaseError::MissingGroupingColumn => {
+                write!(
+                    f,
+                    "Grouped time series query is missing the grouping column"
+                )
+            }
+            DatasetDatabaseError::UnsupportedSynchronizers(synchronizers) => {
+                write!(
+                    f,
+                    "Can not synchronize time series queries with {:?}",
+                    synchronizers
+                )
+            }
+        }
+    }
+}
+
+impl Error for DatasetDatabaseError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatasetFormat {
+    Parquet,
+    ArrowIPC,
+}
+
+impl DatasetFormat {
+    fn has_extension(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|x| x.to_str());
+        match self {
+            DatasetFormat::Parquet => matches!(extension, Some("parquet")),
+            DatasetFormat::ArrowIPC => matches!(extension, Some("arrow" | "ipc" | "feather")),
+        }
+    }
+}
+
+//Bounds on the timestamps that can pass the filters above a basic query, inclusive
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct TimestampRange {
+    lower: Option<NaiveDateTime>,
+    upper: Option<NaiveDateTime>,
+}
+
+impl TimestampRange {
+    fn intersection(&self, other: &TimestampRange) -> TimestampRange {
+        TimestampRange {
+            lower: match (self.lower, other.lower) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            upper: match (self.upper, other.upper) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    fn union(&self, other: &TimestampRange) -> TimestampRange {
+        TimestampRange {
+            lower: match (self.lower, other.lower) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                _ => None,
+            },
+            upper: match (self.upper, other.upper) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                _ => None,
+            },
+        }
+    }
+
+    //Start inclusive, end exclusive
+    fn overlaps(&self, start: &NaiveDateTime, end: &NaiveDateTime) -> bool {
+        if let Some(lower) = &self.lower {
+            if end <= lower {
+                return false;
+            }
+        }
+        if let Some(upper) = &self.upper {
+            if start > upper {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+//Reads time series from a directory of Parquet or Arrow IPC files, one subdirectory per table,
+// optionally partitioned hive-style by the year, month and day columns of the table.
+pub struct DatasetDatabase {
+    root: PathBuf,
+    format: DatasetFormat,
+    time_series_tables: Vec<TimeSeriesTable>,
+}
+
+impl DatasetDatabase {
+    pub fn new<P: AsRef<Path>>(
+        root: P,
+        format: DatasetFormat,
+        time_series_tables: Vec<TimeSeriesTable>,
+    ) -> DatasetDatabase {
+        DatasetDatabase {
+            root: root.as_ref().to_path_buf(),
+            format,
+            time_series_tables,
+        }
+    }
+
+    #[async_recursion]
+    async fn execute_query(
+        &self,
+        tsq: &TimeSeriesQuery,
+        range: &TimestampRange,
+    ) -> Result<LazyFrame, DatasetDatabaseError> {
+        match tsq {
+            TimeSeriesQuery::Basic(b) => self.execute_basic(b, range),
+            TimeSeriesQuery::Filtered(inner, filter_expression) => {
+                let timestamp_variables: Vec<&str> = inner
+                    .get_timestamp_variables()
+                    .into_iter()
+                    .map(|x| x.variable.as_str())
+                    .collect();
+                let filter_range = timestamp_range(filter_expression, &timestamp_variables);
+                let lf = self
+                    .execute_query(inner, &range.intersection(&filter_range))
+                    .await?;
+                filter_lazy_frame(lf, filter_expression).await
+            }
+            TimeSeriesQuery::InnerSynchronized(inners, synchronizers) => {
+                let mut lfs = vec![];
+                for q in inners {
+                    lfs.push(self.execute_query(q, range).await?);
+                }
+                synchronize_lazy_frames(lfs, synchronizers)
+            }
+            TimeSeriesQuery::Grouped(grouped) => {
+                let lf = self.execute_query(&grouped.tsq, range).await?;
+                group_lazy_frame(lf, grouped).await
+            }
+            TimeSeriesQuery::GroupedBasic(btsq, df, ..) => {
+                let basic_lf = self.execute_basic(btsq, range)?;
+                join_grouping_column(basic_lf, btsq, df)
+            }
+            TimeSeriesQuery::ExpressionAs(inner, v, e) => {
+                let lf = self.execute_query(inner, range).await?;
+                expression_as_lazy_frame(lf, v, e).await
+            }
+            TimeSeriesQuery::Limited(inner, limit) => {
+                //Filters above a limit must not remove rows below it
+                let lf = self
+                    .execute_query(inner, &TimestampRange::default())
+                    .await?;
+                Ok(lf.limit(*limit as IdxSize))
+            }
+        }
+    }
+
+    fn execute_basic(
+        &self,
+        btsq: &BasicTimeSeriesQuery,
+        range: &TimestampRange,
+    ) -> Result<LazyFrame, DatasetDatabaseError> {
+        let routed = route_basic_query(&self.time_series_tables, btsq)
+            .map_err(DatasetDatabaseError::TableRoutingError)?;
+        let mut lfs = vec![];
+        for (table, table_btsq) in routed {
+            lfs.push(self.execute_basic_table(table, &table_btsq, range)?);
+        }
+        if lfs.is_empty() {
+            return Err(DatasetDatabaseError::NoFilesFound(self.root.clone()));
+        }
+        concat(lfs, UnionArgs::default()).map_err(DatasetDatabaseError::PolarsError)
+    }
+
+    fn execute_basic_table(
+        &self,
+        table: &TimeSeriesTable,
+        btsq: &BasicTimeSeriesQuery,
+        range: &TimestampRange,
+    ) -> Result<LazyFrame, DatasetDatabaseError> {
+        let mut table_path = self.root.clone();
+        if let Some(schema) = &table.schema {
+            table_path.push(schema);
+        }
+        table_path.push(&table.time_series_table);
+
+        let mut files = vec![];
+        find_files(&table_path, self.format, &mut files)?;
+        files.sort();
+        if files.is_empty() {
+            return Err(DatasetDatabaseError::NoFilesFound(table_path));
+        }
+        let mut keep = vec![];
+        for f in &files {
+            if partition_overlaps(table, &table_path, f, range) {
+                keep.push(f.clone());
+            } else {
+                debug!("Pruned partition file {}", f.display());
+            }
+        }
+
+        let mut lfs = vec![];
+        for f in &keep {
+            lfs.push(self.scan(f)?);
+        }
+        //Everything was pruned, but we still need the schema of the result
+        if lfs.is_empty() {
+            lfs.push(self.scan(files.get(0).unwrap())?.filter(lit(false)));
+        }
+        let mut lf =
+            concat(lfs, UnionArgs::default()).map_err(DatasetDatabaseError::PolarsError)?;
+
+        if table.is_wide() {
+            return select_wide(lf, table, btsq);
+        }
+
+        let identifier_variable = btsq
+            .identifier_variable
+            .as_ref()
+            .ok_or(DatasetDatabaseError::MissingIdentifierVariable)?;
+        let (condition, identifier) = identifier_condition_and_expression(table, btsq)?;
+        lf = lf.filter(condition);
+
+        let mut select = vec![identifier.alias(identifier_variable.as_str())];
+        if let Some(value_variable) = &btsq.value_variable {
+            select.push(col(&table.value_column).alias(value_variable.variable.as_str()));
+        }
+        if let Some(quality_variable) = &btsq.quality_variable {
+            let quality_column = table.quality_column.as_ref().ok_or_else(|| {
+                DatasetDatabaseError::TableRoutingError(
+                    TimeSeriesQueryToSQLError::MissingQualityColumn(
+                        table.time_series_table.clone(),
+                    ),
+                )
+            })?;
+            select.push(col(quality_column).alias(quality_variable.variable.as_str()));
+        }
+        if let Some(timestamp_variable) = &btsq.timestamp_variable {
+            select.push(
+                table
+                    .get_timestamp_encoding()
+                    .normalize_expr(col(&table.timestamp_column))
+                    .alias(timestamp_variable.variable.as_str()),
+            );
+        }
+        Ok(lf.select(select))
+    }
+
+    fn scan(&self, path: &Path) -> Result<LazyFrame, DatasetDatabaseError> {
+        match self.format {
+            DatasetFormat::Parquet => LazyFrame::scan_parquet(path, ScanArgsParquet::default()),
+            DatasetFormat::ArrowIPC => LazyFrame::scan_ipc(path, ScanArgsIpc::default()),
+        }
+        .map_err(DatasetDatabaseError::PolarsError)
+    }
+}
+
+#[async_trait]
+impl TimeSeriesQueryable for DatasetDatabase {
+    async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
+        let lf = self.execute_query(tsq, &TimestampRange::default()).await?;
+        Ok(lf.collect().map_err(DatasetDatabaseError::PolarsError)?)
+    }
+
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        let mut capabilities = TimeSeriesQueryCapabilities::all();
+        capabilities.table_annotations = has_table_iris(&self.time_series_tables);
+        capabilities
+    }
+}
+
+//The results identify time series by the string form of the external id,
+// in the same way as the SQL queries
+fn identifier_condition_and_expression(
+    table: &TimeSeriesTable,
+    btsq: &BasicTimeSeriesQuery,
+) -> Result<(Expr, Expr), DatasetDatabaseError> {
+    let ids = btsq.ids.as_ref().ok_or(DatasetDatabaseError::MissingIds)?;
+    let identifier_columns = table.identifier_columns();
+    if table.identifier_columns.is_none() {
+        let id_strings: Vec<String> = ids.iter().map(|x| x.to_string()).collect();
+        let identifier = col(&table.identifier_column).cast(DataType::Utf8);
+        return Ok((
+            identifier
+                .clone()
+                .is_in(lit(Series::new("ids", id_strings))),
+            identifier,
+        ));
+    }
+    let mut id_conditions = vec![];
+    for id in ids {
+        let parts = id.parts();
+        if parts.len() != identifier_columns.len() {
+            return Err(DatasetDatabaseError::TableRoutingError(
+                TimeSeriesQueryToSQLError::IdentifierColumnsMismatch(
+                    id.to_string(),
+                    table.time_series_table.clone(),
+                ),
+            ));
+        }
+        let mut id_condition = lit(true);
+        for (c, p) in identifier_columns.iter().zip(parts) {
+            let value = match p {
+                ExternalId::Integer(i) => lit(*i),
+                _ => lit(p.to_string()),
+            };
+            id_condition = id_condition.and(col(c).eq(value));
+        }
+        id_conditions.push((id_condition, id.to_string()));
+    }
+    let mut condition = lit(false);
+    for (id_condition, _) in &id_conditions {
+        condition = condition.or(id_condition.clone());
+    }
+    //Rows of other ids are filtered away, so the last id does not need a condition
+    let mut identifier = if let Some((_, id)) = id_conditions.pop() {
+        lit(id)
+    } else {
+        col(identifier_columns.get(0).unwrap()).cast(DataType::Utf8)
+    };
+    for (id_condition, id) in id_conditions.into_iter().rev() {
+        identifier = when(id_condition).then(lit(id)).otherwise(identifier);
+    }
+    Ok((condition, identifier))
+}
+
+//Each id selects a value column, the results are stacked in the same shape as for long tables
+fn select_wide(
+    lf: LazyFrame,
+    table: &TimeSeriesTable,
+    btsq: &BasicTimeSeriesQuery,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    if btsq.quality_variable.is_some() {
+        return Err(DatasetDatabaseError::TableRoutingError(
+            TimeSeriesQueryToSQLError::MissingQualityColumn(table.time_series_table.clone()),
+        ));
+    }
+    let value_columns = table.value_columns.as_ref().unwrap();
+    let identifier_variable = btsq
+        .identifier_variable
+        .as_ref()
+        .ok_or(DatasetDatabaseError::MissingIdentifierVariable)?;
+    let mut ids: Vec<String> = btsq
+        .ids
+        .as_ref()
+        .ok_or(DatasetDatabaseError::MissingIds)?
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    let no_rows = ids.is_empty();
+    if no_rows {
+        //We still need the schema of the result
+        ids.extend(value_columns.keys().min().cloned());
+    }
+    let mut id_lfs = vec![];
+    for id in &ids {
+        let value_column = value_columns.get(id).ok_or_else(|| {
+            DatasetDatabaseError::TableRoutingError(TimeSeriesQueryToSQLError::MissingValueColumn(
+                id.clone(),
+                table.time_series_table.clone(),
+            ))
+        })?;
+        let mut select = vec![lit(id.as_str()).alias(identifier_variable.as_str())];
+        if let Some(value_variable) = &btsq.value_variable {
+            select.push(col(value_column).alias(value_variable.variable.as_str()));
+        }
+        if let Some(timestamp_variable) = &btsq.timestamp_variable {
+            select.push(
+                table
+                    .get_timestamp_encoding()
+                    .normalize_expr(col(&table.timestamp_column))
+                    .alias(timestamp_variable.variable.as_str()),
+            );
+        }
+        id_lfs.push(lf.clone().select(select));
+    }
+    let mut lf = concat(id_lfs, UnionArgs::default()).map_err(DatasetDatabaseError::PolarsError)?;
+    if no_rows {
+        lf = lf.filter(lit(false));
+    }
+    Ok(lf)
+}
+
+//Expressions and aggregations are evaluated by a combiner, which does not query its database
+fn evaluation_combiner() -> Combiner {
+    Combiner::new(
+        "".to_string(),
+        all_pushdowns(),
+        Box::new(InMemoryTimeseriesDatabase {
+            frames: Default::default(),
+        }),
+        vec![],
+        Default::default(),
+    )
+}
+
+fn lazy_frame_solution_mappings(lf: LazyFrame) -> Result<SolutionMappings, DatasetDatabaseError> {
+    let columns = lf
+        .schema()
+        .map_err(DatasetDatabaseError::PolarsError)?
+        .iter_names()
+        .map(|x| x.to_string())
+        .collect();
+    Ok(SolutionMappings::new(lf, columns, HashMap::new()))
+}
+
+async fn filter_lazy_frame(
+    lf: LazyFrame,
+    filter_expression: &Expression,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    let tmp_context = Context::from_path(vec![PathEntry::Coalesce(12)]);
+    let solution_mappings = evaluation_combiner()
+        .lazy_expression(
+            filter_expression,
+            lazy_frame_solution_mappings(lf)?,
+            None,
+            None,
+            &tmp_context,
+        )
+        .await
+        .map_err(DatasetDatabaseError::EvaluationError)?;
+    Ok(solution_mappings
+        .mappings
+        .filter(col(tmp_context.as_str()))
+        .drop_columns([tmp_context.as_str()]))
+}
+
+async fn expression_as_lazy_frame(
+    lf: LazyFrame,
+    v: &Variable,
+    e: &Expression,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    let tmp_context = Context::from_path(vec![PathEntry::Coalesce(13)]);
+    let solution_mappings = evaluation_combiner()
+        .lazy_expression(
+            e,
+            lazy_frame_solution_mappings(lf)?,
+            None,
+            None,
+            &tmp_context,
+        )
+        .await
+        .map_err(DatasetDatabaseError::EvaluationError)?;
+    Ok(solution_mappings
+        .mappings
+        .rename([tmp_context.as_str()], [v.as_str()]))
+}
+
+async fn group_lazy_frame(
+    lf: LazyFrame,
+    grouped: &GroupedTimeSeriesQuery,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    let grouping_column = grouped
+        .tsq
+        .get_groupby_column()
+        .ok_or(DatasetDatabaseError::MissingGroupingColumn)?;
+    let mut combiner = evaluation_combiner();
+    let mut solution_mappings = lazy_frame_solution_mappings(lf)?;
+    let mut aggregation_exprs = vec![];
+    for (i, (v, agg)) in grouped.aggregations.iter().enumerate() {
+        let (new_solution_mappings, agg_expr, _) = combiner
+            .sparql_aggregate_expression_as_lazy_column_and_expression(
+                v,
+                agg,
+                solution_mappings,
+                &grouped
+                    .context
+                    .extension_with(PathEntry::GroupAggregation(i as u16)),
+            )
+            .await
+            .map_err(DatasetDatabaseError::EvaluationError)?;
+        solution_mappings = new_solution_mappings;
+        aggregation_exprs.push(agg_expr);
+    }
+    let mut by = vec![col(grouping_column)];
+    let tsfuncs = grouped.tsq.get_timeseries_functions(&grouped.context);
+    for b in &grouped.by {
+        if tsfuncs.iter().any(|(v, _)| b == *v) {
+            by.push(col(b.as_str()));
+        }
+    }
+    Ok(solution_mappings
+        .mappings
+        .groupby(by)
+        .agg(aggregation_exprs.as_slice()))
+}
+
+//The results of the inner queries are joined on the timestamp and the grouping columns
+fn synchronize_lazy_frames(
+    lfs: Vec<LazyFrame>,
+    synchronizers: &Vec<Synchronizer>,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    let unsupported = || DatasetDatabaseError::UnsupportedSynchronizers(synchronizers.clone());
+    let timestamp_col = match synchronizers.as_slice() {
+        [Synchronizer::Identity(timestamp_col)] => timestamp_col,
+        _ => return Err(unsupported()),
+    };
+    let mut on = vec![timestamp_col.clone()];
+    for lf in &lfs {
+        let schema = lf.schema().map_err(DatasetDatabaseError::PolarsError)?;
+        for c in schema.iter_names() {
+            if c.starts_with(GROUPING_COL) && !on.iter().any(|x| x == c.as_str()) {
+                on.push(c.to_string());
+            }
+        }
+    }
+    let on: Vec<Expr> = on.iter().map(|x| col(x)).collect();
+    let mut lfs = lfs.into_iter();
+    let mut out_lf = lfs.next().ok_or_else(unsupported)?;
+    for lf in lfs {
+        out_lf = out_lf.join(
+            lf,
+            on.as_slice(),
+            on.as_slice(),
+            JoinArgs::new(JoinType::Inner),
+        );
+    }
+    Ok(out_lf)
+}
+
+//Replaces the identifier with the grouping column of the time series
+fn join_grouping_column(
+    basic_lf: LazyFrame,
+    btsq: &BasicTimeSeriesQuery,
+    df: &DataFrame,
+) -> Result<LazyFrame, DatasetDatabaseError> {
+    let identifier_variable = btsq
+        .identifier_variable
+        .as_ref()
+        .ok_or(DatasetDatabaseError::MissingIdentifierVariable)?
+        .as_str();
+    Ok(basic_lf
+        .join(
+            df.clone().lazy(),
+            [col(identifier_variable)],
+            [col(identifier_variable)],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .drop_columns([identifier_variable]))
+}
+
+fn find_files(
+    dir: &Path,
+    format: DatasetFormat,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), DatasetDatabaseError> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|x| DatasetDatabaseError::IOError(dir.to_path_buf(), x))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|x| DatasetDatabaseError::IOError(dir.to_path_buf(), x))?
+            .path();
+        if path.is_dir() {
+            find_files(&path, format, files)?;
+        } else if format.has_extension(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+//Uses the key=value directories between the table directory and the file to decide if the file
+// can contain timestamps in the range
+fn partition_overlaps(
+    table: &TimeSeriesTable,
+    table_path: &Path,
+    file: &Path,
+    range: &TimestampRange,
+) -> bool {
+    let scheme = if let Some(scheme) = table.get_partition_scheme() {
+        scheme
+    } else {
+        return true;
+    };
+    let mut values = HashMap::new();
+    if let Ok(relative) = file.strip_prefix(table_path) {
+        for component in relative.parent().into_iter().flat_map(|x| x.iter()) {
+            if let Some((key, value)) = component.to_str().and_then(|x| x.split_once('=')) {
+                if let Some(c) = scheme.columns.iter().find(|x| x.column == key) {
+                    values.insert(c.kind, value);
+                }
+            }
+        }
+    }
+    if let Some((start, end)) = scheme.timestamp_interval(&values) {
+        range.overlaps(&start, &end)
+    } else {
+        true
+    }
+}
+
+fn timestamp_range(expression: &Expression, timestamp_variables: &Vec<&str>) -> TimestampRange {
+    match expression {
+        Expression::And(left, right) => timestamp_range(left, timestamp_variables)
+            .intersection(&timestamp_range(right, timestamp_variables)),
+        Expression::Or(left, right) => timestamp_range(left, timestamp_variables)
+            .union(&timestamp_range(right, timestamp_variables)),
+        Expression::Greater(left, right) | Expression::GreaterOrEqual(left, right) => {
+            comparison_range(left, right, timestamp_variables, true)
+        }
+        Expression::Less(left, right) | Expression::LessOrEqual(left, right) => {
+            comparison_range(left, right, timestamp_variables, false)
+        }
+        Expression::Equal(left, right) => {
+            let lower = comparison_range(left, right, timestamp_variables, true);
+            let upper = comparison_range(left, right, timestamp_variables, false);
+            lower.intersection(&upper)
+        }
+        _ => TimestampRange::default(),
+    }
+}
+
+//Range given by variable > literal when greater is true, variable < literal otherwise,
+// flipped when the literal is on the left
+fn comparison_range(
+    left: &Expression,
+    right: &Expression,
+    timestamp_variables: &Vec<&str>,
+    greater: bool,
+) -> TimestampRange {
+    let (datetime, greater) = match (left, right) {
+        (Expression::Variable(v), Expression::Literal(l))
+            if timestamp_variables.contains(&v.as_str()) =>
+        {
+            (datetime_literal(l), greater)
+        }
+        (Expression::Literal(l), Expression::Variable(v))
+            if timestamp_variables.contains(&v.as_str()) =>
+        {
+            (datetime_literal(l), !greater)
+        }
+        _ => (None, greater),
+    };
+    if greater {
+        TimestampRange {
+            lower: datetime,
+            upper: None,
+        }
+    } else {
+        TimestampRange {
+            lower: None,
+            upper: datetime,
+        }
+    }
+}
+
+fn datetime_literal(l: &oxrdf::Literal) -> Option<NaiveDateTime> {
+    if l.datatype() != xsd::DATE_TIME {
+        return None;
+    }
+    if let Ok(dt) = l.value().parse::<NaiveDateTime>() {
+        Some(dt)
+    } else if let Ok(dt) = l.value().parse::<DateTime<Utc>>() {
+        Some(dt.naive_utc())
+    } else {
+        None
+    }
+}
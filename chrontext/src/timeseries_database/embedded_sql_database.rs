@@ -0,0 +1,360 @@
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
+use crate::timeseries_database::sql_result_to_polars::{
+    columns_to_dataframe, expected_column_types, is_timestamp_column, new_columns, ColumnValues,
+    SQLResultConversionError, SQLValue,
+};
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::has_table_iris;
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::normalize_timestamps;
+use crate::timeseries_database::timeseries_sql_rewrite::{
+    TimeSeriesQueryToSQLError, TimeSeriesQueryToSQLTransformer, TimeSeriesTable,
+};
+use crate::timeseries_database::TimeSeriesQueryable;
+use crate::timeseries_query::TimeSeriesQuery;
+use async_trait::async_trait;
+use log::debug;
+use polars::frame::DataFrame;
+use polars_core::prelude::DataType;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinError;
+
+#[derive(Debug)]
+pub enum EmbeddedSQLDatabaseError {
+    SQLiteError(rusqlite::Error),
+    DuckDBError(duckdb::Error),
+    TranslationError(TimeSeriesQueryToSQLError),
+    ResultConversionError(SQLResultConversionError),
+    UnsupportedDialect(SqlDialect),
+    ConnectionLockError,
+    TaskJoinError(JoinError),
+    MissingResultSchema,
+}
+
+impl Display for EmbeddedSQLDatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbeddedSQLDatabaseError::SQLiteError(err) => {
+                write!(f, "SQLite error: {}", err)
+            }
+            EmbeddedSQLDatabaseError::DuckDBError(err) => {
+                write!(f, "DuckDB error: {}", err)
+            }
+            EmbeddedSQLDatabaseError::TranslationError(err) => {
+                write!(f, "Error during query translation: {}", err)
+            }
+            EmbeddedSQLDatabaseError::ResultConversionError(err) => {
+                write!(f, "{}", err)
+            }
+            EmbeddedSQLDatabaseError::UnsupportedDialect(d) => {
+                write!(
+                    f,
+                    "Embedded databases must use the SQLite or DuckDB dialect, got {:?}",
+                    d
+                )
+            }
+            EmbeddedSQLDatabaseError::ConnectionLockError => {
+                write!(f, "Could not lock the database connection")
+            }
+            EmbeddedSQLDatabaseError::TaskJoinError(err) => {
+                write!(f, "Database query task failed: {}", err)
+            }
+            EmbeddedSQLDatabaseError::MissingResultSchema => {
+                write!(f, "DuckDB returned a result without a schema")
+            }
+        }
+    }
+}
+
+impl Error for EmbeddedSQLDatabaseError {}
+
+enum EmbeddedConnection {
+    SQLite(rusqlite::Connection),
+    DuckDB(duckdb::Connection),
+}
+
+//Runs the generated SQL against a local SQLite or DuckDB database file
+pub struct EmbeddedSQLDatabase {
+    //Queries block, so they are run on the blocking thread pool with a shared connection
+    connection: Arc<Mutex<EmbeddedConnection>>,
+    sql_dialect: SqlDialect,
+    time_series_tables: Vec<TimeSeriesTable>,
+}
+
+impl EmbeddedSQLDatabase {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        sql_dialect: SqlDialect,
+        time_series_tables: Vec<TimeSeriesTable>,
+    ) -> Result<EmbeddedSQLDatabase, EmbeddedSQLDatabaseError> {
+        let connection = match sql_dialect {
+            SqlDialect::SQLite => EmbeddedConnection::SQLite(
+                rusqlite::Connection::open(path).map_err(EmbeddedSQLDatabaseError::SQLiteError)?,
+            ),
+            SqlDialect::DuckDB => EmbeddedConnection::DuckDB(
+                duckdb::Connection::open(path).map_err(EmbeddedSQLDatabaseError::DuckDBError)?,
+            ),
+            _ => return Err(EmbeddedSQLDatabaseError::UnsupportedDialect(sql_dialect)),
+        };
+        Ok(EmbeddedSQLDatabase {
+            connection: Arc::new(Mutex::new(connection)),
+            sql_dialect,
+            time_series_tables,
+        })
+    }
+
+    pub fn open_in_memory(
+        sql_dialect: SqlDialect,
+        time_series_tables: Vec<TimeSeriesTable>,
+    ) -> Result<EmbeddedSQLDatabase, EmbeddedSQLDatabaseError> {
+        let connection = match sql_dialect {
+            SqlDialect::SQLite => EmbeddedConnection::SQLite(
+                rusqlite::Connection::open_in_memory()
+                    .map_err(EmbeddedSQLDatabaseError::SQLiteError)?,
+            ),
+            SqlDialect::DuckDB => EmbeddedConnection::DuckDB(
+                duckdb::Connection::open_in_memory()
+                    .map_err(EmbeddedSQLDatabaseError::DuckDBError)?,
+            ),
+            _ => return Err(EmbeddedSQLDatabaseError::UnsupportedDialect(sql_dialect)),
+        };
+        Ok(EmbeddedSQLDatabase {
+            connection: Arc::new(Mutex::new(connection)),
+            sql_dialect,
+            time_series_tables,
+        })
+    }
+
+    //Executes statements that return no rows, e.g. for loading data
+    pub fn execute_batch(&self, sql: &str) -> Result<(), EmbeddedSQLDatabaseError> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| EmbeddedSQLDatabaseError::ConnectionLockError)?;
+        match &*connection {
+            EmbeddedConnection::SQLite(conn) => conn
+                .execute_batch(sql)
+                .map_err(EmbeddedSQLDatabaseError::SQLiteError),
+            EmbeddedConnection::DuckDB(conn) => conn
+                .execute_batch(sql)
+                .map_err(EmbeddedSQLDatabaseError::DuckDBError),
+        }
+    }
+
+    //Column types are inferred from the values, the provided types are used for columns without any values
+    pub fn execute_sql_query(
+        &self,
+        query: &str,
+        column_types: &HashMap<String, DataType>,
+    ) -> Result<DataFrame, EmbeddedSQLDatabaseError> {
+        execute_sql_query(&self.connection, query, column_types)
+    }
+}
+
+#[async_trait]
+impl TimeSeriesQueryable for EmbeddedSQLDatabase {
+    async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
+        let query_string;
+        {
+            let transformer =
+                TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
+            let (query, _) = transformer
+                .create_query(tsq, false)
+                .map_err(EmbeddedSQLDatabaseError::TranslationError)?;
+            query_string = self.sql_dialect.build_query(&query);
+            debug!("SQL: {}", query_string);
+        }
+        let column_types = expected_column_types(tsq, &self.time_series_tables);
+        let connection = self.connection.clone();
+        let df = tokio::task::spawn_blocking(move || {
+            execute_sql_query(&connection, &query_string, &column_types)
+        })
+        .await
+        .map_err(EmbeddedSQLDatabaseError::TaskJoinError)??;
+        Ok(normalize_timestamps(df, tsq, &self.time_series_tables)?)
+    }
+
+    fn validate_pushdown(&self, tsq: &TimeSeriesQuery) -> Result<(), Box<dyn Error>> {
+        let transformer =
+            TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
+        transformer
+            .validate_aggregations(tsq)
+            .map_err(EmbeddedSQLDatabaseError::TranslationError)?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        let mut capabilities = self.sql_dialect.capabilities();
+        capabilities.table_annotations = has_table_iris(&self.time_series_tables);
+        capabilities
+    }
+}
+
+fn execute_sql_query(
+    connection: &Mutex<EmbeddedConnection>,
+    query: &str,
+    column_types: &HashMap<String, DataType>,
+) -> Result<DataFrame, EmbeddedSQLDatabaseError> {
+    let connection = connection
+        .lock()
+        .map_err(|_| EmbeddedSQLDatabaseError::ConnectionLockError)?;
+    let (column_names, columns) = match &*connection {
+        EmbeddedConnection::SQLite(conn) => query_sqlite(conn, query, column_types)?,
+        EmbeddedConnection::DuckDB(conn) => query_duckdb(conn, query, column_types)?,
+    };
+    columns_to_dataframe(&column_names, columns, column_types)
+        .map_err(EmbeddedSQLDatabaseError::ResultConversionError)
+}
+
+fn query_sqlite(
+    conn: &rusqlite::Connection,
+    query: &str,
+    column_types: &HashMap<String, DataType>,
+) -> Result<(Vec<String>, Vec<ColumnValues>), EmbeddedSQLDatabaseError> {
+    let mut statement = conn
+        .prepare(query)
+        .map_err(EmbeddedSQLDatabaseError::SQLiteError)?;
+    let column_names: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+    let mut columns = new_columns(&column_names);
+    let mut rows = statement
+        .query([])
+        .map_err(EmbeddedSQLDatabaseError::SQLiteError)?;
+    while let Some(row) = rows.next().map_err(EmbeddedSQLDatabaseError::SQLiteError)? {
+        for (i, column_name) in column_names.iter().enumerate() {
+            let value_ref = row
+                .get_ref(i)
+                .map_err(EmbeddedSQLDatabaseError::SQLiteError)?;
+            let value = match value_ref {
+                rusqlite::types::ValueRef::Null => SQLValue::Null,
+                rusqlite::types::ValueRef::Integer(i) => SQLValue::Integer(i),
+                rusqlite::types::ValueRef::Real(f) => SQLValue::Float(f),
+                rusqlite::types::ValueRef::Text(t) => {
+                    SQLValue::Text(String::from_utf8_lossy(t).to_string())
+                }
+                rusqlite::types::ValueRef::Blob(_) => {
+                    return Err(EmbeddedSQLDatabaseError::ResultConversionError(
+                        SQLResultConversionError::UnsupportedValueType(
+                            column_name.clone(),
+                            "BLOB".to_string(),
+                        ),
+                    ))
+                }
+            };
+            columns
+                .get_mut(i)
+                .unwrap()
+                .push(
+                    column_name,
+                    value,
+                    is_timestamp_column(column_types, column_name),
+                )
+                .map_err(EmbeddedSQLDatabaseError::ResultConversionError)?;
+        }
+    }
+    Ok((column_names, columns))
+}
+
+fn query_duckdb(
+    conn: &duckdb::Connection,
+    query: &str,
+    column_types: &HashMap<String, DataType>,
+) -> Result<(Vec<String>, Vec<ColumnValues>), EmbeddedSQLDatabaseError> {
+    let mut statement = conn
+        .prepare(query)
+        .map_err(EmbeddedSQLDatabaseError::DuckDBError)?;
+    let mut rows = statement
+        .query([])
+        .map_err(EmbeddedSQLDatabaseError::DuckDBError)?;
+    //The result schema is only known after the statement has been executed
+    let column_names: Vec<String> = rows
+        .as_ref()
+        .ok_or(EmbeddedSQLDatabaseError::MissingResultSchema)?
+        .column_names()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+    let mut columns = new_columns(&column_names);
+    while let Some(row) = rows.next().map_err(EmbeddedSQLDatabaseError::DuckDBError)? {
+        for (i, column_name) in column_names.iter().enumerate() {
+            let value_ref = row
+                .get_ref(i)
+                .map_err(EmbeddedSQLDatabaseError::DuckDBError)?;
+            let value = match value_ref {
+                duckdb::types::ValueRef::Null => SQLValue::Null,
+                duckdb::types::ValueRef::Boolean(b) => SQLValue::Boolean(b),
+                duckdb::types::ValueRef::TinyInt(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::SmallInt(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::Int(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::BigInt(i) => SQLValue::Integer(i),
+                duckdb::types::ValueRef::HugeInt(i) => {
+                    SQLValue::Integer(checked_integer(column_name, i)?)
+                }
+                duckdb::types::ValueRef::UTinyInt(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::USmallInt(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::UInt(i) => SQLValue::Integer(i as i64),
+                duckdb::types::ValueRef::UBigInt(i) => {
+                    SQLValue::Integer(checked_integer(column_name, i)?)
+                }
+                duckdb::types::ValueRef::Float(f) => SQLValue::Float(f as f64),
+                duckdb::types::ValueRef::Double(f) => SQLValue::Float(f),
+                duckdb::types::ValueRef::Text(t) => {
+                    SQLValue::Text(String::from_utf8_lossy(t).to_string())
+                }
+                duckdb::types::ValueRef::Timestamp(unit, t) => {
+                    let nanos_per_unit = match unit {
+                        duckdb::types::TimeUnit::Second => 1_000_000_000,
+                        duckdb::types::TimeUnit::Millisecond => 1_000_000,
+                        duckdb::types::TimeUnit::Microsecond => 1_000,
+                        duckdb::types::TimeUnit::Nanosecond => 1,
+                    };
+                    let nanos = t.checked_mul(nanos_per_unit).ok_or_else(|| {
+                        EmbeddedSQLDatabaseError::ResultConversionError(
+                            SQLResultConversionError::IntegerOutOfRange(
+                                column_name.clone(),
+                                t.to_string(),
+                            ),
+                        )
+                    })?;
+                    SQLValue::Timestamp(nanos)
+                }
+                other => {
+                    return Err(EmbeddedSQLDatabaseError::ResultConversionError(
+                        SQLResultConversionError::UnsupportedValueType(
+                            column_name.clone(),
+                            format!("{:?}", other),
+                        ),
+                    ))
+                }
+            };
+            columns
+                .get_mut(i)
+                .unwrap()
+                .push(
+                    column_name,
+                    value,
+                    is_timestamp_column(column_types, column_name),
+                )
+                .map_err(EmbeddedSQLDatabaseError::ResultConversionError)?;
+        }
+    }
+    Ok((column_names, columns))
+}
+
+//Integer types that are wider than the 64 bit integers we use for results are converted if they fit
+fn checked_integer<T: TryInto<i64> + ToString + Copy>(
+    column_name: &str,
+    value: T,
+) -> Result<i64, EmbeddedSQLDatabaseError> {
+    value.try_into().map_err(|_| {
+        EmbeddedSQLDatabaseError::ResultConversionError(
+            SQLResultConversionError::IntegerOutOfRange(column_name.to_string(), value.to_string()),
+        )
+    })
+}
@@ -1,39 +1,51 @@
 use crate::constants::DATETIME_AS_SECONDS;
+use crate::external_ids::ExternalId;
 use crate::query_context::Context;
+use crate::timeseries_database::capabilities::{
+    ExpressionKind, TimeSeriesQueryCapabilities, TimeSeriesQueryKind,
+};
 use crate::timeseries_database::TimeSeriesQueryable;
 use crate::timeseries_query::TimeSeriesQuery;
 use async_trait::async_trait;
+use log::warn;
 use opcua_client::prelude::{
-    AggregateConfiguration, AttributeService, ByteString, Client, ClientBuilder, DateTime,
-    EndpointDescription, Guid, HistoryData, HistoryReadAction, HistoryReadResult,
-    HistoryReadValueId, Identifier, IdentityToken, MessageSecurityMode, NodeId, QualifiedName,
-    ReadProcessedDetails, ReadRawModifiedDetails, Session, TimestampsToReturn, UAString,
-    UserTokenPolicy, Variant,
+    AggregateConfiguration, AttributeService, ByteString, CertificateStore, Client,
+    ClientBuilder, DateTime, EndpointDescription, Guid, HistoryData, HistoryReadAction,
+    HistoryReadResult, HistoryReadValueId, Identifier, IdentityToken, MessageSecurityMode, NodeId,
+    ObjectId, QualifiedName, ReadProcessedDetails, ReadRawModifiedDetails, SecurityPolicy, Session,
+    StatusCode, TimestampsToReturn, UAString, UserTokenPolicy, UserTokenType, Variant,
 };
 use oxrdf::vocab::xsd;
-use oxrdf::{Literal, Variable};
+use oxrdf::{Literal, NamedNodeRef, Variable};
 use polars::export::chrono::{DateTime as ChronoDateTime, Duration, NaiveDateTime, TimeZone, Utc};
-use polars::prelude::{concat, IntoLazy, UnionArgs};
+use polars::prelude::{concat, IntoLazy, PolarsError, UnionArgs};
 use polars_core::frame::DataFrame;
-use polars_core::prelude::{AnyValue, DataType, NamedFrom};
+use polars_core::prelude::{AnyValue, DataType, NamedFrom, TimeUnit};
 use polars_core::series::Series;
 use spargebra::algebra::{AggregateExpression, Expression, Function};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 
 const OPCUA_AGG_FUNC_AVERAGE: u32 = 2342;
 const OPCUA_AGG_FUNC_COUNT: u32 = 2352;
 const OPCUA_AGG_FUNC_MINIMUM: u32 = 2346;
 const OPCUA_AGG_FUNC_MAXIMUM: u32 = 2347;
 const OPCUA_AGG_FUNC_TOTAL: u32 = 2344;
+//Custom aggregates in this namespace are mapped to the standard aggregate with the same name
+pub const OPCUA_AGG_FUNC_PREFIX: &str = "https://github.com/magbak/chrontext/opcua#";
+const DEFAULT_PKI_DIR: &str = "pki";
 
 #[allow(dead_code)]
 pub struct OPCUAHistoryRead {
     client: Client,
     session: Arc<RwLock<Session>>,
+    endpoint: EndpointDescription,
+    identity_token: IdentityToken,
     namespace: u16,
 }
 
@@ -41,6 +53,18 @@ pub struct OPCUAHistoryRead {
 pub enum OPCUAHistoryReadError {
     InvalidNodeIdError(String),
     TimeSeriesQueryTypeNotSupported,
+    ClientBuildError,
+    TrustedCertificateError(PathBuf, String),
+    ConnectError(StatusCode),
+    SessionError(StatusCode),
+    BadStatusCodeError(NodeId, StatusCode),
+    DecodeError(NodeId, String),
+    AggregateNotSupported(String),
+    SessionLockError,
+    PolarsError(PolarsError),
+    GroupingMappingError(String),
+    MissingVariableError(&'static str),
+    DuplicateTimeConditionError(String),
 }
 
 impl Display for OPCUAHistoryReadError {
@@ -52,51 +76,355 @@ impl Display for OPCUAHistoryReadError {
             OPCUAHistoryReadError::TimeSeriesQueryTypeNotSupported => {
                 write!(f, "Only grouped and basic query types are supported")
             }
+            OPCUAHistoryReadError::ClientBuildError => {
+                write!(f, "Could not build OPC UA client, check the client configuration")
+            }
+            OPCUAHistoryReadError::TrustedCertificateError(p, s) => {
+                write!(f, "Could not trust server certificate {:?}: {}", p, s)
+            }
+            OPCUAHistoryReadError::ConnectError(s) => {
+                write!(f, "Could not connect to OPC UA server: {}", s)
+            }
+            OPCUAHistoryReadError::SessionError(s) => {
+                write!(f, "OPC UA session error: {}", s)
+            }
+            OPCUAHistoryReadError::BadStatusCodeError(n, s) => {
+                write!(f, "History read of node {} returned bad status code {}", n, s)
+            }
+            OPCUAHistoryReadError::DecodeError(n, s) => {
+                write!(f, "Could not decode history data of node {}: {}", n, s)
+            }
+            OPCUAHistoryReadError::AggregateNotSupported(a) => {
+                write!(
+                    f,
+                    "Aggregate {} is not supported by OPC UA ReadProcessed",
+                    a
+                )
+            }
+            OPCUAHistoryReadError::SessionLockError => {
+                write!(f, "OPC UA session lock was poisoned")
+            }
+            OPCUAHistoryReadError::PolarsError(e) => {
+                write!(f, "Could not create history read result: {}", e)
+            }
+            OPCUAHistoryReadError::GroupingMappingError(s) => {
+                write!(f, "Invalid grouping of history read: {}", s)
+            }
+            OPCUAHistoryReadError::MissingVariableError(v) => {
+                write!(f, "Time series query has no {} variable", v)
+            }
+            OPCUAHistoryReadError::DuplicateTimeConditionError(e) => {
+                write!(
+                    f,
+                    "Filter {} has more than one condition on the same end of the time interval",
+                    e
+                )
+            }
         }
     }
 }
 
 impl Error for OPCUAHistoryReadError {}
 
+#[derive(Clone, Debug)]
+pub struct OPCUASecurityConfig {
+    pub security_policy: SecurityPolicy,
+    pub message_security_mode: MessageSecurityMode,
+    pub identity_token: IdentityToken,
+    pub pki_dir: Option<PathBuf>,
+    //Client application instance certificate and key, a sample keypair is created when missing
+    pub certificate_path: Option<PathBuf>,
+    pub private_key_path: Option<PathBuf>,
+    //DER encoded server certificates that are added to the trusted folder of the PKI directory
+    pub trusted_server_certificates: Vec<PathBuf>,
+    pub trust_server_certs: bool,
+}
+
+impl Default for OPCUASecurityConfig {
+    fn default() -> Self {
+        OPCUASecurityConfig {
+            security_policy: SecurityPolicy::None,
+            message_security_mode: MessageSecurityMode::None,
+            identity_token: IdentityToken::Anonymous,
+            pki_dir: None,
+            certificate_path: None,
+            private_key_path: None,
+            trusted_server_certificates: vec![],
+            trust_server_certs: false,
+        }
+    }
+}
+
 impl OPCUAHistoryRead {
-    pub fn new(endpoint: &str, namespace: u16) -> OPCUAHistoryRead {
+    pub fn new(endpoint: &str, namespace: u16) -> Result<OPCUAHistoryRead, OPCUAHistoryReadError> {
+        OPCUAHistoryRead::new_with_security_config(
+            endpoint,
+            namespace,
+            OPCUASecurityConfig::default(),
+        )
+    }
+
+    pub fn new_with_security_config(
+        endpoint: &str,
+        namespace: u16,
+        security_config: OPCUASecurityConfig,
+    ) -> Result<OPCUAHistoryRead, OPCUAHistoryReadError> {
+        let OPCUASecurityConfig {
+            security_policy,
+            message_security_mode,
+            identity_token,
+            pki_dir,
+            certificate_path,
+            private_key_path,
+            trusted_server_certificates,
+            trust_server_certs,
+        } = security_config;
+        let pki_dir = pki_dir.unwrap_or(PathBuf::from(DEFAULT_PKI_DIR));
+        trust_server_certificates(&pki_dir, &trusted_server_certificates)?;
+
         //From: https://github.com/locka99/opcua/blob/master/docs/client.md
-        let mut client = ClientBuilder::new()
+        let mut builder = ClientBuilder::new()
             .application_name("My First Client")
             .application_uri("urn:MyFirstClient")
-            .create_sample_keypair(true)
-            .trust_server_certs(true)
-            .session_retry_limit(3)
+            .pki_dir(pki_dir)
+            .create_sample_keypair(certificate_path.is_none())
+            .trust_server_certs(trust_server_certs)
+            .session_retry_limit(3);
+        if let Some(certificate_path) = certificate_path {
+            builder = builder.certificate_path(certificate_path);
+        }
+        if let Some(private_key_path) = private_key_path {
+            builder = builder.private_key_path(private_key_path);
+        }
+        let mut client = builder
             .client()
-            .unwrap();
+            .ok_or(OPCUAHistoryReadError::ClientBuildError)?;
 
         let endpoint: EndpointDescription = (
             endpoint,
-            "None",
-            MessageSecurityMode::None,
-            UserTokenPolicy::anonymous(),
+            security_policy.to_str(),
+            message_security_mode,
+            user_token_policy(&identity_token),
         )
             .into();
 
         let session = client
-            .connect_to_endpoint(endpoint, IdentityToken::Anonymous)
-            .unwrap();
+            .connect_to_endpoint(endpoint.clone(), identity_token.clone())
+            .map_err(OPCUAHistoryReadError::ConnectError)?;
 
-        OPCUAHistoryRead {
+        Ok(OPCUAHistoryRead {
             client,
             session,
+            endpoint,
+            identity_token,
             namespace,
+        })
+    }
+
+    fn session(&self) -> Result<RwLockReadGuard<Session>, OPCUAHistoryReadError> {
+        self.session
+            .read()
+            .map_err(|_| OPCUAHistoryReadError::SessionLockError)
+    }
+
+    fn reconnect(&mut self) -> Result<(), OPCUAHistoryReadError> {
+        warn!("Lost connection to OPC UA server, reconnecting");
+        self.session
+            .write()
+            .map_err(|_| OPCUAHistoryReadError::SessionLockError)?
+            .disconnect();
+        self.session = self
+            .client
+            .connect_to_endpoint(self.endpoint.clone(), self.identity_token.clone())
+            .map_err(OPCUAHistoryReadError::ConnectError)?;
+        Ok(())
+    }
+
+    fn history_read(
+        &mut self,
+        action: HistoryReadAction,
+        release_continuation_points: bool,
+        nodes_to_read: &[HistoryReadValueId],
+    ) -> Result<Vec<HistoryReadResult>, OPCUAHistoryReadError> {
+        if !self.session()?.is_connected() {
+            self.reconnect()?;
+        }
+        let result = self.session()?.history_read(
+            action.clone(),
+            TimestampsToReturn::Source,
+            release_continuation_points,
+            nodes_to_read,
+        );
+        match result {
+            Ok(r) => Ok(r),
+            Err(s) if is_connection_lost(&s) => {
+                self.reconnect()?;
+                //Continuation points belong to the lost session, so only first reads are retried
+                if nodes_to_read.iter().any(|n| !n.continuation_point.is_null()) {
+                    return Err(OPCUAHistoryReadError::SessionError(s));
+                }
+                self.session()?
+                    .history_read(
+                        action,
+                        TimestampsToReturn::Source,
+                        release_continuation_points,
+                        nodes_to_read,
+                    )
+                    .map_err(OPCUAHistoryReadError::SessionError)
+            }
+            Err(s) => Err(OPCUAHistoryReadError::SessionError(s)),
+        }
+    }
+
+    //Servers hold resources for each continuation point until it is released,
+    // so the continuation points left after paging are released on every exit path
+    fn read_pages(
+        &mut self,
+        tsq: &TimeSeriesQuery,
+        processed_details: &Option<ReadProcessedDetails>,
+        raw_modified_details: &Option<ReadRawModifiedDetails>,
+        nodes_to_read_vec: &mut Vec<HistoryReadValueId>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<(Series, Series, Series)>>, OPCUAHistoryReadError> {
+        let result = self.read_unreleased_pages(
+            tsq,
+            processed_details,
+            raw_modified_details,
+            nodes_to_read_vec,
+            limit,
+        );
+        let continued: Vec<usize> = nodes_to_read_vec
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| !n.continuation_point.is_null())
+            .map(|(i, _)| i)
+            .collect();
+        if !continued.is_empty() {
+            let nodes: Vec<HistoryReadValueId> = continued
+                .iter()
+                .map(|i| nodes_to_read_vec.get(*i).unwrap().clone())
+                .collect();
+            if let Err(e) =
+                create_history_read_action(processed_details, raw_modified_details, &continued)
+                    .and_then(|action| self.history_read(action, true, nodes.as_slice()))
+            {
+                warn!("Failed to release continuation points: {}", e);
+            }
+        }
+        result
+    }
+
+    //Each node is paged separately, as servers may return continuation points for some nodes only.
+    // The continuation points of the nodes are kept up to date so that they can be released.
+    fn read_unreleased_pages(
+        &mut self,
+        tsq: &TimeSeriesQuery,
+        processed_details: &Option<ReadProcessedDetails>,
+        raw_modified_details: &Option<ReadRawModifiedDetails>,
+        nodes_to_read_vec: &mut Vec<HistoryReadValueId>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<(Series, Series, Series)>>, OPCUAHistoryReadError> {
+        let mut pages: Vec<Vec<(Series, Series, Series)>> =
+            nodes_to_read_vec.iter().map(|_| vec![]).collect();
+        let mut unfinished: Vec<usize> = (0..nodes_to_read_vec.len()).collect();
+        let mut values_read = 0;
+        while !unfinished.is_empty() {
+            let action =
+                create_history_read_action(processed_details, raw_modified_details, &unfinished)?;
+            let nodes: Vec<HistoryReadValueId> = unfinished
+                .iter()
+                .map(|i| nodes_to_read_vec.get(*i).unwrap().clone())
+                .collect();
+            let resp = self.history_read(action, false, nodes.as_slice())?;
+            let mut still_unfinished = vec![];
+            for (i, h) in unfinished.iter().zip(resp.iter()) {
+                nodes_to_read_vec.get_mut(*i).unwrap().continuation_point =
+                    h.continuation_point.clone();
+                if !h.continuation_point.is_null() {
+                    still_unfinished.push(*i);
+                }
+            }
+            for (i, h) in unfinished.iter().zip(resp.into_iter()) {
+                let HistoryReadResult {
+                    status_code,
+                    continuation_point: _,
+                    history_data,
+                } = h;
+                let node_id = &nodes_to_read_vec.get(*i).unwrap().node_id;
+                if status_code.is_bad() {
+                    return Err(OPCUAHistoryReadError::BadStatusCodeError(
+                        node_id.clone(),
+                        status_code,
+                    ));
+                }
+                let history_data = history_data
+                    .decode_inner::<HistoryData>(&Default::default())
+                    .map_err(|s| {
+                        OPCUAHistoryReadError::DecodeError(node_id.clone(), s.to_string())
+                    })?;
+                let (ts, mut val, status, datatype) = history_data_to_series_tuple(history_data)
+                    .map_err(|s| OPCUAHistoryReadError::DecodeError(node_id.clone(), s))?;
+                if raw_modified_details.is_some() {
+                    val = cast_to_expected_datatype(tsq, node_id, val, datatype)?;
+                }
+                values_read += ts.len();
+                pages.get_mut(*i).unwrap().push((ts, val, status));
+            }
+            unfinished = still_unfinished;
+            if let Some(limit) = limit {
+                if values_read >= limit {
+                    break;
+                }
+            }
         }
+        Ok(pages)
     }
 }
 
+//The user token policy must match the kind of identity token we authenticate with
+fn user_token_policy(identity_token: &IdentityToken) -> UserTokenPolicy {
+    let (policy_id, token_type) = match identity_token {
+        IdentityToken::Anonymous => return UserTokenPolicy::anonymous(),
+        IdentityToken::UserName(..) => ("username", UserTokenType::UserName),
+        IdentityToken::X509(..) => ("x509", UserTokenType::Certificate),
+    };
+    UserTokenPolicy {
+        policy_id: UAString::from(policy_id),
+        token_type,
+        issued_token_type: UAString::null(),
+        issuer_endpoint_url: UAString::null(),
+        security_policy_uri: UAString::null(),
+    }
+}
+
+fn is_connection_lost(status_code: &StatusCode) -> bool {
+    [
+        StatusCode::BadNotConnected,
+        StatusCode::BadConnectionClosed,
+        StatusCode::BadSecureChannelClosed,
+        StatusCode::BadSecureChannelIdInvalid,
+        StatusCode::BadSessionClosed,
+        StatusCode::BadSessionIdInvalid,
+        StatusCode::BadCommunicationError,
+        StatusCode::BadServerNotConnected,
+        StatusCode::BadTimeout,
+    ]
+    .contains(status_code)
+}
+
 #[async_trait]
 impl TimeSeriesQueryable for OPCUAHistoryRead {
     async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
         validate_tsq(tsq, true, false)?;
-        let session = self.session.write().unwrap();
-        let start_time = find_time(tsq, &FindTime::Start);
-        let end_time = find_time(tsq, &FindTime::End);
+        //Limits are enforced by the combiner when slicing the solution mappings,
+        //here we only use them to stop paging early
+        let (tsq, limit) = if let TimeSeriesQuery::Limited(inner, limit) = tsq {
+            (inner.as_ref(), Some(*limit))
+        } else {
+            (tsq, None)
+        };
+        let start_time = find_time(tsq, &FindTime::Start)?;
+        let end_time = find_time(tsq, &FindTime::End)?;
 
         let mut processed_details = None;
         let mut timestamp_grouping_colname = None;
@@ -106,12 +434,8 @@ impl TimeSeriesQueryable for OPCUAHistoryRead {
         let mut grouping_col_lookup = HashMap::new();
         let mut grouping_col_name = None;
         if let TimeSeriesQuery::Grouped(grouped) = tsq {
-            let (colname, processed_details_some) = create_read_processed_details(
-                tsq,
-                start_time,
-                end_time,
-                &grouped.context,
-            );
+            let (colname, processed_details_some) =
+                create_read_processed_details(tsq, start_time, end_time, &grouped.context)?;
             processed_details = Some(processed_details_some);
             timestamp_grouping_colname = colname;
             for c in grouped.tsq.get_ids() {
@@ -119,169 +443,221 @@ impl TimeSeriesQueryable for OPCUAHistoryRead {
                     colnames_identifiers.push((v.as_str().to_string(), c.clone()));
                 }
             }
-            let mapping_df = grouped.tsq.get_groupby_mapping_df().unwrap();
-            grouping_col_name = Some(grouped.tsq.get_groupby_column().unwrap());
+            let mapping_error = || {
+                OPCUAHistoryReadError::GroupingMappingError(
+                    "Grouped query is missing the mapping from identifiers to groups".to_string(),
+                )
+            };
+            let mapping_df = grouped
+                .tsq
+                .get_groupby_mapping_df()
+                .ok_or_else(mapping_error)?;
+            grouping_col_name = Some(grouped.tsq.get_groupby_column().ok_or_else(mapping_error)?);
             let identifier_var = grouped
                 .tsq
                 .get_identifier_variables()
                 .get(0)
-                .unwrap()
+                .ok_or_else(mapping_error)?
                 .as_str();
-            let mut id_iter = mapping_df.column(identifier_var).unwrap().iter();
-            let mut grouping_col_iter = mapping_df
+            let id_iter = mapping_df
+                .column(identifier_var)
+                .map_err(OPCUAHistoryReadError::PolarsError)?
+                .iter();
+            let grouping_col_iter = mapping_df
                 .column(grouping_col_name.unwrap().as_str())
-                .unwrap()
+                .map_err(OPCUAHistoryReadError::PolarsError)?
                 .iter();
-            for _ in 0..mapping_df.height() {
-                let id_value = match id_iter.next().unwrap() {
-                    AnyValue::Utf8(id_value) => id_value,
-                    _ => {
-                        panic!("Should never happen")
+            for (id_value, grouping_col_value) in id_iter.zip(grouping_col_iter) {
+                match (id_value, grouping_col_value) {
+                    (AnyValue::Utf8(id_value), AnyValue::Int64(grouping_col_value)) => {
+                        grouping_col_lookup.insert(id_value, grouping_col_value);
                     }
-                };
-                let grouping_col_value = match grouping_col_iter.next().unwrap() {
-                    AnyValue::Int64(grouping_col_value) => grouping_col_value,
-                    _ => {
-                        panic!("Should never happen")
+                    (id_value, grouping_col_value) => {
+                        return Err(Box::new(OPCUAHistoryReadError::GroupingMappingError(
+                            format!(
+                                "Expected a string identifier and an integer group, got {} and {}",
+                                id_value, grouping_col_value
+                            ),
+                        )))
                     }
-                };
-                grouping_col_lookup.insert(id_value, grouping_col_value);
+                }
             }
         } else {
-            raw_modified_details = Some(create_raw_details(start_time, end_time));
+            raw_modified_details = Some(create_raw_details(start_time, end_time, limit));
+            let value_variable = tsq
+                .get_value_variables()
+                .get(0)
+                .map(|x| &x.variable)
+                .ok_or(OPCUAHistoryReadError::MissingVariableError("value"))?;
             for c in tsq.get_ids() {
-                colnames_identifiers.push((
-                    tsq.get_value_variables()
-                        .get(0)
-                        .as_ref()
-                        .unwrap()
-                        .variable
-                        .as_str()
-                        .to_string(),
-                    c.clone(),
-                ))
+                colnames_identifiers.push((value_variable.as_str().to_string(), c.clone()))
             }
         }
 
+        if colnames_identifiers.is_empty() {
+            return Ok(empty_frame(
+                tsq,
+                grouping_col_name,
+                &timestamp_grouping_colname,
+            )?);
+        }
+
         let mut nodes_to_read_vec = vec![];
         for (_, id) in &colnames_identifiers {
             let hrvi = HistoryReadValueId {
-                node_id: node_id_from_string(id)?,
+                node_id: node_id_from_external_id(id, self.namespace)?,
                 index_range: UAString::null(),
                 data_encoding: QualifiedName::null(),
                 continuation_point: ByteString::null(),
             };
             nodes_to_read_vec.push(hrvi);
         }
-        //let series = vec![];
-        let mut stopped = false;
-        let mut dfs = vec![];
-        while !stopped {
-            let action = if let Some(d) = &processed_details {
-                HistoryReadAction::ReadProcessedDetails(d.clone())
-            } else if let Some(d) = &raw_modified_details {
-                HistoryReadAction::ReadRawModifiedDetails(d.clone())
+        let pages = self.read_pages(
+            tsq,
+            &processed_details,
+            &raw_modified_details,
+            &mut nodes_to_read_vec,
+            limit,
+        )?;
+
+        //Status codes are kept in the quality variable,
+        // but not for aggregates, as there may be many aggregates per node
+        let status_code_column = if raw_modified_details.is_some() {
+            tsq.get_quality_variables()
+                .get(0)
+                .map(|x| x.variable.as_str().to_string())
+        } else {
+            None
+        };
+        let mut series_map: HashMap<String, Vec<(Series, Series)>> = HashMap::new();
+        let mut status_map: HashMap<String, Series> = HashMap::new();
+        for (i, node_pages) in pages.into_iter().enumerate() {
+            let (mut ts, mut val, mut status) =
+                stitch_pages(&nodes_to_read_vec.get(i).unwrap().node_id, node_pages)?;
+            let (colname, id) = colnames_identifiers.get(i).unwrap();
+            let id = id.to_string();
+            if let Some(grvar) = &timestamp_grouping_colname {
+                ts.rename(grvar);
             } else {
-                panic!("");
-            };
-            let resp = session
-                .history_read(
-                    action,
-                    TimestampsToReturn::Source,
-                    false,
-                    nodes_to_read_vec.as_slice(),
-                )
-                .expect("");
-            //First we set the new continuation points:
-            for (i, h) in resp.iter().enumerate() {
-                if !h.continuation_point.is_null() {
-                    nodes_to_read_vec.get_mut(i).unwrap().continuation_point =
-                        h.continuation_point.clone();
-                    todo!("Continuation points are just halfway implemented...");
-                } else {
-                    stopped = true;
-                }
+                ts.rename(timestamp_variable_name(tsq)?);
             }
+            val.rename(colname);
+            if let Some(status_code_column) = &status_code_column {
+                status.rename(status_code_column);
+                status_map.insert(id.clone(), status);
+            }
+            if let Some(v) = series_map.get_mut(&id) {
+                v.push((ts, val));
+            } else {
+                series_map.insert(id, vec![(ts, val)]);
+            }
+        }
 
-            let mut series_map: HashMap<String, Vec<(Series, Series)>> = HashMap::new();
-
-            //Now we process the data
-            for (i, h) in resp.into_iter().enumerate() {
-                let HistoryReadResult {
-                    status_code: _,
-                    continuation_point: _,
-                    history_data,
-                } = h;
-                let (mut ts, mut val) = history_data_to_series_tuple(
-                    history_data
-                        .decode_inner::<HistoryData>(&Default::default())
-                        .unwrap(),
-                );
-                let (colname, id) = colnames_identifiers.get(i).unwrap();
-                if let Some(grvar) = &timestamp_grouping_colname {
-                    ts.rename(grvar);
-                } else {
-                    ts.rename(
-                        tsq.get_timestamp_variables()
-                            .get(0)
-                            .unwrap()
-                            .variable
-                            .as_str(),
-                    );
-                }
-                val.rename(colname);
-                if let Some(v) = series_map.get_mut(id) {
-                    v.push((ts, val));
+        let mut dfs = vec![];
+        let mut keys: Vec<String> = series_map.keys().map(|x| x.clone()).collect();
+        keys.sort();
+        for k in keys {
+            let series_vec = series_map.remove(&k).unwrap();
+            let mut first_ts = None;
+            let mut value_vec = vec![];
+            for (ts, val) in series_vec.into_iter() {
+                if let Some(_) = &first_ts {
                 } else {
-                    series_map.insert(id.clone(), vec![(ts, val)]);
+                    first_ts = Some(ts);
                 }
+                value_vec.push(val);
             }
-            let mut keys: Vec<String> = series_map.keys().map(|x| x.clone()).collect();
-            keys.sort();
-            for k in keys {
-                let series_vec = series_map.remove(&k).unwrap();
-                let mut first_ts = None;
-                let mut value_vec = vec![];
-                for (ts, val) in series_vec.into_iter() {
-                    if let Some(_) = &first_ts {
-                    } else {
-                        first_ts = Some(ts);
-                    }
-                    value_vec.push(val);
-                }
-                let mut identifier_series = if let Some(grouping_col) = grouping_col_name {
-                    Series::new_empty(grouping_col, &DataType::Int64)
-                } else {
-                    Series::new_empty(
-                        tsq.get_identifier_variables().get(0).unwrap().as_str(),
-                        &DataType::Utf8,
-                    )
-                };
-                identifier_series = if let Some(_) = grouping_col_name {
-                    identifier_series
-                        .extend_constant(
-                            AnyValue::Int64(*grouping_col_lookup.get(k.as_str()).unwrap()),
-                            first_ts.as_ref().unwrap().len(),
-                        )
-                        .unwrap()
-                } else {
-                    identifier_series
-                        .extend_constant(AnyValue::Utf8(&k), first_ts.as_ref().unwrap().len())
-                        .unwrap()
-                };
-                value_vec.push(identifier_series);
-                value_vec.push(first_ts.unwrap());
-                value_vec.sort_by_key(|x| x.name().to_string());
-                dfs.push(DataFrame::new(value_vec).unwrap().lazy())
+            let first_ts = first_ts.unwrap();
+            let identifier_series = if let Some(grouping_col) = grouping_col_name {
+                let grouping_col_value = grouping_col_lookup.get(k.as_str()).ok_or_else(|| {
+                    OPCUAHistoryReadError::GroupingMappingError(format!(
+                        "Identifier {} has no group",
+                        k
+                    ))
+                })?;
+                Series::new_empty(grouping_col, &DataType::Int64)
+                    .extend_constant(AnyValue::Int64(*grouping_col_value), first_ts.len())
+            } else {
+                Series::new_empty(identifier_variable_name(tsq)?, &DataType::Utf8)
+                    .extend_constant(AnyValue::Utf8(&k), first_ts.len())
             }
+            .map_err(OPCUAHistoryReadError::PolarsError)?;
+            value_vec.push(identifier_series);
+            value_vec.push(first_ts);
+            if let Some(status) = status_map.remove(&k) {
+                value_vec.push(status);
+            }
+            value_vec.sort_by_key(|x| x.name().to_string());
+            dfs.push(
+                DataFrame::new(value_vec)
+                    .map_err(OPCUAHistoryReadError::PolarsError)?
+                    .lazy(),
+            )
         }
-        let df = concat(dfs, UnionArgs::default()).unwrap().collect().unwrap();
+        let df = concat(dfs, UnionArgs::default())
+            .and_then(|lf| lf.collect())
+            .map_err(OPCUAHistoryReadError::PolarsError)?;
         Ok(df)
     }
 
-    fn allow_compound_timeseries_queries(&self) -> bool {
-        false
+    fn validate_pushdown(&self, tsq: &TimeSeriesQuery) -> Result<(), Box<dyn Error>> {
+        validate_tsq(tsq, true, false)?;
+        let tsq = if let TimeSeriesQuery::Limited(inner, _) = tsq {
+            inner.as_ref()
+        } else {
+            tsq
+        };
+        find_aggregate_types(tsq)?;
+        Ok(())
+    }
+
+    //Filters are only used to find the start and end time of the history read,
+    // and expressions are only used to find the processing interval of aggregates
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        TimeSeriesQueryCapabilities {
+            query_kinds: [
+                TimeSeriesQueryKind::Filtered,
+                TimeSeriesQueryKind::ExpressionAs,
+                TimeSeriesQueryKind::Grouped,
+                TimeSeriesQueryKind::Limited,
+            ]
+            .into(),
+            expression_kinds: [
+                ExpressionKind::And,
+                ExpressionKind::Comparison,
+                ExpressionKind::Arithmetic,
+                ExpressionKind::DateFunction,
+                ExpressionKind::FunctionCall,
+            ]
+            .into(),
+            functions: None,
+            timestamp_conditions: true,
+            value_conditions: false,
+            table_annotations: false,
+        }
+    }
+}
+
+fn trust_server_certificates(
+    pki_dir: &PathBuf,
+    certificate_paths: &Vec<PathBuf>,
+) -> Result<(), OPCUAHistoryReadError> {
+    if certificate_paths.is_empty() {
+        return Ok(());
     }
+    //The certificate store looks up trusted certificates by a file name derived from the certificate
+    let trusted_dir = pki_dir.join("trusted");
+    fs::create_dir_all(&trusted_dir).map_err(|e| {
+        OPCUAHistoryReadError::TrustedCertificateError(trusted_dir.clone(), e.to_string())
+    })?;
+    for p in certificate_paths {
+        let cert = CertificateStore::read_cert(p)
+            .map_err(|e| OPCUAHistoryReadError::TrustedCertificateError(p.clone(), e))?;
+        fs::copy(p, trusted_dir.join(CertificateStore::cert_file_name(&cert))).map_err(|e| {
+            OPCUAHistoryReadError::TrustedCertificateError(p.clone(), e.to_string())
+        })?;
+    }
+    Ok(())
 }
 
 fn validate_tsq(
@@ -310,15 +686,84 @@ fn validate_tsq(
             Err(OPCUAHistoryReadError::TimeSeriesQueryTypeNotSupported)
         }
         TimeSeriesQuery::ExpressionAs(t, _, _) => validate_tsq(t, false, inside_grouping),
+        TimeSeriesQuery::Limited(t, _) => {
+            if !toplevel {
+                Err(OPCUAHistoryReadError::TimeSeriesQueryTypeNotSupported)
+            } else {
+                validate_tsq(t, true, inside_grouping)
+            }
+        }
     }
 }
 
-fn create_raw_details(start_time: DateTime, end_time: DateTime) -> ReadRawModifiedDetails {
+fn identifier_variable_name(tsq: &TimeSeriesQuery) -> Result<&str, OPCUAHistoryReadError> {
+    tsq.get_identifier_variables()
+        .get(0)
+        .map(|x| x.as_str())
+        .ok_or(OPCUAHistoryReadError::MissingVariableError("identifier"))
+}
+
+fn timestamp_variable_name(tsq: &TimeSeriesQuery) -> Result<&str, OPCUAHistoryReadError> {
+    tsq.get_timestamp_variables()
+        .get(0)
+        .map(|x| x.variable.as_str())
+        .ok_or(OPCUAHistoryReadError::MissingVariableError("timestamp"))
+}
+
+//Queries without any nodes to read give a frame with the columns that would otherwise be read
+fn empty_frame(
+    tsq: &TimeSeriesQuery,
+    grouping_col_name: Option<&String>,
+    timestamp_grouping_colname: &Option<String>,
+) -> Result<DataFrame, OPCUAHistoryReadError> {
+    let timestamp_type = DataType::Datetime(TimeUnit::Milliseconds, None);
+    let mut columns = vec![];
+    if let TimeSeriesQuery::Grouped(grouped) = tsq {
+        if let Some(grouping_col_name) = grouping_col_name {
+            columns.push(Series::new_empty(grouping_col_name, &DataType::Int64));
+        }
+        if let Some(grvar) = timestamp_grouping_colname {
+            columns.push(Series::new_empty(grvar, &timestamp_type));
+        }
+        for (v, _) in &grouped.aggregations {
+            columns.push(Series::new_empty(v.as_str(), &DataType::Float64));
+        }
+    } else {
+        columns.push(Series::new_empty(
+            identifier_variable_name(tsq)?,
+            &DataType::Utf8,
+        ));
+        columns.push(Series::new_empty(
+            timestamp_variable_name(tsq)?,
+            &timestamp_type,
+        ));
+        for v in tsq.get_value_variables() {
+            columns.push(Series::new_empty(v.variable.as_str(), &DataType::Null));
+        }
+        for v in tsq.get_quality_variables() {
+            columns.push(Series::new_empty(v.variable.as_str(), &DataType::UInt32));
+        }
+    }
+    columns.sort_by_key(|x| x.name().to_string());
+    DataFrame::new(columns).map_err(OPCUAHistoryReadError::PolarsError)
+}
+
+fn create_raw_details(
+    start_time: DateTime,
+    end_time: DateTime,
+    limit: Option<usize>,
+) -> ReadRawModifiedDetails {
+    //Zero means that the server decides the page size
+    let num_values_per_node = if let Some(limit) = limit {
+        u32::try_from(limit).unwrap_or(0)
+    } else {
+        0
+    };
     ReadRawModifiedDetails {
         is_read_modified: false,
         start_time,
         end_time,
-        num_values_per_node: 0,
+        num_values_per_node,
         return_bounds: false,
     }
 }
@@ -328,8 +773,8 @@ fn create_read_processed_details(
     start_time: DateTime,
     end_time: DateTime,
     context: &Context,
-) -> (Option<String>, ReadProcessedDetails) {
-    let aggregate_type = find_aggregate_types(tsq);
+) -> Result<(Option<String>, ReadProcessedDetails), OPCUAHistoryReadError> {
+    let aggregate_type = find_aggregate_types(tsq)?;
 
     let config = AggregateConfiguration {
         use_server_capabilities_defaults: false,
@@ -352,113 +797,334 @@ fn create_read_processed_details(
         aggregate_type,
         aggregate_configuration: config,
     };
-    (out_string, details)
+    Ok((out_string, details))
 }
 
-fn history_data_to_series_tuple(hd: HistoryData) -> (Series, Series) {
+fn create_history_read_action(
+    processed_details: &Option<ReadProcessedDetails>,
+    raw_modified_details: &Option<ReadRawModifiedDetails>,
+    node_indices: &Vec<usize>,
+) -> Result<HistoryReadAction, OPCUAHistoryReadError> {
+    if let Some(d) = processed_details {
+        //The aggregate types are given per node, so they must follow the nodes we read
+        let mut d = d.clone();
+        if let Some(aggregate_types) = &d.aggregate_type {
+            d.aggregate_type = Some(
+                node_indices
+                    .iter()
+                    .map(|i| aggregate_types.get(*i).unwrap().clone())
+                    .collect(),
+            );
+        }
+        Ok(HistoryReadAction::ReadProcessedDetails(d))
+    } else if let Some(d) = raw_modified_details {
+        Ok(HistoryReadAction::ReadRawModifiedDetails(d.clone()))
+    } else {
+        Err(OPCUAHistoryReadError::TimeSeriesQueryTypeNotSupported)
+    }
+}
+
+fn stitch_pages(
+    node_id: &NodeId,
+    pages: Vec<(Series, Series, Series)>,
+) -> Result<(Series, Series, Series), OPCUAHistoryReadError> {
+    let decode_error = |s: String| OPCUAHistoryReadError::DecodeError(node_id.clone(), s);
+    let mut page_iter = pages.into_iter();
+    let (mut ts, mut val, mut status) = page_iter
+        .next()
+        .ok_or_else(|| decode_error("No history data was read".to_string()))?;
+    for (page_ts, page_val, page_status) in page_iter {
+        if page_ts.len() == 0 {
+            continue;
+        }
+        //Empty pages may not have the right value type, so we just replace them
+        if ts.len() == 0 {
+            ts = page_ts;
+            val = page_val;
+            status = page_status;
+        } else {
+            if val.dtype() != page_val.dtype() {
+                return Err(decode_error(format!(
+                    "Pages have values of different types {} and {}",
+                    val.dtype(),
+                    page_val.dtype()
+                )));
+            }
+            ts.append(&page_ts)
+                .map_err(|e| decode_error(e.to_string()))?;
+            val.append(&page_val)
+                .map_err(|e| decode_error(e.to_string()))?;
+            status
+                .append(&page_status)
+                .map_err(|e| decode_error(e.to_string()))?;
+        }
+    }
+    Ok((ts, val, status))
+}
+
+fn history_data_to_series_tuple(
+    hd: HistoryData,
+) -> Result<(Series, Series, Series, Option<NamedNodeRef<'static>>), String> {
     let HistoryData { data_values } = hd;
-    let data_values_vec = data_values.unwrap();
+    let data_values_vec = data_values.unwrap_or_default();
     let mut any_value_vec = vec![];
     let mut ts_value_vec = vec![];
+    let mut status_vec = vec![];
+    let mut datatype = None;
     for data_value in data_values_vec {
-        if let Some(ts) = data_value.source_timestamp {
-            let polars_datetime = NaiveDateTime::from_timestamp(ts.as_chrono().timestamp(), 0);
-            ts_value_vec.push(polars_datetime);
-        }
+        ts_value_vec.push(
+            data_value
+                .source_timestamp
+                .map(|ts| NaiveDateTime::from_timestamp(ts.as_chrono().timestamp(), 0)),
+        );
+        //A missing status code means that the value is good
+        status_vec.push(data_value.status.unwrap_or(StatusCode::Good).bits());
         if let Some(val) = data_value.value {
-            let any_value = match val {
-                Variant::Double(d) => AnyValue::Float64(d),
-                Variant::Int64(i) => AnyValue::Int64(i),
-                _ => {
-                    todo!("Implement: {}", val)
-                }
-            };
+            let (any_value, value_datatype) = variant_to_any_value(val)?;
+            if datatype.is_none() {
+                datatype = value_datatype;
+            }
             any_value_vec.push(any_value);
+        } else {
+            any_value_vec.push(AnyValue::Null);
         }
     }
     let timestamps = Series::new("timestamp", ts_value_vec.as_slice());
-    let values = Series::from_any_values("value", any_value_vec.as_slice(), false).unwrap();
-    (timestamps, values)
+    let values = Series::from_any_values("value", any_value_vec.as_slice(), false)
+        .map_err(|e| e.to_string())?;
+    let statuses = Series::new("status_code", status_vec.as_slice());
+    Ok((timestamps, values, statuses, datatype))
+}
+
+fn variant_to_any_value(
+    val: Variant,
+) -> Result<(AnyValue<'static>, Option<NamedNodeRef<'static>>), String> {
+    //Enumerations are encoded as Int32
+    let out = match val {
+        Variant::Empty => (AnyValue::Null, None),
+        Variant::Boolean(b) => (AnyValue::Boolean(b), Some(xsd::BOOLEAN)),
+        Variant::SByte(i) => (AnyValue::Int8(i), Some(xsd::BYTE)),
+        Variant::Byte(u) => (AnyValue::UInt8(u), Some(xsd::UNSIGNED_BYTE)),
+        Variant::Int16(i) => (AnyValue::Int16(i), Some(xsd::SHORT)),
+        Variant::UInt16(u) => (AnyValue::UInt16(u), Some(xsd::UNSIGNED_SHORT)),
+        Variant::Int32(i) => (AnyValue::Int32(i), Some(xsd::INT)),
+        Variant::UInt32(u) => (AnyValue::UInt32(u), Some(xsd::UNSIGNED_INT)),
+        Variant::Int64(i) => (AnyValue::Int64(i), Some(xsd::LONG)),
+        Variant::UInt64(u) => (AnyValue::UInt64(u), Some(xsd::UNSIGNED_LONG)),
+        Variant::Float(f) => (AnyValue::Float32(f), Some(xsd::FLOAT)),
+        Variant::Double(d) => (AnyValue::Float64(d), Some(xsd::DOUBLE)),
+        Variant::String(s) | Variant::XmlElement(s) => {
+            (ua_string_to_any_value(&s), Some(xsd::STRING))
+        }
+        Variant::LocalizedText(l) => (ua_string_to_any_value(&l.text), Some(xsd::STRING)),
+        Variant::QualifiedName(q) => (ua_string_to_any_value(&q.name), Some(xsd::STRING)),
+        Variant::Guid(g) => (AnyValue::Utf8Owned(g.to_string().into()), Some(xsd::STRING)),
+        Variant::NodeId(n) => (AnyValue::Utf8Owned(n.to_string().into()), Some(xsd::STRING)),
+        Variant::ExpandedNodeId(n) => {
+            (AnyValue::Utf8Owned(n.to_string().into()), Some(xsd::STRING))
+        }
+        Variant::DateTime(dt) => (
+            AnyValue::Datetime(
+                dt.as_chrono().timestamp_millis(),
+                TimeUnit::Milliseconds,
+                &None,
+            ),
+            Some(xsd::DATE_TIME),
+        ),
+        Variant::ByteString(b) => {
+            if let Some(bytes) = b.value {
+                (AnyValue::BinaryOwned(bytes), Some(xsd::BASE_64_BINARY))
+            } else {
+                (AnyValue::Null, Some(xsd::BASE_64_BINARY))
+            }
+        }
+        Variant::StatusCode(s) => (AnyValue::UInt32(s.bits()), Some(xsd::UNSIGNED_INT)),
+        _ => return Err(format!("Unsupported value {}", val)),
+    };
+    Ok(out)
 }
 
-fn find_aggregate_types(tsq: &TimeSeriesQuery) -> Option<Vec<NodeId>> {
+fn ua_string_to_any_value(s: &UAString) -> AnyValue<'static> {
+    if let Some(s) = s.value() {
+        AnyValue::Utf8Owned(s.as_str().into())
+    } else {
+        AnyValue::Null
+    }
+}
+
+//Values are converted to the datatype of the time series when the node has values of another type
+fn cast_to_expected_datatype(
+    tsq: &TimeSeriesQuery,
+    node_id: &NodeId,
+    values: Series,
+    datatype: Option<NamedNodeRef>,
+) -> Result<Series, OPCUAHistoryReadError> {
+    let expected = if let Some(value_variable) = tsq.get_value_variables().get(0) {
+        tsq.get_datatype_map()
+            .remove(value_variable.variable.as_str())
+    } else {
+        None
+    };
+    match (expected, datatype) {
+        (Some(expected), Some(datatype)) if expected.as_ref() != datatype => {
+            let cast_error = |reason: String| {
+                OPCUAHistoryReadError::DecodeError(
+                    node_id.clone(),
+                    format!(
+                        "Values of type {} could not be converted to {}: {}",
+                        datatype, expected, reason
+                    ),
+                )
+            };
+            let polars_datatype = polars_datatype(expected.as_ref())
+                .ok_or_else(|| cast_error("unsupported datatype".to_string()))?;
+            values
+                .strict_cast(&polars_datatype)
+                .map_err(|e| cast_error(e.to_string()))
+        }
+        _ => Ok(values),
+    }
+}
+
+fn polars_datatype(datatype: NamedNodeRef) -> Option<DataType> {
+    let polars_datatype = match datatype {
+        xsd::BOOLEAN => DataType::Boolean,
+        xsd::BYTE => DataType::Int8,
+        xsd::UNSIGNED_BYTE => DataType::UInt8,
+        xsd::SHORT => DataType::Int16,
+        xsd::UNSIGNED_SHORT => DataType::UInt16,
+        xsd::INT => DataType::Int32,
+        xsd::UNSIGNED_INT => DataType::UInt32,
+        xsd::LONG | xsd::INTEGER => DataType::Int64,
+        xsd::UNSIGNED_LONG => DataType::UInt64,
+        xsd::FLOAT => DataType::Float32,
+        xsd::DOUBLE | xsd::DECIMAL => DataType::Float64,
+        xsd::STRING => DataType::Utf8,
+        xsd::DATE_TIME => DataType::Datetime(TimeUnit::Milliseconds, None),
+        xsd::BASE_64_BINARY => DataType::Binary,
+        _ => return None,
+    };
+    Some(polars_datatype)
+}
+
+fn find_aggregate_types(
+    tsq: &TimeSeriesQuery,
+) -> Result<Option<Vec<NodeId>>, OPCUAHistoryReadError> {
     if let TimeSeriesQuery::Grouped(grouped) = tsq {
         let mut nodes = vec![];
         for (_, agg) in &grouped.aggregations {
             let value_var_str = tsq.get_value_variables().get(0).unwrap().variable.as_str();
-            let expr_is_ok = |expr: &Expression| -> bool {
-                if let Expression::Variable(v) = expr {
-                    v.as_str() == value_var_str
-                } else {
-                    false
-                }
+            let not_supported = || OPCUAHistoryReadError::AggregateNotSupported(agg.to_string());
+            //Only aggregates of the value variable itself can be read from the server
+            let check_expr = |expr: &Expression, distinct: bool| match expr {
+                Expression::Variable(v) if !distinct && v.as_str() == value_var_str => Ok(()),
+                _ => Err(not_supported()),
             };
             let aggfunc = match agg {
                 AggregateExpression::Count { expr, distinct } => {
-                    assert!(!distinct);
-                    if let Some(e) = expr {
-                        assert!(expr_is_ok(e));
-                    }
-                    NodeId {
-                        namespace: 0,
-                        identifier: Identifier::Numeric(OPCUA_AGG_FUNC_COUNT),
+                    if let Some(expr) = expr {
+                        check_expr(expr, *distinct)?;
+                    } else if *distinct {
+                        return Err(not_supported());
                     }
+                    OPCUA_AGG_FUNC_COUNT
                 }
                 AggregateExpression::Sum { expr, distinct } => {
-                    assert!(!distinct);
-                    assert!(expr_is_ok(expr));
-                    NodeId {
-                        namespace: 0,
-                        identifier: Identifier::Numeric(OPCUA_AGG_FUNC_TOTAL),
-                    }
+                    check_expr(expr, *distinct)?;
+                    OPCUA_AGG_FUNC_TOTAL
                 }
                 AggregateExpression::Avg { expr, distinct } => {
-                    assert!(!distinct);
-                    assert!(expr_is_ok(expr));
-                    NodeId {
-                        namespace: 0,
-                        identifier: Identifier::Numeric(OPCUA_AGG_FUNC_AVERAGE),
-                    }
+                    check_expr(expr, *distinct)?;
+                    OPCUA_AGG_FUNC_AVERAGE
                 }
                 AggregateExpression::Min { expr, distinct } => {
-                    assert!(!distinct);
-                    assert!(expr_is_ok(expr));
-                    NodeId {
-                        namespace: 0,
-                        identifier: Identifier::Numeric(OPCUA_AGG_FUNC_MINIMUM),
-                    }
+                    check_expr(expr, *distinct)?;
+                    OPCUA_AGG_FUNC_MINIMUM
                 }
                 AggregateExpression::Max { expr, distinct } => {
-                    assert!(!distinct);
-                    assert!(expr_is_ok(expr));
-                    NodeId {
-                        namespace: 0,
-                        identifier: Identifier::Numeric(OPCUA_AGG_FUNC_MAXIMUM),
-                    }
+                    check_expr(expr, *distinct)?;
+                    OPCUA_AGG_FUNC_MAXIMUM
                 }
-                _ => {
-                    panic!("Not supported {:?}, should not happen", agg)
+                AggregateExpression::Custom {
+                    name,
+                    expr,
+                    distinct,
+                } => {
+                    check_expr(expr, *distinct)?;
+                    name.as_str()
+                        .strip_prefix(OPCUA_AGG_FUNC_PREFIX)
+                        .and_then(standard_aggregate_function)
+                        .ok_or_else(not_supported)? as u32
                 }
+                _ => return Err(not_supported()),
             };
-            nodes.push(aggfunc);
+            nodes.push(NodeId {
+                namespace: 0,
+                identifier: Identifier::Numeric(aggfunc),
+            });
         }
         let mut outnodes = vec![];
         for _ in tsq.get_ids() {
             outnodes.extend_from_slice(nodes.as_slice())
         }
-        Some(outnodes)
+        Ok(Some(outnodes))
     } else {
-        None
+        Ok(None)
     }
 }
 
+//Standard aggregates from OPC UA Part 13, referred to by their browse name
+fn standard_aggregate_function(name: &str) -> Option<ObjectId> {
+    let object_id = match name {
+        "Interpolative" => ObjectId::AggregateFunction_Interpolative,
+        "Average" => ObjectId::AggregateFunction_Average,
+        "TimeAverage" => ObjectId::AggregateFunction_TimeAverage,
+        "TimeAverage2" => ObjectId::AggregateFunction_TimeAverage2,
+        "Total" => ObjectId::AggregateFunction_Total,
+        "Total2" => ObjectId::AggregateFunction_Total2,
+        "Minimum" => ObjectId::AggregateFunction_Minimum,
+        "Maximum" => ObjectId::AggregateFunction_Maximum,
+        "MinimumActualTime" => ObjectId::AggregateFunction_MinimumActualTime,
+        "MaximumActualTime" => ObjectId::AggregateFunction_MaximumActualTime,
+        "Minimum2" => ObjectId::AggregateFunction_Minimum2,
+        "Maximum2" => ObjectId::AggregateFunction_Maximum2,
+        "Range" => ObjectId::AggregateFunction_Range,
+        "Range2" => ObjectId::AggregateFunction_Range2,
+        "Count" => ObjectId::AggregateFunction_Count,
+        "AnnotationCount" => ObjectId::AggregateFunction_AnnotationCount,
+        "DurationInStateZero" => ObjectId::AggregateFunction_DurationInStateZero,
+        "DurationInStateNonZero" => ObjectId::AggregateFunction_DurationInStateNonZero,
+        "NumberOfTransitions" => ObjectId::AggregateFunction_NumberOfTransitions,
+        "Start" => ObjectId::AggregateFunction_Start,
+        "End" => ObjectId::AggregateFunction_End,
+        "Delta" => ObjectId::AggregateFunction_Delta,
+        "StartBound" => ObjectId::AggregateFunction_StartBound,
+        "EndBound" => ObjectId::AggregateFunction_EndBound,
+        "DeltaBounds" => ObjectId::AggregateFunction_DeltaBounds,
+        "DurationGood" => ObjectId::AggregateFunction_DurationGood,
+        "DurationBad" => ObjectId::AggregateFunction_DurationBad,
+        "PercentGood" => ObjectId::AggregateFunction_PercentGood,
+        "PercentBad" => ObjectId::AggregateFunction_PercentBad,
+        "WorstQuality" => ObjectId::AggregateFunction_WorstQuality,
+        "WorstQuality2" => ObjectId::AggregateFunction_WorstQuality2,
+        "StandardDeviationSample" => ObjectId::AggregateFunction_StandardDeviationSample,
+        "StandardDeviationPopulation" => ObjectId::AggregateFunction_StandardDeviationPopulation,
+        "VarianceSample" => ObjectId::AggregateFunction_VarianceSample,
+        "VariancePopulation" => ObjectId::AggregateFunction_VariancePopulation,
+        _ => return None,
+    };
+    Some(object_id)
+}
+
 enum FindTime {
     Start,
     End,
 }
 
-fn find_time(tsq: &TimeSeriesQuery, find_time: &FindTime) -> DateTime {
-    let mut found_time = None;
+fn find_time(
+    tsq: &TimeSeriesQuery,
+    find_time: &FindTime,
+) -> Result<DateTime, OPCUAHistoryReadError> {
     let filter = if let TimeSeriesQuery::Grouped(gr) = tsq {
         if let TimeSeriesQuery::Filtered(_, filter) = gr.tsq.as_ref() {
             Some(filter)
@@ -470,23 +1136,14 @@ fn find_time(tsq: &TimeSeriesQuery, find_time: &FindTime) -> DateTime {
     } else {
         None
     };
-    if let Some(e) = filter {
-        let found_time_opt = find_time_condition(
-            &tsq.get_timestamp_variables().get(0).unwrap().variable,
-            e,
-            find_time,
-        );
-        if found_time_opt.is_some() {
-            if found_time.is_some() {
-                panic!("Two duplicate conditions??");
-            }
-            found_time = found_time_opt;
-        }
+    let mut found_time = None;
+    if let (Some(e), Some(timestamp_variable)) = (filter, tsq.get_timestamp_variables().get(0)) {
+        found_time = find_time_condition(&timestamp_variable.variable, e, find_time)?;
     }
     if let Some(dt) = found_time {
-        dt
+        Ok(dt)
     } else {
-        DateTime::null()
+        Ok(DateTime::null())
     }
 }
 
@@ -494,21 +1151,28 @@ fn find_time_condition(
     timestamp_variable: &Variable,
     expr: &Expression,
     find_time: &FindTime,
+) -> Result<Option<DateTime>, OPCUAHistoryReadError> {
+    if let Expression::And(left, right) = expr {
+        let left_cond = find_time_condition(timestamp_variable, left, find_time)?;
+        let right_cond = find_time_condition(timestamp_variable, right, find_time)?;
+        if left_cond.is_some() && right_cond.is_some() {
+            Err(OPCUAHistoryReadError::DuplicateTimeConditionError(
+                expr.to_string(),
+            ))
+        } else {
+            Ok(left_cond.or(right_cond))
+        }
+    } else {
+        Ok(find_time_comparison(timestamp_variable, expr, find_time))
+    }
+}
+
+fn find_time_comparison(
+    timestamp_variable: &Variable,
+    expr: &Expression,
+    find_time: &FindTime,
 ) -> Option<DateTime> {
     match expr {
-        Expression::And(left, right) => {
-            let left_cond = find_time_condition(timestamp_variable, left, find_time);
-            let right_cond = find_time_condition(timestamp_variable, right, find_time);
-            if left_cond.is_some() && right_cond.is_some() {
-                panic!("Not allowed");
-            } else if let Some(cond) = left_cond {
-                Some(cond)
-            } else if let Some(cond) = right_cond {
-                Some(cond)
-            } else {
-                None
-            }
-        }
         Expression::Greater(left, right) => {
             match find_time {
                 FindTime::Start => {
@@ -756,6 +1420,45 @@ fn from_numeric_datatype(lit: &Literal) -> Option<f64> {
     }
 }
 
+//Integer ids are numeric node ids in the namespace of the server,
+// composite ids consist of the namespace index and the identifier of the node
+fn node_id_from_external_id(
+    id: &ExternalId,
+    namespace: u16,
+) -> Result<NodeId, OPCUAHistoryReadError> {
+    let numeric_identifier = |i: &i64| {
+        u32::try_from(*i)
+            .map(Identifier::Numeric)
+            .map_err(|_| OPCUAHistoryReadError::InvalidNodeIdError(id.to_string()))
+    };
+    match id {
+        ExternalId::String(s) => node_id_from_string(s),
+        ExternalId::Integer(i) => Ok(NodeId {
+            namespace,
+            identifier: numeric_identifier(i)?,
+        }),
+        ExternalId::Composite(parts) => {
+            if let [ExternalId::Integer(ns), identifier] = parts.as_slice() {
+                let namespace = u16::try_from(*ns)
+                    .map_err(|_| OPCUAHistoryReadError::InvalidNodeIdError(id.to_string()))?;
+                let identifier = match identifier {
+                    ExternalId::Integer(i) => numeric_identifier(i)?,
+                    ExternalId::String(s) => Identifier::String(UAString::from(s.clone())),
+                    ExternalId::Composite(_) => {
+                        return Err(OPCUAHistoryReadError::InvalidNodeIdError(id.to_string()))
+                    }
+                };
+                Ok(NodeId {
+                    namespace,
+                    identifier,
+                })
+            } else {
+                Err(OPCUAHistoryReadError::InvalidNodeIdError(id.to_string()))
+            }
+        }
+    }
+}
+
 fn node_id_from_string(s: &str) -> Result<NodeId, OPCUAHistoryReadError> {
     let mut splitstring = s.split(";");
     let ns_str = if let Some(ns_str) = splitstring.next() {
@@ -797,7 +1500,7 @@ fn node_id_from_string(s: &str) -> Result<NodeId, OPCUAHistoryReadError> {
             ),
         })
     } else if identifier_string.starts_with("b=") {
-        let identifier = identifier_string.strip_prefix("g=").unwrap();
+        let identifier = identifier_string.strip_prefix("b=").unwrap();
         let byte_string = if let Some(byte_string) = ByteString::from_base64(identifier) {
             byte_string
         } else {
@@ -811,3 +1514,277 @@ fn node_id_from_string(s: &str) -> Result<NodeId, OPCUAHistoryReadError> {
         Err(OPCUAHistoryReadError::InvalidNodeIdError(s.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cast_to_expected_datatype, find_aggregate_types, history_data_to_series_tuple,
+        node_id_from_external_id, variant_to_any_value, OPCUA_AGG_FUNC_PREFIX,
+    };
+    use crate::external_ids::ExternalId;
+    use crate::query_context::{Context, VariableInContext};
+    use crate::timeseries_query::{BasicTimeSeriesQuery, GroupedTimeSeriesQuery, TimeSeriesQuery};
+    use opcua_client::prelude::{
+        ByteString, DataValue, DateTime, HistoryData, Identifier, NodeId, ObjectId, StatusCode,
+        UAString, Variant,
+    };
+    use oxrdf::vocab::xsd;
+    use oxrdf::{NamedNode, Variable};
+    use polars::export::chrono::{TimeZone, Utc};
+    use polars_core::frame::DataFrame;
+    use polars_core::prelude::{AnyValue, DataType, NamedFrom, TimeUnit};
+    use polars_core::series::Series;
+    use spargebra::algebra::{AggregateExpression, Expression};
+
+    fn double_tsq() -> TimeSeriesQuery {
+        TimeSeriesQuery::Basic(double_basic_tsq())
+    }
+
+    fn double_basic_tsq() -> BasicTimeSeriesQuery {
+        BasicTimeSeriesQuery {
+            identifier_variable: Some(Variable::new_unchecked("id")),
+            value_variable: Some(VariableInContext::new(
+                Variable::new_unchecked("v"),
+                Context::new(),
+            )),
+            datatype: Some(xsd::DOUBLE.into_owned()),
+            timestamp_variable: Some(VariableInContext::new(
+                Variable::new_unchecked("t"),
+                Context::new(),
+            )),
+            ids: Some(vec![ExternalId::String("ns=2;i=1".to_string())]),
+            ..BasicTimeSeriesQuery::new_empty()
+        }
+    }
+
+    #[test]
+    fn test_variant_to_any_value() {
+        let dt = Utc.with_ymd_and_hms(2022, 6, 1, 8, 46, 53).unwrap();
+        let cases = vec![
+            (Variant::Empty, AnyValue::Null, None),
+            (
+                Variant::Boolean(true),
+                AnyValue::Boolean(true),
+                Some(xsd::BOOLEAN),
+            ),
+            (
+                Variant::Byte(7),
+                AnyValue::UInt8(7),
+                Some(xsd::UNSIGNED_BYTE),
+            ),
+            (Variant::Int16(-3), AnyValue::Int16(-3), Some(xsd::SHORT)),
+            (
+                Variant::UInt16(3),
+                AnyValue::UInt16(3),
+                Some(xsd::UNSIGNED_SHORT),
+            ),
+            //Enumerations are read as Int32
+            (Variant::Int32(2), AnyValue::Int32(2), Some(xsd::INT)),
+            (
+                Variant::Double(1.5),
+                AnyValue::Float64(1.5),
+                Some(xsd::DOUBLE),
+            ),
+            (
+                Variant::String(UAString::from("running")),
+                AnyValue::Utf8Owned("running".into()),
+                Some(xsd::STRING),
+            ),
+            (
+                Variant::String(UAString::null()),
+                AnyValue::Null,
+                Some(xsd::STRING),
+            ),
+            (
+                Variant::DateTime(Box::new(DateTime::from(dt))),
+                AnyValue::Datetime(dt.timestamp_millis(), TimeUnit::Milliseconds, &None),
+                Some(xsd::DATE_TIME),
+            ),
+            (
+                Variant::ByteString(ByteString::from(vec![1u8, 2u8])),
+                AnyValue::BinaryOwned(vec![1u8, 2u8]),
+                Some(xsd::BASE_64_BINARY),
+            ),
+            (
+                Variant::StatusCode(StatusCode::BadSensorFailure),
+                AnyValue::UInt32(StatusCode::BadSensorFailure.bits()),
+                Some(xsd::UNSIGNED_INT),
+            ),
+        ];
+        for (variant, expected_value, expected_datatype) in cases {
+            let (value, datatype) = variant_to_any_value(variant).unwrap();
+            assert_eq!(expected_value, value);
+            assert_eq!(expected_datatype, datatype);
+        }
+        assert!(variant_to_any_value(Variant::Variant(Box::new(Variant::Int32(1)))).is_err());
+    }
+
+    #[test]
+    fn test_history_data_status_codes() {
+        let timestamp = |s| {
+            Some(DateTime::from(
+                Utc.with_ymd_and_hms(2022, 6, 1, 8, 46, s).unwrap(),
+            ))
+        };
+        let data_values = vec![
+            DataValue {
+                value: Some(Variant::Double(1.0)),
+                status: None,
+                source_timestamp: timestamp(53),
+                ..Default::default()
+            },
+            DataValue {
+                value: Some(Variant::Double(2.0)),
+                status: Some(StatusCode::BadSensorFailure),
+                source_timestamp: timestamp(54),
+                ..Default::default()
+            },
+            DataValue {
+                value: None,
+                status: Some(StatusCode::UncertainLastUsableValue),
+                source_timestamp: timestamp(55),
+                ..Default::default()
+            },
+        ];
+        let (ts, values, statuses, datatype) = history_data_to_series_tuple(HistoryData {
+            data_values: Some(data_values),
+        })
+        .unwrap();
+        assert_eq!(3, ts.len());
+        assert_eq!(Some(xsd::DOUBLE), datatype);
+        assert!(values.series_equal_missing(&Series::new("value", [Some(1.0), Some(2.0), None])));
+        //Values without a status code are good
+        let expected_statuses = Series::new(
+            "status_code",
+            [
+                StatusCode::Good.bits(),
+                StatusCode::BadSensorFailure.bits(),
+                StatusCode::UncertainLastUsableValue.bits(),
+            ],
+        );
+        assert!(statuses.series_equal(&expected_statuses));
+    }
+
+    #[test]
+    fn test_cast_to_expected_datatype() {
+        let tsq = double_tsq();
+        let node_id = NodeId::new(2, 1);
+        let floats = Series::new("value", [1.5f32, 2.0f32]);
+        let cast = cast_to_expected_datatype(&tsq, &node_id, floats, Some(xsd::FLOAT)).unwrap();
+        assert_eq!(&DataType::Float64, cast.dtype());
+
+        let strings = Series::new("value", ["on", "off"]);
+        assert!(cast_to_expected_datatype(&tsq, &node_id, strings, Some(xsd::STRING)).is_err());
+    }
+
+    #[test]
+    fn test_custom_aggregate_types() {
+        let grouped = |names: Vec<&str>| {
+            let aggregations = names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    (
+                        Variable::new_unchecked(format!("agg_{}", i)),
+                        AggregateExpression::Custom {
+                            name: NamedNode::new_unchecked(format!(
+                                "{}{}",
+                                OPCUA_AGG_FUNC_PREFIX, name
+                            )),
+                            expr: Box::new(Expression::Variable(Variable::new_unchecked("v"))),
+                            distinct: false,
+                        },
+                    )
+                })
+                .collect();
+            TimeSeriesQuery::Grouped(GroupedTimeSeriesQuery {
+                context: Context::new(),
+                tsq: Box::new(TimeSeriesQuery::GroupedBasic(
+                    double_basic_tsq(),
+                    DataFrame::new(vec![
+                        Series::new("id", ["ns=2;i=1"]),
+                        Series::new("grouping_col_0", [0i64]),
+                    ])
+                    .unwrap(),
+                    "grouping_col_0".to_string(),
+                )),
+                by: vec![Variable::new_unchecked("grouping_col_0")],
+                aggregations,
+            })
+        };
+        let aggregate_types = find_aggregate_types(&grouped(vec!["TimeAverage", "Delta"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            vec![
+                NodeId::new(0, ObjectId::AggregateFunction_TimeAverage as u32),
+                NodeId::new(0, ObjectId::AggregateFunction_Delta as u32)
+            ],
+            aggregate_types
+        );
+        assert!(find_aggregate_types(&grouped(vec!["NotAnAggregate"])).is_err());
+    }
+
+    #[test]
+    fn test_node_id_from_external_id() {
+        let node_id = |namespace: u16, identifier: Identifier| NodeId {
+            namespace,
+            identifier,
+        };
+        let string_identifier = |s: &str| Identifier::String(UAString::from(s));
+        let valid = [
+            (
+                ExternalId::String("ns=2;s=my;tag".to_string()),
+                node_id(2, string_identifier("my;tag")),
+            ),
+            (
+                ExternalId::String("ns=2;i=5".to_string()),
+                node_id(2, Identifier::Numeric(5)),
+            ),
+            (
+                ExternalId::String("ns=2;b=AQI=".to_string()),
+                node_id(2, Identifier::ByteString(ByteString::from(vec![1u8, 2u8]))),
+            ),
+            //Integer ids are numeric identifiers in the configured namespace
+            (ExternalId::Integer(7), node_id(1, Identifier::Numeric(7))),
+            (
+                ExternalId::Composite(vec![ExternalId::Integer(3), ExternalId::Integer(7)]),
+                node_id(3, Identifier::Numeric(7)),
+            ),
+            (
+                ExternalId::Composite(vec![
+                    ExternalId::Integer(3),
+                    ExternalId::String("tag".to_string()),
+                ]),
+                node_id(3, string_identifier("tag")),
+            ),
+        ];
+        for (id, expected) in valid {
+            assert_eq!(node_id_from_external_id(&id, 1).unwrap(), expected);
+        }
+
+        let invalid = [
+            ExternalId::String("s=tag".to_string()),
+            ExternalId::String("ns=2;x=tag".to_string()),
+            ExternalId::String("ns=2;i=tag".to_string()),
+            ExternalId::Integer(-1),
+            ExternalId::Composite(vec![ExternalId::Integer(70000), ExternalId::Integer(7)]),
+            ExternalId::Composite(vec![
+                ExternalId::String("ns".to_string()),
+                ExternalId::Integer(7),
+            ]),
+            ExternalId::Composite(vec![
+                ExternalId::Integer(3),
+                ExternalId::Integer(7),
+                ExternalId::Integer(8),
+            ]),
+        ];
+        for id in invalid {
+            assert!(
+                node_id_from_external_id(&id, 1).is_err(),
+                "{} was accepted",
+                id
+            );
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
+use crate::timeseries_database::sql_result_to_polars::{
+    columns_to_dataframe, expected_column_types, is_timestamp_column, new_columns,
+    SQLResultConversionError, SQLValue,
+};
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::has_table_iris;
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::normalize_timestamps;
+use crate::timeseries_database::timeseries_sql_rewrite::{
+    TimeSeriesQueryToSQLError, TimeSeriesQueryToSQLTransformer, TimeSeriesTable,
+};
+use crate::timeseries_database::TimeSeriesQueryable;
+use crate::timeseries_query::TimeSeriesQuery;
+use async_trait::async_trait;
+use deadpool_postgres::{CreatePoolError, Pool, PoolConfig, PoolError, Runtime};
+use log::debug;
+use polars::export::chrono::{DateTime, NaiveDateTime, Utc};
+use polars::frame::DataFrame;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{NoTls, Row};
+use tokio_stream::StreamExt;
+
+#[derive(Debug)]
+pub enum PostgresDatabaseError {
+    PostgresError(tokio_postgres::Error),
+    PoolError(PoolError),
+    CreatePoolError(CreatePoolError),
+    TlsError(native_tls::Error),
+    ReadCertificateError(String, std::io::Error),
+    TranslationError(TimeSeriesQueryToSQLError),
+    ResultConversionError(SQLResultConversionError),
+    UnsupportedDialect(SqlDialect),
+    NumericConversionError(String, Decimal),
+}
+
+impl Display for PostgresDatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostgresDatabaseError::PostgresError(err) => {
+                write!(f, "Postgres error: {}", err)
+            }
+            PostgresDatabaseError::PoolError(err) => {
+                write!(f, "Could not get connection from pool: {}", err)
+            }
+            PostgresDatabaseError::CreatePoolError(err) => {
+                write!(f, "Could not create connection pool: {}", err)
+            }
+            PostgresDatabaseError::TlsError(err) => {
+                write!(f, "TLS configuration error: {}", err)
+            }
+            PostgresDatabaseError::ReadCertificateError(path, err) => {
+                write!(f, "Could not read certificate {}: {}", path, err)
+            }
+            PostgresDatabaseError::TranslationError(err) => {
+                write!(f, "Error during query translation: {}", err)
+            }
+            PostgresDatabaseError::ResultConversionError(err) => {
+                write!(f, "{}", err)
+            }
+            PostgresDatabaseError::UnsupportedDialect(d) => {
+                write!(
+                    f,
+                    "Postgres databases must use the Postgres or TimescaleDB dialect, got {:?}",
+                    d
+                )
+            }
+            PostgresDatabaseError::NumericConversionError(c, x) => {
+                write!(f, "Could not convert numeric value {} in column {}", x, c)
+            }
+        }
+    }
+}
+
+impl Error for PostgresDatabaseError {}
+
+#[derive(Clone, Debug)]
+pub struct PostgresConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: Option<String>,
+    pub dbname: String,
+    pub use_tls: bool,
+    //PEM encoded, in addition to the system root certificates
+    pub root_certificate_path: Option<String>,
+    pub accept_invalid_certificates: bool,
+    pub max_pool_size: usize,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            host: "localhost".to_string(),
+            port: 5432,
+            user: "postgres".to_string(),
+            password: None,
+            dbname: "postgres".to_string(),
+            use_tls: false,
+            root_certificate_path: None,
+            accept_invalid_certificates: false,
+            max_pool_size: 16,
+        }
+    }
+}
+
+pub struct PostgresDatabase {
+    pool: Pool,
+    sql_dialect: SqlDialect,
+    time_series_tables: Vec<TimeSeriesTable>,
+}
+
+impl PostgresDatabase {
+    pub async fn new(
+        config: PostgresConfig,
+        sql_dialect: SqlDialect,
+        time_series_tables: Vec<TimeSeriesTable>,
+    ) -> Result<PostgresDatabase, PostgresDatabaseError> {
+        if sql_dialect != SqlDialect::Postgres && sql_dialect != SqlDialect::TimescaleDB {
+            return Err(PostgresDatabaseError::UnsupportedDialect(sql_dialect));
+        }
+        let pool = create_pool(&config)?;
+        //Fail early if the database cannot be reached
+        pool.get().await.map_err(PostgresDatabaseError::PoolError)?;
+        Ok(PostgresDatabase {
+            pool,
+            sql_dialect,
+            time_series_tables,
+        })
+    }
+
+    pub async fn execute_sql_query(
+        &self,
+        query: &str,
+        tsq: &TimeSeriesQuery,
+    ) -> Result<DataFrame, PostgresDatabaseError> {
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(PostgresDatabaseError::PoolError)?;
+        let statement = client
+            .prepare(query)
+            .await
+            .map_err(PostgresDatabaseError::PostgresError)?;
+        let column_names: Vec<String> = statement
+            .columns()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect();
+        let column_types = expected_column_types(tsq, &self.time_series_tables);
+        let mut columns = new_columns(&column_names);
+        let params: Vec<&(dyn ToSql + Sync)> = vec![];
+        let row_stream = client
+            .query_raw(&statement, params)
+            .await
+            .map_err(PostgresDatabaseError::PostgresError)?;
+        let mut row_stream = Box::pin(row_stream);
+        while let Some(row) = row_stream.next().await {
+            let row = row.map_err(PostgresDatabaseError::PostgresError)?;
+            for (i, column_name) in column_names.iter().enumerate() {
+                let value = postgres_value(&row, i, column_name)?;
+                columns
+                    .get_mut(i)
+                    .unwrap()
+                    .push(
+                        column_name,
+                        value,
+                        is_timestamp_column(&column_types, column_name),
+                    )
+                    .map_err(PostgresDatabaseError::ResultConversionError)?;
+            }
+        }
+        columns_to_dataframe(&column_names, columns, &column_types)
+            .map_err(PostgresDatabaseError::ResultConversionError)
+    }
+}
+
+#[async_trait]
+impl TimeSeriesQueryable for PostgresDatabase {
+    async fn execute(&mut self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
+        let query_string;
+        {
+            let transformer =
+                TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
+            let (query, _) = transformer
+                .create_query(tsq, false)
+                .map_err(PostgresDatabaseError::TranslationError)?;
+            query_string = self.sql_dialect.build_query(&query);
+            debug!("SQL: {}", query_string);
+        }
+        let df = self.execute_sql_query(&query_string, tsq).await?;
+        Ok(normalize_timestamps(df, tsq, &self.time_series_tables)?)
+    }
+
+    fn validate_pushdown(&self, tsq: &TimeSeriesQuery) -> Result<(), Box<dyn Error>> {
+        let transformer =
+            TimeSeriesQueryToSQLTransformer::new(&self.time_series_tables, self.sql_dialect);
+        transformer
+            .validate_aggregations(tsq)
+            .map_err(PostgresDatabaseError::TranslationError)?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        let mut capabilities = self.sql_dialect.capabilities();
+        capabilities.table_annotations = has_table_iris(&self.time_series_tables);
+        capabilities
+    }
+}
+
+fn create_pool(config: &PostgresConfig) -> Result<Pool, PostgresDatabaseError> {
+    let mut pool_config = deadpool_postgres::Config::new();
+    pool_config.host = Some(config.host.clone());
+    pool_config.port = Some(config.port);
+    pool_config.user = Some(config.user.clone());
+    pool_config.password = config.password.clone();
+    pool_config.dbname = Some(config.dbname.clone());
+    pool_config.pool = Some(PoolConfig::new(config.max_pool_size));
+    let pool = if config.use_tls {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(path) = &config.root_certificate_path {
+            let pem = std::fs::read(path)
+                .map_err(|e| PostgresDatabaseError::ReadCertificateError(path.clone(), e))?;
+            let certificate =
+                native_tls::Certificate::from_pem(&pem).map_err(PostgresDatabaseError::TlsError)?;
+            builder.add_root_certificate(certificate);
+        }
+        builder.danger_accept_invalid_certs(config.accept_invalid_certificates);
+        let connector = builder.build().map_err(PostgresDatabaseError::TlsError)?;
+        pool_config.create_pool(
+            Some(Runtime::Tokio1),
+            postgres_native_tls::MakeTlsConnector::new(connector),
+        )
+    } else {
+        pool_config.create_pool(Some(Runtime::Tokio1), NoTls)
+    };
+    pool.map_err(PostgresDatabaseError::CreatePoolError)
+}
+
+fn postgres_value(
+    row: &Row,
+    i: usize,
+    column_name: &str,
+) -> Result<SQLValue, PostgresDatabaseError> {
+    let t = row.columns().get(i).unwrap().type_();
+    let value = if t == &Type::BOOL {
+        get_value::<bool>(row, i)?.map(SQLValue::Boolean)
+    } else if t == &Type::INT2 {
+        get_value::<i16>(row, i)?.map(|x| SQLValue::Integer(x as i64))
+    } else if t == &Type::INT4 {
+        get_value::<i32>(row, i)?.map(|x| SQLValue::Integer(x as i64))
+    } else if t == &Type::INT8 {
+        get_value::<i64>(row, i)?.map(SQLValue::Integer)
+    } else if t == &Type::FLOAT4 {
+        get_value::<f32>(row, i)?.map(|x| SQLValue::Float(x as f64))
+    } else if t == &Type::FLOAT8 {
+        get_value::<f64>(row, i)?.map(SQLValue::Float)
+    } else if t == &Type::NUMERIC {
+        //E.g. SUM of integers, which cannot overflow in Postgres
+        match get_value::<Decimal>(row, i)? {
+            Some(x) => Some(numeric_value(column_name, x)?),
+            None => None,
+        }
+    } else if t == &Type::TEXT || t == &Type::VARCHAR || t == &Type::BPCHAR || t == &Type::NAME {
+        get_value::<String>(row, i)?.map(SQLValue::Text)
+    } else if t == &Type::TIMESTAMP {
+        get_value::<NaiveDateTime>(row, i)?.map(|x| {
+            SQLValue::Timestamp(x.timestamp() * 1_000_000_000 + x.timestamp_subsec_nanos() as i64)
+        })
+    } else if t == &Type::TIMESTAMPTZ {
+        get_value::<DateTime<Utc>>(row, i)?.map(|x| {
+            SQLValue::Timestamp(x.timestamp() * 1_000_000_000 + x.timestamp_subsec_nanos() as i64)
+        })
+    } else {
+        return Err(PostgresDatabaseError::ResultConversionError(
+            SQLResultConversionError::UnsupportedValueType(column_name.to_string(), t.to_string()),
+        ));
+    };
+    Ok(value.unwrap_or(SQLValue::Null))
+}
+
+//Always a float, as whether the values have fractional parts can differ between queries
+fn numeric_value(column_name: &str, x: Decimal) -> Result<SQLValue, PostgresDatabaseError> {
+    x.to_f64()
+        .map(SQLValue::Float)
+        .ok_or_else(|| PostgresDatabaseError::NumericConversionError(column_name.to_string(), x))
+}
+
+fn get_value<'a, T: tokio_postgres::types::FromSql<'a>>(
+    row: &'a Row,
+    i: usize,
+) -> Result<Option<T>, PostgresDatabaseError> {
+    row.try_get::<_, Option<T>>(i)
+        .map_err(PostgresDatabaseError::PostgresError)
+}
@@ -3,6 +3,7 @@ use crate::combiner::Combiner;
 use crate::constants::GROUPING_COL;
 use crate::pushdown_setting::all_pushdowns;
 use crate::query_context::{Context, PathEntry};
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
 use crate::timeseries_database::TimeSeriesQueryable;
 use crate::timeseries_query::{
     BasicTimeSeriesQuery, GroupedTimeSeriesQuery, Synchronizer, TimeSeriesQuery,
@@ -10,12 +11,13 @@ use crate::timeseries_query::{
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use polars::frame::DataFrame;
+use polars::prelude::DataFrameJoinOps;
 use polars::prelude::{col, concat, lit, IntoLazy, UnionArgs};
 use polars_core::prelude::{JoinArgs, JoinType};
 use spargebra::algebra::Expression;
+use spargebra::term::Variable;
 use std::collections::HashMap;
 use std::error::Error;
-use polars::prelude::DataFrameJoinOps;
 
 pub struct InMemoryTimeseriesDatabase {
     pub frames: HashMap<String, DataFrame>,
@@ -27,8 +29,8 @@ impl TimeSeriesQueryable for InMemoryTimeseriesDatabase {
         self.execute_query(tsq).await
     }
 
-    fn allow_compound_timeseries_queries(&self) -> bool {
-        true
+    fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        TimeSeriesQueryCapabilities::all()
     }
 }
 
@@ -37,51 +39,24 @@ impl InMemoryTimeseriesDatabase {
     async fn execute_query(&self, tsq: &TimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
         match tsq {
             TimeSeriesQuery::Basic(b) => self.execute_basic(b),
-            TimeSeriesQuery::Filtered(inner, filter) => self.execute_filtered(inner, filter).await,
+            TimeSeriesQuery::Filtered(inner, filter_expression) => {
+                self.execute_filtered(inner, filter_expression).await
+            }
             TimeSeriesQuery::InnerSynchronized(inners, synchronizers) => {
                 self.execute_inner_synchronized(inners, synchronizers).await
             }
             TimeSeriesQuery::Grouped(grouped) => self.execute_grouped(grouped).await,
             TimeSeriesQuery::GroupedBasic(btsq, df, ..) => {
-                let mut basic_df = self.execute_basic(btsq)?;
-                basic_df = basic_df
-                    .join(
-                        df,
-                        [btsq.identifier_variable.as_ref().unwrap().as_str()],
-                        [btsq.identifier_variable.as_ref().unwrap().as_str()],
-                        JoinArgs::new(JoinType::Inner),
-
-                    )
-                    .unwrap();
-                basic_df = basic_df
-                    .drop(btsq.identifier_variable.as_ref().unwrap().as_str())
-                    .unwrap();
-                Ok(basic_df)
+                let basic_df = self.execute_basic(btsq)?;
+                join_grouped_basic(basic_df, btsq, df)
             }
             TimeSeriesQuery::ExpressionAs(tsq, v, e) => {
-                let mut df = self.execute_query(tsq).await?;
-                let tmp_context = Context::from_path(vec![PathEntry::Coalesce(13)]);
-                let columns = df
-                    .get_column_names()
-                    .into_iter()
-                    .map(|x| x.to_string())
-                    .collect();
-                let solution_mappings = SolutionMappings::new(df.lazy(), columns, HashMap::new());
-                let mut combiner = Combiner::new(
-                    "".to_string(),
-                    all_pushdowns(),
-                    Box::new(InMemoryTimeseriesDatabase {
-                        frames: Default::default(),
-                    }),
-                    vec![],
-                    Default::default(),
-                );
-                let mut out_lf = combiner
-                    .lazy_expression(e, solution_mappings, None, None, &tmp_context)
-                    .await?;
-                out_lf.mappings = out_lf.mappings.rename([tmp_context.as_str()], [v.as_str()]);
-                df = out_lf.mappings.collect().unwrap();
-                Ok(df)
+                let df = self.execute_query(tsq).await?;
+                expression_as(df, v, e).await
+            }
+            TimeSeriesQuery::Limited(inner, limit) => {
+                let df = self.execute_query(inner).await?;
+                Ok(df.head(Some(*limit)))
             }
         }
     }
@@ -89,7 +64,7 @@ impl InMemoryTimeseriesDatabase {
     fn execute_basic(&self, btsq: &BasicTimeSeriesQuery) -> Result<DataFrame, Box<dyn Error>> {
         let mut lfs = vec![];
         for id in btsq.ids.as_ref().unwrap() {
-            if let Some(df) = self.frames.get(id) {
+            if let Some(df) = self.frames.get(&id.to_string()) {
                 assert!(btsq.identifier_variable.is_some());
                 let mut df = df.clone();
 
@@ -99,6 +74,12 @@ impl InMemoryTimeseriesDatabase {
                 } else {
                     df = df.drop("value").expect("Drop value problem");
                 }
+                if let Some(quality_variable) = &btsq.quality_variable {
+                    df.rename("quality", quality_variable.variable.as_str())
+                        .expect("Rename problem");
+                } else if df.get_column_names().contains(&"quality") {
+                    df = df.drop("quality").expect("Drop quality problem");
+                }
                 if let Some(timestamp_variable) = &btsq.timestamp_variable {
                     df.rename("timestamp", timestamp_variable.variable.as_str())
                         .expect("Rename problem");
@@ -123,33 +104,10 @@ impl InMemoryTimeseriesDatabase {
     async fn execute_filtered(
         &self,
         tsq: &TimeSeriesQuery,
-        filter: &Expression,
+        filter_expression: &Expression,
     ) -> Result<DataFrame, Box<dyn Error>> {
         let df = self.execute_query(tsq).await?;
-        let columns = df
-            .get_column_names()
-            .into_iter()
-            .map(|x| x.to_string())
-            .collect();
-        let tmp_context = Context::from_path(vec![PathEntry::Coalesce(12)]);
-        let mut solution_mappings = SolutionMappings::new(df.lazy(), columns, HashMap::new());
-        let mut combiner = Combiner::new(
-            "".to_string(),
-            all_pushdowns(),
-            Box::new(InMemoryTimeseriesDatabase {
-                frames: Default::default(),
-            }),
-            vec![],
-            Default::default(),
-        );
-        solution_mappings = combiner
-            .lazy_expression(filter, solution_mappings, None, None, &tmp_context)
-            .await?;
-        solution_mappings.mappings = solution_mappings
-            .mappings
-            .filter(col(tmp_context.as_str()))
-            .drop_columns([tmp_context.as_str()]);
-        Ok(solution_mappings.mappings.collect().unwrap())
+        filter(df, filter_expression).await
     }
 
     async fn execute_grouped(
@@ -157,55 +115,7 @@ impl InMemoryTimeseriesDatabase {
         grouped: &GroupedTimeSeriesQuery,
     ) -> Result<DataFrame, Box<dyn Error>> {
         let df = self.execute_query(&grouped.tsq).await?;
-        let columns = df
-            .get_column_names()
-            .into_iter()
-            .map(|x| x.to_string())
-            .collect();
-        let mut out_lf = df.lazy();
-
-        let mut aggregation_exprs = vec![];
-        let mut combiner = Combiner::new(
-            "".to_string(),
-            all_pushdowns(),
-            Box::new(InMemoryTimeseriesDatabase {
-                frames: Default::default(),
-            }),
-            vec![],
-            Default::default(),
-        );
-        let mut solution_mappings = SolutionMappings::new(out_lf, columns, HashMap::new());
-        for i in 0..grouped.aggregations.len() {
-            let (v, agg) = grouped.aggregations.get(i).unwrap();
-            let (new_solution_mappings, agg_expr, _) = combiner
-                .sparql_aggregate_expression_as_lazy_column_and_expression(
-                    v,
-                    agg,
-                    solution_mappings,
-                    &grouped
-                        .context
-                        .extension_with(PathEntry::GroupAggregation(i as u16)),
-                )
-                .await?;
-            solution_mappings = new_solution_mappings;
-            aggregation_exprs.push(agg_expr);
-        }
-        let mut groupby = vec![col(grouped.tsq.get_groupby_column().unwrap())];
-        let tsfuncs = grouped.tsq.get_timeseries_functions(&grouped.context);
-        for b in &grouped.by {
-            for (v, _) in &tsfuncs {
-                if b == *v {
-                    groupby.push(col(v.as_str()));
-                    break;
-                }
-            }
-        }
-
-        let grouped_lf = solution_mappings.mappings.groupby(groupby);
-        out_lf = grouped_lf.agg(aggregation_exprs.as_slice());
-
-        let collected = out_lf.collect()?;
-        Ok(collected)
+        group(df, grouped).await
     }
 
     async fn execute_inner_synchronized(
@@ -213,31 +123,181 @@ impl InMemoryTimeseriesDatabase {
         inners: &Vec<Box<TimeSeriesQuery>>,
         synchronizers: &Vec<Synchronizer>,
     ) -> Result<DataFrame, Box<dyn Error>> {
-        assert_eq!(synchronizers.len(), 1);
-        #[allow(irrefutable_let_patterns)]
-        if let Synchronizer::Identity(timestamp_col) = synchronizers.get(0).unwrap() {
-            let mut on = vec![timestamp_col.clone()];
-            let mut dfs = vec![];
-            for q in inners {
-                let df = self.execute_query(q).await?;
-                for c in df.get_column_names() {
-                    if c.starts_with(GROUPING_COL) {
-                        let c_string = c.to_string();
-                        if !on.contains(&c_string) {
-                            on.push(c_string);
-                        }
+        let mut dfs = vec![];
+        for q in inners {
+            dfs.push(self.execute_query(q).await?);
+        }
+        synchronize(dfs, synchronizers)
+    }
+}
+
+pub(crate) fn join_grouped_basic(
+    basic_df: DataFrame,
+    btsq: &BasicTimeSeriesQuery,
+    df: &DataFrame,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let mut basic_df = basic_df
+        .join(
+            df,
+            [btsq.identifier_variable.as_ref().unwrap().as_str()],
+            [btsq.identifier_variable.as_ref().unwrap().as_str()],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .unwrap();
+    basic_df = basic_df
+        .drop(btsq.identifier_variable.as_ref().unwrap().as_str())
+        .unwrap();
+    Ok(basic_df)
+}
+
+pub(crate) async fn expression_as(
+    df: DataFrame,
+    v: &Variable,
+    e: &Expression,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let tmp_context = Context::from_path(vec![PathEntry::Coalesce(13)]);
+    let columns = df
+        .get_column_names()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+    let solution_mappings = SolutionMappings::new(df.lazy(), columns, HashMap::new());
+    let mut combiner = Combiner::new(
+        "".to_string(),
+        all_pushdowns(),
+        Box::new(InMemoryTimeseriesDatabase {
+            frames: Default::default(),
+        }),
+        vec![],
+        Default::default(),
+    );
+    let mut out_lf = combiner
+        .lazy_expression(e, solution_mappings, None, None, &tmp_context)
+        .await?;
+    out_lf.mappings = out_lf.mappings.rename([tmp_context.as_str()], [v.as_str()]);
+    Ok(out_lf.mappings.collect().unwrap())
+}
+
+pub(crate) async fn filter(
+    df: DataFrame,
+    filter_expression: &Expression,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let columns = df
+        .get_column_names()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+    let tmp_context = Context::from_path(vec![PathEntry::Coalesce(12)]);
+    let mut solution_mappings = SolutionMappings::new(df.lazy(), columns, HashMap::new());
+    let mut combiner = Combiner::new(
+        "".to_string(),
+        all_pushdowns(),
+        Box::new(InMemoryTimeseriesDatabase {
+            frames: Default::default(),
+        }),
+        vec![],
+        Default::default(),
+    );
+    solution_mappings = combiner
+        .lazy_expression(
+            filter_expression,
+            solution_mappings,
+            None,
+            None,
+            &tmp_context,
+        )
+        .await?;
+    solution_mappings.mappings = solution_mappings
+        .mappings
+        .filter(col(tmp_context.as_str()))
+        .drop_columns([tmp_context.as_str()]);
+    Ok(solution_mappings.mappings.collect().unwrap())
+}
+
+pub(crate) async fn group(
+    df: DataFrame,
+    grouped: &GroupedTimeSeriesQuery,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let columns = df
+        .get_column_names()
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect();
+    let mut out_lf = df.lazy();
+
+    let mut aggregation_exprs = vec![];
+    let mut combiner = Combiner::new(
+        "".to_string(),
+        all_pushdowns(),
+        Box::new(InMemoryTimeseriesDatabase {
+            frames: Default::default(),
+        }),
+        vec![],
+        Default::default(),
+    );
+    let mut solution_mappings = SolutionMappings::new(out_lf, columns, HashMap::new());
+    for i in 0..grouped.aggregations.len() {
+        let (v, agg) = grouped.aggregations.get(i).unwrap();
+        let (new_solution_mappings, agg_expr, _) = combiner
+            .sparql_aggregate_expression_as_lazy_column_and_expression(
+                v,
+                agg,
+                solution_mappings,
+                &grouped
+                    .context
+                    .extension_with(PathEntry::GroupAggregation(i as u16)),
+            )
+            .await?;
+        solution_mappings = new_solution_mappings;
+        aggregation_exprs.push(agg_expr);
+    }
+    let mut groupby = vec![col(grouped.tsq.get_groupby_column().unwrap())];
+    let tsfuncs = grouped.tsq.get_timeseries_functions(&grouped.context);
+    for b in &grouped.by {
+        for (v, _) in &tsfuncs {
+            if b == *v {
+                groupby.push(col(v.as_str()));
+                break;
+            }
+        }
+    }
+
+    let grouped_lf = solution_mappings.mappings.groupby(groupby);
+    out_lf = grouped_lf.agg(aggregation_exprs.as_slice());
+
+    let collected = out_lf.collect()?;
+    Ok(collected)
+}
+
+pub(crate) fn synchronize(
+    mut dfs: Vec<DataFrame>,
+    synchronizers: &Vec<Synchronizer>,
+) -> Result<DataFrame, Box<dyn Error>> {
+    assert_eq!(synchronizers.len(), 1);
+    #[allow(irrefutable_let_patterns)]
+    if let Synchronizer::Identity(timestamp_col) = synchronizers.get(0).unwrap() {
+        let mut on = vec![timestamp_col.clone()];
+        for df in &dfs {
+            for c in df.get_column_names() {
+                if c.starts_with(GROUPING_COL) {
+                    let c_string = c.to_string();
+                    if !on.contains(&c_string) {
+                        on.push(c_string);
                     }
                 }
-                dfs.push(df);
             }
-            let mut first_df = dfs.remove(0);
-            for df in dfs.into_iter() {
-                first_df =
-                    first_df.join(&df, on.as_slice(), on.as_slice(), JoinArgs::new(JoinType::Inner))?;
-            }
-            Ok(first_df)
-        } else {
-            todo!()
         }
+        let mut first_df = dfs.remove(0);
+        for df in dfs.into_iter() {
+            first_df = first_df.join(
+                &df,
+                on.as_slice(),
+                on.as_slice(),
+                JoinArgs::new(JoinType::Inner),
+            )?;
+        }
+        Ok(first_df)
+    } else {
+        todo!()
     }
 }
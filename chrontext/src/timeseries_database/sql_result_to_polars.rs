@@ -0,0 +1,316 @@
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::route_basic_query;
+use crate::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+use crate::timeseries_query::{BasicTimeSeriesQuery, TimeSeriesQuery};
+use oxrdf::vocab::xsd;
+use oxrdf::NamedNodeRef;
+use polars::export::chrono::NaiveDateTime;
+use polars::frame::DataFrame;
+use polars_core::prelude::{DataType, PolarsError, TimeUnit};
+use polars_core::series::Series;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+const TIMESTAMP_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+
+#[derive(Debug)]
+pub enum SQLResultConversionError {
+    PolarsError(PolarsError),
+    UnsupportedValueType(String, String),
+    MixedColumnTypes(String),
+    TimestampParseError(String, String),
+    IntegerOutOfRange(String, String),
+}
+
+impl Display for SQLResultConversionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SQLResultConversionError::PolarsError(err) => {
+                write!(f, "Problem creating dataframe from query result: {}", err)
+            }
+            SQLResultConversionError::UnsupportedValueType(c, t) => {
+                write!(f, "Column {} has unsupported value type {}", c, t)
+            }
+            SQLResultConversionError::MixedColumnTypes(c) => {
+                write!(f, "Column {} contains values of incompatible types", c)
+            }
+            SQLResultConversionError::TimestampParseError(c, v) => {
+                write!(f, "Could not parse timestamp {} in column {}", v, c)
+            }
+            SQLResultConversionError::IntegerOutOfRange(c, v) => {
+                write!(
+                    f,
+                    "Value {} in column {} does not fit in a 64 bit integer",
+                    v, c
+                )
+            }
+        }
+    }
+}
+
+impl Error for SQLResultConversionError {}
+
+//Types of the columns we know from the time series query and the tables,
+// used for timestamps stored as text, for values that are all integral or null,
+// and for columns without any values
+pub(crate) fn expected_column_types(
+    tsq: &TimeSeriesQuery,
+    tables: &Vec<TimeSeriesTable>,
+) -> HashMap<String, DataType> {
+    let mut column_types = HashMap::new();
+    add_value_column_types(tsq, tables, &mut column_types);
+    for v in tsq.get_identifier_variables() {
+        column_types.insert(v.as_str().to_string(), DataType::Utf8);
+    }
+    for v in tsq.get_timestamp_variables() {
+        column_types.insert(
+            v.variable.as_str().to_string(),
+            DataType::Datetime(TimeUnit::Nanoseconds, None),
+        );
+    }
+    if let Some(c) = tsq.get_groupby_column() {
+        column_types.insert(c.clone(), DataType::Int64);
+    }
+    column_types
+}
+
+fn add_value_column_types(
+    tsq: &TimeSeriesQuery,
+    tables: &Vec<TimeSeriesTable>,
+    column_types: &mut HashMap<String, DataType>,
+) {
+    match tsq {
+        TimeSeriesQuery::Basic(b) | TimeSeriesQuery::GroupedBasic(b, ..) => {
+            if let Some((name, data_type)) = value_column_type(b, tables) {
+                column_types.insert(name, data_type);
+            }
+        }
+        TimeSeriesQuery::Filtered(inner, _)
+        | TimeSeriesQuery::ExpressionAs(inner, ..)
+        | TimeSeriesQuery::Limited(inner, _) => add_value_column_types(inner, tables, column_types),
+        TimeSeriesQuery::InnerSynchronized(inners, _) => {
+            for inner in inners {
+                add_value_column_types(inner, tables, column_types);
+            }
+        }
+        TimeSeriesQuery::Grouped(grouped) => {
+            add_value_column_types(&grouped.tsq, tables, column_types)
+        }
+    }
+}
+
+//The value type of the tables the query is routed to, if they agree on it
+fn value_column_type(
+    btsq: &BasicTimeSeriesQuery,
+    tables: &Vec<TimeSeriesTable>,
+) -> Option<(String, DataType)> {
+    let value_variable = btsq.value_variable.as_ref()?;
+    let routed = route_basic_query(tables, btsq).ok()?;
+    let mut data_types = routed
+        .iter()
+        .map(|(table, _)| sql_value_type(table.value_datatype.as_ref()));
+    let data_type = data_types.next()??;
+    if data_types.all(|x| x.as_ref() == Some(&data_type)) {
+        Some((value_variable.variable.as_str().to_string(), data_type))
+    } else {
+        None
+    }
+}
+
+//The types of the SQL values, so integer types are read as 64 bit integers
+fn sql_value_type(datatype: NamedNodeRef) -> Option<DataType> {
+    let data_type = match datatype {
+        xsd::BOOLEAN => DataType::Boolean,
+        xsd::BYTE
+        | xsd::UNSIGNED_BYTE
+        | xsd::SHORT
+        | xsd::UNSIGNED_SHORT
+        | xsd::INT
+        | xsd::UNSIGNED_INT
+        | xsd::LONG
+        | xsd::UNSIGNED_LONG
+        | xsd::INTEGER => DataType::Int64,
+        xsd::FLOAT | xsd::DOUBLE | xsd::DECIMAL => DataType::Float64,
+        xsd::STRING => DataType::Utf8,
+        _ => return None,
+    };
+    Some(data_type)
+}
+
+pub(crate) fn columns_to_dataframe(
+    column_names: &Vec<String>,
+    columns: Vec<ColumnValues>,
+    column_types: &HashMap<String, DataType>,
+) -> Result<DataFrame, SQLResultConversionError> {
+    let mut series_vec = vec![];
+    for (name, column) in column_names.iter().zip(columns.into_iter()) {
+        series_vec.push(column.into_series(name, column_types.get(name))?);
+    }
+    DataFrame::new(series_vec).map_err(SQLResultConversionError::PolarsError)
+}
+
+pub(crate) enum SQLValue {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    //Nanoseconds since the epoch
+    Timestamp(i64),
+}
+
+pub(crate) enum ColumnValues {
+    Null(usize),
+    Boolean(Vec<Option<bool>>),
+    Integer(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Text(Vec<Option<String>>),
+    Timestamp(Vec<Option<i64>>),
+}
+
+impl ColumnValues {
+    pub(crate) fn push(
+        &mut self,
+        column_name: &str,
+        value: SQLValue,
+        is_timestamp: bool,
+    ) -> Result<(), SQLResultConversionError> {
+        //Text based timestamps are e.g. the default in SQLite
+        let value = match value {
+            SQLValue::Text(s) if is_timestamp => {
+                SQLValue::Timestamp(parse_timestamp(column_name, &s)?)
+            }
+            v => v,
+        };
+        if let ColumnValues::Null(n) = self {
+            let n = *n;
+            match &value {
+                SQLValue::Null => {}
+                SQLValue::Boolean(_) => *self = ColumnValues::Boolean(vec![None; n]),
+                SQLValue::Integer(_) => *self = ColumnValues::Integer(vec![None; n]),
+                SQLValue::Float(_) => *self = ColumnValues::Float(vec![None; n]),
+                SQLValue::Text(_) => *self = ColumnValues::Text(vec![None; n]),
+                SQLValue::Timestamp(_) => *self = ColumnValues::Timestamp(vec![None; n]),
+            }
+        }
+        //Mixing integers and floats promotes the column to floats
+        if let (ColumnValues::Integer(ints), SQLValue::Float(_)) = (&self, &value) {
+            *self = ColumnValues::Float(ints.iter().map(|i| i.map(|i| i as f64)).collect());
+        }
+        match (self, value) {
+            (ColumnValues::Null(n), SQLValue::Null) => *n += 1,
+            (ColumnValues::Boolean(v), SQLValue::Null) => v.push(None),
+            (ColumnValues::Integer(v), SQLValue::Null) => v.push(None),
+            (ColumnValues::Float(v), SQLValue::Null) => v.push(None),
+            (ColumnValues::Text(v), SQLValue::Null) => v.push(None),
+            (ColumnValues::Timestamp(v), SQLValue::Null) => v.push(None),
+            (ColumnValues::Boolean(v), SQLValue::Boolean(b)) => v.push(Some(b)),
+            (ColumnValues::Integer(v), SQLValue::Integer(i)) => v.push(Some(i)),
+            (ColumnValues::Float(v), SQLValue::Integer(i)) => v.push(Some(i as f64)),
+            (ColumnValues::Float(v), SQLValue::Float(f)) => v.push(Some(f)),
+            (ColumnValues::Text(v), SQLValue::Text(s)) => v.push(Some(s)),
+            (ColumnValues::Timestamp(v), SQLValue::Timestamp(t)) => v.push(Some(t)),
+            _ => {
+                return Err(SQLResultConversionError::MixedColumnTypes(
+                    column_name.to_string(),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn into_series(
+        self,
+        name: &str,
+        data_type: Option<&DataType>,
+    ) -> Result<Series, SQLResultConversionError> {
+        let series = match self {
+            ColumnValues::Null(n) => {
+                Series::full_null(name, n, data_type.unwrap_or(&DataType::Null))
+            }
+            ColumnValues::Boolean(v) => Series::new(name, v),
+            ColumnValues::Integer(v) => Series::new(name, v),
+            ColumnValues::Float(v) => Series::new(name, v),
+            ColumnValues::Text(v) => Series::new(name, v),
+            ColumnValues::Timestamp(v) => Series::new(name, v)
+                .cast(&DataType::Datetime(TimeUnit::Nanoseconds, None))
+                .map_err(SQLResultConversionError::PolarsError)?,
+        };
+        //E.g. SQLite returns integral REAL values as integers
+        match data_type {
+            Some(data_type) if series.dtype() != data_type => series
+                .cast(data_type)
+                .map_err(SQLResultConversionError::PolarsError),
+            _ => Ok(series),
+        }
+    }
+}
+
+fn parse_timestamp(column_name: &str, s: &str) -> Result<i64, SQLResultConversionError> {
+    for f in TIMESTAMP_FORMATS {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, f) {
+            return Ok(dt.timestamp() * 1_000_000_000 + dt.timestamp_subsec_nanos() as i64);
+        }
+    }
+    Err(SQLResultConversionError::TimestampParseError(
+        column_name.to_string(),
+        s.to_string(),
+    ))
+}
+
+pub(crate) fn new_columns(column_names: &Vec<String>) -> Vec<ColumnValues> {
+    column_names.iter().map(|_| ColumnValues::Null(0)).collect()
+}
+
+pub(crate) fn is_timestamp_column(
+    column_types: &HashMap<String, DataType>,
+    column_name: &str,
+) -> bool {
+    matches!(column_types.get(column_name), Some(DataType::Datetime(..)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query_context::{Context, VariableInContext};
+    use crate::timeseries_database::sql_result_to_polars::{
+        columns_to_dataframe, expected_column_types, new_columns, SQLValue,
+    };
+    use crate::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+    use crate::timeseries_query::{BasicTimeSeriesQuery, TimeSeriesQuery};
+    use oxrdf::vocab::xsd;
+    use oxrdf::Variable;
+    use polars_core::prelude::DataType;
+
+    #[test]
+    fn test_value_columns_get_the_table_value_type() {
+        let tsq = TimeSeriesQuery::Basic(BasicTimeSeriesQuery {
+            value_variable: Some(VariableInContext::new(
+                Variable::new_unchecked("v"),
+                Context::new(),
+            )),
+            datatype: Some(xsd::DOUBLE.into_owned()),
+            ..BasicTimeSeriesQuery::new_empty()
+        });
+        let tables = vec![TimeSeriesTable::new(
+            "timeseries_double".into(),
+            "value".into(),
+            "timestamp".into(),
+            "dir3".into(),
+            xsd::DOUBLE.into_owned(),
+        )];
+        let mut column_types = expected_column_types(&tsq, &tables);
+        assert_eq!(column_types.get("v"), Some(&DataType::Float64));
+
+        //Integral REAL values and all null columns
+        let column_names = vec!["v".to_string(), "w".to_string()];
+        let mut columns = new_columns(&column_names);
+        columns[0].push("v", SQLValue::Integer(1), false).unwrap();
+        columns[0].push("v", SQLValue::Integer(2), false).unwrap();
+        columns[1].push("w", SQLValue::Null, false).unwrap();
+        columns[1].push("w", SQLValue::Null, false).unwrap();
+        column_types.insert("w".to_string(), DataType::Float64);
+        let df = columns_to_dataframe(&column_names, columns, &column_types).unwrap();
+        assert_eq!(df.column("v").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(df.column("w").unwrap().dtype(), &DataType::Float64);
+    }
+}
@@ -1,24 +1,39 @@
 mod expression_rewrite;
+mod identifiers;
+pub mod partition_scheme;
 mod partitioning_support;
+pub mod sql_dialect;
+pub mod table_routing;
+pub mod timestamp_encoding;
+mod wide_tables;
 
 use crate::timeseries_database::timeseries_sql_rewrite::expression_rewrite::SPARQLToSQLExpressionTransformer;
+use crate::timeseries_database::timeseries_sql_rewrite::partition_scheme::{
+    common_partition_kinds, remove_partition_columns, PartitionKind, PartitionScheme,
+};
 use crate::timeseries_database::timeseries_sql_rewrite::partitioning_support::add_partitioned_timestamp_conditions;
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_database::timeseries_sql_rewrite::table_routing::route_basic_query;
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::{
+    common_timestamp_encoding, query_timestamp_encoding, TimestampEncoding,
+};
+use crate::timeseries_database::timeseries_sql_rewrite::wide_tables::{
+    column, id_group_pairs, required_variable,
+};
 use crate::timeseries_query::{BasicTimeSeriesQuery, Synchronizer, TimeSeriesQuery};
 use oxrdf::{NamedNode, Variable};
-use polars_core::datatypes::AnyValue;
 use polars_core::frame::DataFrame;
-use sea_query::{Alias, BinOper, ColumnRef, JoinType, Order, Query, SelectStatement, SimpleExpr, TableRef};
-use sea_query::{Expr as SeaExpr, Iden, Value};
+use sea_query::Iden;
+use sea_query::{
+    Alias, BinOper, ColumnRef, JoinType, Order, Query, SelectStatement, SimpleExpr, TableRef,
+    UnionType,
+};
 use spargebra::algebra::{AggregateExpression, Expression};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Write};
 use std::rc::Rc;
 
-const YEAR_PARTITION_COLUMN_NAME: &str = "year_partition_column_name";
-const MONTH_PARTITION_COLUMN_NAME: &str = "month_partition_column_name";
-const DAY_PARTITION_COLUMN_NAME: &str = "day_partition_column_name";
-
 #[derive(Debug)]
 pub enum TimeSeriesQueryToSQLError {
     UnknownVariable(String),
@@ -26,6 +41,21 @@ pub enum TimeSeriesQueryToSQLError {
     FoundNonValueInInExpression,
     DatatypeNotSupported(String),
     MissingTimeseriesQueryDatatype,
+    UnknownTable(String),
+    MissingValueColumn(String, String),
+    NoValueColumns(String),
+    MissingQualityColumn(String),
+    IdentifierColumnsMismatch(String, String),
+    MissingIds(String),
+    MixedIdentifierTypes(String),
+    AmbiguousIdentifier(String),
+    AggregateNotSupported(String),
+    FunctionNotSupported(String),
+    ExpressionNotSupported(String),
+    InvalidLiteral(String),
+    MissingVariable(String),
+    MissingGroupingColumn(String),
+    InvalidGroupingValue(String),
 }
 
 impl Display for TimeSeriesQueryToSQLError {
@@ -46,6 +76,67 @@ impl Display for TimeSeriesQueryToSQLError {
             TimeSeriesQueryToSQLError::MissingTimeseriesQueryDatatype => {
                 write!(f, "Timeseries value datatype missing")
             }
+            TimeSeriesQueryToSQLError::UnknownTable(t) => {
+                write!(f, "Time series table {} is not configured", t)
+            }
+            TimeSeriesQueryToSQLError::MissingValueColumn(id, t) => {
+                write!(f, "Wide table {} has no value column for {}", t, id)
+            }
+            TimeSeriesQueryToSQLError::NoValueColumns(t) => {
+                write!(f, "Wide table {} has no value columns", t)
+            }
+            TimeSeriesQueryToSQLError::MissingQualityColumn(t) => {
+                write!(
+                    f,
+                    "Quality was queried, but table {} has no quality column",
+                    t
+                )
+            }
+            TimeSeriesQueryToSQLError::IdentifierColumnsMismatch(id, t) => {
+                write!(
+                    f,
+                    "External id {} does not match the identifier columns of table {}",
+                    id, t
+                )
+            }
+            TimeSeriesQueryToSQLError::MissingIds(t) => {
+                write!(
+                    f,
+                    "Table {} with composite identifiers requires the external ids",
+                    t
+                )
+            }
+            TimeSeriesQueryToSQLError::MixedIdentifierTypes(t) => {
+                write!(f, "External ids of table {} have different types", t)
+            }
+            TimeSeriesQueryToSQLError::AmbiguousIdentifier(id) => {
+                write!(f, "Several external ids have the string form {}", id)
+            }
+            TimeSeriesQueryToSQLError::AggregateNotSupported(a) => {
+                write!(f, "Aggregate {} can not be translated to SQL", a)
+            }
+            TimeSeriesQueryToSQLError::FunctionNotSupported(func) => {
+                write!(f, "Function {} can not be translated to SQL", func)
+            }
+            TimeSeriesQueryToSQLError::ExpressionNotSupported(e) => {
+                write!(f, "Expression {} can not be translated to SQL", e)
+            }
+            TimeSeriesQueryToSQLError::InvalidLiteral(l) => {
+                write!(f, "Invalid literal {}", l)
+            }
+            TimeSeriesQueryToSQLError::MissingVariable(role) => {
+                write!(f, "Time series query has no {} variable", role)
+            }
+            TimeSeriesQueryToSQLError::MissingGroupingColumn(c) => {
+                write!(f, "Grouping is missing column {}", c)
+            }
+            TimeSeriesQueryToSQLError::InvalidGroupingValue(v) => {
+                write!(
+                    f,
+                    "Grouping has values {}, expected a string id and an integer group",
+                    v
+                )
+            }
         }
     }
 }
@@ -86,31 +177,63 @@ impl Iden for Name {
 
 #[derive(Clone)]
 pub struct TimeSeriesTable {
+    pub iri: Option<String>,
     pub schema: Option<String>,
     pub time_series_table: String,
     pub value_column: String,
     pub timestamp_column: String,
+    //Native SQL timestamps if not set
+    pub timestamp_encoding: Option<TimestampEncoding>,
     pub identifier_column: String,
+    //Composite external ids have one part in each of these columns, replacing the identifier column
+    pub identifier_columns: Option<Vec<String>>,
     pub value_datatype: NamedNode,
     pub year_column: Option<String>,
     pub month_column: Option<String>,
     pub day_column: Option<String>,
+    //Replaces the year, month and day columns, e.g. for tables partitioned by hour or by date strings
+    pub partition_scheme: Option<PartitionScheme>,
+    pub quality_column: Option<String>,
+    pub identifier_prefixes: Option<Vec<String>>,
+    //Wide tables have a value column for each external id, instead of identifier and value columns
+    pub value_columns: Option<HashMap<String, String>>,
 }
 
 pub struct TimeSeriesQueryToSQLTransformer<'a> {
-    pub partition_support: bool,
+    pub partition_kinds: Vec<PartitionKind>,
+    pub timestamp_encoding: TimestampEncoding,
     pub tables: &'a Vec<TimeSeriesTable>,
+    pub dialect: SqlDialect,
 }
 
 impl TimeSeriesQueryToSQLTransformer<'_> {
-    pub fn new(tables: &Vec<TimeSeriesTable>) -> TimeSeriesQueryToSQLTransformer {
+    pub fn new(
+        tables: &Vec<TimeSeriesTable>,
+        dialect: SqlDialect,
+    ) -> TimeSeriesQueryToSQLTransformer {
         TimeSeriesQueryToSQLTransformer {
-            partition_support: check_partitioning_support(tables),
+            partition_kinds: common_partition_kinds(tables),
+            timestamp_encoding: common_timestamp_encoding(tables),
             tables,
+            dialect,
         }
     }
 
-    pub fn create_query(&self, tsq:&TimeSeriesQuery, project_date_partition:bool) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError>  {
+    pub fn create_query(
+        &self,
+        tsq: &TimeSeriesQuery,
+        project_date_partition: bool,
+    ) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError> {
+        let timestamp_encoding = query_timestamp_encoding(self.tables, tsq);
+        if timestamp_encoding != self.timestamp_encoding {
+            let transformer = TimeSeriesQueryToSQLTransformer {
+                partition_kinds: self.partition_kinds.clone(),
+                timestamp_encoding,
+                tables: self.tables,
+                dialect: self.dialect,
+            };
+            return transformer.create_query(tsq, project_date_partition);
+        }
         let (mut select_statement, map) = self.create_query_nested(tsq, project_date_partition)?;
         let sort_col;
         if let Some(grcol) = tsq.get_groupby_column() {
@@ -120,11 +243,40 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
             assert_eq!(idvars.len(), 1);
             sort_col = idvars.get(0).unwrap().as_str().to_string();
         }
-        select_statement.order_by(ColumnRef::Column(Rc::new(Name::Column(sort_col))), Order::Asc);
+        select_statement.order_by(
+            ColumnRef::Column(Rc::new(Name::Column(sort_col))),
+            Order::Asc,
+        );
 
         Ok((select_statement, map))
     }
 
+    //Checks that the aggregations of grouped queries can be translated before they are pushed down
+    pub fn validate_aggregations(
+        &self,
+        tsq: &TimeSeriesQuery,
+    ) -> Result<(), TimeSeriesQueryToSQLError> {
+        match tsq {
+            TimeSeriesQuery::Basic(_) | TimeSeriesQuery::GroupedBasic(..) => Ok(()),
+            TimeSeriesQuery::Filtered(inner, _)
+            | TimeSeriesQuery::ExpressionAs(inner, ..)
+            | TimeSeriesQuery::Limited(inner, _) => self.validate_aggregations(inner),
+            TimeSeriesQuery::InnerSynchronized(inners, _) => {
+                for inner in inners {
+                    self.validate_aggregations(inner)?;
+                }
+                Ok(())
+            }
+            TimeSeriesQuery::Grouped(grouped) => {
+                let mut transformer = self.create_transformer(None);
+                for (_, agg) in &grouped.aggregations {
+                    transformer.sparql_aggregate_expression_to_sql_expression(agg)?;
+                }
+                self.validate_aggregations(&grouped.tsq)
+            }
+        }
+    }
+
     pub fn create_query_nested(
         &self,
         tsq: &TimeSeriesQuery,
@@ -145,8 +297,8 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
                     ),
                 )?;
 
-                let (select, mut columns) =
-                    self.create_query_nested(tsq, need_partition_columns || project_date_partition)?;
+                let (select, mut columns) = self
+                    .create_query_nested(tsq, need_partition_columns || project_date_partition)?;
 
                 let wraps_inner = if let TimeSeriesQuery::Basic(_) = **tsq {
                     true
@@ -159,9 +311,7 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
                     let mut outer_select = Query::select();
                     outer_select.from_subquery(select, Alias::new(alias));
                     if !project_date_partition && need_partition_columns {
-                        columns.remove(YEAR_PARTITION_COLUMN_NAME);
-                        columns.remove(MONTH_PARTITION_COLUMN_NAME);
-                        columns.remove(DAY_PARTITION_COLUMN_NAME);
+                        remove_partition_columns(&mut columns);
                     }
                     let mut sorted_cols: Vec<&String> = columns.iter().collect();
                     sorted_cols.sort();
@@ -188,12 +338,24 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
                         false
                     }
                 }) {
-                    let mut selects = vec![];
-                    for s in inner {
-                        selects.push(self.create_query_nested(s, true)?);
-                    }
                     let groupby_col = tsq.get_groupby_column().unwrap();
                     if let Some(Synchronizer::Identity(timestamp_col)) = &synchronizers.get(0) {
+                        if let Some(wide_select) = self.create_wide_synchronized(
+                            inner,
+                            timestamp_col,
+                            groupby_col,
+                            project_date_partition,
+                        )? {
+                            return Ok(wide_select);
+                        }
+                        //Partition columns are only joined on when all tables have them
+                        let mut selects = vec![];
+                        for s in inner {
+                            selects.push(self.create_query_nested(
+                                s,
+                                project_date_partition || self.partition_support(),
+                            )?);
+                        }
                         Ok(self.inner_join_selects(selects, timestamp_col, groupby_col))
                     } else {
                         panic!()
@@ -214,6 +376,12 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
             TimeSeriesQuery::ExpressionAs(tsq, v, e) => {
                 self.create_expression_as(tsq, project_date_partition, v, e)
             }
+            TimeSeriesQuery::Limited(tsq, limit) => {
+                let (mut select, columns) =
+                    self.create_query_nested(tsq, project_date_partition)?;
+                select.limit(*limit as u64);
+                Ok((select, columns))
+            }
         }
     }
 
@@ -234,17 +402,13 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
             project_date_partition || expr_transformer.used_partitioning,
         )?;
         if !project_date_partition && expr_transformer.used_partitioning {
-            columns.remove(YEAR_PARTITION_COLUMN_NAME);
-            columns.remove(MONTH_PARTITION_COLUMN_NAME);
-            columns.remove(DAY_PARTITION_COLUMN_NAME);
+            remove_partition_columns(&mut columns);
         }
 
         let mut expression_select = Query::select();
         expression_select.from_subquery(select, Alias::new(subquery_alias));
         if !project_date_partition && expr_transformer.used_partitioning {
-            columns.remove(YEAR_PARTITION_COLUMN_NAME);
-            columns.remove(MONTH_PARTITION_COLUMN_NAME);
-            columns.remove(DAY_PARTITION_COLUMN_NAME);
+            remove_partition_columns(&mut columns);
         }
 
         let mut sorted_cols: Vec<&String> = columns.iter().collect();
@@ -267,25 +431,9 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         df: &DataFrame,
         column_name: &String,
     ) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError> {
-        let mut value_tuples = vec![];
-        let identifier_colname = btsq.identifier_variable.as_ref().unwrap().as_str();
-        let mut identifier_iter = df.column(identifier_colname).unwrap().iter();
-        let mut groupcol_iter = df.column(&column_name).unwrap().iter();
-        for _ in 0..df.height() {
-            let id = identifier_iter.next().unwrap();
-            let grp = groupcol_iter.next().unwrap();
-            let id_value = if let AnyValue::Utf8(id_value) = id {
-                id_value.to_string()
-            } else {
-                panic!("Should never happen");
-            };
-            let grp_value = if let AnyValue::Int64(grp_value) = grp {
-                grp_value
-            } else {
-                panic!("Should never happen");
-            };
-            value_tuples.push((id_value, grp_value));
-        }
+        let value_tuples = id_group_pairs(btsq, df, column_name)?;
+        let identifier_colname =
+            required_variable(btsq.identifier_variable.as_ref(), "identifier")?;
 
         let mut static_select = Query::select();
         let mapping_values_alias = "mapping";
@@ -293,14 +441,14 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         static_select.expr_as(
             SimpleExpr::Column(ColumnRef::TableColumn(
                 Rc::new(Name::Table(mapping_values_alias.to_string())),
-                Rc::new(Name::Column("EXPR$0".to_string())),
+                Rc::new(Name::Column(self.dialect.values_column_name(0))),
             )),
-            Alias::new(identifier_colname),
+            Alias::new(&identifier_colname),
         );
         static_select.expr_as(
             SimpleExpr::Column(ColumnRef::TableColumn(
                 Rc::new(Name::Table(mapping_values_alias.to_string())),
-                Rc::new(Name::Column("EXPR$1".to_string())),
+                Rc::new(Name::Column(self.dialect.values_column_name(1))),
             )),
             Alias::new(column_name),
         );
@@ -331,7 +479,7 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         let mut sorted_cols: Vec<&String> = columns.iter().collect();
         sorted_cols.sort();
         for c in sorted_cols {
-            if c != identifier_colname {
+            if c != &identifier_colname {
                 joined_select.expr_as(
                     SimpleExpr::Column(ColumnRef::TableColumn(
                         Rc::new(Name::Table(basic_alias.to_string())),
@@ -341,7 +489,7 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
                 );
             }
         }
-        columns.remove(identifier_colname);
+        columns.remove(&identifier_colname);
 
         joined_select.expr_as(
             SimpleExpr::Column(ColumnRef::TableColumn(
@@ -360,10 +508,46 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         btsq: &BasicTimeSeriesQuery,
         project_date_partition: bool,
     ) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError> {
-        let table = self.find_right_table(btsq)?;
-        let (select, columns) = table.create_basic_query(btsq, project_date_partition)?;
+        //The selects of the routed tables are combined by a union,
+        // so they can only project the partition columns that all tables have
+        let partition_kinds: &[PartitionKind] = if project_date_partition {
+            &self.partition_kinds
+        } else {
+            &[]
+        };
+        let mut selects = vec![];
+        for (table, table_btsq) in route_basic_query(self.tables, btsq)? {
+            selects.push(table.create_basic_query(
+                &table_btsq,
+                partition_kinds,
+                &self.timestamp_encoding,
+                &self.dialect,
+            )?);
+        }
+        let (mut first_select, columns) = selects.remove(0);
+        if selects.is_empty() {
+            return Ok((first_select, columns));
+        }
 
-        Ok((select, columns))
+        //Columns are projected in the same order from each table
+        for (s, _) in selects {
+            first_select.union(UnionType::All, s);
+        }
+        let mut routed_select = Query::select();
+        let routed_name = "routed_query";
+        routed_select.from_subquery(first_select, Alias::new(routed_name));
+        let mut sorted_cols: Vec<&String> = columns.iter().collect();
+        sorted_cols.sort();
+        for c in sorted_cols {
+            routed_select.expr_as(
+                SimpleExpr::Column(ColumnRef::TableColumn(
+                    Rc::new(Name::Table(routed_name.to_string())),
+                    Rc::new(Name::Column(c.to_string())),
+                )),
+                Alias::new(c),
+            );
+        }
+        Ok((routed_select, columns))
     }
 
     fn inner_join_selects(
@@ -392,13 +576,10 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         for (i, (s, cols)) in selects_and_timestamp_cols.into_iter().enumerate() {
             let select_name = format!("other_{}", i);
             let mut conditions = vec![];
-            let col_conditions = [
-                groupby_col.clone(),
-                timestamp_col.clone(),
-                YEAR_PARTITION_COLUMN_NAME.to_string(),
-                MONTH_PARTITION_COLUMN_NAME.to_string(),
-                DAY_PARTITION_COLUMN_NAME.to_string(),
-            ];
+            let mut col_conditions = vec![groupby_col.clone(), timestamp_col.clone()];
+            for k in &self.partition_kinds {
+                col_conditions.push(k.alias().to_string());
+            }
             for c in col_conditions {
                 conditions.push(
                     SimpleExpr::Column(ColumnRef::TableColumn(
@@ -440,24 +621,6 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         (first_select, first_columns)
     }
 
-    fn find_right_table<'a>(
-        &'a self,
-        btsq: &BasicTimeSeriesQuery,
-    ) -> Result<&'a TimeSeriesTable, TimeSeriesQueryToSQLError> {
-        if let Some(b_datatype) = &btsq.datatype {
-            for table in self.tables {
-                if table.value_datatype.as_str() == b_datatype.as_str() {
-                    return Ok(table);
-                }
-            }
-            Err(TimeSeriesQueryToSQLError::DatatypeNotSupported(
-                b_datatype.as_str().to_string(),
-            ))
-        } else {
-            Err(TimeSeriesQueryToSQLError::MissingTimeseriesQueryDatatype)
-        }
-    }
-
     fn create_filter_expressions(
         &self,
         expression: &Expression,
@@ -466,13 +629,12 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         let mut transformer = self.create_transformer(None);
         let mut se = transformer.sparql_expression_to_sql_expression(expression)?;
         let mut partitioned = false;
-        if self.partition_support {
+        if self.partition_support() {
             let (se_part, part_status) = add_partitioned_timestamp_conditions(
                 se,
                 &timestamp_column.unwrap(),
-                YEAR_PARTITION_COLUMN_NAME,
-                MONTH_PARTITION_COLUMN_NAME,
-                DAY_PARTITION_COLUMN_NAME,
+                &self.partition_kinds,
+                &self.timestamp_encoding,
             );
             se = se_part;
             partitioned = part_status || transformer.used_partitioning;
@@ -506,9 +668,7 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
             agg_transformer.used_partitioning || project_date_partition,
         )?;
         if !project_date_partition && agg_transformer.used_partitioning {
-            columns.remove(YEAR_PARTITION_COLUMN_NAME);
-            columns.remove(MONTH_PARTITION_COLUMN_NAME);
-            columns.remove(DAY_PARTITION_COLUMN_NAME);
+            remove_partition_columns(&mut columns);
         }
         let mut inner_query = Query::select();
 
@@ -561,120 +721,187 @@ impl TimeSeriesQueryToSQLTransformer<'_> {
         &'a self,
         table_name: Option<&'a Name>,
     ) -> SPARQLToSQLExpressionTransformer {
-        if self.partition_support {
-            SPARQLToSQLExpressionTransformer::new(
-                table_name,
-                Some(YEAR_PARTITION_COLUMN_NAME),
-                Some(MONTH_PARTITION_COLUMN_NAME),
-                Some(DAY_PARTITION_COLUMN_NAME),
-            )
-        } else {
-            SPARQLToSQLExpressionTransformer::new(table_name, None, None, None)
-        }
+        let alias = |kind: PartitionKind| {
+            if self.partition_kinds.contains(&kind) {
+                Some(kind.alias())
+            } else {
+                None
+            }
+        };
+        SPARQLToSQLExpressionTransformer::new(
+            table_name,
+            alias(PartitionKind::Year),
+            alias(PartitionKind::Month),
+            alias(PartitionKind::Day),
+            &self.dialect,
+            &self.timestamp_encoding,
+        )
+    }
+
+    pub fn partition_support(&self) -> bool {
+        !self.partition_kinds.is_empty()
     }
 }
 
 impl TimeSeriesTable {
+    //A long table without schema, partitions, quality or prefixes, the optional fields can be set afterwards
+    pub fn new(
+        time_series_table: String,
+        value_column: String,
+        timestamp_column: String,
+        identifier_column: String,
+        value_datatype: NamedNode,
+    ) -> TimeSeriesTable {
+        TimeSeriesTable {
+            iri: None,
+            schema: None,
+            time_series_table,
+            value_column,
+            timestamp_column,
+            timestamp_encoding: None,
+            identifier_column,
+            identifier_columns: None,
+            value_datatype,
+            year_column: None,
+            month_column: None,
+            day_column: None,
+            partition_scheme: None,
+            quality_column: None,
+            identifier_prefixes: None,
+            value_columns: None,
+        }
+    }
+
     pub fn create_basic_query(
         &self,
         btsq: &BasicTimeSeriesQuery,
-        project_date_partition: bool,
+        partition_kinds: &[PartitionKind],
+        timestamp_encoding: &TimestampEncoding,
+        dialect: &SqlDialect,
     ) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError> {
+        if self.is_wide() {
+            return self.create_wide_basic_query(
+                btsq,
+                partition_kinds,
+                timestamp_encoding,
+                dialect,
+            );
+        }
         let mut basic_query = Query::select();
-        let mut variable_column_name_map = HashMap::new();
-        variable_column_name_map.insert(
+        let mut variable_expressions = HashMap::new();
+        variable_expressions.insert(
             btsq.identifier_variable
                 .as_ref()
                 .unwrap()
                 .as_str()
                 .to_string(),
-            self.identifier_column.clone(),
+            self.identifier_expression(btsq, dialect)?,
         );
-        variable_column_name_map.insert(
+        variable_expressions.insert(
             btsq.value_variable
                 .as_ref()
                 .unwrap()
                 .variable
                 .as_str()
                 .to_string(),
-            self.value_column.clone(),
+            column(&self.value_column),
         );
-        variable_column_name_map.insert(
+        variable_expressions.insert(
             btsq.timestamp_variable
                 .as_ref()
                 .unwrap()
                 .variable
                 .as_str()
                 .to_string(),
-            self.timestamp_column.clone(),
+            self.timestamp_expression(timestamp_encoding, dialect),
         );
-        let mut projection_column_name_map = HashMap::new();
-        if project_date_partition {
-            projection_column_name_map.insert(
-                YEAR_PARTITION_COLUMN_NAME.to_string(),
-                self.year_column.as_ref().unwrap().clone(),
-            );
-            projection_column_name_map.insert(
-                MONTH_PARTITION_COLUMN_NAME.to_string(),
-                self.month_column.as_ref().unwrap().clone(),
-            );
-            projection_column_name_map.insert(
-                DAY_PARTITION_COLUMN_NAME.to_string(),
-                self.day_column.as_ref().unwrap().clone(),
-            );
+        if let Some(quality_variable) = &btsq.quality_variable {
+            if let Some(quality_column) = &self.quality_column {
+                variable_expressions.insert(
+                    quality_variable.variable.as_str().to_string(),
+                    column(quality_column),
+                );
+            } else {
+                return Err(TimeSeriesQueryToSQLError::MissingQualityColumn(
+                    self.time_series_table.clone(),
+                ));
+            }
         }
         let mut columns = HashSet::new();
 
-        let mut kvs: Vec<_> = variable_column_name_map.iter().collect();
-        kvs.sort();
+        let mut kvs: Vec<_> = variable_expressions.into_iter().collect();
+        kvs.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
         for (k, v) in kvs {
-            basic_query.expr_as(SeaExpr::col(Name::Column(v.clone())), Alias::new(k));
-            columns.insert(k.clone());
+            basic_query.expr_as(v, Alias::new(&k));
+            columns.insert(k);
         }
+        self.add_partition_projections(&mut basic_query, &mut columns, partition_kinds, dialect);
+        self.add_from(&mut basic_query);
 
-        let mut kvs: Vec<_> = projection_column_name_map.iter().collect();
-        kvs.sort();
-        for (k, v) in kvs {
-            basic_query.expr_as(
-                SeaExpr::col(Name::Column(v.clone())).as_enum(Alias::new("INTEGER")),
-                Alias::new(k),
-            );
-            columns.insert(k.clone());
+        if let Some(ids) = &btsq.ids {
+            basic_query.and_where(self.identifier_condition(ids)?);
+        }
+
+        Ok((basic_query, columns))
+    }
+
+    fn add_partition_projections(
+        &self,
+        select: &mut SelectStatement,
+        columns: &mut HashSet<String>,
+        partition_kinds: &[PartitionKind],
+        dialect: &SqlDialect,
+    ) {
+        let scheme = if let Some(scheme) = self.get_partition_scheme() {
+            scheme
+        } else {
+            return;
+        };
+        let mut partition_columns: Vec<_> = scheme
+            .columns
+            .iter()
+            .filter(|x| partition_kinds.contains(&x.kind))
+            .collect();
+        partition_columns.sort_by_key(|x| x.kind.alias());
+        for c in partition_columns {
+            let partition_column = column(&c.column);
+            let se = if c.kind.is_integer() {
+                dialect.cast(partition_column, "INTEGER")
+            } else {
+                dialect.cast_to_string(partition_column)
+            };
+            select.expr_as(se, Alias::new(c.kind.alias()));
+            columns.insert(c.kind.alias().to_string());
         }
+    }
 
+    fn add_from(&self, select: &mut SelectStatement) {
         if let Some(schema) = &self.schema {
-            basic_query.from((
+            select.from((
                 Name::Schema(schema.clone()),
                 Name::Table(self.time_series_table.clone()),
             ));
         } else {
-            basic_query.from(Name::Table(self.time_series_table.clone()));
-        }
-
-        if let Some(ids) = &btsq.ids {
-            basic_query.and_where(
-                SeaExpr::col(Name::Column(self.identifier_column.clone())).is_in(
-                    ids.iter()
-                        .map(|x| Value::String(Some(Box::new(x.to_string())))),
-                ),
-            );
+            select.from(Name::Table(self.time_series_table.clone()));
         }
-
-        Ok((basic_query, columns))
     }
 }
 
-fn check_partitioning_support(tables: &Vec<TimeSeriesTable>) -> bool {
-    tables
-        .iter()
-        .all(|x| x.day_column.is_some() && x.month_column.is_some() && x.day_column.is_some())
-}
-
 #[cfg(test)]
 mod tests {
+    use crate::constants::{COMPOSITE_ID, DATETIME_AS_SECONDS};
+    use crate::external_ids::ExternalId;
     use crate::query_context::{Context, VariableInContext};
+    use crate::timeseries_database::timeseries_sql_rewrite::partition_scheme::{
+        PartitionColumn, PartitionKind, PartitionScheme,
+    };
+    use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+    use crate::timeseries_database::timeseries_sql_rewrite::table_routing::route_basic_query;
+    use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::{
+        normalize_timestamps, TimestampEncoding,
+    };
     use crate::timeseries_database::timeseries_sql_rewrite::{
-        TimeSeriesQueryToSQLTransformer, TimeSeriesTable,
+        TimeSeriesQueryToSQLError, TimeSeriesQueryToSQLTransformer, TimeSeriesTable,
     };
     use crate::timeseries_query::{
         BasicTimeSeriesQuery, GroupedTimeSeriesQuery, Synchronizer, TimeSeriesQuery,
@@ -682,15 +909,15 @@ mod tests {
     use oxrdf::vocab::xsd;
     use oxrdf::{Literal, NamedNode, Variable};
     use polars_core::frame::DataFrame;
-    use polars_core::prelude::NamedFrom;
+    use polars_core::prelude::{AnyValue, DataType, NamedFrom, TimeUnit};
     use polars_core::series::Series;
-    use sea_query::PostgresQueryBuilder;
     use spargebra::algebra::{AggregateExpression, Expression, Function};
+    use std::collections::HashMap;
     use std::vec;
 
-    #[test]
-    pub fn test_translate() {
-        let basic_tsq = BasicTimeSeriesQuery {
+    //Queries ?id, ?t and ?v of the given double valued time series
+    fn double_basic_tsq(ids: Vec<ExternalId>) -> BasicTimeSeriesQuery {
+        BasicTimeSeriesQuery {
             identifier_variable: Some(Variable::new_unchecked("id")),
             timeseries_variable: Some(VariableInContext::new(
                 Variable::new_unchecked("ts"),
@@ -710,8 +937,27 @@ mod tests {
                 Variable::new_unchecked("t"),
                 Context::new(),
             )),
-            ids: Some(vec!["A".to_string(), "B".to_string()]),
-        };
+            ids: Some(ids),
+            ..BasicTimeSeriesQuery::new_empty()
+        }
+    }
+
+    fn double_table(identifier_column: &str) -> TimeSeriesTable {
+        TimeSeriesTable::new(
+            "timeseries_double".into(),
+            "value".into(),
+            "timestamp".into(),
+            identifier_column.into(),
+            xsd::DOUBLE.into_owned(),
+        )
+    }
+
+    #[test]
+    pub fn test_translate() {
+        let basic_tsq = double_basic_tsq(vec![
+            ExternalId::String("A".to_string()),
+            ExternalId::String("B".to_string()),
+        ]);
         let tsq = TimeSeriesQuery::Filtered(
             Box::new(TimeSeriesQuery::Basic(basic_tsq)),
             Expression::LessOrEqual(
@@ -725,24 +971,103 @@ mod tests {
 
         let table = TimeSeriesTable {
             schema: Some("s3.ct-benchmark".into()),
-            time_series_table: "timeseries_double".into(),
-            value_column: "value".into(),
-            timestamp_column: "timestamp".into(),
-            identifier_column: "dir3".into(),
-            value_datatype: NamedNode::new_unchecked("http://www.w3.org/2001/XMLSchema#double"),
             year_column: Some("dir0".to_string()),
             month_column: Some("dir1".to_string()),
             day_column: Some("dir2".to_string()),
+            ..double_table("dir3")
         };
         let tables = vec![table];
-        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables);
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Dremio);
         let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
         assert_eq!(
-            &sql_query.to_string(PostgresQueryBuilder),
+            &SqlDialect::Dremio.build_query(&sql_query),
             r#"SELECT "id", "t", "v" FROM (SELECT "dir3" AS "id", "timestamp" AS "t", "value" AS "v", CAST("dir2" AS INTEGER) AS "day_partition_column_name", CAST("dir1" AS INTEGER) AS "month_partition_column_name", CAST("dir0" AS INTEGER) AS "year_partition_column_name" FROM "s3.ct-benchmark"."timeseries_double" WHERE "dir3" IN ('A', 'B')) AS "filtering_query" WHERE ("year_partition_column_name" < 2022) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" < 6)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 6) AND ("day_partition_column_name" < 1)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 6) AND ("day_partition_column_name" = 1) AND ("t" <= '2022-06-01 08:46:53')) ORDER BY "id" ASC"#
         );
     }
 
+    #[test]
+    pub fn test_translate_limited() {
+        let basic_tsq = double_basic_tsq(vec![
+            ExternalId::String("A".to_string()),
+            ExternalId::String("B".to_string()),
+        ]);
+        let tsq = TimeSeriesQuery::Limited(Box::new(TimeSeriesQuery::Basic(basic_tsq)), 100);
+
+        let table = TimeSeriesTable {
+            schema: Some("s3.ct-benchmark".into()),
+            ..double_table("dir3")
+        };
+        let tables = vec![table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Dremio);
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        assert_eq!(
+            &SqlDialect::Dremio.build_query(&sql_query),
+            r#"SELECT "dir3" AS "id", "timestamp" AS "t", "value" AS "v" FROM "s3.ct-benchmark"."timeseries_double" WHERE "dir3" IN ('A', 'B') ORDER BY "id" ASC LIMIT 100"#
+        );
+    }
+
+    #[test]
+    pub fn test_translate_postgres_dialect() {
+        let basic_tsq = double_basic_tsq(vec![
+            ExternalId::String("A".to_string()),
+            ExternalId::String("B".to_string()),
+        ]);
+        let tsq = TimeSeriesQuery::ExpressionAs(
+            Box::new(TimeSeriesQuery::Basic(basic_tsq)),
+            Variable::new_unchecked("s"),
+            Expression::FunctionCall(
+                Function::Custom(NamedNode::new_unchecked(DATETIME_AS_SECONDS)),
+                vec![Expression::Variable(Variable::new_unchecked("t"))],
+            ),
+        );
+
+        let table = double_table("id");
+        let tables = vec![table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        assert_eq!(
+            &SqlDialect::Postgres.build_query(&sql_query),
+            r#"SELECT "id" AS "id", "t" AS "t", "v" AS "v", date_part('epoch', "subquery"."t") AS "s" FROM (SELECT "id" AS "id", "timestamp" AS "t", "value" AS "v" FROM "timeseries_double" WHERE "id" IN ('A', 'B')) AS "subquery" ORDER BY "id" ASC"#
+        );
+    }
+
+    #[test]
+    pub fn test_translate_timescaledb_time_bucket() {
+        let basic_tsq = double_basic_tsq(vec![ExternalId::String("A".to_string())]);
+        let tsq = TimeSeriesQuery::ExpressionAs(
+            Box::new(TimeSeriesQuery::Basic(basic_tsq)),
+            Variable::new_unchecked("b"),
+            Expression::Multiply(
+                Box::new(Expression::Literal(Literal::new_typed_literal(
+                    "5",
+                    xsd::INTEGER,
+                ))),
+                Box::new(Expression::FunctionCall(
+                    Function::Floor,
+                    vec![Expression::Divide(
+                        Box::new(Expression::FunctionCall(
+                            Function::Custom(NamedNode::new_unchecked(DATETIME_AS_SECONDS)),
+                            vec![Expression::Variable(Variable::new_unchecked("t"))],
+                        )),
+                        Box::new(Expression::Literal(Literal::new_typed_literal(
+                            "5",
+                            xsd::INTEGER,
+                        ))),
+                    )],
+                )),
+            ),
+        );
+
+        let table = double_table("id");
+        let tables = vec![table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::TimescaleDB);
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        assert_eq!(
+            &SqlDialect::TimescaleDB.build_query(&sql_query),
+            r#"SELECT "id" AS "id", "t" AS "t", "v" AS "v", date_part('epoch', time_bucket(CAST('5 seconds' AS INTERVAL), "subquery"."t", CAST('1970-01-01' AS TIMESTAMP))) AS "b" FROM (SELECT "id" AS "id", "timestamp" AS "t", "value" AS "v" FROM "timeseries_double" WHERE "id" IN ('A')) AS "subquery" ORDER BY "id" ASC"#
+        );
+    }
+
     #[test]
     fn test_synchronized_grouped() {
         let tsq = TimeSeriesQuery::Grouped(GroupedTimeSeriesQuery {
@@ -785,7 +1110,10 @@ mod tests {
                                                             Context::new(),
                                                         ),
                                                     ),
-                                                    ids: Some(vec!["id1".to_string()]),
+                                                    ids: Some(vec![ExternalId::String(
+                                                        "id1".to_string(),
+                                                    )]),
+                                                    ..BasicTimeSeriesQuery::new_empty()
                                                 },
                                                 DataFrame::new(vec![
                                                     Series::new("ts_external_id_1", ["id1"]),
@@ -825,7 +1153,10 @@ mod tests {
                                                             Context::new(),
                                                         ),
                                                     ),
-                                                    ids: Some(vec!["id2".to_string()]),
+                                                    ids: Some(vec![ExternalId::String(
+                                                        "id2".to_string(),
+                                                    )]),
+                                                    ..BasicTimeSeriesQuery::new_empty()
                                                 },
                                                 DataFrame::new(vec![
                                                     Series::new("ts_external_id_2", ["id2"]),
@@ -935,20 +1266,709 @@ mod tests {
 
         let table = TimeSeriesTable {
             schema: Some("s3.ct-benchmark".into()),
-            time_series_table: "timeseries_double".into(),
-            value_column: "value".into(),
-            timestamp_column: "timestamp".into(),
-            identifier_column: "dir3".into(),
-            value_datatype: NamedNode::new_unchecked("http://www.w3.org/2001/XMLSchema#double"),
             year_column: Some("dir0".to_string()),
             month_column: Some("dir1".to_string()),
             day_column: Some("dir2".to_string()),
+            ..double_table("dir3")
         };
         let tables = vec![table];
-        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables);
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Dremio);
         let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
 
         let expected_str = r#"SELECT AVG("outer_query"."val_dir") AS "f7ca5ee9058effba8691ac9c642fbe95", AVG("outer_query"."val_speed") AS "990362f372e4019bc151c13baf0b50d5", "outer_query"."year" AS "year", "outer_query"."month" AS "month", "outer_query"."day" AS "day", "outer_query"."hour" AS "hour", "outer_query"."minute_10" AS "minute_10", "outer_query"."grouping_col_0" AS "grouping_col_0" FROM (SELECT "inner_query"."day" AS "day", "inner_query"."grouping_col_0" AS "grouping_col_0", "inner_query"."hour" AS "hour", "inner_query"."minute_10" AS "minute_10", "inner_query"."month" AS "month", "inner_query"."t" AS "t", "inner_query"."val_dir" AS "val_dir", "inner_query"."val_speed" AS "val_speed", "inner_query"."year" AS "year" FROM (SELECT "day" AS "day", "grouping_col_0" AS "grouping_col_0", "hour" AS "hour", "minute_10" AS "minute_10", "month" AS "month", "t" AS "t", "val_dir" AS "val_dir", "val_speed" AS "val_speed", "subquery"."year_partition_column_name" AS "year" FROM (SELECT "day" AS "day", "day_partition_column_name" AS "day_partition_column_name", "grouping_col_0" AS "grouping_col_0", "hour" AS "hour", "minute_10" AS "minute_10", "month_partition_column_name" AS "month_partition_column_name", "t" AS "t", "val_dir" AS "val_dir", "val_speed" AS "val_speed", "year_partition_column_name" AS "year_partition_column_name", "subquery"."month_partition_column_name" AS "month" FROM (SELECT "day_partition_column_name" AS "day_partition_column_name", "grouping_col_0" AS "grouping_col_0", "hour" AS "hour", "minute_10" AS "minute_10", "month_partition_column_name" AS "month_partition_column_name", "t" AS "t", "val_dir" AS "val_dir", "val_speed" AS "val_speed", "year_partition_column_name" AS "year_partition_column_name", "subquery"."day_partition_column_name" AS "day" FROM (SELECT "day_partition_column_name" AS "day_partition_column_name", "grouping_col_0" AS "grouping_col_0", "minute_10" AS "minute_10", "month_partition_column_name" AS "month_partition_column_name", "t" AS "t", "val_dir" AS "val_dir", "val_speed" AS "val_speed", "year_partition_column_name" AS "year_partition_column_name", date_part('hour', "subquery"."t") AS "hour" FROM (SELECT "day_partition_column_name" AS "day_partition_column_name", "grouping_col_0" AS "grouping_col_0", "month_partition_column_name" AS "month_partition_column_name", "t" AS "t", "val_dir" AS "val_dir", "val_speed" AS "val_speed", "year_partition_column_name" AS "year_partition_column_name", CAST(FLOOR(date_part('minute', "subquery"."t") / 10) AS INTEGER) AS "minute_10" FROM (SELECT "first_query"."day_partition_column_name" AS "day_partition_column_name", "first_query"."grouping_col_0" AS "grouping_col_0", "first_query"."month_partition_column_name" AS "month_partition_column_name", "first_query"."t" AS "t", "first_query"."val_speed" AS "val_speed", "first_query"."year_partition_column_name" AS "year_partition_column_name", "other_0"."day_partition_column_name" AS "day_partition_column_name", "other_0"."grouping_col_0" AS "grouping_col_0", "other_0"."month_partition_column_name" AS "month_partition_column_name", "other_0"."val_dir" AS "val_dir", "other_0"."year_partition_column_name" AS "year_partition_column_name" FROM (SELECT "basic_query"."day_partition_column_name" AS "day_partition_column_name", "basic_query"."month_partition_column_name" AS "month_partition_column_name", "basic_query"."t" AS "t", "basic_query"."val_speed" AS "val_speed", "basic_query"."year_partition_column_name" AS "year_partition_column_name", "static_query"."grouping_col_0" AS "grouping_col_0" FROM (SELECT "timestamp" AS "t", "dir3" AS "ts_external_id_1", "value" AS "val_speed", CAST("dir2" AS INTEGER) AS "day_partition_column_name", CAST("dir1" AS INTEGER) AS "month_partition_column_name", CAST("dir0" AS INTEGER) AS "year_partition_column_name" FROM "s3.ct-benchmark"."timeseries_double" WHERE "dir3" IN ('id1')) AS "basic_query" INNER JOIN (SELECT "mapping"."EXPR$0" AS "ts_external_id_1", "mapping"."EXPR$1" AS "grouping_col_0" FROM (VALUES ('id1', 0)) AS "mapping") AS "static_query" ON "static_query"."ts_external_id_1" = "basic_query"."ts_external_id_1") AS "first_query" INNER JOIN (SELECT "basic_query"."day_partition_column_name" AS "day_partition_column_name", "basic_query"."month_partition_column_name" AS "month_partition_column_name", "basic_query"."t" AS "t", "basic_query"."val_dir" AS "val_dir", "basic_query"."year_partition_column_name" AS "year_partition_column_name", "static_query"."grouping_col_0" AS "grouping_col_0" FROM (SELECT "timestamp" AS "t", "dir3" AS "ts_external_id_2", "value" AS "val_dir", CAST("dir2" AS INTEGER) AS "day_partition_column_name", CAST("dir1" AS INTEGER) AS "month_partition_column_name", CAST("dir0" AS INTEGER) AS "year_partition_column_name" FROM "s3.ct-benchmark"."timeseries_double" WHERE "dir3" IN ('id2')) AS "basic_query" INNER JOIN (SELECT "mapping"."EXPR$0" AS "ts_external_id_2", "mapping"."EXPR$1" AS "grouping_col_0" FROM (VALUES ('id2', 1)) AS "mapping") AS "static_query" ON "static_query"."ts_external_id_2" = "basic_query"."ts_external_id_2") AS "other_0" ON ("first_query"."grouping_col_0" = "other_0"."grouping_col_0") AND ("first_query"."t" = "other_0"."t") AND ("first_query"."year_partition_column_name" = "other_0"."year_partition_column_name") AND ("first_query"."month_partition_column_name" = "other_0"."month_partition_column_name") AND ("first_query"."day_partition_column_name" = "other_0"."day_partition_column_name") WHERE (("year_partition_column_name" > 2022) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" > 8)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 8) AND ("day_partition_column_name" > 30)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 8) AND ("day_partition_column_name" = 30) AND ("t" >= '2022-08-30 08:46:53'))) AND (("year_partition_column_name" < 2022) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" < 8)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 8) AND ("day_partition_column_name" < 30)) OR (("year_partition_column_name" = 2022) AND ("month_partition_column_name" = 8) AND ("day_partition_column_name" = 30) AND ("t" <= '2022-08-30 21:46:53')))) AS "subquery") AS "subquery") AS "subquery") AS "subquery") AS "subquery") AS "inner_query") AS "outer_query" GROUP BY "outer_query"."year", "outer_query"."month", "outer_query"."day", "outer_query"."hour", "outer_query"."minute_10", "outer_query"."grouping_col_0" ORDER BY "grouping_col_0" ASC"#;
-        assert_eq!(sql_query.to_string(PostgresQueryBuilder), expected_str);
+        assert_eq!(SqlDialect::Dremio.build_query(&sql_query), expected_str);
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let basic_tsq = double_basic_tsq(vec![ExternalId::String("A".to_string())]);
+        let v = || Box::new(Expression::Variable(Variable::new_unchecked("v")));
+        let grouped = |aggregation: AggregateExpression| {
+            TimeSeriesQuery::Grouped(GroupedTimeSeriesQuery {
+                context: Context::new(),
+                tsq: Box::new(TimeSeriesQuery::GroupedBasic(
+                    basic_tsq.clone(),
+                    DataFrame::new(vec![
+                        Series::new("id", ["A"]),
+                        Series::new("grouping_col_0", [0i64]),
+                    ])
+                    .unwrap(),
+                    "grouping_col_0".to_string(),
+                )),
+                by: vec![Variable::new_unchecked("grouping_col_0")],
+                aggregations: vec![(Variable::new_unchecked("agg"), aggregation)],
+            })
+        };
+
+        let table = double_table("id");
+        let tables = vec![table];
+        let cases = vec![
+            (
+                AggregateExpression::Count {
+                    expr: None,
+                    distinct: false,
+                },
+                "COUNT(*)",
+            ),
+            (
+                AggregateExpression::Count {
+                    expr: Some(v()),
+                    distinct: true,
+                },
+                r#"COUNT(DISTINCT "outer_query"."v")"#,
+            ),
+            (
+                AggregateExpression::GroupConcat {
+                    expr: v(),
+                    distinct: false,
+                    separator: Some(";".to_string()),
+                },
+                r#"STRING_AGG(CAST("outer_query"."v" AS VARCHAR), ';')"#,
+            ),
+            (
+                AggregateExpression::Sample {
+                    expr: v(),
+                    distinct: false,
+                },
+                r#"ANY_VALUE("outer_query"."v")"#,
+            ),
+            (
+                AggregateExpression::Custom {
+                    name: NamedNode::new_unchecked(
+                        "https://github.com/magbak/chrontext#percentile90",
+                    ),
+                    expr: v(),
+                    distinct: false,
+                },
+                r#"QUANTILE_CONT("outer_query"."v", 0.9)"#,
+            ),
+        ];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::DuckDB);
+        for (aggregation, expected) in cases {
+            let tsq = grouped(aggregation);
+            transformer.validate_aggregations(&tsq).unwrap();
+            let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+            let sql = SqlDialect::DuckDB.build_query(&sql_query);
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //SQLite has no median, so it should be computed by the combiner instead
+        let median = grouped(AggregateExpression::Custom {
+            name: NamedNode::new_unchecked("https://github.com/magbak/chrontext#median"),
+            expr: v(),
+            distinct: false,
+        });
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::SQLite);
+        assert!(transformer.validate_aggregations(&median).is_err());
+
+        //Groupings must map string ids to integer groups
+        let grouped_basic = |grouping: DataFrame| {
+            TimeSeriesQuery::GroupedBasic(basic_tsq.clone(), grouping, "grouping_col_0".to_string())
+        };
+        let invalid_grouping = grouped_basic(
+            DataFrame::new(vec![
+                Series::new("id", [1i64]),
+                Series::new("grouping_col_0", [0i64]),
+            ])
+            .unwrap(),
+        );
+        assert!(matches!(
+            transformer.create_query(&invalid_grouping, false),
+            Err(TimeSeriesQueryToSQLError::InvalidGroupingValue(_))
+        ));
+        let missing_column = grouped_basic(DataFrame::new(vec![Series::new("id", ["A"])]).unwrap());
+        assert!(matches!(
+            transformer.create_query(&missing_column, false),
+            Err(TimeSeriesQueryToSQLError::MissingGroupingColumn(_))
+        ));
+    }
+
+    #[test]
+    fn test_functions() {
+        let basic_tsq = BasicTimeSeriesQuery {
+            datatype: Some(xsd::STRING.into_owned()),
+            ..double_basic_tsq(vec![ExternalId::String("A".to_string())])
+        };
+        let v = || Expression::Variable(Variable::new_unchecked("v"));
+        let s = |x: &str| Expression::Literal(Literal::new_simple_literal(x));
+        let expression_as = |e: Expression| {
+            TimeSeriesQuery::ExpressionAs(
+                Box::new(TimeSeriesQuery::Basic(basic_tsq.clone())),
+                Variable::new_unchecked("e"),
+                e,
+            )
+        };
+
+        let table = TimeSeriesTable::new(
+            "timeseries_string".into(),
+            "value".into(),
+            "timestamp".into(),
+            "id".into(),
+            xsd::STRING.into_owned(),
+        );
+        let tables = vec![table];
+        let cases = vec![
+            (
+                Expression::FunctionCall(Function::StrLen, vec![v()]),
+                r#"LENGTH("subquery"."v") AS "e""#,
+            ),
+            (
+                Expression::FunctionCall(Function::Contains, vec![v(), s("ab")]),
+                r#"STRPOS("subquery"."v", 'ab') > 0"#,
+            ),
+            (
+                Expression::FunctionCall(Function::Regex, vec![v(), s("^a"), s("i")]),
+                r#"texticregexeq("subquery"."v", '^a') AS "e""#,
+            ),
+            (
+                Expression::If(
+                    Box::new(Expression::FunctionCall(
+                        Function::StrStarts,
+                        vec![v(), s("a")],
+                    )),
+                    Box::new(Expression::FunctionCall(Function::UCase, vec![v()])),
+                    Box::new(s("b")),
+                ),
+                r#"(CASE WHEN STRPOS("subquery"."v", 'a') = 1 THEN UPPER("subquery"."v") ELSE 'b' END) AS "e""#,
+            ),
+            (
+                Expression::Coalesce(vec![v(), s("c")]),
+                r#"COALESCE("subquery"."v", 'c') AS "e""#,
+            ),
+        ];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        for (e, expected) in cases {
+            assert!(SqlDialect::Postgres.capabilities().supports_expression(&e));
+            let (sql_query, _) = transformer.create_query(&expression_as(e), false).unwrap();
+            let sql = SqlDialect::Postgres.build_query(&sql_query);
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //Time zones are not pushed down, as the timestamps in time series tables have none
+        let tz = Expression::FunctionCall(Function::Tz, vec![v()]);
+        assert!(!SqlDialect::Postgres.capabilities().supports_expression(&tz));
+
+        //SQLite has no regular expressions, so they should be evaluated by the combiner instead
+        let regex = Expression::FunctionCall(Function::Regex, vec![v(), s("^a")]);
+        assert!(!SqlDialect::SQLite
+            .capabilities()
+            .supports_expression(&regex));
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::SQLite);
+        assert!(transformer
+            .create_query(&expression_as(regex), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_table_routing() {
+        let basic_tsq = BasicTimeSeriesQuery {
+            table_variable: Some(Variable::new_unchecked("tbl")),
+            tables: Some(HashMap::from([(
+                "B1".to_string(),
+                "https://example.com/other_table".to_string(),
+            )])),
+            ..double_basic_tsq(vec![
+                ExternalId::String("A1".to_string()),
+                ExternalId::String("B1".to_string()),
+                ExternalId::String("C1".to_string()),
+            ])
+        };
+        let table =
+            |name: &str, iri: Option<&str>, prefixes: Option<Vec<String>>| TimeSeriesTable {
+                iri: iri.map(|x| x.to_string()),
+                identifier_prefixes: prefixes,
+                ..TimeSeriesTable::new(
+                    name.into(),
+                    "value".into(),
+                    "timestamp".into(),
+                    "id".into(),
+                    xsd::DOUBLE.into_owned(),
+                )
+            };
+        let tables = vec![
+            table("default_table", None, None),
+            table("other_table", Some("https://example.com/other_table"), None),
+            table("a_table", None, Some(vec!["A".to_string()])),
+        ];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let (sql_query, _) = transformer
+            .create_query(&TimeSeriesQuery::Basic(basic_tsq.clone()), false)
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        //The annotated id goes to the annotated table, the prefix rule routes A1 and C1 falls back to the datatype
+        for expected in [
+            r#"FROM "default_table" WHERE "id" IN ('C1')"#,
+            r#"FROM "other_table" WHERE "id" IN ('B1')"#,
+            r#"FROM "a_table" WHERE "id" IN ('A1')"#,
+            "UNION ALL",
+            r#"AS "routed_query" ORDER BY "id" ASC"#,
+        ] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        let mut unknown_tsq = basic_tsq;
+        unknown_tsq.tables = Some(HashMap::from([(
+            "B1".to_string(),
+            "missing_table".to_string(),
+        )]));
+        assert!(transformer
+            .create_query(&TimeSeriesQuery::Basic(unknown_tsq), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_table_routing_with_different_partition_schemes() {
+        let basic_tsq = double_basic_tsq(vec![
+            ExternalId::String("A1".to_string()),
+            ExternalId::String("B1".to_string()),
+        ]);
+        let table = |name: &str, prefix: &str, kinds: Vec<PartitionKind>| TimeSeriesTable {
+            partition_scheme: Some(PartitionScheme::new(
+                kinds
+                    .into_iter()
+                    .map(|kind| PartitionColumn {
+                        kind,
+                        column: format!("{}_column", kind.alias()),
+                    })
+                    .collect(),
+            )),
+            identifier_prefixes: Some(vec![prefix.to_string()]),
+            ..TimeSeriesTable::new(
+                name.into(),
+                "value".into(),
+                "timestamp".into(),
+                "id".into(),
+                xsd::DOUBLE.into_owned(),
+            )
+        };
+
+        //The branches of the union must project the same columns
+        let tables = vec![
+            table(
+                "a_table",
+                "A",
+                vec![PartitionKind::Year, PartitionKind::Month],
+            ),
+            table("b_table", "B", vec![PartitionKind::Year]),
+        ];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let (sql_query, _) = transformer
+            .create_query(&TimeSeriesQuery::Basic(basic_tsq.clone()), true)
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        assert!(sql.contains("UNION ALL"), "{}", sql);
+        assert!(!sql.contains("partition_column_name"), "{}", sql);
+
+        let tables = vec![
+            table("a_table", "A", vec![PartitionKind::Year]),
+            table("b_table", "B", vec![PartitionKind::Year]),
+        ];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let (sql_query, _) = transformer
+            .create_query(&TimeSeriesQuery::Basic(basic_tsq), true)
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        assert_eq!(
+            sql.matches(r#"AS INTEGER) AS "year_partition_column_name""#)
+                .count(),
+            2,
+            "{}",
+            sql
+        );
+    }
+
+    #[test]
+    fn test_wide_table() {
+        let basic_tsq = |id_var: &str, value_var: &str, ids: Vec<&str>| BasicTimeSeriesQuery {
+            identifier_variable: Some(Variable::new_unchecked(id_var)),
+            value_variable: Some(VariableInContext::new(
+                Variable::new_unchecked(value_var),
+                Context::new(),
+            )),
+            ..double_basic_tsq(
+                ids.into_iter()
+                    .map(|x| ExternalId::String(x.to_string()))
+                    .collect(),
+            )
+        };
+        let table = TimeSeriesTable {
+            value_columns: Some(HashMap::from([
+                ("speed".to_string(), "speed_col".to_string()),
+                ("dir".to_string(), "dir_col".to_string()),
+            ])),
+            ..TimeSeriesTable::new(
+                "signals".into(),
+                "value".into(),
+                "timestamp".into(),
+                "id".into(),
+                xsd::DOUBLE.into_owned(),
+            )
+        };
+        let tables = vec![table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+
+        let (sql_query, _) = transformer
+            .create_query(
+                &TimeSeriesQuery::Basic(basic_tsq("id", "v", vec!["dir", "speed"])),
+                false,
+            )
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [
+            r#"SELECT 'dir' AS "id", "timestamp" AS "t", "dir_col" AS "v" FROM "signals""#,
+            r#"SELECT 'speed' AS "id", "timestamp" AS "t", "speed_col" AS "v" FROM "signals""#,
+            "UNION ALL",
+        ] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //Signals of the same wide table are synchronized without joins
+        let grouped_basic = |id_var: &str, value_var: &str, id: &str| {
+            Box::new(TimeSeriesQuery::GroupedBasic(
+                basic_tsq(id_var, value_var, vec![id]),
+                DataFrame::new(vec![
+                    Series::new(id_var, [id]),
+                    Series::new("grouping_col_0", [0i64]),
+                ])
+                .unwrap(),
+                "grouping_col_0".to_string(),
+            ))
+        };
+        let tsq = TimeSeriesQuery::Grouped(GroupedTimeSeriesQuery {
+            context: Context::new(),
+            tsq: Box::new(TimeSeriesQuery::InnerSynchronized(
+                vec![
+                    grouped_basic("id_speed", "val_speed", "speed"),
+                    grouped_basic("id_dir", "val_dir", "dir"),
+                ],
+                vec![Synchronizer::Identity("t".to_string())],
+            )),
+            by: vec![Variable::new_unchecked("grouping_col_0")],
+            aggregations: vec![(
+                Variable::new_unchecked("avg_dir"),
+                AggregateExpression::Avg {
+                    expr: Box::new(Expression::Variable(Variable::new_unchecked("val_dir"))),
+                    distinct: false,
+                },
+            )],
+        });
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        let expected = r#"SELECT 0 AS "grouping_col_0", "timestamp" AS "t", "speed_col" AS "val_speed", "dir_col" AS "val_dir" FROM "signals""#;
+        assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        assert!(!sql.contains("JOIN"), "{}", sql);
+
+        //Groupings that do not identify time series by string give an error
+        let invalid_grouped_basic = Box::new(TimeSeriesQuery::GroupedBasic(
+            basic_tsq("id_speed", "val_speed", vec!["speed"]),
+            DataFrame::new(vec![
+                Series::new("id_speed", [1i64]),
+                Series::new("grouping_col_0", [0i64]),
+            ])
+            .unwrap(),
+            "grouping_col_0".to_string(),
+        ));
+        let tsq = TimeSeriesQuery::InnerSynchronized(
+            vec![
+                invalid_grouped_basic,
+                grouped_basic("id_dir", "val_dir", "dir"),
+            ],
+            vec![Synchronizer::Identity("t".to_string())],
+        );
+        assert!(transformer.create_query(&tsq, false).is_err());
+
+        //A wide table without value columns gives an error
+        let mut empty_table = tables.get(0).unwrap().clone();
+        empty_table.value_columns = Some(HashMap::new());
+        let tables = vec![empty_table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        assert!(transformer
+            .create_query(&TimeSeriesQuery::Basic(basic_tsq("id", "v", vec![])), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_typed_and_composite_ids() {
+        let table = |identifier_columns: Option<Vec<String>>| TimeSeriesTable {
+            identifier_columns,
+            ..TimeSeriesTable::new(
+                "signals".into(),
+                "value".into(),
+                "timestamp".into(),
+                "id".into(),
+                xsd::DOUBLE.into_owned(),
+            )
+        };
+
+        //Integer ids are compared as integers and returned as strings
+        let tables = vec![table(None)];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let integer_ids = vec![
+            ExternalId::from_literal(&Literal::from(1i64)).unwrap(),
+            ExternalId::Integer(2),
+        ];
+        let (sql_query, _) = transformer
+            .create_query(
+                &TimeSeriesQuery::Basic(double_basic_tsq(integer_ids)),
+                false,
+            )
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [r#"AS TEXT) AS "id""#, r#"WHERE "id" IN (1, 2)"#] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        let tables = vec![table(Some(vec![
+            "asset_id".to_string(),
+            "tag_name".to_string(),
+        ]))];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let composite_id = ExternalId::from_literal(&Literal::new_typed_literal(
+            r#"["asset_1", "TT-101"]"#,
+            NamedNode::new_unchecked(COMPOSITE_ID),
+        ))
+        .unwrap();
+        assert_eq!(composite_id.to_string(), r#"["asset_1","TT-101"]"#);
+        let composite_ids = vec![
+            composite_id,
+            ExternalId::Composite(vec![
+                ExternalId::String("asset_2".to_string()),
+                ExternalId::Integer(7),
+            ]),
+        ];
+        let (sql_query, _) = transformer
+            .create_query(
+                &TimeSeriesQuery::Basic(double_basic_tsq(composite_ids)),
+                false,
+            )
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [
+            r#"WHEN "asset_id" = 'asset_1' AND "tag_name" = 'TT-101' THEN '["asset_1","TT-101"]'"#,
+            r#"WHEN "asset_id" = 'asset_2' AND "tag_name" = 7 THEN '["asset_2",7]'"#,
+            r#"END) AS "id""#,
+        ] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //Ids must have one part for each identifier column
+        assert!(transformer
+            .create_query(
+                &TimeSeriesQuery::Basic(double_basic_tsq(vec![ExternalId::String(
+                    "A".to_string()
+                )])),
+                false
+            )
+            .is_err());
+
+        //Ids of different types would be indistinguishable in the results
+        let tables = vec![table(None)];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let mixed_ids = vec![ExternalId::String("A".to_string()), ExternalId::Integer(1)];
+        assert!(matches!(
+            transformer.create_query(&TimeSeriesQuery::Basic(double_basic_tsq(mixed_ids)), false),
+            Err(TimeSeriesQueryToSQLError::MixedIdentifierTypes(_))
+        ));
+        let colliding_ids = vec![ExternalId::String("1".to_string()), ExternalId::Integer(1)];
+        assert!(matches!(
+            transformer.create_query(
+                &TimeSeriesQuery::Basic(double_basic_tsq(colliding_ids)),
+                false
+            ),
+            Err(TimeSeriesQueryToSQLError::AmbiguousIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_partition_scheme() {
+        let basic_tsq = |id_var: &str, value_var: &str, id: &str| BasicTimeSeriesQuery {
+            identifier_variable: Some(Variable::new_unchecked(id_var)),
+            value_variable: Some(VariableInContext::new(
+                Variable::new_unchecked(value_var),
+                Context::new(),
+            )),
+            ..double_basic_tsq(vec![ExternalId::String(id.to_string())])
+        };
+        let table = |partition_scheme: Option<PartitionScheme>| TimeSeriesTable {
+            partition_scheme,
+            ..TimeSeriesTable::new(
+                "signals".into(),
+                "value".into(),
+                "timestamp".into(),
+                "id".into(),
+                xsd::DOUBLE.into_owned(),
+            )
+        };
+
+        //Timestamps on the left hand side of comparisons are also used for pruning
+        let tables = vec![table(Some(PartitionScheme::new(vec![
+            PartitionColumn {
+                kind: PartitionKind::Date,
+                column: "dt".to_string(),
+            },
+            PartitionColumn {
+                kind: PartitionKind::Hour,
+                column: "hr".to_string(),
+            },
+        ])))];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let tsq = TimeSeriesQuery::Filtered(
+            Box::new(TimeSeriesQuery::Basic(basic_tsq("id", "v", "A"))),
+            Expression::LessOrEqual(
+                Box::new(Expression::Literal(Literal::new_typed_literal(
+                    "2023-04-01T10:30:00",
+                    xsd::DATE_TIME,
+                ))),
+                Box::new(Expression::Variable(Variable::new_unchecked("t"))),
+            ),
+        );
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [
+            r#"CAST("dt" AS TEXT) AS "date_partition_column_name", CAST("hr" AS INTEGER) AS "hour_partition_column_name""#,
+            r#"WHERE ("date_partition_column_name" > '2023-04-01') OR (("date_partition_column_name" = '2023-04-01') AND ("hour_partition_column_name" > 10)) OR"#,
+        ] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //Synchronized queries only join on partition columns when the tables have them
+        let tables = vec![table(None)];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+        let grouped_basic = |id_var: &str, value_var: &str, id: &str| {
+            Box::new(TimeSeriesQuery::GroupedBasic(
+                basic_tsq(id_var, value_var, id),
+                DataFrame::new(vec![
+                    Series::new(id_var, [id]),
+                    Series::new("grouping_col_0", [0i64]),
+                ])
+                .unwrap(),
+                "grouping_col_0".to_string(),
+            ))
+        };
+        let tsq = TimeSeriesQuery::InnerSynchronized(
+            vec![
+                grouped_basic("id_speed", "val_speed", "speed"),
+                grouped_basic("id_dir", "val_dir", "dir"),
+            ],
+            vec![Synchronizer::Identity("t".to_string())],
+        );
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        assert!(sql.contains("INNER JOIN"), "{}", sql);
+        assert!(!sql.contains("partition_column_name"), "{}", sql);
+    }
+
+    #[test]
+    fn test_timestamp_encoding() {
+        let basic_tsq = double_basic_tsq(vec![ExternalId::String("A".to_string())]);
+        let table = TimeSeriesTable {
+            timestamp_encoding: Some(TimestampEncoding::EpochMilliseconds),
+            ..TimeSeriesTable::new(
+                "signals".into(),
+                "value".into(),
+                "timestamp".into(),
+                "id".into(),
+                xsd::DOUBLE.into_owned(),
+            )
+        };
+        let tables = vec![table];
+        let transformer = TimeSeriesQueryToSQLTransformer::new(&tables, SqlDialect::Postgres);
+
+        let after = |tsq: TimeSeriesQuery| {
+            TimeSeriesQuery::Filtered(
+                Box::new(tsq),
+                Expression::GreaterOrEqual(
+                    Box::new(Expression::Variable(Variable::new_unchecked("t"))),
+                    Box::new(Expression::Literal(Literal::new_typed_literal(
+                        "2022-06-01T08:46:53",
+                        xsd::DATE_TIME,
+                    ))),
+                ),
+            )
+        };
+
+        //Filters compare the stored epoch milliseconds
+        let tsq = after(TimeSeriesQuery::Basic(basic_tsq.clone()));
+        let (sql_query, _) = transformer.create_query(&tsq, false).unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [r#""timestamp" AS "t""#, r#"WHERE "t" >= 1654073213000"#] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+
+        //Expressions and aggregates may project the timestamps, so these are decoded first
+        let expression_tsq = TimeSeriesQuery::ExpressionAs(
+            Box::new(TimeSeriesQuery::Basic(basic_tsq.clone())),
+            Variable::new_unchecked("s"),
+            Expression::FunctionCall(
+                Function::Custom(NamedNode::new_unchecked(DATETIME_AS_SECONDS)),
+                vec![Expression::Variable(Variable::new_unchecked("t"))],
+            ),
+        );
+        let (sql_query, _) = transformer
+            .create_query(&after(expression_tsq), false)
+            .unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        for expected in [
+            r#"timezone('UTC', to_timestamp("timestamp" / "#,
+            r#"date_part('epoch', "subquery"."t")"#,
+        ] {
+            assert!(sql.contains(expected), "{} not in {}", expected, sql);
+        }
+        assert!(!sql.contains("1654073213000"), "{}", sql);
+        let grouped_tsq = TimeSeriesQuery::Grouped(GroupedTimeSeriesQuery {
+            context: Context::new(),
+            tsq: Box::new(TimeSeriesQuery::GroupedBasic(
+                basic_tsq.clone(),
+                DataFrame::new(vec![
+                    Series::new("id", ["A"]),
+                    Series::new("grouping_col_0", [0i64]),
+                ])
+                .unwrap(),
+                "grouping_col_0".to_string(),
+            )),
+            by: vec![Variable::new_unchecked("grouping_col_0")],
+            aggregations: vec![(
+                Variable::new_unchecked("first_t"),
+                AggregateExpression::Min {
+                    expr: Box::new(Expression::Variable(Variable::new_unchecked("t"))),
+                    distinct: false,
+                },
+            )],
+        });
+        let (sql_query, _) = transformer.create_query(&grouped_tsq, false).unwrap();
+        let sql = SqlDialect::Postgres.build_query(&sql_query);
+        assert!(
+            sql.contains(r#"timezone('UTC', to_timestamp("timestamp" / "#),
+            "{}",
+            sql
+        );
+
+        //Decoded timestamps are already native in the results
+        let native_df = DataFrame::new(vec![
+            Series::new("grouping_col_0", [0i64]),
+            Series::new("first_t", [1654073213000i64])
+                .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))
+                .unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(
+            normalize_timestamps(native_df.clone(), &grouped_tsq, &tables).unwrap(),
+            native_df
+        );
+
+        //Results are normalized to polars datetimes
+        let df = DataFrame::new(vec![
+            Series::new("id", ["A"]),
+            Series::new("t", [1654073213000i64]),
+            Series::new("v", [1.0]),
+        ])
+        .unwrap();
+        let df = normalize_timestamps(df, &TimeSeriesQuery::Basic(basic_tsq), &tables).unwrap();
+        let t = df.column("t").unwrap();
+        assert_eq!(t.dtype(), &DataType::Datetime(TimeUnit::Nanoseconds, None));
+        assert_eq!(
+            t.get(0).unwrap(),
+            AnyValue::Datetime(1654073213000000000, TimeUnit::Nanoseconds, &None)
+        );
     }
 }
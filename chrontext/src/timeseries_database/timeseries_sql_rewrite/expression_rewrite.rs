@@ -6,6 +6,10 @@ use spargebra::algebra::Expression;
 use std::rc::Rc;
 
 use crate::constants::DATETIME_AS_SECONDS;
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::{
+    coalesce, DatePart, SqlDialect,
+};
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::TimestampEncoding;
 use crate::timeseries_database::timeseries_sql_rewrite::{Name, TimeSeriesQueryToSQLError};
 
 pub mod aggregate_expressions;
@@ -15,6 +19,9 @@ pub(crate) struct SPARQLToSQLExpressionTransformer<'a> {
     year_col: Option<&'a str>,
     month_col: Option<&'a str>,
     day_col: Option<&'a str>,
+    dialect: &'a SqlDialect,
+    //Timestamp literals are compared with timestamps in the stored representation
+    timestamp_encoding: &'a TimestampEncoding,
     pub used_partitioning: bool,
 }
 
@@ -24,12 +31,16 @@ impl SPARQLToSQLExpressionTransformer<'_> {
         year_col: Option<&'a str>,
         month_col: Option<&'a str>,
         day_col: Option<&'a str>,
+        dialect: &'a SqlDialect,
+        timestamp_encoding: &'a TimestampEncoding,
     ) -> SPARQLToSQLExpressionTransformer<'a> {
         SPARQLToSQLExpressionTransformer {
             table_name,
             year_col,
             month_col,
             day_col,
+            dialect,
+            timestamp_encoding,
             used_partitioning: false,
         }
     }
@@ -61,7 +72,7 @@ impl SPARQLToSQLExpressionTransformer<'_> {
                         } else if let Ok(dt) = v.parse::<DateTime<Utc>>() {
                             Value::ChronoDateTimeUtc(Some(Box::new(dt)))
                         } else {
-                            todo!("Could not parse {}", v);
+                            return Err(TimeSeriesQueryToSQLError::InvalidLiteral(l.to_string()));
                         }
                     }
                     _ => {
@@ -70,7 +81,7 @@ impl SPARQLToSQLExpressionTransformer<'_> {
                         ));
                     }
                 };
-                SimpleExpr::Value(value)
+                SimpleExpr::Value(self.timestamp_encoding.encode_literal(value))
             }
             Expression::Variable(v) => simple_expr_from_column_name(&self.table_name, v.as_str()),
             Expression::And(left, right) => self
@@ -124,11 +135,17 @@ impl SPARQLToSQLExpressionTransformer<'_> {
             Expression::Subtract(left, right) => self
                 .sparql_expression_to_sql_expression(left)?
                 .sub(self.sparql_expression_to_sql_expression(right)?),
-            Expression::Multiply(left, right) => SimpleExpr::Binary(
-                Box::new(self.sparql_expression_to_sql_expression(left)?),
-                BinOper::Mul,
-                Box::new(self.sparql_expression_to_sql_expression(right)?),
-            ),
+            Expression::Multiply(left, right) => {
+                if let Some(bucket) = self.try_time_bucket(left, right)? {
+                    bucket
+                } else {
+                    SimpleExpr::Binary(
+                        Box::new(self.sparql_expression_to_sql_expression(left)?),
+                        BinOper::Mul,
+                        Box::new(self.sparql_expression_to_sql_expression(right)?),
+                    )
+                }
+            }
             Expression::Divide(left, right) => SimpleExpr::Binary(
                 Box::new(self.sparql_expression_to_sql_expression(left)?),
                 BinOper::Div,
@@ -178,61 +195,125 @@ impl SPARQLToSQLExpressionTransformer<'_> {
                             self.day_col.as_ref().unwrap(),
                         )
                     } else {
-                        let date_part_name = match f {
-                            spargebra::algebra::Function::Year => "year",
-                            spargebra::algebra::Function::Month => "month",
-                            spargebra::algebra::Function::Day => "day",
-                            spargebra::algebra::Function::Hours => "hour",
-                            spargebra::algebra::Function::Minutes => "minute",
-                            spargebra::algebra::Function::Seconds => "second",
+                        let date_part = match f {
+                            spargebra::algebra::Function::Year => DatePart::Year,
+                            spargebra::algebra::Function::Month => DatePart::Month,
+                            spargebra::algebra::Function::Day => DatePart::Day,
+                            spargebra::algebra::Function::Hours => DatePart::Hour,
+                            spargebra::algebra::Function::Minutes => DatePart::Minute,
+                            spargebra::algebra::Function::Seconds => DatePart::Second,
                             _ => {
                                 panic!("Cannot happen")
                             }
                         };
-                        SimpleExpr::FunctionCall(
-                            Function::Custom(Rc::new(Name::Function("date_part".to_string()))),
-                            vec![
-                                SimpleExpr::Value(Value::String(Some(Box::new(
-                                    date_part_name.to_string(),
-                                )))),
-                                mapped_e,
-                            ],
-                        )
+                        self.dialect
+                            .date_part(&date_part, self.decode_timestamp(mapped_e))
                     }
                 }
                 spargebra::algebra::Function::Custom(c) => {
                     let e = expressions.first().unwrap();
                     let mapped_e = self.sparql_expression_to_sql_expression(e)?;
                     if c.as_str() == DATETIME_AS_SECONDS {
-                        SimpleExpr::FunctionCall(
-                            Function::Custom(Rc::new(Name::Function("UNIX_TIMESTAMP".to_string()))),
-                            vec![
-                                mapped_e,
-                                SimpleExpr::Value(Value::String(Some(Box::new(
-                                    "YYYY-MM-DD HH:MI:SS.FFF".to_string(),
-                                )))),
-                            ],
-                        )
+                        self.dialect.epoch_seconds(self.decode_timestamp(mapped_e))
                     } else if c.as_str() == xsd::INTEGER.as_str() {
-                        SimpleExpr::AsEnum(
-                            Rc::new(Name::Table("INTEGER".to_string())),
-                            Box::new(mapped_e),
-                        )
+                        self.dialect.cast_to_integer(mapped_e)
+                    } else if c.as_str() == xsd::DOUBLE.as_str() {
+                        self.dialect.cast_to_double(mapped_e)
+                    } else if c.as_str() == xsd::STRING.as_str() {
+                        self.dialect.cast_to_string(mapped_e)
                     } else {
-                        todo!("Fix custom {}", c)
+                        return Err(TimeSeriesQueryToSQLError::FunctionNotSupported(
+                            f.to_string(),
+                        ));
                     }
                 }
                 _ => {
-                    todo!("{}", f)
+                    let mut args = vec![];
+                    for e in expressions {
+                        args.push(self.sparql_expression_to_sql_expression(e)?);
+                    }
+                    self.dialect.function_call(f, args).ok_or(
+                        TimeSeriesQueryToSQLError::FunctionNotSupported(f.to_string()),
+                    )?
                 }
             },
-            _ => {
-                unimplemented!("")
+            Expression::SameTerm(left, right) => self
+                .sparql_expression_to_sql_expression(left)?
+                .equals(self.sparql_expression_to_sql_expression(right)?),
+            Expression::If(condition, then, otherwise) => {
+                let condition = self.sparql_expression_to_sql_expression(condition)?;
+                let then = self.sparql_expression_to_sql_expression(then)?;
+                let otherwise = self.sparql_expression_to_sql_expression(otherwise)?;
+                self.dialect.if_then_else(condition, then, otherwise)
+            }
+            Expression::Coalesce(inner) => {
+                let mut args = vec![];
+                for e in inner {
+                    args.push(self.sparql_expression_to_sql_expression(e)?);
+                }
+                coalesce(args)
+            }
+            Expression::Bound(v) => {
+                SeaExpr::expr(simple_expr_from_column_name(&self.table_name, v.as_str()))
+                    .is_not_null()
+            }
+            //IRIs and graph patterns do not exist in the time series database
+            Expression::NamedNode(_) | Expression::Exists(_) => {
+                return Err(TimeSeriesQueryToSQLError::ExpressionNotSupported(
+                    e.to_string(),
+                ));
             }
         })
     }
 }
 
+impl SPARQLToSQLExpressionTransformer<'_> {
+    //Translates FLOOR(DateTimeAsSeconds(?t) / n) * n to native bucketing where the dialect has it
+    fn try_time_bucket(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Option<SimpleExpr>, TimeSeriesQueryToSQLError> {
+        let found = find_time_bucket(left, right).or_else(|| find_time_bucket(right, left));
+        if let Some((timestamp_expression, width)) = found {
+            let mapped_e = self.sparql_expression_to_sql_expression(timestamp_expression)?;
+            Ok(self
+                .dialect
+                .time_bucket(width, self.decode_timestamp(mapped_e)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    //Date functions need native timestamps
+    fn decode_timestamp(&self, e: SimpleExpr) -> SimpleExpr {
+        self.dialect.decode_timestamp(e, self.timestamp_encoding)
+    }
+}
+
+fn find_time_bucket<'a>(a: &'a Expression, b: &Expression) -> Option<(&'a Expression, f64)> {
+    if let (
+        Expression::FunctionCall(spargebra::algebra::Function::Floor, args),
+        Expression::Literal(multiplier),
+    ) = (a, b)
+    {
+        if let Some(Expression::Divide(left, right)) = args.first() {
+            if let (
+                Expression::FunctionCall(spargebra::algebra::Function::Custom(c), inner),
+                Expression::Literal(divisor),
+            ) = (left.as_ref(), right.as_ref())
+            {
+                if c.as_str() == DATETIME_AS_SECONDS && divisor == multiplier && inner.len() == 1 {
+                    if let Ok(width) = divisor.value().parse::<f64>() {
+                        return Some((inner.first().unwrap(), width));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
 fn simple_expr_from_column_name(table_name: &Option<&Name>, column_name: &str) -> SimpleExpr {
     if let Some(name) = table_name {
         SimpleExpr::Column(ColumnRef::TableColumn(
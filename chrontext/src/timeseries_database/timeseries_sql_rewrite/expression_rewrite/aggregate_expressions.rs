@@ -1,33 +1,50 @@
 use super::SPARQLToSQLExpressionTransformer;
+use crate::custom_aggregates::CustomAggregate;
 use crate::timeseries_database::timeseries_sql_rewrite::TimeSeriesQueryToSQLError;
-use sea_query::{Function, SimpleExpr};
+use sea_query::{ColumnRef, Function, SimpleExpr};
 use spargebra::algebra::AggregateExpression;
 
 impl SPARQLToSQLExpressionTransformer<'_> {
-    //TODO: Support distinct in aggregates.. how???
+    //Aggregates that cannot be expressed in the dialect give an error,
+    // so that they can be computed by the combiner instead
     pub(crate) fn sparql_aggregate_expression_to_sql_expression(
         &mut self,
         agg: &AggregateExpression,
     ) -> Result<SimpleExpr, TimeSeriesQueryToSQLError> {
         Ok(match agg {
-            AggregateExpression::Count { expr, distinct: _ } => {
+            AggregateExpression::Count { expr, distinct } => {
                 if let Some(some_expr) = expr {
+                    let arg = self.sparql_expression_to_sql_expression(some_expr)?;
                     SimpleExpr::FunctionCall(
                         Function::Count,
-                        vec![self.sparql_expression_to_sql_expression(some_expr)?],
+                        vec![self.dialect.with_distinct(arg, *distinct)],
+                    )
+                } else if !distinct {
+                    SimpleExpr::FunctionCall(
+                        Function::Count,
+                        vec![SimpleExpr::Column(ColumnRef::Asterisk)],
                     )
                 } else {
-                    todo!("")
+                    return Err(TimeSeriesQueryToSQLError::AggregateNotSupported(
+                        agg.to_string(),
+                    ));
                 }
             }
-            AggregateExpression::Sum { expr, distinct: _ } => SimpleExpr::FunctionCall(
-                Function::Sum,
-                vec![self.sparql_expression_to_sql_expression(expr)?],
-            ),
-            AggregateExpression::Avg { expr, distinct: _ } => SimpleExpr::FunctionCall(
-                Function::Avg,
-                vec![self.sparql_expression_to_sql_expression(expr)?],
-            ),
+            AggregateExpression::Sum { expr, distinct } => {
+                let arg = self.sparql_expression_to_sql_expression(expr)?;
+                SimpleExpr::FunctionCall(
+                    Function::Sum,
+                    vec![self.dialect.with_distinct(arg, *distinct)],
+                )
+            }
+            AggregateExpression::Avg { expr, distinct } => {
+                let arg = self.sparql_expression_to_sql_expression(expr)?;
+                SimpleExpr::FunctionCall(
+                    Function::Avg,
+                    vec![self.dialect.with_distinct(arg, *distinct)],
+                )
+            }
+            //Distinct does not change the minimum or the maximum
             AggregateExpression::Min { expr, distinct: _ } => SimpleExpr::FunctionCall(
                 Function::Min,
                 vec![self.sparql_expression_to_sql_expression(expr)?],
@@ -37,24 +54,35 @@ impl SPARQLToSQLExpressionTransformer<'_> {
                 vec![self.sparql_expression_to_sql_expression(expr)?],
             ),
             AggregateExpression::GroupConcat {
-                expr: _,
-                distinct: _,
-                separator: _,
+                expr,
+                distinct,
+                separator,
             } => {
-                todo!("")
+                //Same default separator as when the combiner computes the aggregate
+                let separator = separator.as_ref().map(|x| x.as_str()).unwrap_or("");
+                let arg = self.sparql_expression_to_sql_expression(expr)?;
+                self.dialect.string_agg(arg, separator, *distinct).ok_or(
+                    TimeSeriesQueryToSQLError::AggregateNotSupported(agg.to_string()),
+                )?
             }
-            AggregateExpression::Sample {
-                expr: _,
-                distinct: _,
-            } => {
-                todo!("")
+            AggregateExpression::Sample { expr, distinct: _ } => {
+                let arg = self.sparql_expression_to_sql_expression(expr)?;
+                self.dialect.sample(arg)
             }
             AggregateExpression::Custom {
-                expr: _,
-                distinct: _,
-                name: _,
+                expr,
+                distinct,
+                name,
             } => {
-                todo!("")
+                let custom = CustomAggregate::from_iri(name.as_str()).ok_or(
+                    TimeSeriesQueryToSQLError::AggregateNotSupported(agg.to_string()),
+                )?;
+                let arg = self.sparql_expression_to_sql_expression(expr)?;
+                self.dialect
+                    .custom_aggregate(&custom, arg, *distinct)
+                    .ok_or(TimeSeriesQueryToSQLError::AggregateNotSupported(
+                        agg.to_string(),
+                    ))?
             }
         })
     }
@@ -0,0 +1,113 @@
+use super::wide_tables::column;
+use super::{Name, TimeSeriesQueryToSQLError, TimeSeriesTable};
+use crate::external_ids::ExternalId;
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_query::BasicTimeSeriesQuery;
+use sea_query::{BinOper, Expr as SeaExpr, SimpleExpr, Value};
+
+impl TimeSeriesTable {
+    pub fn identifier_columns(&self) -> Vec<&String> {
+        if let Some(identifier_columns) = &self.identifier_columns {
+            identifier_columns.iter().collect()
+        } else {
+            vec![&self.identifier_column]
+        }
+    }
+
+    //The results identify time series by the string form of the external id,
+    // so composite ids are mapped from the identifier columns to this form
+    pub(crate) fn identifier_expression(
+        &self,
+        btsq: &BasicTimeSeriesQuery,
+        dialect: &SqlDialect,
+    ) -> Result<SimpleExpr, TimeSeriesQueryToSQLError> {
+        if self.identifier_columns.is_some() {
+            let ids = btsq.ids.as_ref().ok_or_else(|| {
+                TimeSeriesQueryToSQLError::MissingIds(self.time_series_table.clone())
+            })?;
+            let null = SimpleExpr::Value(Value::String(None));
+            if ids.is_empty() {
+                return Ok(null);
+            }
+            let mut branches = vec![];
+            for id in ids {
+                branches.push((
+                    self.id_condition(id)?,
+                    SimpleExpr::Value(Value::String(Some(Box::new(id.to_string())))),
+                ));
+            }
+            Ok(dialect.case_when(branches, null))
+        } else if btsq.ids.iter().flatten().all(|x| x.is_string()) {
+            Ok(column(&self.identifier_column))
+        } else {
+            Ok(dialect.cast_to_string(column(&self.identifier_column)))
+        }
+    }
+
+    pub(crate) fn identifier_condition(
+        &self,
+        ids: &Vec<ExternalId>,
+    ) -> Result<SimpleExpr, TimeSeriesQueryToSQLError> {
+        if self.identifier_columns.is_some() {
+            let mut condition = None;
+            for id in ids {
+                condition = Some(combine(condition, BinOper::Or, self.id_condition(id)?));
+            }
+            Ok(condition.unwrap_or(SimpleExpr::Value(Value::Bool(Some(false)))))
+        } else {
+            let mut values = vec![];
+            for id in ids {
+                values.push(sql_value(self.id_parts(id)?.get(0).unwrap()));
+            }
+            Ok(SeaExpr::col(Name::Column(self.identifier_column.clone())).is_in(values))
+        }
+    }
+
+    fn id_condition(&self, id: &ExternalId) -> Result<SimpleExpr, TimeSeriesQueryToSQLError> {
+        let mut condition = None;
+        for (c, p) in self
+            .identifier_columns()
+            .into_iter()
+            .zip(self.id_parts(id)?)
+        {
+            let equals = SimpleExpr::Binary(
+                Box::new(column(c)),
+                BinOper::Equal,
+                Box::new(SimpleExpr::Value(sql_value(p))),
+            );
+            condition = Some(combine(condition, BinOper::And, equals));
+        }
+        Ok(condition.unwrap())
+    }
+
+    fn id_parts<'a>(
+        &self,
+        id: &'a ExternalId,
+    ) -> Result<Vec<&'a ExternalId>, TimeSeriesQueryToSQLError> {
+        let parts = id.parts();
+        if parts.len() == self.identifier_columns().len() {
+            Ok(parts)
+        } else {
+            Err(TimeSeriesQueryToSQLError::IdentifierColumnsMismatch(
+                id.to_string(),
+                self.time_series_table.clone(),
+            ))
+        }
+    }
+}
+
+fn combine(left: Option<SimpleExpr>, op: BinOper, right: SimpleExpr) -> SimpleExpr {
+    if let Some(left) = left {
+        SimpleExpr::Binary(Box::new(left), op, Box::new(right))
+    } else {
+        right
+    }
+}
+
+fn sql_value(id: &ExternalId) -> Value {
+    match id {
+        ExternalId::String(s) => Value::String(Some(Box::new(s.clone()))),
+        ExternalId::Integer(i) => Value::BigInt(Some(*i)),
+        ExternalId::Composite(_) => Value::String(Some(Box::new(id.to_string()))),
+    }
+}
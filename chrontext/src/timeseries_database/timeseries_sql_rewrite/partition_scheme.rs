@@ -0,0 +1,256 @@
+use super::TimeSeriesTable;
+use polars_core::export::chrono::{
+    Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Weekday,
+};
+use sea_query::Value;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub struct UnknownPartitionKindError(String);
+
+impl Display for UnknownPartitionKindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown partition kind {}, expected one of year, month, day, hour, iso_year, iso_week, date, epoch_day",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownPartitionKindError {}
+
+//The part of the timestamp that is stored in a partition column
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PartitionKind {
+    Year,
+    Month,
+    Day,
+    Hour,
+    //ISO 8601 week based year and week number, used for weekly partitions
+    IsoYear,
+    IsoWeek,
+    //Date strings such as 2023-04-01
+    Date,
+    //Number of days since 1970-01-01
+    EpochDay,
+}
+
+impl FromStr for PartitionKind {
+    type Err = UnknownPartitionKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year" => Ok(PartitionKind::Year),
+            "month" => Ok(PartitionKind::Month),
+            "day" => Ok(PartitionKind::Day),
+            "hour" => Ok(PartitionKind::Hour),
+            "iso_year" => Ok(PartitionKind::IsoYear),
+            "iso_week" => Ok(PartitionKind::IsoWeek),
+            "date" => Ok(PartitionKind::Date),
+            "epoch_day" => Ok(PartitionKind::EpochDay),
+            _ => Err(UnknownPartitionKindError(s.to_string())),
+        }
+    }
+}
+
+impl PartitionKind {
+    const ALL: [PartitionKind; 8] = [
+        PartitionKind::Year,
+        PartitionKind::Month,
+        PartitionKind::Day,
+        PartitionKind::Hour,
+        PartitionKind::IsoYear,
+        PartitionKind::IsoWeek,
+        PartitionKind::Date,
+        PartitionKind::EpochDay,
+    ];
+
+    //Partition columns are projected with the same name from all tables
+    pub(crate) fn alias(&self) -> &'static str {
+        match self {
+            PartitionKind::Year => "year_partition_column_name",
+            PartitionKind::Month => "month_partition_column_name",
+            PartitionKind::Day => "day_partition_column_name",
+            PartitionKind::Hour => "hour_partition_column_name",
+            PartitionKind::IsoYear => "iso_year_partition_column_name",
+            PartitionKind::IsoWeek => "iso_week_partition_column_name",
+            PartitionKind::Date => "date_partition_column_name",
+            PartitionKind::EpochDay => "epoch_day_partition_column_name",
+        }
+    }
+
+    pub(crate) fn is_integer(&self) -> bool {
+        self != &PartitionKind::Date
+    }
+
+    //The value of the partition column for rows with this timestamp
+    pub(crate) fn value(&self, dt: &NaiveDateTime) -> Value {
+        match self {
+            PartitionKind::Year => Value::Int(Some(dt.year())),
+            PartitionKind::Month => Value::Int(Some(dt.month() as i32)),
+            PartitionKind::Day => Value::Int(Some(dt.day() as i32)),
+            PartitionKind::Hour => Value::Int(Some(dt.hour() as i32)),
+            PartitionKind::IsoYear => Value::Int(Some(dt.iso_week().year())),
+            PartitionKind::IsoWeek => Value::Int(Some(dt.iso_week().week() as i32)),
+            PartitionKind::Date => {
+                Value::String(Some(Box::new(dt.date().format("%Y-%m-%d").to_string())))
+            }
+            PartitionKind::EpochDay => Value::Int(Some(
+                dt.date().signed_duration_since(epoch()).num_days() as i32,
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionColumn {
+    pub kind: PartitionKind,
+    pub column: String,
+}
+
+//Partition columns are ordered from the coarsest to the finest, e.g. year, month, day,
+// so that comparing the partition values in this order is the same as comparing timestamps
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionScheme {
+    pub columns: Vec<PartitionColumn>,
+}
+
+impl PartitionScheme {
+    pub fn new(columns: Vec<PartitionColumn>) -> PartitionScheme {
+        PartitionScheme { columns }
+    }
+
+    pub fn kinds(&self) -> Vec<PartitionKind> {
+        self.columns.iter().map(|x| x.kind).collect()
+    }
+
+    //The timestamps that can be in a partition with the given values, as a half open interval
+    pub fn timestamp_interval(
+        &self,
+        values: &HashMap<PartitionKind, &str>,
+    ) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let integer = |k: PartitionKind| values.get(&k).and_then(|x| x.parse::<i64>().ok());
+        let date = if let Some(d) = values.get(&PartitionKind::Date) {
+            NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()
+        } else if let Some(n) = integer(PartitionKind::EpochDay) {
+            epoch().checked_add_signed(Duration::days(n))
+        } else if let (Some(y), Some(m), Some(d)) = (
+            integer(PartitionKind::Year),
+            integer(PartitionKind::Month),
+            integer(PartitionKind::Day),
+        ) {
+            NaiveDate::from_ymd_opt(y as i32, m as u32, d as u32)
+        } else {
+            None
+        };
+
+        let mut interval = if let Some(date) = date {
+            let start = date.and_hms_opt(0, 0, 0).unwrap();
+            if let Some(h) = integer(PartitionKind::Hour) {
+                let start = start + Duration::hours(h);
+                Some((start, start + Duration::hours(1)))
+            } else {
+                Some((start, start + Duration::days(1)))
+            }
+        } else if let (Some(y), Some(m)) =
+            (integer(PartitionKind::Year), integer(PartitionKind::Month))
+        {
+            let end = if m == 12 {
+                NaiveDate::from_ymd_opt(y as i32 + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(y as i32, m as u32 + 1, 1)
+            };
+            start_and_end(NaiveDate::from_ymd_opt(y as i32, m as u32, 1), end)
+        } else if let Some(y) = integer(PartitionKind::Year) {
+            start_and_end(
+                NaiveDate::from_ymd_opt(y as i32, 1, 1),
+                NaiveDate::from_ymd_opt(y as i32 + 1, 1, 1),
+            )
+        } else {
+            None
+        };
+
+        if let (Some(y), Some(w)) = (
+            integer(PartitionKind::IsoYear),
+            integer(PartitionKind::IsoWeek),
+        ) {
+            let week_start = NaiveDate::from_isoywd_opt(y as i32, w as u32, Weekday::Mon);
+            if let Some((start, end)) =
+                start_and_end(week_start, week_start.map(|x| x + Duration::days(7)))
+            {
+                interval = Some(if let Some((s, e)) = interval {
+                    (s.max(start), e.min(end))
+                } else {
+                    (start, end)
+                });
+            }
+        }
+        interval
+    }
+}
+
+impl TimeSeriesTable {
+    //The year, month and day columns are shorthand for a scheme with these partition columns
+    pub fn get_partition_scheme(&self) -> Option<PartitionScheme> {
+        if let Some(scheme) = &self.partition_scheme {
+            return Some(scheme.clone());
+        }
+        let mut columns = vec![];
+        for (kind, column) in [
+            (PartitionKind::Year, &self.year_column),
+            (PartitionKind::Month, &self.month_column),
+            (PartitionKind::Day, &self.day_column),
+        ] {
+            if let Some(column) = column {
+                columns.push(PartitionColumn {
+                    kind,
+                    column: column.clone(),
+                });
+            } else {
+                break;
+            }
+        }
+        if columns.is_empty() {
+            None
+        } else {
+            Some(PartitionScheme::new(columns))
+        }
+    }
+}
+
+//Partition columns can only be used when they are the same for all tables
+pub(crate) fn common_partition_kinds(tables: &Vec<TimeSeriesTable>) -> Vec<PartitionKind> {
+    let mut kinds = None;
+    for t in tables {
+        let table_kinds = t
+            .get_partition_scheme()
+            .map(|x| x.kinds())
+            .unwrap_or_default();
+        if kinds.as_ref().map_or(false, |x| x != &table_kinds) {
+            return vec![];
+        }
+        kinds = Some(table_kinds);
+    }
+    kinds.unwrap_or_default()
+}
+
+pub(crate) fn remove_partition_columns(columns: &mut HashSet<String>) {
+    for kind in PartitionKind::ALL {
+        columns.remove(kind.alias());
+    }
+}
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+fn start_and_end(
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    Some((start?.and_hms_opt(0, 0, 0)?, end?.and_hms_opt(0, 0, 0)?))
+}
@@ -1,29 +1,28 @@
+use super::partition_scheme::PartitionKind;
+use super::timestamp_encoding::TimestampEncoding;
 use super::Name;
 use log::debug;
-use polars_core::export::chrono::Datelike;
-use sea_query::{BinOper, ColumnRef, SimpleExpr, Value};
+use polars_core::export::chrono::NaiveDateTime;
+use sea_query::{BinOper, ColumnRef, SimpleExpr};
 use std::rc::Rc;
 
+//Adds conditions on the partition columns to comparisons with the timestamp column,
+// the partition kinds are ordered from the coarsest to the finest
 pub fn add_partitioned_timestamp_conditions(
     se: SimpleExpr,
     timestamp_col: &str,
-    year_col: &str,
-    month_col: &str,
-    day_col: &str,
+    partition_kinds: &[PartitionKind],
+    timestamp_encoding: &TimestampEncoding,
 ) -> (SimpleExpr, bool) {
     match se {
         SimpleExpr::Unary(op, inner) => {
             let (inner_rewrite, added) = add_partitioned_timestamp_conditions(
                 *inner,
                 timestamp_col,
-                year_col,
-                month_col,
-                day_col,
+                partition_kinds,
+                timestamp_encoding,
             );
-            (
-                SimpleExpr::Unary(op.clone(), Box::new(inner_rewrite)),
-                added,
-            )
+            (SimpleExpr::Unary(op, Box::new(inner_rewrite)), added)
         }
         SimpleExpr::FunctionCall(func, inner) => {
             let rewrites_and_added: Vec<(SimpleExpr, bool)> = inner
@@ -32,9 +31,8 @@ pub fn add_partitioned_timestamp_conditions(
                     add_partitioned_timestamp_conditions(
                         x,
                         timestamp_col,
-                        year_col,
-                        month_col,
-                        day_col,
+                        partition_kinds,
+                        timestamp_encoding,
                     )
                 })
                 .collect();
@@ -47,9 +45,8 @@ pub fn add_partitioned_timestamp_conditions(
             op,
             *right,
             timestamp_col,
-            year_col,
-            month_col,
-            day_col,
+            partition_kinds,
+            timestamp_encoding,
         ),
         _ => (se, false),
     }
@@ -60,9 +57,8 @@ fn rewrite_binary_expression(
     op: BinOper,
     right: SimpleExpr,
     timestamp_col: &str,
-    year_col: &str,
-    month_col: &str,
-    day_col: &str,
+    partition_kinds: &[PartitionKind],
+    timestamp_encoding: &TimestampEncoding,
 ) -> (SimpleExpr, bool) {
     let original = SimpleExpr::Binary(Box::new(left.clone()), op, Box::new(right.clone()));
     match op {
@@ -72,92 +68,34 @@ fn rewrite_binary_expression(
         BinOper::NotIn => {
             debug!("Binary not_in expression partition rewriting not supported yet")
         }
-        BinOper::Equal => {
-            if let Some(e) = oper_or_original(
-                &original,
-                &left,
-                &right,
-                BinOper::NotEqual,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
-            }
-        }
-        BinOper::NotEqual => {
-            if let Some(e) = oper_or_original(
-                &original,
-                &left,
-                &right,
-                BinOper::NotEqual,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
-            }
-        }
-        BinOper::SmallerThan => {
-            if let Some(e) = smaller_than_or_original(
-                &original,
-                &left,
-                &right,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
-            }
-        }
-        BinOper::GreaterThan => {
-            if let Some(e) = greater_than_or_original(
-                &original,
-                &left,
-                &right,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
-            }
-        }
-        BinOper::SmallerThanOrEqual => {
-            if let Some(e) = smaller_than_or_original(
-                &original,
-                &left,
-                &right,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
-            }
-        }
-        BinOper::GreaterThanOrEqual => {
-            if let Some(e) = greater_than_or_original(
-                &original,
-                &left,
-                &right,
-                timestamp_col,
-                year_col,
-                month_col,
-                day_col,
-            ) {
-                return (e, true);
+        BinOper::Equal
+        | BinOper::SmallerThan
+        | BinOper::SmallerThanOrEqual
+        | BinOper::GreaterThan
+        | BinOper::GreaterThanOrEqual => {
+            if let Some((op, dt)) =
+                timestamp_comparison(&left, op, &right, timestamp_col, timestamp_encoding)
+            {
+                return (
+                    partitioned_comparison(&original, op, &dt, partition_kinds),
+                    true,
+                );
             }
         }
         _ => {}
     };
-    let (left_part, left_added) =
-        add_partitioned_timestamp_conditions(left, timestamp_col, year_col, month_col, day_col);
-    let (right_part, right_added) =
-        add_partitioned_timestamp_conditions(right, timestamp_col, year_col, month_col, day_col);
+    let (left_part, left_added) = add_partitioned_timestamp_conditions(
+        left,
+        timestamp_col,
+        partition_kinds,
+        timestamp_encoding,
+    );
+    let (right_part, right_added) = add_partitioned_timestamp_conditions(
+        right,
+        timestamp_col,
+        partition_kinds,
+        timestamp_encoding,
+    );
 
     (
         SimpleExpr::Binary(Box::new(left_part), op, Box::new(right_part)),
@@ -165,430 +103,76 @@ fn rewrite_binary_expression(
     )
 }
 
-fn greater_than_or_original(
-    original: &SimpleExpr,
+//Finds comparisons between the timestamp column and a timestamp in the stored representation,
+// with the operator flipped if the timestamp is on the left side
+fn timestamp_comparison(
     left: &SimpleExpr,
+    op: BinOper,
     right: &SimpleExpr,
     timestamp_col: &str,
-    year_col: &str,
-    month_col: &str,
-    day_col: &str,
-) -> Option<SimpleExpr> {
-    if let SimpleExpr::Column(left_column) = left {
-        if let Some(colname) = find_colname(left_column) {
-            if timestamp_col == &colname {
-                if let SimpleExpr::Value(right_value) = right {
-                    if let Value::ChronoDateTime(Some(right_dt)) = right_value {
-                        let right_year = right_dt.year();
-                        let right_month = right_dt.month();
-                        let right_day = right_dt.day();
-                        let year_greater_than_year_expr = col_name_oper_const_num(
-                            year_col.to_string(),
-                            right_year,
-                            BinOper::GreaterThan,
-                        );
-                        let year_equal_and_month_greater_expr = SimpleExpr::Binary(
-                            Box::new(col_name_oper_const_num(
-                                year_col.to_string(),
-                                right_year,
-                                BinOper::Equal,
-                            )),
-                            BinOper::And,
-                            Box::new(col_name_oper_const_num(
-                                month_col.to_string(),
-                                right_month as i32,
-                                BinOper::GreaterThan,
-                            )),
-                        );
-                        let year_equal_and_month_equal_and_day_greater = SimpleExpr::Binary(
-                            Box::new(year_equal_and_month_equal(
-                                year_col.to_string(),
-                                right_year,
-                                month_col.to_string(),
-                                right_month,
-                            )),
-                            BinOper::And,
-                            Box::new(col_name_oper_const_num(
-                                day_col.to_string(),
-                                right_day as i32,
-                                BinOper::GreaterThan,
-                            )),
-                        );
-                        let year_equal_and_month_equal_and_day_equal_and_original =
-                            SimpleExpr::Binary(
-                                Box::new(year_equal_and_month_equal_and_day_equal(
-                                    year_col.to_string(),
-                                    right_year,
-                                    month_col.to_string(),
-                                    right_month,
-                                    day_col.to_string(),
-                                    right_day,
-                                )),
-                                BinOper::And,
-                                Box::new(original.clone()),
-                            );
-                        return Some(iterated_binoper(
-                            vec![
-                                year_greater_than_year_expr,
-                                year_equal_and_month_greater_expr,
-                                year_equal_and_month_equal_and_day_greater,
-                                year_equal_and_month_equal_and_day_equal_and_original,
-                            ],
-                            BinOper::Or,
-                        ));
-                    }
-                }
-            }
-        } else if let SimpleExpr::Value(left_value) = &left {
-            if let Value::ChronoDateTime(Some(left_dt)) = left_value {
-                if let SimpleExpr::Column(right_column) = &right {
-                    if let Some(colname) = find_colname(right_column) {
-                        if timestamp_col == &colname {
-                            let left_year = left_dt.year();
-                            let left_month = left_dt.month();
-                            let left_day = left_dt.day();
-                            let year_greater_than_year_expr = const_num_oper_col_name(
-                                left_year,
-                                year_col.to_string(),
-                                BinOper::GreaterThan,
-                            );
-                            let year_equal_and_month_greater_expr = SimpleExpr::Binary(
-                                Box::new(const_num_oper_col_name(
-                                    left_year,
-                                    year_col.to_string(),
-                                    BinOper::Equal,
-                                )),
-                                BinOper::And,
-                                Box::new(const_num_oper_col_name(
-                                    left_month as i32,
-                                    month_col.to_string(),
-                                    BinOper::GreaterThan,
-                                )),
-                            );
-                            let year_equal_and_month_equal_and_day_greater = SimpleExpr::Binary(
-                                Box::new(year_equal_and_month_equal(
-                                    year_col.to_string(),
-                                    left_year,
-                                    month_col.to_string(),
-                                    left_month,
-                                )),
-                                BinOper::And,
-                                Box::new(col_name_oper_const_num(
-                                    day_col.to_string(),
-                                    left_day as i32,
-                                    BinOper::GreaterThan,
-                                )),
-                            );
-                            let year_equal_and_month_equal_and_day_equal_and_original =
-                                SimpleExpr::Binary(
-                                    Box::new(year_equal_and_month_equal_and_day_equal(
-                                        year_col.to_string(),
-                                        left_year,
-                                        month_col.to_string(),
-                                        left_month,
-                                        day_col.to_string(),
-                                        left_day,
-                                    )),
-                                    BinOper::And,
-                                    Box::new(original.clone()),
-                                );
-                            return Some(iterated_binoper(
-                                vec![
-                                    year_greater_than_year_expr,
-                                    year_equal_and_month_greater_expr,
-                                    year_equal_and_month_equal_and_day_greater,
-                                    year_equal_and_month_equal_and_day_equal_and_original,
-                                ],
-                                BinOper::Or,
-                            ));
-                        }
-                    }
-                }
-            }
+    timestamp_encoding: &TimestampEncoding,
+) -> Option<(BinOper, NaiveDateTime)> {
+    match (left, right) {
+        (SimpleExpr::Column(c), SimpleExpr::Value(v))
+            if find_colname(c).as_deref() == Some(timestamp_col) =>
+        {
+            Some((op, timestamp_encoding.decode(v)?))
         }
-    }
-    None
-}
-
-//Used for equal/non equal
-fn oper_or_original(
-    original: &SimpleExpr,
-    left: &SimpleExpr,
-    right: &SimpleExpr,
-    oper: BinOper,
-    timestamp_col: &str,
-    year_col: &str,
-    month_col: &str,
-    day_col: &str,
-) -> Option<SimpleExpr> {
-    if let SimpleExpr::Column(left_column) = left {
-        if let Some(colname) = find_colname(left_column) {
-            if timestamp_col == &colname {
-                if let SimpleExpr::Value(right_value) = right {
-                    if let Value::ChronoDateTime(Some(right_dt)) = right_value {
-                        let right_year = right_dt.year();
-                        let right_month = right_dt.month();
-                        let right_day = right_dt.day();
-
-                        let year_not_equal =
-                            col_name_oper_const_num(year_col.to_string(), right_year, oper.clone());
-                        let month_not_equal = col_name_oper_const_num(
-                            month_col.to_string(),
-                            right_month as i32,
-                            oper.clone(),
-                        );
-                        let day_not_equal = col_name_oper_const_num(
-                            day_col.to_string(),
-                            right_day as i32,
-                            oper.clone(),
-                        );
-                        return Some(iterated_binoper(
-                            vec![
-                                year_not_equal,
-                                month_not_equal,
-                                day_not_equal,
-                                original.clone(),
-                            ],
-                            BinOper::Or,
-                        ));
-                    }
-                }
-            }
-        }
-    } else if let SimpleExpr::Value(left_value) = left {
-        if let Value::ChronoDateTime(Some(left_dt)) = left_value {
-            if let SimpleExpr::Column(right_column) = right {
-                if let Some(colname) = find_colname(right_column) {
-                    if timestamp_col == &colname {
-                        let left_year = left_dt.year();
-                        let left_month = left_dt.month();
-                        let left_day = left_dt.day();
-
-                        let year_not_equal =
-                            const_num_oper_col_name(left_year, year_col.to_string(), oper.clone());
-                        let month_not_equal = const_num_oper_col_name(
-                            left_month as i32,
-                            month_col.to_string(),
-                            oper.clone(),
-                        );
-                        let day_not_equal = const_num_oper_col_name(
-                            left_day as i32,
-                            day_col.to_string(),
-                            oper.clone(),
-                        );
-                        return Some(iterated_binoper(
-                            vec![
-                                year_not_equal,
-                                month_not_equal,
-                                day_not_equal,
-                                original.clone(),
-                            ],
-                            BinOper::Or,
-                        ));
-                    }
-                }
-            }
+        (SimpleExpr::Value(v), SimpleExpr::Column(c))
+            if find_colname(c).as_deref() == Some(timestamp_col) =>
+        {
+            let flipped = match op {
+                BinOper::SmallerThan => BinOper::GreaterThan,
+                BinOper::SmallerThanOrEqual => BinOper::GreaterThanOrEqual,
+                BinOper::GreaterThan => BinOper::SmallerThan,
+                BinOper::GreaterThanOrEqual => BinOper::SmallerThanOrEqual,
+                _ => op,
+            };
+            Some((flipped, timestamp_encoding.decode(v)?))
         }
+        _ => None,
     }
-    None
 }
 
-fn smaller_than_or_original(
+//The partition values are compared in order, and the original condition is only needed
+// when all the partition values are equal.
+fn partitioned_comparison(
     original: &SimpleExpr,
-    left: &SimpleExpr,
-    right: &SimpleExpr,
-    timestamp_col: &str,
-    year_col: &str,
-    month_col: &str,
-    day_col: &str,
-) -> Option<SimpleExpr> {
-    if let SimpleExpr::Column(left_column) = left {
-        if let Some(colname) = find_colname(left_column) {
-            if timestamp_col == &colname {
-                if let SimpleExpr::Value(right_value) = right {
-                    if let Value::ChronoDateTime(Some(right_dt)) = right_value {
-                        let right_year = right_dt.year();
-                        let right_month = right_dt.month();
-                        let right_day = right_dt.day();
-                        let year_smaller_than_year_expr = col_name_oper_const_num(
-                            year_col.to_string(),
-                            right_year,
-                            BinOper::SmallerThan,
-                        );
-                        let year_equal_and_month_smaller_expr = SimpleExpr::Binary(
-                            Box::new(col_name_oper_const_num(
-                                year_col.to_string(),
-                                right_year,
-                                BinOper::Equal,
-                            )),
-                            BinOper::And,
-                            Box::new(col_name_oper_const_num(
-                                month_col.to_string(),
-                                right_month as i32,
-                                BinOper::SmallerThan,
-                            )),
-                        );
-                        let year_equal_and_month_equal_and_day_smaller = SimpleExpr::Binary(
-                            Box::new(year_equal_and_month_equal(
-                                year_col.to_string(),
-                                right_year,
-                                month_col.to_string(),
-                                right_month,
-                            )),
-                            BinOper::And,
-                            Box::new(col_name_oper_const_num(
-                                day_col.to_string(),
-                                right_day as i32,
-                                BinOper::SmallerThan,
-                            )),
-                        );
-                        let year_equal_and_month_equal_and_day_equal_and_original =
-                            SimpleExpr::Binary(
-                                Box::new(year_equal_and_month_equal_and_day_equal(
-                                    year_col.to_string(),
-                                    right_year,
-                                    month_col.to_string(),
-                                    right_month,
-                                    day_col.to_string(),
-                                    right_day,
-                                )),
-                                BinOper::And,
-                                Box::new(original.clone()),
-                            );
-                        return Some(iterated_binoper(
-                            vec![
-                                year_smaller_than_year_expr,
-                                year_equal_and_month_smaller_expr,
-                                year_equal_and_month_equal_and_day_smaller,
-                                year_equal_and_month_equal_and_day_equal_and_original,
-                            ],
-                            BinOper::Or,
-                        ));
-                    }
-                }
-            }
-        } else if let SimpleExpr::Value(left_value) = left {
-            if let Value::ChronoDateTime(Some(left_dt)) = left_value {
-                if let SimpleExpr::Column(right_column) = right {
-                    if let Some(colname) = find_colname(right_column) {
-                        if timestamp_col == &colname {
-                            let left_year = left_dt.year();
-                            let left_month = left_dt.month();
-                            let left_day = left_dt.day();
-                            let year_smaller_than_year_expr = const_num_oper_col_name(
-                                left_year,
-                                year_col.to_string(),
-                                BinOper::SmallerThan,
-                            );
-                            let year_equal_and_month_smaller_expr = SimpleExpr::Binary(
-                                Box::new(const_num_oper_col_name(
-                                    left_year,
-                                    year_col.to_string(),
-                                    BinOper::Equal,
-                                )),
-                                BinOper::And,
-                                Box::new(const_num_oper_col_name(
-                                    left_month as i32,
-                                    month_col.to_string(),
-                                    BinOper::SmallerThan,
-                                )),
-                            );
-                            let year_equal_and_month_equal_and_day_smaller = SimpleExpr::Binary(
-                                Box::new(year_equal_and_month_equal(
-                                    year_col.to_string(),
-                                    left_year,
-                                    month_col.to_string(),
-                                    left_month,
-                                )),
-                                BinOper::And,
-                                Box::new(col_name_oper_const_num(
-                                    day_col.to_string(),
-                                    left_day as i32,
-                                    BinOper::SmallerThan,
-                                )),
-                            );
-                            let year_equal_and_month_equal_and_day_equal_and_original =
-                                SimpleExpr::Binary(
-                                    Box::new(year_equal_and_month_equal_and_day_equal(
-                                        year_col.to_string(),
-                                        left_year,
-                                        month_col.to_string(),
-                                        left_month,
-                                        day_col.to_string(),
-                                        left_day,
-                                    )),
-                                    BinOper::And,
-                                    Box::new(original.clone()),
-                                );
-                            return Some(iterated_binoper(
-                                vec![
-                                    year_smaller_than_year_expr,
-                                    year_equal_and_month_smaller_expr,
-                                    year_equal_and_month_equal_and_day_smaller,
-                                    year_equal_and_month_equal_and_day_equal_and_original,
-                                ],
-                                BinOper::Or,
-                            ));
-                        }
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
-fn named_column_box_simple_expression(name: String) -> Box<SimpleExpr> {
-    Box::new(SimpleExpr::Column(ColumnRef::Column(Rc::new(
-        Name::Column(name),
-    ))))
-}
-
-fn year_equal_and_month_equal_and_day_equal(
-    year_col: String,
-    year: i32,
-    month_col: String,
-    month: u32,
-    day_col: String,
-    day: u32,
-) -> SimpleExpr {
-    SimpleExpr::Binary(
-        Box::new(year_equal_and_month_equal(year_col, year, month_col, month)),
-        BinOper::And,
-        Box::new(col_name_oper_const_num(day_col, day as i32, BinOper::Equal)),
-    )
-}
-
-fn year_equal_and_month_equal(
-    year_col: String,
-    year: i32,
-    month_col: String,
-    month: u32,
+    op: BinOper,
+    dt: &NaiveDateTime,
+    partition_kinds: &[PartitionKind],
 ) -> SimpleExpr {
-    SimpleExpr::Binary(
-        Box::new(col_name_oper_const_num(year_col, year, BinOper::Equal)),
-        BinOper::And,
-        Box::new(col_name_oper_const_num(
-            month_col,
-            month as i32,
-            BinOper::Equal,
-        )),
-    )
-}
+    let equals: Vec<SimpleExpr> = partition_kinds
+        .iter()
+        .map(|x| partition_oper_value(x, BinOper::Equal, dt))
+        .collect();
+    let mut all_equal_and_original = equals.clone();
+    all_equal_and_original.push(original.clone());
+    if op == BinOper::Equal {
+        return iterated_binoper(all_equal_and_original, BinOper::And);
+    }
 
-fn col_name_oper_const_num(col_name: String, num: i32, oper: BinOper) -> SimpleExpr {
-    SimpleExpr::Binary(
-        named_column_box_simple_expression(col_name),
-        oper,
-        Box::new(SimpleExpr::Value(Value::Int(Some(num)))),
-    )
+    let partition_op = match op {
+        BinOper::SmallerThan | BinOper::SmallerThanOrEqual => BinOper::SmallerThan,
+        _ => BinOper::GreaterThan,
+    };
+    let mut alternatives = vec![];
+    for (i, kind) in partition_kinds.iter().enumerate() {
+        let mut alternative: Vec<SimpleExpr> = equals.iter().take(i).cloned().collect();
+        alternative.push(partition_oper_value(kind, partition_op, dt));
+        alternatives.push(iterated_binoper(alternative, BinOper::And));
+    }
+    alternatives.push(iterated_binoper(all_equal_and_original, BinOper::And));
+    iterated_binoper(alternatives, BinOper::Or)
 }
 
-fn const_num_oper_col_name(num: i32, col_name: String, oper: BinOper) -> SimpleExpr {
+fn partition_oper_value(kind: &PartitionKind, oper: BinOper, dt: &NaiveDateTime) -> SimpleExpr {
     SimpleExpr::Binary(
-        Box::new(SimpleExpr::Value(Value::Int(Some(num)))),
+        Box::new(SimpleExpr::Column(ColumnRef::Column(Rc::new(
+            Name::Column(kind.alias().to_string()),
+        )))),
         oper,
-        named_column_box_simple_expression(col_name),
+        Box::new(SimpleExpr::Value(kind.value(dt))),
     )
 }
 
@@ -604,7 +188,7 @@ fn find_colname(cr: &ColumnRef) -> Option<String> {
 fn iterated_binoper(mut exprs: Vec<SimpleExpr>, oper: BinOper) -> SimpleExpr {
     let mut expr = exprs.remove(0);
     for e in exprs {
-        expr = SimpleExpr::Binary(Box::new(expr), oper.clone(), Box::new(e))
+        expr = SimpleExpr::Binary(Box::new(expr), oper, Box::new(e))
     }
     expr
 }
@@ -0,0 +1,536 @@
+use crate::constants::DATETIME_AS_SECONDS;
+use crate::custom_aggregates::CustomAggregate;
+use crate::timeseries_database::capabilities::TimeSeriesQueryCapabilities;
+use crate::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::TimestampEncoding;
+use crate::timeseries_database::timeseries_sql_rewrite::Name;
+use oxrdf::vocab::xsd;
+use oxrdf::NamedNode;
+use sea_query::{
+    Alias, BinOper, Func, Function, PostgresQueryBuilder, SelectStatement, SimpleExpr,
+    SqliteQueryBuilder, Value,
+};
+use spargebra::algebra::Function as SparqlFunction;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub struct UnknownSqlDialectError(String);
+
+impl Display for UnknownSqlDialectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown SQL dialect {}, expected one of Dremio, Postgres, TimescaleDB, SQLite, DuckDB",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownSqlDialectError {}
+
+//Controls the parts of the generated SQL that differ between databases
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SqlDialect {
+    Dremio,
+    Postgres,
+    //Postgres with time_bucket for grouping by time intervals
+    TimescaleDB,
+    SQLite,
+    DuckDB,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatePart {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl Default for SqlDialect {
+    fn default() -> Self {
+        SqlDialect::Dremio
+    }
+}
+
+impl FromStr for SqlDialect {
+    type Err = UnknownSqlDialectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dremio" => Ok(SqlDialect::Dremio),
+            "postgres" | "postgresql" => Ok(SqlDialect::Postgres),
+            "timescaledb" => Ok(SqlDialect::TimescaleDB),
+            "sqlite" => Ok(SqlDialect::SQLite),
+            "duckdb" => Ok(SqlDialect::DuckDB),
+            _ => Err(UnknownSqlDialectError(s.to_string())),
+        }
+    }
+}
+
+impl SqlDialect {
+    //The query builder decides identifier quoting and literal formatting
+    pub fn build_query(&self, query: &SelectStatement) -> String {
+        match self {
+            SqlDialect::Dremio
+            | SqlDialect::Postgres
+            | SqlDialect::TimescaleDB
+            | SqlDialect::DuckDB => query.to_string(PostgresQueryBuilder),
+            SqlDialect::SQLite => query.to_string(SqliteQueryBuilder),
+        }
+    }
+
+    pub fn date_part(&self, part: &DatePart, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::Dremio
+            | SqlDialect::Postgres
+            | SqlDialect::TimescaleDB
+            | SqlDialect::DuckDB => {
+                let part_name = match part {
+                    DatePart::Year => "year",
+                    DatePart::Month => "month",
+                    DatePart::Day => "day",
+                    DatePart::Hour => "hour",
+                    DatePart::Minute => "minute",
+                    DatePart::Second => "second",
+                };
+                custom_function("date_part", vec![string_value(part_name), e])
+            }
+            SqlDialect::SQLite => {
+                let (format, type_name) = match part {
+                    DatePart::Year => ("%Y", "INTEGER"),
+                    DatePart::Month => ("%m", "INTEGER"),
+                    DatePart::Day => ("%d", "INTEGER"),
+                    DatePart::Hour => ("%H", "INTEGER"),
+                    DatePart::Minute => ("%M", "INTEGER"),
+                    //Seconds with fractional part
+                    DatePart::Second => ("%f", "REAL"),
+                };
+                self.cast(
+                    custom_function("strftime", vec![string_value(format), e]),
+                    type_name,
+                )
+            }
+        }
+    }
+
+    pub fn epoch_seconds(&self, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::Dremio => custom_function(
+                "UNIX_TIMESTAMP",
+                vec![e, string_value("YYYY-MM-DD HH:MI:SS.FFF")],
+            ),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB => {
+                custom_function("date_part", vec![string_value("epoch"), e])
+            }
+            SqlDialect::DuckDB => custom_function("epoch", vec![e]),
+            SqlDialect::SQLite => self.cast(
+                custom_function("strftime", vec![string_value("%s"), e]),
+                "INTEGER",
+            ),
+        }
+    }
+
+    pub fn cast_to_integer(&self, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::Dremio | SqlDialect::SQLite => self.cast(e, "INTEGER"),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB | SqlDialect::DuckDB => {
+                self.cast(e, "BIGINT")
+            }
+        }
+    }
+
+    pub fn cast(&self, e: SimpleExpr, type_name: &str) -> SimpleExpr {
+        match self {
+            //The Postgres query builder renders AsEnum as a CAST
+            SqlDialect::Dremio
+            | SqlDialect::Postgres
+            | SqlDialect::TimescaleDB
+            | SqlDialect::DuckDB => {
+                SimpleExpr::AsEnum(Rc::new(Name::Table(type_name.to_string())), Box::new(e))
+            }
+            SqlDialect::SQLite => Func::cast_as(e, Alias::new(type_name)),
+        }
+    }
+
+    pub fn cast_to_string(&self, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::Dremio | SqlDialect::DuckDB => self.cast(e, "VARCHAR"),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB | SqlDialect::SQLite => {
+                self.cast(e, "TEXT")
+            }
+        }
+    }
+
+    //Concatenation of the string values of a group, None if the dialect cannot express it
+    pub fn string_agg(&self, e: SimpleExpr, separator: &str, distinct: bool) -> Option<SimpleExpr> {
+        let arg = self.with_distinct(self.cast_to_string(e), distinct);
+        match self {
+            SqlDialect::Dremio => Some(custom_function(
+                "LISTAGG",
+                vec![arg, string_value(separator)],
+            )),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB | SqlDialect::DuckDB => Some(
+                custom_function("STRING_AGG", vec![arg, string_value(separator)]),
+            ),
+            //SQLite only allows DISTINCT in aggregates with a single argument,
+            // the separator is then always a comma
+            SqlDialect::SQLite => {
+                if !distinct {
+                    Some(custom_function(
+                        "GROUP_CONCAT",
+                        vec![arg, string_value(separator)],
+                    ))
+                } else if separator == "," {
+                    Some(custom_function("GROUP_CONCAT", vec![arg]))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    //Any value from the group
+    pub fn sample(&self, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::DuckDB => custom_function("ANY_VALUE", vec![e]),
+            _ => SimpleExpr::FunctionCall(Function::Min, vec![e]),
+        }
+    }
+
+    //None if the dialect lacks the aggregate function
+    pub fn custom_aggregate(
+        &self,
+        aggregate: &CustomAggregate,
+        e: SimpleExpr,
+        distinct: bool,
+    ) -> Option<SimpleExpr> {
+        let arg = self.with_distinct(e, distinct);
+        match (self, aggregate) {
+            (SqlDialect::SQLite, _) => None,
+            (_, CustomAggregate::StdDev) => Some(custom_function("STDDEV_SAMP", vec![arg])),
+            (_, CustomAggregate::Variance) => Some(custom_function("VAR_SAMP", vec![arg])),
+            (SqlDialect::Dremio | SqlDialect::DuckDB, CustomAggregate::Median) => {
+                Some(custom_function("MEDIAN", vec![arg]))
+            }
+            (SqlDialect::DuckDB, CustomAggregate::Percentile(p)) => Some(custom_function(
+                "QUANTILE_CONT",
+                vec![arg, SimpleExpr::Value(Value::Double(Some(*p)))],
+            )),
+            //Postgres and Dremio need an ordered-set aggregate (WITHIN GROUP) for these
+            (_, CustomAggregate::Median | CustomAggregate::Percentile(_)) => None,
+        }
+    }
+
+    //Epoch seconds of the start of the bucket of the given width containing the timestamp,
+    // None if the dialect has no native bucketing
+    pub fn time_bucket(&self, width_seconds: f64, e: SimpleExpr) -> Option<SimpleExpr> {
+        match self {
+            SqlDialect::TimescaleDB => {
+                let width = self.cast(
+                    string_value(&format!("{} seconds", width_seconds)),
+                    "INTERVAL",
+                );
+                //Buckets are aligned to the epoch, as FLOOR(seconds / width) * width would be.
+                //The origin is typed, as an untyped literal also matches the offset and time zone overloads
+                let origin = self.cast(string_value("1970-01-01"), "TIMESTAMP");
+                let bucket = custom_function("time_bucket", vec![width, e, origin]);
+                Some(self.epoch_seconds(bucket))
+            }
+            _ => None,
+        }
+    }
+
+    //Converts timestamps stored in the given encoding to native timestamps
+    pub fn decode_timestamp(&self, e: SimpleExpr, encoding: &TimestampEncoding) -> SimpleExpr {
+        if let Some(unit) = encoding.nanoseconds_per_unit() {
+            let seconds = if unit == 1_000_000_000 {
+                e
+            } else {
+                SimpleExpr::Binary(
+                    Box::new(e),
+                    BinOper::Div,
+                    Box::new(SimpleExpr::Value(Value::Double(Some(
+                        1_000_000_000.0 / unit as f64,
+                    )))),
+                )
+            };
+            match self {
+                SqlDialect::Dremio => custom_function("TO_TIMESTAMP", vec![seconds]),
+                SqlDialect::Postgres | SqlDialect::TimescaleDB => custom_function(
+                    "timezone",
+                    vec![
+                        string_value("UTC"),
+                        custom_function("to_timestamp", vec![seconds]),
+                    ],
+                ),
+                SqlDialect::DuckDB => {
+                    let milliseconds = SimpleExpr::Binary(
+                        Box::new(seconds),
+                        BinOper::Mul,
+                        Box::new(SimpleExpr::Value(Value::Int(Some(1000)))),
+                    );
+                    custom_function("epoch_ms", vec![self.cast_to_integer(milliseconds)])
+                }
+                SqlDialect::SQLite => {
+                    custom_function("datetime", vec![seconds, string_value("unixepoch")])
+                }
+            }
+        } else if encoding == &TimestampEncoding::IsoString {
+            match self {
+                SqlDialect::SQLite => custom_function("datetime", vec![e]),
+                _ => self.cast(e, "TIMESTAMP"),
+            }
+        } else {
+            e
+        }
+    }
+
+    //Functions used by date_part and epoch_seconds
+    pub fn date_functions(&self) -> Vec<&'static str> {
+        match self {
+            SqlDialect::Dremio => vec!["date_part", "UNIX_TIMESTAMP"],
+            SqlDialect::Postgres | SqlDialect::TimescaleDB => vec!["date_part"],
+            SqlDialect::DuckDB => vec!["date_part", "epoch"],
+            SqlDialect::SQLite => vec!["strftime"],
+        }
+    }
+
+    //Capabilities of databases that are queried with SQL in this dialect
+    pub fn capabilities(&self) -> TimeSeriesQueryCapabilities {
+        let mut capabilities = TimeSeriesQueryCapabilities::all();
+        capabilities.functions = Some(self.supported_functions());
+        capabilities
+    }
+
+    //SPARQL functions that can be translated, calls to other functions are evaluated by the combiner
+    pub fn supported_functions(&self) -> HashSet<SparqlFunction> {
+        let mut functions: HashSet<SparqlFunction> = [
+            SparqlFunction::Year,
+            SparqlFunction::Month,
+            SparqlFunction::Day,
+            SparqlFunction::Hours,
+            SparqlFunction::Minutes,
+            SparqlFunction::Seconds,
+            SparqlFunction::Now,
+            SparqlFunction::Abs,
+            SparqlFunction::Ceil,
+            SparqlFunction::Floor,
+            SparqlFunction::Round,
+            SparqlFunction::StrLen,
+            SparqlFunction::SubStr,
+            SparqlFunction::UCase,
+            SparqlFunction::LCase,
+            SparqlFunction::Contains,
+            SparqlFunction::StrStarts,
+            SparqlFunction::StrEnds,
+            SparqlFunction::Custom(NamedNode::new_unchecked(DATETIME_AS_SECONDS)),
+            SparqlFunction::Custom(xsd::INTEGER.into_owned()),
+            SparqlFunction::Custom(xsd::DOUBLE.into_owned()),
+            SparqlFunction::Custom(xsd::STRING.into_owned()),
+        ]
+        .into();
+        match self {
+            SqlDialect::Postgres | SqlDialect::TimescaleDB | SqlDialect::DuckDB => {
+                functions.insert(SparqlFunction::Rand);
+                functions.insert(SparqlFunction::Concat);
+                functions.insert(SparqlFunction::Regex);
+            }
+            SqlDialect::Dremio => {
+                functions.insert(SparqlFunction::Rand);
+                functions.insert(SparqlFunction::Concat);
+            }
+            SqlDialect::SQLite => {}
+        }
+        functions
+    }
+
+    //Function calls not handled by the expression transformer, None if they can not be translated
+    pub fn function_call(
+        &self,
+        function: &SparqlFunction,
+        mut args: Vec<SimpleExpr>,
+    ) -> Option<SimpleExpr> {
+        if !self.supported_functions().contains(function) {
+            return None;
+        }
+        let e = match function {
+            SparqlFunction::Abs => custom_function("ABS", args),
+            SparqlFunction::Ceil => custom_function("CEIL", args),
+            SparqlFunction::Floor => custom_function("FLOOR", args),
+            SparqlFunction::Round => custom_function("ROUND", args),
+            SparqlFunction::Rand => match self {
+                SqlDialect::Dremio => custom_function("RAND", vec![]),
+                _ => custom_function("RANDOM", vec![]),
+            },
+            SparqlFunction::StrLen => custom_function("LENGTH", args),
+            //SQL strings are also indexed from 1
+            SparqlFunction::SubStr => custom_function("SUBSTR", args),
+            SparqlFunction::UCase => custom_function("UPPER", args),
+            SparqlFunction::LCase => custom_function("LOWER", args),
+            SparqlFunction::Concat => custom_function("CONCAT", args),
+            SparqlFunction::Contains => SimpleExpr::Binary(
+                Box::new(self.string_position(args.remove(0), args.remove(0))),
+                BinOper::GreaterThan,
+                Box::new(SimpleExpr::Value(Value::Int(Some(0)))),
+            ),
+            SparqlFunction::StrStarts => self
+                .string_position(args.remove(0), args.remove(0))
+                .equals(SimpleExpr::Value(Value::Int(Some(1)))),
+            SparqlFunction::StrEnds => {
+                let s = args.remove(0);
+                let suffix = args.remove(0);
+                let start = custom_function("LENGTH", vec![s.clone()])
+                    .sub(custom_function("LENGTH", vec![suffix.clone()]))
+                    .add(SimpleExpr::Value(Value::Int(Some(1))));
+                custom_function("SUBSTR", vec![s, start]).equals(suffix)
+            }
+            SparqlFunction::Regex => {
+                let case_insensitive = match args.get(2) {
+                    None => false,
+                    Some(SimpleExpr::Value(Value::String(Some(flags)))) if flags.as_str() == "" => {
+                        false
+                    }
+                    Some(SimpleExpr::Value(Value::String(Some(flags))))
+                        if flags.as_str() == "i" =>
+                    {
+                        true
+                    }
+                    _ => return None,
+                };
+                args.truncate(2);
+                match self {
+                    //The functions behind the ~ and ~* operators
+                    SqlDialect::Postgres | SqlDialect::TimescaleDB => {
+                        if case_insensitive {
+                            custom_function("texticregexeq", args)
+                        } else {
+                            custom_function("textregexeq", args)
+                        }
+                    }
+                    SqlDialect::DuckDB => {
+                        if case_insensitive {
+                            args.push(string_value("i"));
+                        }
+                        custom_function("regexp_matches", args)
+                    }
+                    SqlDialect::Dremio | SqlDialect::SQLite => return None,
+                }
+            }
+            //The current time in UTC without time zone, like the timestamps in time series tables
+            SparqlFunction::Now => match self {
+                SqlDialect::Postgres | SqlDialect::TimescaleDB => custom_function(
+                    "timezone",
+                    vec![string_value("UTC"), custom_function("now", vec![])],
+                ),
+                SqlDialect::DuckDB => self.cast(custom_function("now", vec![]), "TIMESTAMP"),
+                SqlDialect::Dremio => custom_function("NOW", vec![]),
+                SqlDialect::SQLite => custom_function(
+                    "strftime",
+                    vec![string_value("%Y-%m-%d %H:%M:%f"), string_value("now")],
+                ),
+            },
+            _ => return None,
+        };
+        Some(e)
+    }
+
+    //Position of the first occurrence of the substring counting from 1, 0 if it is not found
+    fn string_position(&self, s: SimpleExpr, substring: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::SQLite => custom_function("INSTR", vec![s, substring]),
+            _ => custom_function("STRPOS", vec![s, substring]),
+        }
+    }
+
+    pub fn cast_to_double(&self, e: SimpleExpr) -> SimpleExpr {
+        match self {
+            SqlDialect::Dremio | SqlDialect::DuckDB => self.cast(e, "DOUBLE"),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB => self.cast(e, "DOUBLE PRECISION"),
+            SqlDialect::SQLite => self.cast(e, "REAL"),
+        }
+    }
+
+    pub fn if_then_else(
+        &self,
+        condition: SimpleExpr,
+        then: SimpleExpr,
+        otherwise: SimpleExpr,
+    ) -> SimpleExpr {
+        self.case_when(vec![(condition, then)], otherwise)
+    }
+
+    //The conditions and branches are arguments of the CASE expression,
+    // so they are written by the query builder together with the rest of the query
+    pub fn case_when(
+        &self,
+        branches: Vec<(SimpleExpr, SimpleExpr)>,
+        otherwise: SimpleExpr,
+    ) -> SimpleExpr {
+        let mut template = "(CASE".to_string();
+        let mut args = vec![];
+        for (condition, then) in branches {
+            template.push_str(" WHEN ? THEN ?");
+            args.push(condition);
+            args.push(then);
+        }
+        template.push_str(" ELSE ? END)");
+        args.push(otherwise);
+        self.custom_with_expressions(&template, args)
+    }
+
+    //Renders as DISTINCT e inside the enclosing aggregate call, so it only considers distinct values
+    pub(crate) fn with_distinct(&self, e: SimpleExpr, distinct: bool) -> SimpleExpr {
+        if distinct {
+            self.custom_with_expressions("DISTINCT ?", vec![e])
+        } else {
+            e
+        }
+    }
+
+    //Each ? in the template is replaced by the next argument,
+    // using the placeholders that the query builder of the dialect expects
+    fn custom_with_expressions(&self, template: &str, args: Vec<SimpleExpr>) -> SimpleExpr {
+        let mut parts = template.split('?');
+        let mut custom = parts.next().unwrap().to_string();
+        for (i, part) in parts.enumerate() {
+            match self {
+                SqlDialect::SQLite => custom.push('?'),
+                _ => custom.push_str(&format!("${}", i + 1)),
+            }
+            custom.push_str(part);
+        }
+        SimpleExpr::CustomWithExpr(custom, args)
+    }
+
+    //Name of the i-th column of a VALUES-clause used as a table
+    pub fn values_column_name(&self, i: usize) -> String {
+        match self {
+            SqlDialect::Dremio => format!("EXPR${}", i),
+            SqlDialect::Postgres | SqlDialect::TimescaleDB | SqlDialect::SQLite => {
+                format!("column{}", i + 1)
+            }
+            SqlDialect::DuckDB => format!("col{}", i),
+        }
+    }
+}
+
+fn custom_function(name: &str, args: Vec<SimpleExpr>) -> SimpleExpr {
+    SimpleExpr::FunctionCall(
+        Function::Custom(Rc::new(Name::Function(name.to_string()))),
+        args,
+    )
+}
+
+pub(crate) fn coalesce(args: Vec<SimpleExpr>) -> SimpleExpr {
+    custom_function("COALESCE", args)
+}
+
+fn string_value(s: &str) -> SimpleExpr {
+    SimpleExpr::Value(Value::String(Some(Box::new(s.to_string()))))
+}
@@ -0,0 +1,143 @@
+use super::{TimeSeriesQueryToSQLError, TimeSeriesTable};
+use crate::external_ids::ExternalId;
+use crate::timeseries_query::BasicTimeSeriesQuery;
+use std::collections::HashMap;
+use std::mem::discriminant;
+
+impl TimeSeriesTable {
+    //Tables can be annotated by IRI, by table name or by schema qualified table name
+    pub fn has_name(&self, name: &str) -> bool {
+        if self.iri.as_deref() == Some(name) || self.time_series_table == name {
+            return true;
+        }
+        if let Some(schema) = &self.schema {
+            if format!("{}.{}", schema, self.time_series_table) == name {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn longest_prefix_match(&self, id: &str) -> Option<usize> {
+        self.identifier_prefixes
+            .iter()
+            .flatten()
+            .filter(|x| id.starts_with(x.as_str()))
+            .map(|x| x.len())
+            .max()
+    }
+}
+
+//The static queries only fetch chrontext:hasTable annotations when some table can be identified by IRI
+pub fn has_table_iris(tables: &Vec<TimeSeriesTable>) -> bool {
+    tables.iter().any(|x| x.iri.is_some())
+}
+
+//Splits a basic query into one query per table, each restricted to the ids stored in that table.
+//Ids are matched by their string form, composite ids e.g. as ["asset_1","TT-101"].
+//The table of an id is given by its chrontext:hasTable annotation if present,
+// then by the value columns of wide tables, then by the longest matching identifier prefix,
+// and finally by the value datatype.
+//Results identify time series by the string form of the id, so the ids routed to a table must have the same type,
+// and distinct ids with the same string form, such as "1" and 1, are rejected.
+pub fn route_basic_query<'a>(
+    tables: &'a Vec<TimeSeriesTable>,
+    btsq: &BasicTimeSeriesQuery,
+) -> Result<Vec<(&'a TimeSeriesTable, BasicTimeSeriesQuery)>, TimeSeriesQueryToSQLError> {
+    let ids = match &btsq.ids {
+        Some(ids) if !ids.is_empty() => ids,
+        _ => {
+            let i = find_table_index_by_datatype(tables, btsq)?;
+            return Ok(vec![(tables.get(i).unwrap(), btsq.clone())]);
+        }
+    };
+
+    let mut canonical_ids: HashMap<String, &ExternalId> = HashMap::new();
+    let mut table_ids: HashMap<usize, Vec<ExternalId>> = HashMap::new();
+    for id in ids {
+        let canonical_id = id.to_string();
+        if let Some(other) = canonical_ids.insert(canonical_id.clone(), id) {
+            if other != id {
+                return Err(TimeSeriesQueryToSQLError::AmbiguousIdentifier(canonical_id));
+            }
+        }
+        let table_index = find_table_index(tables, btsq, &canonical_id)?;
+        if let Some(v) = table_ids.get_mut(&table_index) {
+            v.push(id.clone());
+        } else {
+            table_ids.insert(table_index, vec![id.clone()]);
+        }
+    }
+
+    //Keep the order of the table configuration to make the queries deterministic
+    let mut table_indices: Vec<usize> = table_ids.keys().cloned().collect();
+    table_indices.sort();
+    let mut routed = vec![];
+    for i in table_indices {
+        let mut table_btsq = btsq.clone();
+        let ids = table_ids.remove(&i).unwrap();
+        if ids
+            .iter()
+            .any(|x| discriminant(x) != discriminant(ids.first().unwrap()))
+        {
+            return Err(TimeSeriesQueryToSQLError::MixedIdentifierTypes(
+                tables.get(i).unwrap().time_series_table.clone(),
+            ));
+        }
+        table_btsq.ids = Some(ids);
+        routed.push((tables.get(i).unwrap(), table_btsq));
+    }
+    Ok(routed)
+}
+
+fn find_table_index(
+    tables: &Vec<TimeSeriesTable>,
+    btsq: &BasicTimeSeriesQuery,
+    id: &str,
+) -> Result<usize, TimeSeriesQueryToSQLError> {
+    if let Some(name) = btsq.tables.as_ref().and_then(|x| x.get(id)) {
+        return if let Some(i) = tables.iter().position(|x| x.has_name(name)) {
+            Ok(i)
+        } else {
+            Err(TimeSeriesQueryToSQLError::UnknownTable(name.clone()))
+        };
+    }
+
+    //Wide tables store each time series in a column of its own
+    if let Some(i) = tables.iter().position(|x| x.has_value_column_for(id)) {
+        return Ok(i);
+    }
+
+    let mut best_prefix_match = None;
+    for (i, table) in tables.iter().enumerate() {
+        if let Some(length) = table.longest_prefix_match(id) {
+            if best_prefix_match.map_or(true, |(_, best)| length > best) {
+                best_prefix_match = Some((i, length));
+            }
+        }
+    }
+    if let Some((i, _)) = best_prefix_match {
+        return Ok(i);
+    }
+
+    find_table_index_by_datatype(tables, btsq)
+}
+
+fn find_table_index_by_datatype(
+    tables: &Vec<TimeSeriesTable>,
+    btsq: &BasicTimeSeriesQuery,
+) -> Result<usize, TimeSeriesQueryToSQLError> {
+    if let Some(b_datatype) = &btsq.datatype {
+        if let Some(i) = tables
+            .iter()
+            .position(|x| x.value_datatype.as_str() == b_datatype.as_str())
+        {
+            return Ok(i);
+        }
+        Err(TimeSeriesQueryToSQLError::DatatypeNotSupported(
+            b_datatype.as_str().to_string(),
+        ))
+    } else {
+        Err(TimeSeriesQueryToSQLError::MissingTimeseriesQueryDatatype)
+    }
+}
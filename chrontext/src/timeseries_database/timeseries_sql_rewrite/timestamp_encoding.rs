@@ -0,0 +1,206 @@
+use super::wide_tables::column;
+use super::TimeSeriesTable;
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_query::TimeSeriesQuery;
+use polars::prelude::{col, lit, DataFrame, Expr, IntoLazy, PolarsError};
+use polars_core::export::chrono::NaiveDateTime;
+use polars_core::prelude::{DataType, TimeUnit};
+use sea_query::{SimpleExpr, Value};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
+const ISO_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+#[derive(Debug)]
+pub struct UnknownTimestampEncodingError(String);
+
+impl Display for UnknownTimestampEncodingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Unknown timestamp encoding {}, expected one of native, epoch_seconds, epoch_milliseconds, epoch_nanoseconds, iso_string",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownTimestampEncodingError {}
+
+//How the timestamp column of a table is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampEncoding {
+    Native,
+    EpochSeconds,
+    EpochMilliseconds,
+    EpochNanoseconds,
+    //ISO 8601 strings such as 2023-04-01T10:30:00, filters compare them as strings
+    IsoString,
+}
+
+impl Default for TimestampEncoding {
+    fn default() -> Self {
+        TimestampEncoding::Native
+    }
+}
+
+impl FromStr for TimestampEncoding {
+    type Err = UnknownTimestampEncodingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "native" => Ok(TimestampEncoding::Native),
+            "epoch_seconds" => Ok(TimestampEncoding::EpochSeconds),
+            "epoch_milliseconds" => Ok(TimestampEncoding::EpochMilliseconds),
+            "epoch_nanoseconds" => Ok(TimestampEncoding::EpochNanoseconds),
+            "iso_string" => Ok(TimestampEncoding::IsoString),
+            _ => Err(UnknownTimestampEncodingError(s.to_string())),
+        }
+    }
+}
+
+impl TimestampEncoding {
+    pub(crate) fn nanoseconds_per_unit(&self) -> Option<i64> {
+        match self {
+            TimestampEncoding::EpochSeconds => Some(NANOSECONDS_PER_SECOND),
+            TimestampEncoding::EpochMilliseconds => Some(1_000_000),
+            TimestampEncoding::EpochNanoseconds => Some(1),
+            TimestampEncoding::Native | TimestampEncoding::IsoString => None,
+        }
+    }
+
+    //The stored representation of a timestamp, used for literals in filters
+    pub(crate) fn encode(&self, dt: &NaiveDateTime) -> Value {
+        if let Some(unit) = self.nanoseconds_per_unit() {
+            let nanos = dt.timestamp() as i128 * NANOSECONDS_PER_SECOND as i128
+                + dt.timestamp_subsec_nanos() as i128;
+            Value::BigInt(Some((nanos / unit as i128) as i64))
+        } else if self == &TimestampEncoding::IsoString {
+            Value::String(Some(Box::new(dt.format(ISO_FORMAT).to_string())))
+        } else {
+            Value::ChronoDateTime(Some(Box::new(*dt)))
+        }
+    }
+
+    pub(crate) fn decode(&self, value: &Value) -> Option<NaiveDateTime> {
+        match (self.nanoseconds_per_unit(), value) {
+            (Some(unit), Value::BigInt(Some(n))) => {
+                let nanos = n.checked_mul(unit)?;
+                NaiveDateTime::from_timestamp_opt(
+                    nanos.div_euclid(NANOSECONDS_PER_SECOND),
+                    nanos.rem_euclid(NANOSECONDS_PER_SECOND) as u32,
+                )
+            }
+            (None, Value::String(Some(s))) if self == &TimestampEncoding::IsoString => {
+                NaiveDateTime::parse_from_str(s, ISO_FORMAT).ok()
+            }
+            (None, Value::ChronoDateTime(Some(dt))) if self == &TimestampEncoding::Native => {
+                Some(*dt.as_ref())
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn encode_literal(&self, value: Value) -> Value {
+        match value {
+            Value::ChronoDateTime(Some(dt)) => self.encode(&dt),
+            Value::ChronoDateTimeUtc(Some(dt)) if self != &TimestampEncoding::Native => {
+                self.encode(&dt.naive_utc())
+            }
+            _ => value,
+        }
+    }
+
+    //Converts a column in this encoding to a polars Datetime
+    pub fn normalize_expr(&self, e: Expr) -> Expr {
+        let datetime = DataType::Datetime(TimeUnit::Nanoseconds, None);
+        if let Some(unit) = self.nanoseconds_per_unit() {
+            (e.cast(DataType::Int64) * lit(unit)).cast(datetime)
+        } else {
+            //Polars parses ISO 8601 strings when casting them
+            e.cast(datetime)
+        }
+    }
+}
+
+impl TimeSeriesTable {
+    pub fn get_timestamp_encoding(&self) -> TimestampEncoding {
+        self.timestamp_encoding.unwrap_or_default()
+    }
+
+    //Tables that do not use the encoding of the query are converted to native timestamps
+    pub(crate) fn timestamp_expression(
+        &self,
+        timestamp_encoding: &TimestampEncoding,
+        dialect: &SqlDialect,
+    ) -> SimpleExpr {
+        let table_encoding = self.get_timestamp_encoding();
+        if &table_encoding == timestamp_encoding {
+            column(&self.timestamp_column)
+        } else {
+            dialect.decode_timestamp(column(&self.timestamp_column), &table_encoding)
+        }
+    }
+}
+
+//Filters can only use the stored representation when it is the same for all tables
+pub(crate) fn common_timestamp_encoding(tables: &Vec<TimeSeriesTable>) -> TimestampEncoding {
+    let mut encodings = tables.iter().map(|x| x.get_timestamp_encoding());
+    let first = encodings.next().unwrap_or_default();
+    if encodings.all(|x| x == first) {
+        first
+    } else {
+        TimestampEncoding::Native
+    }
+}
+
+//Aggregates and expressions may project the timestamps under other names, e.g. MIN(?t) or BIND(?t AS ?x),
+// so queries with these decode the timestamps before computing them, as native tables would return them
+pub(crate) fn query_timestamp_encoding(
+    tables: &Vec<TimeSeriesTable>,
+    tsq: &TimeSeriesQuery,
+) -> TimestampEncoding {
+    if computes_on_timestamps(tsq) {
+        TimestampEncoding::Native
+    } else {
+        common_timestamp_encoding(tables)
+    }
+}
+
+fn computes_on_timestamps(tsq: &TimeSeriesQuery) -> bool {
+    match tsq {
+        TimeSeriesQuery::Basic(_) | TimeSeriesQuery::GroupedBasic(..) => false,
+        TimeSeriesQuery::Filtered(inner, _) | TimeSeriesQuery::Limited(inner, _) => {
+            computes_on_timestamps(inner)
+        }
+        TimeSeriesQuery::InnerSynchronized(inners, _) => {
+            inners.iter().any(|x| computes_on_timestamps(x))
+        }
+        TimeSeriesQuery::ExpressionAs(..) | TimeSeriesQuery::Grouped(_) => true,
+    }
+}
+
+//Time series query results have the timestamps in the encoding used by the query
+pub fn normalize_timestamps(
+    df: DataFrame,
+    tsq: &TimeSeriesQuery,
+    tables: &Vec<TimeSeriesTable>,
+) -> Result<DataFrame, PolarsError> {
+    let encoding = query_timestamp_encoding(tables, tsq);
+    if encoding == TimestampEncoding::Native {
+        return Ok(df);
+    }
+    let column_names = df.get_column_names();
+    let mut exprs = vec![];
+    for tsv in tsq.get_timestamp_variables() {
+        let name = tsv.variable.as_str();
+        if column_names.contains(&name) {
+            exprs.push(encoding.normalize_expr(col(name)).alias(name));
+        }
+    }
+    if exprs.is_empty() {
+        return Ok(df);
+    }
+    df.lazy().with_columns(exprs).collect()
+}
@@ -0,0 +1,335 @@
+use super::partition_scheme::{remove_partition_columns, PartitionKind};
+use super::table_routing::route_basic_query;
+use super::timestamp_encoding::TimestampEncoding;
+use super::{Name, TimeSeriesQueryToSQLError, TimeSeriesQueryToSQLTransformer, TimeSeriesTable};
+use crate::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use crate::timeseries_query::{BasicTimeSeriesQuery, TimeSeriesQuery};
+use polars_core::datatypes::AnyValue;
+use polars_core::frame::DataFrame;
+use sea_query::{Alias, BinOper, ColumnRef, Query, SelectStatement, SimpleExpr, UnionType, Value};
+use spargebra::algebra::Expression;
+use spargebra::term::Variable;
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+impl TimeSeriesTable {
+    pub fn is_wide(&self) -> bool {
+        self.value_columns.is_some()
+    }
+
+    pub fn has_value_column_for(&self, id: &str) -> bool {
+        if let Some(value_columns) = &self.value_columns {
+            value_columns.contains_key(id)
+        } else {
+            false
+        }
+    }
+
+    fn value_column_for(&self, id: &str) -> Result<&String, TimeSeriesQueryToSQLError> {
+        self.value_columns.as_ref().and_then(|x| x.get(id)).ok_or(
+            TimeSeriesQueryToSQLError::MissingValueColumn(
+                id.to_string(),
+                self.time_series_table.clone(),
+            ),
+        )
+    }
+
+    //Unpivots the value columns of the ids, so that the result has the same shape as for long tables
+    pub(crate) fn create_wide_basic_query(
+        &self,
+        btsq: &BasicTimeSeriesQuery,
+        partition_kinds: &[PartitionKind],
+        timestamp_encoding: &TimestampEncoding,
+        dialect: &SqlDialect,
+    ) -> Result<(SelectStatement, HashSet<String>), TimeSeriesQueryToSQLError> {
+        if btsq.quality_variable.is_some() {
+            return Err(TimeSeriesQueryToSQLError::MissingQualityColumn(
+                self.time_series_table.clone(),
+            ));
+        }
+        let mut all_ids: Vec<String> = self
+            .value_columns
+            .as_ref()
+            .ok_or_else(|| {
+                TimeSeriesQueryToSQLError::NoValueColumns(self.time_series_table.clone())
+            })?
+            .keys()
+            .cloned()
+            .collect();
+        all_ids.sort();
+        let ids = match &btsq.ids {
+            Some(ids) if !ids.is_empty() => ids.iter().map(|x| x.to_string()).collect(),
+            Some(_) => {
+                //Keeps the shape of the result when no time series are queried
+                all_ids.truncate(1);
+                all_ids
+            }
+            None => all_ids,
+        };
+        let no_rows = btsq.ids.as_ref().map_or(false, |x| x.is_empty());
+        let identifier_variable =
+            required_variable(btsq.identifier_variable.as_ref(), "identifier")?;
+        let value_variable =
+            required_variable(btsq.value_variable.as_ref().map(|x| &x.variable), "value")?;
+        let timestamp_variable = required_variable(
+            btsq.timestamp_variable.as_ref().map(|x| &x.variable),
+            "timestamp",
+        )?;
+
+        let mut id_selects = vec![];
+        let mut columns = HashSet::new();
+        for id in &ids {
+            //Sorted by variable name in the same way as for long tables
+            let mut variable_expressions = BTreeMap::new();
+            variable_expressions.insert(
+                identifier_variable.clone(),
+                SimpleExpr::Value(Value::String(Some(Box::new(id.clone())))),
+            );
+            variable_expressions.insert(value_variable.clone(), column(self.value_column_for(id)?));
+            variable_expressions.insert(
+                timestamp_variable.clone(),
+                self.timestamp_expression(timestamp_encoding, dialect),
+            );
+            let mut id_select = Query::select();
+            for (k, v) in variable_expressions {
+                id_select.expr_as(v, Alias::new(&k));
+                columns.insert(k);
+            }
+            self.add_partition_projections(&mut id_select, &mut columns, partition_kinds, dialect);
+            self.add_from(&mut id_select);
+            if no_rows {
+                id_select.and_where(SimpleExpr::Value(Value::Bool(Some(false))));
+            }
+            id_selects.push(id_select);
+        }
+        let wide_select = wrap_union(id_selects, &columns).ok_or_else(|| {
+            TimeSeriesQueryToSQLError::NoValueColumns(self.time_series_table.clone())
+        })?;
+        Ok((wide_select, columns))
+    }
+}
+
+impl TimeSeriesQueryToSQLTransformer<'_> {
+    //Time series in the same wide table share the timestamp column, so they can be synchronized
+    // by reading their value columns side by side instead of joining them.
+    //Returns None when the synchronized queries are not all grouped basic queries of one wide table.
+    pub(crate) fn create_wide_synchronized(
+        &self,
+        inner: &Vec<Box<TimeSeriesQuery>>,
+        timestamp_col: &String,
+        groupby_col: &String,
+        project_date_partition: bool,
+    ) -> Result<Option<(SelectStatement, HashSet<String>)>, TimeSeriesQueryToSQLError> {
+        let mut wide_table: Option<&TimeSeriesTable> = None;
+        let mut basics = vec![];
+        for tsq in inner {
+            let (btsq, df, filters) = if let Some(grouped_basic) = grouped_basic_with_filters(tsq) {
+                grouped_basic
+            } else {
+                return Ok(None);
+            };
+            let routed = route_basic_query(self.tables, btsq)?;
+            if routed.len() != 1 {
+                return Ok(None);
+            }
+            let table = routed.get(0).unwrap().0;
+            if !table.is_wide() || wide_table.map_or(false, |x| !std::ptr::eq(x, table)) {
+                return Ok(None);
+            }
+            if btsq.quality_variable.is_some() {
+                return Err(TimeSeriesQueryToSQLError::MissingQualityColumn(
+                    table.time_series_table.clone(),
+                ));
+            }
+            wide_table = Some(table);
+            basics.push((btsq, ids_by_group(btsq, df, groupby_col)?, filters));
+        }
+        let table = if let Some(table) = wide_table {
+            table
+        } else {
+            return Ok(None);
+        };
+
+        let mut conditions = vec![];
+        let mut need_partition_columns = project_date_partition;
+        for (_, _, filters) in &basics {
+            for f in filters {
+                let (se, need_partition) =
+                    self.create_filter_expressions(f, Some(timestamp_col))?;
+                need_partition_columns = need_partition_columns || need_partition;
+                conditions.push(se);
+            }
+        }
+
+        //Synchronized queries are inner joined on the group, so only groups in all queries remain
+        let (_, first_groups, _) = basics.get(0).unwrap();
+        let groups: Vec<i64> = first_groups
+            .keys()
+            .filter(|g| basics.iter().all(|(_, x, _)| x.contains_key(g)))
+            .cloned()
+            .collect();
+
+        let mut group_selects = vec![];
+        let mut columns = HashSet::new();
+        for g in groups {
+            let mut id_combinations: Vec<Vec<&String>> = vec![vec![]];
+            for (_, ids, _) in &basics {
+                let mut new_combinations = vec![];
+                for c in &id_combinations {
+                    for id in ids.get(&g).unwrap() {
+                        let mut new_c = c.clone();
+                        new_c.push(id);
+                        new_combinations.push(new_c);
+                    }
+                }
+                id_combinations = new_combinations;
+            }
+            for c in id_combinations {
+                let mut group_select = Query::select();
+                group_select.expr_as(
+                    SimpleExpr::Value(Value::BigInt(Some(g))),
+                    Alias::new(groupby_col),
+                );
+                group_select.expr_as(
+                    table.timestamp_expression(&self.timestamp_encoding, &self.dialect),
+                    Alias::new(timestamp_col),
+                );
+                columns.insert(groupby_col.clone());
+                columns.insert(timestamp_col.clone());
+                for ((btsq, _, _), id) in basics.iter().zip(c) {
+                    let value_variable = required_variable(
+                        btsq.value_variable.as_ref().map(|x| &x.variable),
+                        "value",
+                    )?;
+                    group_select.expr_as(
+                        column(table.value_column_for(id)?),
+                        Alias::new(&value_variable),
+                    );
+                    columns.insert(value_variable);
+                }
+                if need_partition_columns {
+                    table.add_partition_projections(
+                        &mut group_select,
+                        &mut columns,
+                        &self.partition_kinds,
+                        &self.dialect,
+                    );
+                }
+                table.add_from(&mut group_select);
+                group_selects.push(group_select);
+            }
+        }
+
+        //Partition columns may be needed by the filters without being projected
+        if need_partition_columns && !project_date_partition {
+            remove_partition_columns(&mut columns);
+        }
+        let mut wide_select = if let Some(wide_select) = wrap_union(group_selects, &columns) {
+            wide_select
+        } else {
+            return Ok(None);
+        };
+        if !conditions.is_empty() {
+            let mut condition = conditions.remove(0);
+            for c in conditions {
+                condition = SimpleExpr::Binary(Box::new(condition), BinOper::And, Box::new(c));
+            }
+            wide_select.and_where(condition);
+        }
+        Ok(Some((wide_select, columns)))
+    }
+}
+
+fn grouped_basic_with_filters(
+    tsq: &TimeSeriesQuery,
+) -> Option<(&BasicTimeSeriesQuery, &DataFrame, Vec<&Expression>)> {
+    match tsq {
+        TimeSeriesQuery::GroupedBasic(btsq, df, _) => Some((btsq, df, vec![])),
+        TimeSeriesQuery::Filtered(inner, filter) => {
+            let (btsq, df, mut filters) = grouped_basic_with_filters(inner)?;
+            filters.push(filter);
+            Some((btsq, df, filters))
+        }
+        _ => None,
+    }
+}
+
+fn ids_by_group(
+    btsq: &BasicTimeSeriesQuery,
+    df: &DataFrame,
+    groupby_col: &str,
+) -> Result<BTreeMap<i64, Vec<String>>, TimeSeriesQueryToSQLError> {
+    let mut groups: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for (id, g) in id_group_pairs(btsq, df, groupby_col)? {
+        let ids = groups.entry(g).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    Ok(groups)
+}
+
+//The rows of the grouping of a grouped basic query, each a string id and an integer group
+pub(super) fn id_group_pairs(
+    btsq: &BasicTimeSeriesQuery,
+    df: &DataFrame,
+    groupby_col: &str,
+) -> Result<Vec<(String, i64)>, TimeSeriesQueryToSQLError> {
+    let identifier_colname = required_variable(btsq.identifier_variable.as_ref(), "identifier")?;
+    let grouping_column = |name: &str| {
+        df.column(name)
+            .map_err(|_| TimeSeriesQueryToSQLError::MissingGroupingColumn(name.to_string()))
+    };
+    let identifier_iter = grouping_column(&identifier_colname)?.iter();
+    let groupcol_iter = grouping_column(groupby_col)?.iter();
+    let mut pairs = vec![];
+    for (id, g) in identifier_iter.zip(groupcol_iter) {
+        if let (AnyValue::Utf8(id), AnyValue::Int64(g)) = (&id, &g) {
+            pairs.push((id.to_string(), *g));
+        } else {
+            return Err(TimeSeriesQueryToSQLError::InvalidGroupingValue(format!(
+                "{}, {}",
+                id, g
+            )));
+        }
+    }
+    Ok(pairs)
+}
+
+//Basic queries are completed before they are translated, so this only fails for invalid queries
+pub(super) fn required_variable(
+    variable: Option<&Variable>,
+    role: &str,
+) -> Result<String, TimeSeriesQueryToSQLError> {
+    variable
+        .map(|x| x.as_str().to_string())
+        .ok_or_else(|| TimeSeriesQueryToSQLError::MissingVariable(role.to_string()))
+}
+
+pub(super) fn column(name: &str) -> SimpleExpr {
+    SimpleExpr::Column(ColumnRef::Column(Rc::new(Name::Column(name.to_string()))))
+}
+
+//The selects must project the same columns in the same order, None if there are no selects
+fn wrap_union(selects: Vec<SelectStatement>, columns: &HashSet<String>) -> Option<SelectStatement> {
+    let mut selects = selects.into_iter();
+    let mut union_select = selects.next()?;
+    for s in selects {
+        union_select.union(UnionType::All, s);
+    }
+    let wide_name = "wide_query";
+    let mut wide_select = Query::select();
+    wide_select.from_subquery(union_select, Alias::new(wide_name));
+    let mut sorted_cols: Vec<&String> = columns.iter().collect();
+    sorted_cols.sort();
+    for c in sorted_cols {
+        wide_select.expr_as(
+            SimpleExpr::Column(ColumnRef::TableColumn(
+                Rc::new(Name::Table(wide_name.to_string())),
+                Rc::new(Name::Column(c.to_string())),
+            )),
+            Alias::new(c),
+        );
+    }
+    Some(wide_select)
+}
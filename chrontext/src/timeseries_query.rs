@@ -1,3 +1,4 @@
+use crate::external_ids::ExternalId;
 use crate::find_query_variables::{find_all_used_variables_in_aggregate_expression, find_all_used_variables_in_expression};
 use crate::query_context::{Context, VariableInContext};
 use oxrdf::NamedNode;
@@ -18,6 +19,7 @@ pub enum TimeSeriesQuery {
     InnerSynchronized(Vec<Box<TimeSeriesQuery>>, Vec<Synchronizer>),
     ExpressionAs(Box<TimeSeriesQuery>, Variable, Expression),
     Grouped(GroupedTimeSeriesQuery),
+    Limited(Box<TimeSeriesQuery>, usize),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,10 +41,14 @@ pub struct BasicTimeSeriesQuery {
     pub timeseries_variable: Option<VariableInContext>,
     pub data_point_variable: Option<VariableInContext>,
     pub value_variable: Option<VariableInContext>,
+    pub quality_variable: Option<VariableInContext>,
     pub datatype_variable: Option<Variable>,
     pub datatype: Option<NamedNode>,
+    pub table_variable: Option<Variable>,
+    //Tables annotated with chrontext:hasTable, by identifier
+    pub tables: Option<HashMap<String, String>>,
     pub timestamp_variable: Option<VariableInContext>,
-    pub ids: Option<Vec<String>>,
+    pub ids: Option<Vec<ExternalId>>,
 }
 
 impl BasicTimeSeriesQuery {
@@ -52,6 +58,9 @@ impl BasicTimeSeriesQuery {
         if let Some(vv) = &self.value_variable {
             expected_columns.insert(vv.variable.as_str());
         }
+        if let Some(qv) = &self.quality_variable {
+            expected_columns.insert(qv.variable.as_str());
+        }
         if let Some(tsv) = &self.timestamp_variable {
             expected_columns.insert(tsv.variable.as_str());
         }
@@ -134,6 +143,7 @@ impl TimeSeriesQuery {
                 expected
             }
             TimeSeriesQuery::ExpressionAs(t, ..) => t.expected_columns(),
+            TimeSeriesQuery::Limited(t, ..) => t.expected_columns(),
         }
     }
 
@@ -150,7 +160,7 @@ impl TimeSeriesQuery {
         false
     }
 
-    pub(crate) fn get_ids(&self) -> Vec<&String> {
+    pub(crate) fn get_ids(&self) -> Vec<&ExternalId> {
         match self {
             TimeSeriesQuery::Basic(b) => {
                 if let Some(ids) = &b.ids {
@@ -176,6 +186,7 @@ impl TimeSeriesQuery {
                 }
             }
             TimeSeriesQuery::ExpressionAs(tsq, ..) => tsq.get_ids(),
+            TimeSeriesQuery::Limited(tsq, ..) => tsq.get_ids(),
         }
     }
 
@@ -205,6 +216,30 @@ impl TimeSeriesQuery {
                 }
             }
             TimeSeriesQuery::ExpressionAs(t, ..) => t.get_value_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_value_variables(),
+        }
+    }
+
+    pub(crate) fn get_quality_variables(&self) -> Vec<&VariableInContext> {
+        match self {
+            TimeSeriesQuery::Basic(b) | TimeSeriesQuery::GroupedBasic(b, ..) => {
+                if let Some(quality_var) = &b.quality_variable {
+                    vec![quality_var]
+                } else {
+                    vec![]
+                }
+            }
+            TimeSeriesQuery::Filtered(inner, _) => inner.get_quality_variables(),
+            TimeSeriesQuery::InnerSynchronized(inners, _) => {
+                let mut vs = vec![];
+                for inner in inners {
+                    vs.extend(inner.get_quality_variables())
+                }
+                vs
+            }
+            TimeSeriesQuery::Grouped(grouped) => grouped.tsq.get_quality_variables(),
+            TimeSeriesQuery::ExpressionAs(t, ..) => t.get_quality_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_quality_variables(),
         }
     }
 
@@ -234,6 +269,7 @@ impl TimeSeriesQuery {
                 }
             }
             TimeSeriesQuery::ExpressionAs(t, ..) => t.get_identifier_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_identifier_variables(),
         }
     }
     
@@ -263,6 +299,37 @@ impl TimeSeriesQuery {
                 }
             }
             TimeSeriesQuery::ExpressionAs(t, ..) => t.get_datatype_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_datatype_variables(),
+        }
+    }
+
+    pub(crate) fn get_table_variables(&self) -> Vec<&Variable> {
+        match self {
+            TimeSeriesQuery::Basic(b) => {
+                if let Some(table_var) = &b.table_variable {
+                    vec![table_var]
+                } else {
+                    vec![]
+                }
+            }
+            TimeSeriesQuery::Filtered(inner, _) => inner.get_table_variables(),
+            TimeSeriesQuery::InnerSynchronized(inners, _) => {
+                let mut vs = vec![];
+                for inner in inners {
+                    vs.extend(inner.get_table_variables())
+                }
+                vs
+            }
+            TimeSeriesQuery::Grouped(grouped) => grouped.tsq.get_table_variables(),
+            TimeSeriesQuery::GroupedBasic(b, ..) => {
+                if let Some(table_var) = &b.table_variable {
+                    vec![table_var]
+                } else {
+                    vec![]
+                }
+            }
+            TimeSeriesQuery::ExpressionAs(t, ..) => t.get_table_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_table_variables(),
         }
     }
 
@@ -305,6 +372,7 @@ impl TimeSeriesQuery {
                 }
             }
             TimeSeriesQuery::ExpressionAs(t, ..) => t.get_timestamp_variables(),
+            TimeSeriesQuery::Limited(t, ..) => t.get_timestamp_variables(),
         }
     }
 }
@@ -316,8 +384,11 @@ impl BasicTimeSeriesQuery {
             timeseries_variable: None,
             data_point_variable: None,
             value_variable: None,
+            quality_variable: None,
             datatype_variable: None,
             datatype: None,
+            table_variable: None,
+            tables: None,
             timestamp_variable: None,
             ids: None,
         }
@@ -344,6 +415,7 @@ impl TimeSeriesQuery {
                 colname
             }
             TimeSeriesQuery::ExpressionAs(tsq, ..) => tsq.get_groupby_column(),
+            TimeSeriesQuery::Limited(tsq, ..) => tsq.get_groupby_column(),
             TimeSeriesQuery::Grouped(grouped) => grouped.tsq.get_groupby_column(),
         }
     }
@@ -367,6 +439,7 @@ impl TimeSeriesQuery {
                 colname
             }
             TimeSeriesQuery::ExpressionAs(tsq, ..) => tsq.get_groupby_mapping_df(),
+            TimeSeriesQuery::Limited(tsq, ..) => tsq.get_groupby_mapping_df(),
             TimeSeriesQuery::Grouped(grouped) => grouped.tsq.get_groupby_mapping_df(),
         }
     }
@@ -408,6 +481,7 @@ impl TimeSeriesQuery {
                 tsfs
             }
             TimeSeriesQuery::Grouped(tsq, ..) => tsq.tsq.get_timeseries_functions(context),
+            TimeSeriesQuery::Limited(tsq, ..) => tsq.get_timeseries_functions(context),
         }
     }
 
@@ -422,10 +496,18 @@ impl TimeSeriesQuery {
                 if let Some(v) = &b.value_variable.clone() {
                     map.insert(v.variable.as_str().to_string(), b.datatype.as_ref().unwrap().clone());
                 }
+                //Qualities are numeric codes, such as the OPC UA StatusCode
+                if let Some(q) = &b.quality_variable {
+                    map.insert(q.variable.as_str().to_string(), xsd::UNSIGNED_INT.into_owned());
+                }
                 map
             }
             TimeSeriesQuery::GroupedBasic(b, .. ) => {
-                HashMap::from([(b.value_variable.as_ref().unwrap().variable.as_str().to_string(), b.datatype.as_ref().unwrap().clone())])
+                let mut map = HashMap::from([(b.value_variable.as_ref().unwrap().variable.as_str().to_string(), b.datatype.as_ref().unwrap().clone())]);
+                if let Some(q) = &b.quality_variable {
+                    map.insert(q.variable.as_str().to_string(), xsd::UNSIGNED_INT.into_owned());
+                }
+                map
             }
             TimeSeriesQuery::Filtered(tsq, _) => tsq.get_datatype_map(),
             TimeSeriesQuery::InnerSynchronized(tsqs, _) => {
@@ -464,6 +546,7 @@ impl TimeSeriesQuery {
                 }
                 map
             }
+            TimeSeriesQuery::Limited(tsq, ..) => tsq.get_datatype_map(),
         }
     }
 }
@@ -7,7 +7,7 @@ use polars_core::frame::DataFrame;
 use polars_core::prelude::TimeUnit;
 use std::collections::HashMap;
 use std::ops::{Div, Mul};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 const OPCUA_AGG_FUNC_AVERAGE: u32 = 2342;
 #[allow(dead_code)]
@@ -17,19 +17,30 @@ const OPCUA_AGG_FUNC_MINIMUM: u32 = 2346;
 #[allow(dead_code)]
 const OPCUA_AGG_FUNC_MAXIMUM: u32 = 2347;
 const OPCUA_AGG_FUNC_TOTAL: u32 = 2344;
+const OPCUA_AGG_FUNC_TIME_AVERAGE: u32 = ObjectId::AggregateFunction_TimeAverage as u32;
+const OPCUA_AGG_FUNC_DELTA: u32 = ObjectId::AggregateFunction_Delta as u32;
+
+//The aggregate types of processed reads, so that tests can check what was pushed down
+pub static AGGREGATE_TYPES_READ: Mutex<Vec<NodeId>> = Mutex::new(vec![]);
 
 pub struct OPCUADataProvider {
     pub frames: HashMap<String, DataFrame>,
+    //When set, at most this many values are returned per node along with a continuation point
+    pub page_size: Option<usize>,
 }
 
 impl OPCUADataProvider {
     #[allow(dead_code)]
     pub fn new(frames: HashMap<String, DataFrame>) -> OPCUADataProvider {
-        OPCUADataProvider { frames }
+        OPCUADataProvider {
+            frames,
+            page_size: None,
+        }
     }
 
     fn read(
         &self,
+        release_continuation_points: bool,
         nodes_to_read: &[HistoryReadValueId],
         aggregation_types: Option<Vec<NodeId>>,
         start_time: &DateTime,
@@ -38,6 +49,17 @@ impl OPCUADataProvider {
     ) -> Result<Vec<HistoryReadResult>, StatusCode> {
         let mut results = vec![];
         for (i, n) in nodes_to_read.iter().enumerate() {
+            if release_continuation_points {
+                let h = HistoryData {
+                    data_values: Some(vec![]),
+                };
+                results.push(HistoryReadResult {
+                    status_code: StatusCode::Good,
+                    continuation_point: Default::default(),
+                    history_data: ExtensionObject::from_encodable(h.object_id(), &h),
+                });
+                continue;
+            }
             let NodeId {
                 namespace: _,
                 identifier,
@@ -88,6 +110,10 @@ impl OPCUADataProvider {
                     agg_col = Some(match agg_func_i {
                         &OPCUA_AGG_FUNC_AVERAGE => col("value").mean(),
                         &OPCUA_AGG_FUNC_TOTAL => col("value").sum(),
+                        //The values are evenly spaced, so the time average is the average
+                        &OPCUA_AGG_FUNC_TIME_AVERAGE => col("value").mean(),
+                        //The values of each group are in timestamp order
+                        &OPCUA_AGG_FUNC_DELTA => col("value").last() - col("value").first(),
                         _ => {
                             unimplemented!(
                                 "We do not support this aggregation function: {}",
@@ -99,6 +125,23 @@ impl OPCUADataProvider {
                 lf = lfgr.agg([agg_col.unwrap().alias("value")]);
             }
             df = lf.collect().unwrap();
+            //The continuation point is just the offset of the next page
+            let offset: usize = if n.continuation_point.is_null() {
+                0
+            } else {
+                String::from_utf8(n.continuation_point.value.clone().unwrap())
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            };
+            let mut continuation_point = ByteString::null();
+            if let Some(page_size) = self.page_size {
+                if offset + page_size < df.height() {
+                    continuation_point =
+                        ByteString::from((offset + page_size).to_string().into_bytes());
+                }
+                df = df.slice(offset as i64, page_size);
+            }
             let mut ts_iter = df.column("timestamp").unwrap().iter();
             let mut v_iter = df.column("value").unwrap().iter();
             let mut data_values = vec![];
@@ -147,7 +190,7 @@ impl OPCUADataProvider {
             };
             let r = HistoryReadResult {
                 status_code: StatusCode::Good,
-                continuation_point: Default::default(),
+                continuation_point,
                 history_data: ExtensionObject::from_encodable(h.object_id(), &h),
             };
             results.push(r);
@@ -162,10 +205,11 @@ impl HistoricalDataProvider for OPCUADataProvider {
         _address_space: Arc<RwLock<AddressSpace>>,
         request: ReadRawModifiedDetails,
         _timestamps_to_return: TimestampsToReturn,
-        _release_continuation_points: bool,
+        release_continuation_points: bool,
         nodes_to_read: &[HistoryReadValueId],
     ) -> Result<Vec<HistoryReadResult>, StatusCode> {
         self.read(
+            release_continuation_points,
             nodes_to_read,
             None,
             &request.start_time,
@@ -179,10 +223,17 @@ impl HistoricalDataProvider for OPCUADataProvider {
         _address_space: Arc<RwLock<AddressSpace>>,
         request: ReadProcessedDetails,
         _timestamps_to_return: TimestampsToReturn,
-        _release_continuation_points: bool,
+        release_continuation_points: bool,
         nodes_to_read: &[HistoryReadValueId],
     ) -> Result<Vec<HistoryReadResult>, StatusCode> {
+        if let Some(aggregate_types) = &request.aggregate_type {
+            AGGREGATE_TYPES_READ
+                .lock()
+                .unwrap()
+                .extend(aggregate_types.iter().cloned());
+        }
         self.read(
+            release_continuation_points,
             nodes_to_read,
             Some(request.aggregate_type.unwrap()),
             &request.start_time,
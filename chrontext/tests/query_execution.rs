@@ -1,7 +1,7 @@
 mod common;
 
 use chrontext::engine::Engine;
-use chrontext::pushdown_setting::all_pushdowns;
+use chrontext::pushdown_setting::PushdownSetting;
 use chrontext::splitter::parse_sparql_select_query;
 use chrontext::static_sparql::execute_sparql_query;
 use chrontext::timeseries_database::simple_in_memory_timeseries::InMemoryTimeseriesDatabase;
@@ -11,7 +11,7 @@ use polars::prelude::{CsvReader, SerReader};
 use rstest::*;
 use serial_test::serial;
 use sparesults::QuerySolution;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::PathBuf;
 
@@ -74,7 +74,7 @@ fn inmem_time_series_database(testdata_path: PathBuf) -> InMemoryTimeseriesDatab
 
 #[fixture]
 fn engine(inmem_time_series_database: InMemoryTimeseriesDatabase) -> Engine {
-    Engine::new(all_pushdowns(), Box::new(inmem_time_series_database), QUERY_ENDPOINT.to_string())
+    Engine::new(Box::new(inmem_time_series_database), QUERY_ENDPOINT.to_string())
 }
 
 #[rstest]
@@ -265,6 +265,56 @@ async fn test_pushdown_group_by_hybrid_query(
     // println!("{}", df);
 }
 
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_group_by_without_pushdown_hybrid_query(
+    #[future] with_testdata: (),
+    inmem_time_series_database: InMemoryTimeseriesDatabase,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let mut engine = Engine::new_with_pushdown_settings(
+        HashSet::from([PushdownSetting::ValueConditions]),
+        Box::new(inmem_time_series_database),
+        QUERY_ENDPOINT.to_string(),
+    );
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w (SUM(?v) as ?sum_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime) .
+    } GROUP BY ?w
+    "#;
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_pushdown_group_by_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    assert_eq!(expected_df, df);
+}
+
 #[rstest]
 #[tokio::test]
 #[serial]
@@ -1124,3 +1174,117 @@ async fn test_coalesce_query(
     // writer.finish(&mut df).expect("writeok");
     // println!("{}", df);
 }
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_limit_offset_query(
+    #[future] with_testdata: (),
+    mut engine: Engine,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+    } ORDER BY DESC(?v) LIMIT 3 OFFSET 1
+    "#;
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_limit_offset_query.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+    // let file = File::create(file_path.as_path()).expect("could not open file");
+    // let writer = CsvWriter::new(file);
+    // writer.finish(&mut df).expect("writeok");
+    // println!("{}", df);
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_pushdown_limit_query(
+    #[future] with_testdata: (),
+    mut engine: Engine,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+    } LIMIT 5
+    "#;
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error");
+    assert_eq!(df.height(), 5);
+    assert_eq!(df.get_column_names(), vec!["w", "s", "t", "v"]);
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_reduced_query(
+    #[future] with_testdata: (),
+    mut engine: Engine,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT REDUCED ?w (IF(?v>300,?v,300) as ?v_with_min) WHERE {
+        ?w types:hasSensor/chrontext:hasTimeseries/chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasValue ?v .
+    }
+    "#;
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["w", "v_with_min"], vec![false], false).unwrap();
+    //Reduced has the same semantics as distinct in chrontext
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_distinct_query.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error")
+        .sort(&["w", "v_with_min"], vec![false], false).unwrap();
+    assert_eq!(expected_df, df);
+}
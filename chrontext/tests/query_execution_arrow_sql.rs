@@ -9,7 +9,6 @@ use bollard::models::{HostConfig, PortBinding};
 use bollard::Docker;
 use futures_util::stream::StreamExt;
 use chrontext::engine::Engine;
-use chrontext::pushdown_setting::all_pushdowns;
 use chrontext::timeseries_database::arrow_flight_sql_database::ArrowFlightSQLDatabase;
 use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
 use log::debug;
@@ -191,14 +190,13 @@ async fn with_sparql_testdata(#[future] sparql_endpoint: (), mut shared_testdata
 fn timeseries_table() -> TimeSeriesTable {
     TimeSeriesTable {
         schema: Some("my_nas".to_string()),
-        time_series_table: "ts.parquet".to_string(),
-        value_column: "v".to_string(),
-        timestamp_column: "ts".to_string(),
-        identifier_column: "id".to_string(),
-        value_datatype: xsd::UNSIGNED_INT.into_owned(),
-        year_column: None,
-        month_column: None,
-        day_column: None,
+        ..TimeSeriesTable::new(
+            "ts.parquet".to_string(),
+            "v".to_string(),
+            "ts".to_string(),
+            "id".to_string(),
+            xsd::UNSIGNED_INT.into_owned(),
+        )
     }
 }
 
@@ -333,7 +331,7 @@ async fn test_simple_hybrid_query(
         FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
     }
     "#;
-    let mut engine = Engine::new(all_pushdowns(), Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
     let mut df = engine
         .execute_hybrid_query(query)
         .await
@@ -1,7 +1,6 @@
 mod common;
 
 use chrontext::engine::Engine;
-use chrontext::pushdown_setting::all_pushdowns;
 use chrontext::timeseries_database::simple_in_memory_timeseries::InMemoryTimeseriesDatabase;
 use log::debug;
 use polars::prelude::{CsvReader, SerReader};
@@ -72,7 +71,7 @@ fn inmem_time_series_database(testdata_path: PathBuf) -> InMemoryTimeseriesDatab
 
 #[fixture]
 fn engine(inmem_time_series_database: InMemoryTimeseriesDatabase) -> Engine {
-    Engine::new(all_pushdowns(), Box::new(inmem_time_series_database), QUERY_ENDPOINT.to_string())
+    Engine::new(Box::new(inmem_time_series_database), QUERY_ENDPOINT.to_string())
 }
 
 #[rstest]
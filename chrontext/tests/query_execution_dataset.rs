@@ -0,0 +1,252 @@
+mod common;
+
+use crate::common::{add_sparql_testdata, start_sparql_container, QUERY_ENDPOINT};
+use chrontext::engine::Engine;
+use chrontext::timeseries_database::dataset_database::{DatasetDatabase, DatasetFormat};
+use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+use log::debug;
+use oxrdf::vocab::xsd;
+use polars::prelude::{
+    CsvReader, DataFrame, IpcWriter, NamedFrom, ParquetWriter, SerReader, SerWriter, Series,
+};
+use polars_core::datatypes::DataType;
+use polars_core::prelude::TimeUnit;
+use rstest::*;
+use serial_test::serial;
+use std::fs::File;
+use std::path::PathBuf;
+
+#[fixture]
+fn use_logger() {
+    let res = env_logger::try_init();
+    match res {
+        Ok(_) => {}
+        Err(_) => {
+            debug!("Tried to initialize logger which is already initialize")
+        }
+    }
+}
+
+#[fixture]
+fn testdata_path() -> PathBuf {
+    let manidir = env!("CARGO_MANIFEST_DIR");
+    let mut testdata_path = PathBuf::new();
+    testdata_path.push(manidir);
+    testdata_path.push("tests");
+    testdata_path.push("query_execution_testdata");
+    testdata_path
+}
+
+#[fixture]
+async fn sparql_endpoint() {
+    start_sparql_container().await
+}
+
+#[fixture]
+async fn with_testdata(#[future] sparql_endpoint: (), testdata_path: PathBuf) {
+    let _ = sparql_endpoint.await;
+    let mut testdata_path = testdata_path.clone();
+    testdata_path.push("testdata.sparql");
+    add_sparql_testdata(testdata_path).await;
+}
+
+#[fixture]
+fn timeseries_table() -> TimeSeriesTable {
+    TimeSeriesTable {
+        year_column: Some("year".to_string()),
+        month_column: Some("month".to_string()),
+        day_column: Some("day".to_string()),
+        ..TimeSeriesTable::new(
+            "ts".to_string(),
+            "v".to_string(),
+            "ts".to_string(),
+            "id".to_string(),
+            xsd::UNSIGNED_INT.into_owned(),
+        )
+    }
+}
+
+fn partition_dir(root: &PathBuf, partition: (i32, u32, u32)) -> PathBuf {
+    let (year, month, day) = partition;
+    let mut path = root.clone();
+    path.push("ts");
+    path.push(format!("year={}", year));
+    path.push(format!("month={}", month));
+    path.push(format!("day={}", day));
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn write_partition(
+    root: &PathBuf,
+    format: DatasetFormat,
+    partition: (i32, u32, u32),
+    name: &str,
+    df: &mut DataFrame,
+) {
+    let mut path = partition_dir(root, partition);
+    match format {
+        DatasetFormat::Parquet => {
+            path.push(format!("{}.parquet", name));
+            let file = File::create(path).unwrap();
+            ParquetWriter::new(file).finish(df).unwrap();
+        }
+        DatasetFormat::ArrowIPC => {
+            path.push(format!("{}.arrow", name));
+            let file = File::create(path).unwrap();
+            IpcWriter::new(file).finish(df).unwrap();
+        }
+    }
+}
+
+fn write_unreadable_partition(
+    root: &PathBuf,
+    format: DatasetFormat,
+    partition: (i32, u32, u32),
+    name: &str,
+) {
+    let mut path = partition_dir(root, partition);
+    match format {
+        DatasetFormat::Parquet => path.push(format!("{}.parquet", name)),
+        DatasetFormat::ArrowIPC => path.push(format!("{}.arrow", name)),
+    }
+    std::fs::write(path, b"not a dataset file").unwrap();
+}
+
+fn dataset_db(
+    format: DatasetFormat,
+    timeseries_table: TimeSeriesTable,
+    testdata_path: &PathBuf,
+) -> DatasetDatabase {
+    let mut root = std::env::temp_dir();
+    root.push(format!("chrontext_dataset_{:?}", format));
+    if root.exists() {
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    for t in ["ts1", "ts2"] {
+        let mut file_path = testdata_path.clone();
+        file_path.push(t.to_string() + ".csv");
+        let file = File::open(file_path.as_path()).expect("could not open file");
+        let mut df = CsvReader::new(file)
+            .infer_schema(None)
+            .has_header(true)
+            .with_try_parse_dates(true)
+            .finish()
+            .expect("DF read error");
+        df.rename("timestamp", "ts").unwrap();
+        df.rename("value", "v").unwrap();
+        df.with_column(Series::new("id", vec![t; df.height()]))
+            .unwrap();
+        write_partition(&root, format, (2022, 6, 1), t, &mut df);
+
+        //Outside of the timestamp filter, the file can not be read so the queries fail unless it is pruned
+        write_unreadable_partition(&root, format, (2021, 1, 1), t);
+    }
+    DatasetDatabase::new(root, format, vec![timeseries_table])
+}
+
+#[rstest]
+#[case(DatasetFormat::Parquet)]
+#[case(DatasetFormat::ArrowIPC)]
+#[tokio::test]
+#[serial]
+async fn test_simple_hybrid_query(
+    #[case] format: DatasetFormat,
+    #[future] with_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let db = dataset_db(format, timeseries_table, &testdata_path);
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
+    }
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["t"], vec![false], false)
+        .expect("Sort error");
+    df.with_column(
+        df.column("t")
+            .unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Microseconds, None))
+            .unwrap(),
+    )
+    .unwrap();
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_simple_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[case(DatasetFormat::Parquet)]
+#[case(DatasetFormat::ArrowIPC)]
+#[tokio::test]
+#[serial]
+async fn test_pushdown_group_by_hybrid_query(
+    #[case] format: DatasetFormat,
+    #[future] with_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let db = dataset_db(format, timeseries_table, &testdata_path);
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w (SUM(?v) as ?sum_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime) .
+    } GROUP BY ?w
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_pushdown_group_by_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    assert_eq!(expected_df, df);
+}
@@ -0,0 +1,285 @@
+mod common;
+
+use crate::common::{add_sparql_testdata, start_sparql_container, QUERY_ENDPOINT};
+use chrontext::engine::Engine;
+use chrontext::timeseries_database::embedded_sql_database::EmbeddedSQLDatabase;
+use chrontext::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+use log::debug;
+use oxrdf::vocab::xsd;
+use polars::prelude::{CsvReader, SerReader};
+use polars_core::datatypes::DataType;
+use polars_core::prelude::TimeUnit;
+use rstest::*;
+use serial_test::serial;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+#[fixture]
+fn use_logger() {
+    let res = env_logger::try_init();
+    match res {
+        Ok(_) => {}
+        Err(_) => {
+            debug!("Tried to initialize logger which is already initialize")
+        }
+    }
+}
+
+#[fixture]
+fn testdata_path() -> PathBuf {
+    let manidir = env!("CARGO_MANIFEST_DIR");
+    let mut testdata_path = PathBuf::new();
+    testdata_path.push(manidir);
+    testdata_path.push("tests");
+    testdata_path.push("query_execution_testdata");
+    testdata_path
+}
+
+#[fixture]
+async fn sparql_endpoint() {
+    start_sparql_container().await
+}
+
+#[fixture]
+async fn with_testdata(#[future] sparql_endpoint: (), testdata_path: PathBuf) {
+    let _ = sparql_endpoint.await;
+    let mut testdata_path = testdata_path.clone();
+    testdata_path.push("testdata.sparql");
+    add_sparql_testdata(testdata_path).await;
+}
+
+#[fixture]
+async fn with_integer_id_testdata(#[future] sparql_endpoint: (), testdata_path: PathBuf) {
+    let _ = sparql_endpoint.await;
+    let mut testdata_path = testdata_path.clone();
+    testdata_path.push("testdata_integer_ids.sparql");
+    add_sparql_testdata(testdata_path).await;
+}
+
+#[fixture]
+fn timeseries_table() -> TimeSeriesTable {
+    TimeSeriesTable::new(
+        "ts".to_string(),
+        "v".to_string(),
+        "ts".to_string(),
+        "id".to_string(),
+        xsd::UNSIGNED_INT.into_owned(),
+    )
+}
+
+fn embedded_db(
+    sql_dialect: SqlDialect,
+    timeseries_table: TimeSeriesTable,
+    testdata_path: &PathBuf,
+) -> EmbeddedSQLDatabase {
+    embedded_db_with_ids(
+        sql_dialect,
+        timeseries_table,
+        testdata_path,
+        "VARCHAR",
+        ["'ts1'", "'ts2'"],
+    )
+}
+
+//The ids are SQL literals of the id column type, given for the time series in ts1.csv and ts2.csv
+fn embedded_db_with_ids(
+    sql_dialect: SqlDialect,
+    timeseries_table: TimeSeriesTable,
+    testdata_path: &PathBuf,
+    id_type: &str,
+    ids: [&str; 2],
+) -> EmbeddedSQLDatabase {
+    let db = EmbeddedSQLDatabase::open_in_memory(sql_dialect, vec![timeseries_table]).unwrap();
+    db.execute_batch(&format!(
+        "CREATE TABLE ts (id {}, ts TIMESTAMP, v BIGINT);",
+        id_type
+    ))
+    .unwrap();
+    for (t, id) in ["ts1", "ts2"].into_iter().zip(ids) {
+        let mut file_path = testdata_path.clone();
+        file_path.push(t.to_string() + ".csv");
+        let file = File::open(file_path.as_path()).expect("could not open file");
+        for line in BufReader::new(file).lines().skip(1) {
+            let line = line.unwrap();
+            let (timestamp, value) = line.split_once(",").unwrap();
+            db.execute_batch(&format!(
+                "INSERT INTO ts VALUES ({}, '{}', {});",
+                id,
+                timestamp.replace("T", " "),
+                value
+            ))
+            .unwrap();
+        }
+    }
+    db
+}
+
+#[rstest]
+#[case(SqlDialect::SQLite)]
+#[case(SqlDialect::DuckDB)]
+#[tokio::test]
+#[serial]
+async fn test_simple_hybrid_query(
+    #[case] sql_dialect: SqlDialect,
+    #[future] with_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let db = embedded_db(sql_dialect, timeseries_table, &testdata_path);
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
+    }
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["t"], vec![false], false)
+        .expect("Sort error");
+    df.with_column(
+        df.column("t")
+            .unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Microseconds, None))
+            .unwrap(),
+    )
+    .unwrap();
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_simple_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[case(SqlDialect::SQLite)]
+#[case(SqlDialect::DuckDB)]
+#[tokio::test]
+#[serial]
+async fn test_simple_hybrid_query_with_integer_ids(
+    #[case] sql_dialect: SqlDialect,
+    #[future] with_integer_id_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_integer_id_testdata.await;
+    let db = embedded_db_with_ids(
+        sql_dialect,
+        timeseries_table,
+        &testdata_path,
+        "BIGINT",
+        ["1", "2"],
+    );
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
+    }
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["t"], vec![false], false)
+        .expect("Sort error");
+    df.with_column(
+        df.column("t")
+            .unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Microseconds, None))
+            .unwrap(),
+    )
+    .unwrap();
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_simple_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[case(SqlDialect::SQLite)]
+#[case(SqlDialect::DuckDB)]
+#[tokio::test]
+#[serial]
+async fn test_pushdown_group_by_hybrid_query(
+    #[case] sql_dialect: SqlDialect,
+    #[future] with_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let db = embedded_db(sql_dialect, timeseries_table, &testdata_path);
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w (SUM(?v) as ?sum_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime) .
+    } GROUP BY ?w
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_pushdown_group_by_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error")
+        .sort(&["w"], vec![false], false)
+        .expect("Sort error");
+    assert_eq!(expected_df, df);
+}
@@ -0,0 +1,653 @@
+mod common;
+
+use crate::common::{add_sparql_testdata, start_sparql_container, QUERY_ENDPOINT};
+use arrow2::array::{
+    Array, BooleanArray, Int32Array, Int64Array, ListArray, UInt32Array, UnionArray, Utf8Array,
+};
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType as ArrowDataType, Field, Schema, UnionMode};
+use arrow2::io::flight as flight2;
+use arrow2::io::ipc::write::{default_ipc_fields, WriteOptions};
+use arrow2::offset::Offsets;
+use arrow_format::flight::data::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, Location, PutResult, Result as FlightResult, SchemaResult,
+    Ticket,
+};
+use arrow_format::flight::service::flight_service_server::{FlightService, FlightServiceServer};
+use chrontext::engine::Engine;
+use chrontext::timeseries_database::arrow_flight_sql_database::flight_sql::{
+    ActionClosePreparedStatementRequest, ActionCreatePreparedStatementRequest,
+    ActionCreatePreparedStatementResult, CommandGetSqlInfo, CommandPreparedStatementQuery,
+    CommandStatementQuery, FlightSqlMessage, CLOSE_PREPARED_STATEMENT, CREATE_PREPARED_STATEMENT,
+    FLIGHT_SQL_SERVER_NAME,
+};
+use chrontext::timeseries_database::arrow_flight_sql_database::ArrowFlightSQLDatabase;
+use chrontext::timeseries_database::capabilities::ExpressionKind;
+use chrontext::timeseries_database::embedded_sql_database::EmbeddedSQLDatabase;
+use chrontext::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+use chrontext::timeseries_database::TimeSeriesQueryable;
+use futures_util::Stream;
+use log::debug;
+use oxrdf::vocab::xsd;
+use polars::prelude::{df, CsvReader, DataFrame, NamedFrom, SerReader};
+use polars_core::datatypes::{AnyValue, DataType};
+use polars_core::prelude::TimeUnit;
+use rstest::*;
+use serial_test::serial;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+use tonic::{Request, Response, Status, Streaming};
+
+type BoxedStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + 'static>>;
+
+//A minimal Flight SQL server answering statements, prepared statements and GetSqlInfo from DuckDB
+struct TestFlightSqlServer {
+    db: Mutex<EmbeddedSQLDatabase>,
+    prepared_statements: Mutex<HashMap<Vec<u8>, (String, Option<DataFrame>)>>,
+    //A new token is issued for each handshake, setting it to something else expires it
+    valid_token: Arc<Mutex<String>>,
+    handshakes: Mutex<u32>,
+    location: String,
+}
+
+impl TestFlightSqlServer {
+    fn check_token<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|x| x.to_str().ok());
+        if token == Some(self.valid_token.lock().unwrap().as_str()) {
+            Ok(())
+        } else {
+            Err(Status::unauthenticated("Token expired"))
+        }
+    }
+
+    fn query(&self, query: &str) -> Result<DataFrame, Status> {
+        debug!("Test server executing {}", query);
+        self.db
+            .lock()
+            .unwrap()
+            .execute_sql_query(query, &HashMap::new())
+            .map_err(|x| Status::internal(x.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for TestFlightSqlServer {
+    type HandshakeStream = BoxedStream<HandshakeResponse>;
+    type ListFlightsStream = BoxedStream<FlightInfo>;
+    type DoGetStream = BoxedStream<FlightData>;
+    type DoPutStream = BoxedStream<PutResult>;
+    type DoExchangeStream = BoxedStream<FlightData>;
+    type DoActionStream = BoxedStream<FlightResult>;
+    type ListActionsStream = BoxedStream<ActionType>;
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        let token = {
+            let mut handshakes = self.handshakes.lock().unwrap();
+            *handshakes += 1;
+            format!("Bearer token{}", handshakes)
+        };
+        *self.valid_token.lock().unwrap() = token.clone();
+        let mut response: Response<Self::HandshakeStream> =
+            Response::new(Box::pin(tokio_stream::iter(vec![Ok(HandshakeResponse {
+                protocol_version: 2,
+                payload: vec![],
+            })])));
+        response
+            .metadata_mut()
+            .insert("authorization", token.parse().unwrap());
+        Ok(response)
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("list_flights"))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.check_token(&request)?;
+        let descriptor = request.into_inner();
+        //Only proper Flight SQL commands are accepted, not raw SQL
+        if CommandStatementQuery::from_any(&descriptor.cmd)
+            .ok()
+            .flatten()
+            .is_none()
+            && CommandGetSqlInfo::from_any(&descriptor.cmd)
+                .ok()
+                .flatten()
+                .is_none()
+            && CommandPreparedStatementQuery::from_any(&descriptor.cmd)
+                .ok()
+                .flatten()
+                .is_none()
+        {
+            return Err(Status::invalid_argument("Expected a Flight SQL command"));
+        }
+        let mut endpoint = vec![FlightEndpoint {
+            ticket: Some(Ticket {
+                ticket: descriptor.cmd.clone(),
+            }),
+            ..Default::default()
+        }];
+        //Statement results get a second, empty endpoint that must be fetched from its location
+        if let Some(cmd) = CommandStatementQuery::from_any(&descriptor.cmd).unwrap() {
+            let empty_cmd = CommandStatementQuery {
+                query: format!("SELECT * FROM ({}) AS q WHERE false", cmd.query),
+                transaction_id: None,
+            };
+            endpoint.push(FlightEndpoint {
+                ticket: Some(Ticket {
+                    ticket: empty_cmd.as_any(),
+                }),
+                location: vec![Location {
+                    uri: self.location.clone(),
+                }],
+            });
+        }
+        Ok(Response::new(FlightInfo {
+            endpoint,
+            flight_descriptor: Some(descriptor),
+            total_records: -1,
+            total_bytes: -1,
+            ..Default::default()
+        }))
+    }
+
+    async fn get_schema(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("get_schema"))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        self.check_token(&request)?;
+        let ticket = request.into_inner().ticket;
+        let flight_data = if let Some(cmd) = CommandStatementQuery::from_any(&ticket).unwrap() {
+            dataframe_to_flight_data(self.query(&cmd.query)?)
+        } else if CommandGetSqlInfo::from_any(&ticket).unwrap().is_some() {
+            sql_info_flight_data()
+        } else if let Some(cmd) = CommandPreparedStatementQuery::from_any(&ticket).unwrap() {
+            let (mut query, parameters) = self
+                .prepared_statements
+                .lock()
+                .unwrap()
+                .get(&cmd.prepared_statement_handle)
+                .cloned()
+                .ok_or(Status::not_found("No such prepared statement"))?;
+            if let Some(parameters) = parameters {
+                for c in parameters.get_columns() {
+                    let literal = match c.get(0).unwrap() {
+                        AnyValue::Utf8(s) => format!("'{}'", s),
+                        other => other.to_string(),
+                    };
+                    query = query.replacen("?", &literal, 1);
+                }
+            }
+            dataframe_to_flight_data(self.query(&query)?)
+        } else {
+            return Err(Status::invalid_argument("Unknown ticket"));
+        };
+        Ok(Response::new(Box::pin(tokio_stream::iter(
+            flight_data.into_iter().map(Ok),
+        ))))
+    }
+
+    async fn do_put(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        self.check_token(&request)?;
+        let mut stream = request.into_inner();
+        let first = stream.next().await.unwrap()?;
+        let cmd = CommandPreparedStatementQuery::from_any(&first.flight_descriptor.unwrap().cmd)
+            .unwrap()
+            .unwrap();
+        let (schema, ipc_schema) = flight2::deserialize_schemas(&first.data_header).unwrap();
+        let mut parameters = None;
+        while let Some(flight_data) = stream.next().await {
+            let chunk = flight2::deserialize_batch(
+                &flight_data?,
+                schema.fields.as_slice(),
+                &ipc_schema,
+                &Default::default(),
+            )
+            .unwrap();
+            parameters = Some(DataFrame::try_from((chunk, schema.fields.as_slice())).unwrap());
+        }
+        self.prepared_statements
+            .lock()
+            .unwrap()
+            .get_mut(&cmd.prepared_statement_handle)
+            .unwrap()
+            .1 = parameters;
+        Ok(Response::new(Box::pin(tokio_stream::iter(vec![]))))
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("do_exchange"))
+    }
+
+    async fn do_action(
+        &self,
+        request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        self.check_token(&request)?;
+        let action = request.into_inner();
+        let mut results = vec![];
+        if action.r#type == CREATE_PREPARED_STATEMENT {
+            let create = ActionCreatePreparedStatementRequest::from_any(&action.body)
+                .unwrap()
+                .unwrap();
+            let mut prepared_statements = self.prepared_statements.lock().unwrap();
+            let handle = format!("statement{}", prepared_statements.len()).into_bytes();
+            prepared_statements.insert(handle.clone(), (create.query, None));
+            results.push(Ok(FlightResult {
+                body: ActionCreatePreparedStatementResult {
+                    prepared_statement_handle: handle,
+                    dataset_schema: vec![],
+                    parameter_schema: vec![],
+                }
+                .as_any(),
+            }));
+        } else if action.r#type == CLOSE_PREPARED_STATEMENT {
+            let close = ActionClosePreparedStatementRequest::from_any(&action.body)
+                .unwrap()
+                .unwrap();
+            self.prepared_statements
+                .lock()
+                .unwrap()
+                .remove(&close.prepared_statement_handle);
+        } else {
+            return Err(Status::unimplemented(action.r#type));
+        }
+        Ok(Response::new(Box::pin(tokio_stream::iter(results))))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("list_actions"))
+    }
+}
+
+//String columns are dictionary encoded, as many Flight SQL servers do for identifiers
+fn dataframe_to_flight_data(mut df: DataFrame) -> Vec<FlightData> {
+    df.rechunk();
+    for c in df.get_column_names_owned() {
+        if df.column(&c).unwrap().dtype() == &DataType::Utf8 {
+            let categorical = df
+                .column(&c)
+                .unwrap()
+                .cast(&DataType::Categorical(None))
+                .unwrap();
+            df.with_column(categorical).unwrap();
+        }
+    }
+    let schema = df.schema().to_arrow();
+    let ipc_fields = default_ipc_fields(&schema.fields);
+    let mut flight_data = vec![flight2::serialize_schema(&schema, Some(&ipc_fields))];
+    for chunk in df.iter_chunks() {
+        let (dictionaries, batch) =
+            flight2::serialize_batch(&chunk, &ipc_fields, &WriteOptions { compression: None })
+                .unwrap();
+        flight_data.extend(dictionaries);
+        flight_data.push(batch);
+    }
+    flight_data
+}
+
+fn sql_info_flight_data() -> Vec<FlightData> {
+    let string_list_type =
+        ArrowDataType::List(Box::new(Field::new("item", ArrowDataType::Utf8, true)));
+    let union_fields = vec![
+        Field::new("string_value", ArrowDataType::Utf8, true),
+        Field::new("bool_value", ArrowDataType::Boolean, true),
+        Field::new("bigint_value", ArrowDataType::Int64, true),
+        Field::new("int32_bitmask", ArrowDataType::Int32, true),
+        Field::new("string_list", string_list_type.clone(), true),
+    ];
+    let union_type = ArrowDataType::Union(union_fields, None, UnionMode::Dense);
+    let string_list = ListArray::<i32>::new(
+        string_list_type,
+        Offsets::try_from(vec![0, 2]).unwrap().into(),
+        Utf8Array::<i32>::from_slice(["date_part", "epoch"]).boxed(),
+        None,
+    );
+    let values = UnionArray::new(
+        union_type.clone(),
+        vec![0, 0, 1, 4].into(),
+        vec![
+            Utf8Array::<i32>::from_slice(["chrontext-test", "0.1"]).boxed(),
+            BooleanArray::from_slice([false]).boxed(),
+            Int64Array::new_empty(ArrowDataType::Int64).boxed(),
+            Int32Array::new_empty(ArrowDataType::Int32).boxed(),
+            string_list.boxed(),
+        ],
+        Some(vec![0, 1, 0, 0].into()),
+    );
+    let names = UInt32Array::from_slice([0, 1, 3, 512]);
+    let schema = Schema::from(vec![
+        Field::new("info_name", ArrowDataType::UInt32, false),
+        Field::new("value", union_type, false),
+    ]);
+    let ipc_fields = default_ipc_fields(&schema.fields);
+    let chunk: Chunk<Box<dyn Array>> = Chunk::new(vec![names.boxed(), values.boxed()]);
+    let (_, batch) =
+        flight2::serialize_batch(&chunk, &ipc_fields, &WriteOptions { compression: None }).unwrap();
+    vec![flight2::serialize_schema(&schema, Some(&ipc_fields)), batch]
+}
+
+#[fixture]
+fn use_logger() {
+    let res = env_logger::try_init();
+    match res {
+        Ok(_) => {}
+        Err(_) => {
+            debug!("Tried to initialize logger which is already initialize")
+        }
+    }
+}
+
+#[fixture]
+fn testdata_path() -> PathBuf {
+    let manidir = env!("CARGO_MANIFEST_DIR");
+    let mut testdata_path = PathBuf::new();
+    testdata_path.push(manidir);
+    testdata_path.push("tests");
+    testdata_path.push("query_execution_testdata");
+    testdata_path
+}
+
+#[fixture]
+async fn sparql_endpoint() {
+    start_sparql_container().await
+}
+
+#[fixture]
+async fn with_testdata(#[future] sparql_endpoint: (), testdata_path: PathBuf) {
+    let _ = sparql_endpoint.await;
+    let mut testdata_path = testdata_path.clone();
+    testdata_path.push("testdata.sparql");
+    add_sparql_testdata(testdata_path).await;
+}
+
+#[fixture]
+fn timeseries_table() -> TimeSeriesTable {
+    TimeSeriesTable::new(
+        "ts".to_string(),
+        "v".to_string(),
+        "ts".to_string(),
+        "id".to_string(),
+        xsd::UNSIGNED_INT.into_owned(),
+    )
+}
+
+//Starts the server on a free port and returns its endpoint and currently valid token
+async fn flight_sql_server(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: &PathBuf,
+) -> (String, Arc<Mutex<String>>) {
+    let db =
+        EmbeddedSQLDatabase::open_in_memory(SqlDialect::DuckDB, vec![timeseries_table]).unwrap();
+    db.execute_batch("CREATE TABLE ts (id VARCHAR, ts TIMESTAMP, v BIGINT);")
+        .unwrap();
+    for t in ["ts1", "ts2"] {
+        let mut file_path = testdata_path.clone();
+        file_path.push(t.to_string() + ".csv");
+        let file = File::open(file_path.as_path()).expect("could not open file");
+        for line in BufReader::new(file).lines().skip(1) {
+            let line = line.unwrap();
+            let (timestamp, value) = line.split_once(",").unwrap();
+            db.execute_batch(&format!(
+                "INSERT INTO ts VALUES ('{}', '{}', {});",
+                t,
+                timestamp.replace("T", " "),
+                value
+            ))
+            .unwrap();
+        }
+    }
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let valid_token = Arc::new(Mutex::new(String::new()));
+    let server = TestFlightSqlServer {
+        db: Mutex::new(db),
+        prepared_statements: Mutex::new(HashMap::new()),
+        valid_token: valid_token.clone(),
+        handshakes: Mutex::new(0),
+        location: format!("grpc+tcp://{}", addr),
+    };
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(FlightServiceServer::new(server))
+            .serve(addr),
+    );
+    sleep(Duration::from_millis(200)).await;
+    (format!("http://{}", addr), valid_token)
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_get_sql_info_capability_detection(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let (endpoint, _) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    let sql_info = db.sql_info().expect("Server should support GetSqlInfo");
+    assert_eq!(
+        sql_info.get_string(FLIGHT_SQL_SERVER_NAME),
+        Some(&"chrontext-test".to_string())
+    );
+
+    db.set_sql_dialect(SqlDialect::DuckDB);
+    assert!(db
+        .capabilities()
+        .expression_kinds
+        .contains(&ExpressionKind::DateFunction));
+    //The server does not report UNIX_TIMESTAMP
+    db.set_sql_dialect(SqlDialect::Dremio);
+    assert!(!db
+        .capabilities()
+        .expression_kinds
+        .contains(&ExpressionKind::DateFunction));
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_prepared_statement_with_parameters(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let (endpoint, _) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    let parameters = df!("id"=>["ts1"], "v"=>[200i64]).unwrap();
+    let df = db
+        .execute_prepared_sql_query(
+            "SELECT id, v FROM ts WHERE id = ? AND v < ?".to_string(),
+            Some(parameters),
+        )
+        .await
+        .unwrap();
+    assert_eq!(df.height(), 5);
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_simple_hybrid_query(
+    #[future] with_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_testdata.await;
+    let (endpoint, _) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    db.set_sql_dialect(SqlDialect::DuckDB);
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
+    }
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["t"], vec![false], false)
+        .expect("Sort error");
+    df.with_column(
+        df.column("t")
+            .unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Microseconds, None))
+            .unwrap(),
+    )
+    .unwrap();
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_simple_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_token_refresh_on_unauthenticated(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let (endpoint, valid_token) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    *valid_token.lock().unwrap() = "expired".to_string();
+    let df = db
+        .execute_sql_query("SELECT id, v FROM ts".to_string())
+        .await
+        .unwrap();
+    assert_eq!(df.height(), 16);
+    assert_eq!(valid_token.lock().unwrap().as_str(), "Bearer token2");
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_stream_multiple_endpoints(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let (endpoint, _) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    let mut stream = db
+        .execute_sql_query_stream("SELECT id, v FROM ts".to_string())
+        .await
+        .unwrap();
+    let mut batches = 0;
+    let mut rows = 0;
+    while let Some(df) = stream.next().await {
+        let df = df.unwrap();
+        assert_eq!(df.get_column_names(), vec!["id", "v"]);
+        batches += 1;
+        rows += df.height();
+    }
+    //One batch from the first endpoint, and an empty frame from the second
+    assert_eq!(batches, 2);
+    assert_eq!(rows, 16);
+}
+
+#[rstest]
+#[tokio::test]
+#[serial]
+async fn test_dictionary_encoded_identifiers(
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let (endpoint, _) = flight_sql_server(timeseries_table.clone(), &testdata_path).await;
+    let mut db = ArrowFlightSQLDatabase::new(&endpoint, "user", "pass", vec![timeseries_table])
+        .await
+        .unwrap();
+    let df = db
+        .execute_sql_query("SELECT id, v FROM ts ORDER BY id, v".to_string())
+        .await
+        .unwrap();
+    let id = df.column("id").unwrap();
+    assert!(matches!(id.dtype(), DataType::Categorical(_)));
+    let ids: Vec<String> = id
+        .cast(&DataType::Utf8)
+        .unwrap()
+        .utf8()
+        .unwrap()
+        .into_iter()
+        .map(|x| x.unwrap().to_string())
+        .collect();
+    assert_eq!(ids.len(), 16);
+    assert_eq!(ids.first().unwrap(), "ts1");
+    assert_eq!(ids.last().unwrap(), "ts2");
+}
@@ -2,9 +2,9 @@ mod common;
 mod opcua_data_provider;
 
 use chrontext::engine::Engine;
-use chrontext::pushdown_setting::PushdownSetting;
-use chrontext::timeseries_database::opcua_history_read::OPCUAHistoryRead;
+use chrontext::timeseries_database::opcua_history_read::{OPCUAHistoryRead, OPCUASecurityConfig};
 use log::debug;
+use opcua_client::prelude::{IdentityToken, X509Data, X509};
 use opcua_server::prelude::*;
 use polars::io::SerReader;
 use polars::prelude::CsvReader;
@@ -12,14 +12,20 @@ use polars_core::frame::DataFrame;
 use rstest::*;
 use serial_test::serial;
 use std::collections::HashMap;
+use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::thread::{sleep, JoinHandle};
 use std::{thread, time};
 use tokio::runtime::Builder;
 
 use crate::common::{add_sparql_testdata, start_sparql_container, QUERY_ENDPOINT};
-use crate::opcua_data_provider::OPCUADataProvider;
+use crate::opcua_data_provider::{OPCUADataProvider, AGGREGATE_TYPES_READ};
+
+const SAMPLE_USER_TOKEN_ID: &str = "sample_user";
+const SAMPLE_USER: &str = "sample";
+const SAMPLE_PASSWORD: &str = "sample1";
+const SAMPLE_X509_TOKEN_ID: &str = "sample_x509";
 
 #[fixture]
 fn use_logger() {
@@ -82,10 +88,51 @@ fn frames(testdata_path: PathBuf) -> HashMap<String, DataFrame> {
 
 #[fixture]
 fn opcua_server_fixture(frames: HashMap<String, DataFrame>) -> JoinHandle<()> {
-    let port = 1234;
+    start_opcua_server(frames, 1234, None, None)
+}
+
+#[fixture]
+fn paged_opcua_server_fixture(frames: HashMap<String, DataFrame>) -> JoinHandle<()> {
+    start_opcua_server(frames, 1235, None, Some(2))
+}
+
+#[fixture]
+fn user_certificate() -> (PathBuf, PathBuf) {
+    //A self signed certificate used as the X509 identity of the user
+    let user_dir = PathBuf::from("./pki-user");
+    fs::create_dir_all(&user_dir).unwrap();
+    let (cert, pkey) = X509::cert_and_pkey(&X509Data::sample_cert()).unwrap();
+    let cert_path = user_dir.join("user.der");
+    let private_key_path = user_dir.join("user.pem");
+    fs::write(&cert_path, cert.to_der().unwrap()).unwrap();
+    fs::write(&private_key_path, pkey.private_key_to_pem().unwrap()).unwrap();
+    (cert_path, private_key_path)
+}
+
+#[fixture]
+fn secure_opcua_server_fixture(
+    frames: HashMap<String, DataFrame>,
+    user_certificate: (PathBuf, PathBuf),
+) -> JoinHandle<()> {
+    start_opcua_server(frames, 1236, Some(&user_certificate.0), None)
+}
+
+//Servers with a user certificate also have endpoints that sign and encrypt messages
+fn start_opcua_server(
+    frames: HashMap<String, DataFrame>,
+    port: u16,
+    user_certificate_path: Option<&Path>,
+    page_size: Option<usize>,
+) -> JoinHandle<()> {
     let path = "/";
+    let mut endpoints = vec![(
+        "none",
+        SecurityPolicy::None,
+        MessageSecurityMode::None,
+        vec![ANONYMOUS_USER_TOKEN_ID, SAMPLE_USER_TOKEN_ID],
+    )];
     //From https://github.com/locka99/opcua/blob/master/docs/server.md
-    let server = ServerBuilder::new()
+    let mut builder = ServerBuilder::new()
         .application_name("Server Name")
         .application_uri("urn:server_uri")
         .discovery_urls(vec![format!(
@@ -99,29 +146,50 @@ fn opcua_server_fixture(frames: HashMap<String, DataFrame>) -> JoinHandle<()> {
         .pki_dir("./pki-server")
         .discovery_server_url(None)
         .host_and_port(hostname().unwrap(), port)
+        .user_token(
+            SAMPLE_USER_TOKEN_ID,
+            ServerUserToken::user_pass(SAMPLE_USER, SAMPLE_PASSWORD),
+        );
+    if let Some(user_certificate_path) = user_certificate_path {
+        builder = builder
+            .user_token(
+                SAMPLE_X509_TOKEN_ID,
+                ServerUserToken::x509(SAMPLE_USER, user_certificate_path),
+            )
+            .trust_client_certs();
+        for (id, mode) in [
+            ("basic256sha256_sign", MessageSecurityMode::Sign),
+            (
+                "basic256sha256_sign_encrypt",
+                MessageSecurityMode::SignAndEncrypt,
+            ),
+        ] {
+            endpoints.push((
+                id,
+                SecurityPolicy::Basic256Sha256,
+                mode,
+                vec![ANONYMOUS_USER_TOKEN_ID, SAMPLE_X509_TOKEN_ID],
+            ));
+        }
+    }
+    let server = builder
         .endpoints(
-            [(
-                "",
-                "/",
-                SecurityPolicy::None,
-                MessageSecurityMode::None,
-                &[ANONYMOUS_USER_TOKEN_ID],
-            )]
-            .iter()
-            .map(|v| {
-                (
-                    v.0.to_string(),
-                    ServerEndpoint::from((v.1, v.2, v.3, &v.4[..])),
-                )
-            })
-            .collect(),
+            endpoints
+                .iter()
+                .map(|(id, policy, mode, user_token_ids)| {
+                    (
+                        id.to_string(),
+                        ServerEndpoint::from((path, *policy, *mode, &user_token_ids[..])),
+                    )
+                })
+                .collect(),
         )
         .server()
         .unwrap();
     {
         let server_state = server.server_state();
         let mut server_state = server_state.write().unwrap();
-        server_state.set_historical_data_provider(Box::new(OPCUADataProvider { frames }))
+        server_state.set_historical_data_provider(Box::new(OPCUADataProvider { frames, page_size }))
     }
     let handle = thread::spawn(move || server.run());
     sleep(time::Duration::from_secs(2));
@@ -130,11 +198,38 @@ fn opcua_server_fixture(frames: HashMap<String, DataFrame>) -> JoinHandle<()> {
 
 #[fixture]
 fn engine() -> Engine {
-    let port = 1234;
+    create_engine(1234)
+}
+
+#[fixture]
+fn paged_engine() -> Engine {
+    create_engine(1235)
+}
+
+#[fixture]
+fn user_pass_engine() -> Engine {
+    create_engine_with_security_config(
+        1234,
+        OPCUASecurityConfig {
+            identity_token: IdentityToken::UserName(
+                SAMPLE_USER.to_string(),
+                SAMPLE_PASSWORD.to_string(),
+            ),
+            ..Default::default()
+        },
+    )
+}
+
+fn create_engine(port: u16) -> Engine {
+    create_engine_with_security_config(port, OPCUASecurityConfig::default())
+}
+
+fn create_engine_with_security_config(port: u16, security_config: OPCUASecurityConfig) -> Engine {
     let path = "/";
     let endpoint = format!("opc.tcp://{}:{}{}", hostname().unwrap(), port, path);
-    let opcua_tsdb = OPCUAHistoryRead::new(&endpoint, 1);
-    let engine = Engine::new([PushdownSetting::GroupBy].into(), Box::new(opcua_tsdb), QUERY_ENDPOINT.to_string());
+    let opcua_tsdb = OPCUAHistoryRead::new_with_security_config(&endpoint, 1, security_config)
+        .expect("Could not connect to OPC UA server");
+    let engine = Engine::new(Box::new(opcua_tsdb), QUERY_ENDPOINT.to_string());
     engine
 }
 
@@ -200,6 +295,265 @@ fn test_basic_query(
     //println!("{}", df);
 }
 
+#[rstest]
+#[serial]
+fn test_good_quality_query(
+    with_testdata: (),
+    use_logger: (),
+    opcua_server_fixture: JoinHandle<()>,
+    testdata_path: PathBuf,
+    mut engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = opcua_server_fixture;
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        ?dp chrontext:hasQuality ?q .
+        FILTER(?t >= "2022-06-01T08:46:53"^^xsd:dateTime && ?t <= "2022-06-01T08:46:58"^^xsd:dateTime && ?q = 0) .
+    }
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_basic_query.csv");
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let mut expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    expected_df
+        .with_column(
+            expected_df
+                .column("t")
+                .unwrap()
+                .cast(&polars::prelude::DataType::Datetime(
+                    polars::prelude::TimeUnit::Milliseconds,
+                    None,
+                ))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[serial]
+fn test_paged_basic_query(
+    with_testdata: (),
+    use_logger: (),
+    paged_opcua_server_fixture: JoinHandle<()>,
+    testdata_path: PathBuf,
+    mut paged_engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = paged_opcua_server_fixture;
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t >= "2022-06-01T08:46:53"^^xsd:dateTime && ?t <= "2022-06-01T08:46:58"^^xsd:dateTime) .
+    }
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(paged_engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_basic_query.csv");
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let mut expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    expected_df
+        .with_column(
+            expected_df
+                .column("t")
+                .unwrap()
+                .cast(&polars::prelude::DataType::Datetime(
+                    polars::prelude::TimeUnit::Milliseconds,
+                    None,
+                ))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[serial]
+fn test_user_pass_basic_query(
+    with_testdata: (),
+    use_logger: (),
+    opcua_server_fixture: JoinHandle<()>,
+    testdata_path: PathBuf,
+    mut user_pass_engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = opcua_server_fixture;
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t >= "2022-06-01T08:46:53"^^xsd:dateTime && ?t <= "2022-06-01T08:46:58"^^xsd:dateTime) .
+    }
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(user_pass_engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_basic_query.csv");
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let mut expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    expected_df
+        .with_column(
+            expected_df
+                .column("t")
+                .unwrap()
+                .cast(&polars::prelude::DataType::Datetime(
+                    polars::prelude::TimeUnit::Milliseconds,
+                    None,
+                ))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[case::sign(MessageSecurityMode::Sign, false)]
+#[case::sign_and_encrypt(MessageSecurityMode::SignAndEncrypt, false)]
+#[case::x509_identity(MessageSecurityMode::SignAndEncrypt, true)]
+#[serial]
+fn test_secure_basic_query(
+    with_testdata: (),
+    use_logger: (),
+    secure_opcua_server_fixture: JoinHandle<()>,
+    user_certificate: (PathBuf, PathBuf),
+    testdata_path: PathBuf,
+    #[case] message_security_mode: MessageSecurityMode,
+    #[case] x509_identity: bool,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = secure_opcua_server_fixture;
+    let identity_token = if x509_identity {
+        IdentityToken::X509(user_certificate.0, user_certificate.1)
+    } else {
+        IdentityToken::Anonymous
+    };
+    let mut engine = create_engine_with_security_config(
+        1236,
+        OPCUASecurityConfig {
+            security_policy: SecurityPolicy::Basic256Sha256,
+            message_security_mode,
+            identity_token,
+            pki_dir: Some(PathBuf::from("./pki-client")),
+            trust_server_certs: true,
+            ..Default::default()
+        },
+    );
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t >= "2022-06-01T08:46:53"^^xsd:dateTime && ?t <= "2022-06-01T08:46:58"^^xsd:dateTime) .
+    }
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_basic_query.csv");
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let mut expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    expected_df
+        .with_column(
+            expected_df
+                .column("t")
+                .unwrap()
+                .cast(&polars::prelude::DataType::Datetime(
+                    polars::prelude::TimeUnit::Milliseconds,
+                    None,
+                ))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[serial]
+fn test_connect_error(use_logger: ()) {
+    let _ = use_logger;
+    let endpoint = format!("opc.tcp://{}:{}{}", hostname().unwrap(), 1299, "/");
+    let res = OPCUAHistoryRead::new(&endpoint, 1);
+    assert!(res.is_err());
+}
+
 #[rstest]
 #[serial]
 fn test_basic_no_end_time_query(
@@ -389,6 +743,154 @@ fn test_no_pushdown_because_of_filter_query(
     assert_eq!(expected_df, df);
 }
 
+#[rstest]
+#[serial]
+fn test_unsupported_aggregate_falls_back_query(
+    with_testdata: (),
+    use_logger: (),
+    opcua_server_fixture: JoinHandle<()>,
+    mut engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = opcua_server_fixture;
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?datetime_seconds (SUM(?v) as ?sum_v) (COUNT(DISTINCT ?v) as ?count_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(xsd:integer(5 * FLOOR(chrontext:DateTimeAsSeconds(?t) / 5.0)) as ?datetime_seconds)
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime)
+    } GROUP BY ?w ?datetime_seconds
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    assert_eq!(
+        df.get_column_names(),
+        vec!["w", "datetime_seconds", "sum_v", "count_v"]
+    );
+    assert!(df.height() > 0);
+}
+
+#[rstest]
+#[serial]
+fn test_pushdown_custom_aggregates_query(
+    with_testdata: (),
+    use_logger: (),
+    opcua_server_fixture: JoinHandle<()>,
+    mut engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = opcua_server_fixture;
+    AGGREGATE_TYPES_READ.lock().unwrap().clear();
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX opcua:<https://github.com/magbak/chrontext/opcua#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?datetime_seconds (opcua:TimeAverage(?v) as ?avg_v) (opcua:Delta(?v) as ?delta_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(5 * FLOOR(chrontext:DateTimeAsSeconds(?t) / 5) as ?datetime_seconds)
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime)
+    } GROUP BY ?w ?datetime_seconds
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    assert_eq!(
+        df.get_column_names(),
+        vec!["w", "datetime_seconds", "avg_v", "delta_v"]
+    );
+    assert!(df.height() > 0);
+    let aggregate_types_read = AGGREGATE_TYPES_READ.lock().unwrap();
+    let time_average = NodeId::new(0, ObjectId::AggregateFunction_TimeAverage as u32);
+    let delta = NodeId::new(0, ObjectId::AggregateFunction_Delta as u32);
+    assert!(aggregate_types_read.contains(&time_average));
+    assert!(aggregate_types_read.contains(&delta));
+    assert!(aggregate_types_read
+        .iter()
+        .all(|x| x == &time_average || x == &delta));
+}
+
+#[rstest]
+#[serial]
+fn test_only_unsupported_group_falls_back_query(
+    with_testdata: (),
+    use_logger: (),
+    opcua_server_fixture: JoinHandle<()>,
+    mut engine: Engine,
+) {
+    let _ = with_testdata;
+    let _ = use_logger;
+    let _ = opcua_server_fixture;
+    AGGREGATE_TYPES_READ.lock().unwrap().clear();
+
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?datetime_seconds ?avg_v ?count_v WHERE {
+        {
+        SELECT ?w ?datetime_seconds (AVG(?v) as ?avg_v) WHERE {
+            ?w types:hasSensor ?s .
+            ?s chrontext:hasTimeseries ?ts .
+            ?ts chrontext:hasDataPoint ?dp .
+            ?dp chrontext:hasTimestamp ?t .
+            ?dp chrontext:hasValue ?v .
+            BIND(5 * FLOOR(chrontext:DateTimeAsSeconds(?t) / 5) as ?datetime_seconds)
+            FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime)
+        } GROUP BY ?w ?datetime_seconds
+        }
+        {
+        SELECT ?w ?datetime_seconds (COUNT(DISTINCT ?v2) as ?count_v) WHERE {
+            ?w types:hasSensor ?s2 .
+            ?s2 chrontext:hasTimeseries ?ts2 .
+            ?ts2 chrontext:hasDataPoint ?dp2 .
+            ?dp2 chrontext:hasTimestamp ?t2 .
+            ?dp2 chrontext:hasValue ?v2 .
+            BIND(5 * FLOOR(chrontext:DateTimeAsSeconds(?t2) / 5) as ?datetime_seconds)
+            FILTER(?t2 > "2022-06-01T08:46:53"^^xsd:dateTime)
+        } GROUP BY ?w ?datetime_seconds
+        }
+    }
+    "#;
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    let runtime = builder.build().unwrap();
+    let df = runtime
+        .block_on(engine.execute_hybrid_query(query))
+        .expect("Hybrid error");
+    assert_eq!(
+        df.get_column_names(),
+        vec!["w", "datetime_seconds", "avg_v", "count_v"]
+    );
+    assert!(df.height() > 0);
+    //The average is still pushed down even though the distinct count is not supported
+    let average = NodeId::new(0, ObjectId::AggregateFunction_Average as u32);
+    let aggregate_types_read = AGGREGATE_TYPES_READ.lock().unwrap();
+    assert!(aggregate_types_read.contains(&average));
+    assert!(aggregate_types_read.iter().all(|x| x == &average));
+}
+
 //
 //     let file = File::create(file_path.as_path()).expect("could not open file");
 //     let mut writer = CsvWriter::new(file);
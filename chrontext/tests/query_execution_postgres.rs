@@ -0,0 +1,276 @@
+mod common;
+
+use crate::common::{add_sparql_testdata, find_container, start_sparql_container, QUERY_ENDPOINT};
+use bollard::container::{
+    Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::models::{HostConfig, PortBinding};
+use bollard::Docker;
+use chrontext::engine::Engine;
+use chrontext::timeseries_database::postgres_database::{PostgresConfig, PostgresDatabase};
+use chrontext::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable;
+use log::debug;
+use oxrdf::vocab::xsd;
+use polars::prelude::{CsvReader, SerReader};
+use polars_core::datatypes::DataType;
+use polars_core::prelude::TimeUnit;
+use rstest::*;
+use serial_test::serial;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_postgres::NoTls;
+
+const TIMESCALEDB_IMAGE: &str = "timescale/timescaledb:2.11.1-pg15";
+const POSTGRES_PASSWORD: &str = "postgres123";
+
+#[fixture]
+fn use_logger() {
+    let res = env_logger::try_init();
+    match res {
+        Ok(_) => {}
+        Err(_) => {
+            debug!("Tried to initialize logger which is already initialize")
+        }
+    }
+}
+
+#[fixture]
+fn testdata_path() -> PathBuf {
+    let manidir = env!("CARGO_MANIFEST_DIR");
+    let mut testdata_path = PathBuf::new();
+    testdata_path.push(manidir);
+    testdata_path.push("tests");
+    testdata_path.push("query_execution_testdata");
+    testdata_path
+}
+
+#[fixture]
+async fn sparql_endpoint() {
+    start_sparql_container().await
+}
+
+#[fixture]
+async fn with_sparql_testdata(#[future] sparql_endpoint: (), testdata_path: PathBuf) {
+    let _ = sparql_endpoint.await;
+    let mut testdata_path = testdata_path.clone();
+    testdata_path.push("testdata.sparql");
+    add_sparql_testdata(testdata_path).await;
+}
+
+#[fixture]
+async fn postgres_endpoint() {
+    let docker = Docker::connect_with_local_defaults().expect("Could not find local docker");
+    let container_name = "my-timescaledb-server";
+    let existing = find_container(&docker, container_name).await;
+    if let Some(_) = existing {
+        docker
+            .remove_container(
+                container_name,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .expect("Remove existing problem");
+    }
+    let options = CreateContainerOptions {
+        name: container_name,
+    };
+    let password_env = format!("POSTGRES_PASSWORD={}", POSTGRES_PASSWORD);
+    let config = Config {
+        image: Some(TIMESCALEDB_IMAGE),
+        env: Some(vec![password_env.as_str()]),
+        exposed_ports: Some(HashMap::from([("5432/tcp", HashMap::new())])),
+        host_config: Some(HostConfig {
+            port_bindings: Some(HashMap::from([(
+                "5432/tcp".to_string(),
+                Some(vec![PortBinding {
+                    host_ip: None,
+                    host_port: Some("5432/tcp".to_string()),
+                }]),
+            )])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    docker
+        .create_container(Some(options), config)
+        .await
+        .expect("Problem creating container");
+    docker
+        .start_container(container_name, None::<StartContainerOptions<String>>)
+        .await
+        .expect("Started container problem ");
+    sleep(Duration::from_secs(10)).await;
+    let created = find_container(&docker, container_name).await;
+    assert!(created.is_some());
+}
+
+#[fixture]
+async fn with_timeseries_testdata(#[future] postgres_endpoint: (), testdata_path: PathBuf) {
+    let _ = postgres_endpoint.await;
+    let (client, connection) = tokio_postgres::connect(
+        &format!(
+            "host=localhost port=5432 user=postgres password={}",
+            POSTGRES_PASSWORD
+        ),
+        NoTls,
+    )
+    .await
+    .expect("Connection problem");
+    tokio::spawn(connection);
+    client
+        .batch_execute(
+            "CREATE TABLE ts (id VARCHAR, ts TIMESTAMP NOT NULL, v BIGINT);
+             SELECT create_hypertable('ts', 'ts');",
+        )
+        .await
+        .expect("Create table problem");
+    for t in ["ts1", "ts2"] {
+        let mut file_path = testdata_path.clone();
+        file_path.push(t.to_string() + ".csv");
+        let file = File::open(file_path.as_path()).expect("could not open file");
+        for line in BufReader::new(file).lines().skip(1) {
+            let line = line.unwrap();
+            let (timestamp, value) = line.split_once(",").unwrap();
+            client
+                .batch_execute(&format!(
+                    "INSERT INTO ts VALUES ('{}', '{}', {});",
+                    t, timestamp, value
+                ))
+                .await
+                .expect("Insert problem");
+        }
+    }
+}
+
+#[fixture]
+fn timeseries_table() -> TimeSeriesTable {
+    TimeSeriesTable::new(
+        "ts".to_string(),
+        "v".to_string(),
+        "ts".to_string(),
+        "id".to_string(),
+        xsd::UNSIGNED_INT.into_owned(),
+    )
+}
+
+async fn postgres_db(
+    sql_dialect: SqlDialect,
+    timeseries_table: TimeSeriesTable,
+) -> PostgresDatabase {
+    let config = PostgresConfig {
+        password: Some(POSTGRES_PASSWORD.to_string()),
+        ..Default::default()
+    };
+    PostgresDatabase::new(config, sql_dialect, vec![timeseries_table])
+        .await
+        .unwrap()
+}
+
+#[rstest]
+#[case(SqlDialect::Postgres)]
+#[case(SqlDialect::TimescaleDB)]
+#[tokio::test]
+#[serial]
+async fn test_simple_hybrid_query(
+    #[case] sql_dialect: SqlDialect,
+    #[future] with_sparql_testdata: (),
+    #[future] with_timeseries_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    testdata_path: PathBuf,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_sparql_testdata.await;
+    let _ = with_timeseries_testdata.await;
+    let db = postgres_db(sql_dialect, timeseries_table).await;
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime && ?v < 200) .
+    }
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let mut df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error")
+        .sort(&["t"], vec![false], false)
+        .expect("Sort error");
+    df.with_column(
+        df.column("t")
+            .unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Microseconds, None))
+            .unwrap(),
+    )
+    .unwrap();
+    let mut file_path = testdata_path.clone();
+    file_path.push("expected_simple_hybrid.csv");
+
+    let file = File::open(file_path.as_path()).expect("Read file problem");
+    let expected_df = CsvReader::new(file)
+        .infer_schema(None)
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .finish()
+        .expect("DF read error");
+    assert_eq!(expected_df, df);
+}
+
+#[rstest]
+#[case(SqlDialect::Postgres)]
+#[case(SqlDialect::TimescaleDB)]
+#[tokio::test]
+#[serial]
+async fn test_pushdown_group_by_five_second_hybrid_query(
+    #[case] sql_dialect: SqlDialect,
+    #[future] with_sparql_testdata: (),
+    #[future] with_timeseries_testdata: (),
+    timeseries_table: TimeSeriesTable,
+    use_logger: (),
+) {
+    let _ = use_logger;
+    let _ = with_sparql_testdata.await;
+    let _ = with_timeseries_testdata.await;
+    let db = postgres_db(sql_dialect, timeseries_table).await;
+    let query = r#"
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/magbak/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?datetime_seconds (SUM(?v) as ?sum_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(5 * FLOOR(chrontext:DateTimeAsSeconds(?t) / 5) as ?datetime_seconds)
+        FILTER(?t > "2022-06-01T08:46:53"^^xsd:dateTime)
+    } GROUP BY ?w ?datetime_seconds
+    "#;
+    let mut engine = Engine::new(Box::new(db), QUERY_ENDPOINT.to_string());
+    let df = engine
+        .execute_hybrid_query(query)
+        .await
+        .expect("Hybrid error");
+    assert_eq!(
+        df.get_column_names(),
+        vec!["w", "datetime_seconds", "sum_v"]
+    );
+    let sum_v: i64 = df.column("sum_v").unwrap().sum().unwrap();
+    assert_eq!(sum_v, 1215 + 1216);
+}
@@ -35,6 +35,35 @@ fn test_simple_query() {
     assert_eq!(static_rewrite, &expected_query);
 }
 
+#[test]
+fn test_simple_query_with_table_annotations() {
+    let sparql = r#"
+    PREFIX qry:<https://github.com/magbak/chrontext#>
+    SELECT ?var1 ?var2 WHERE {
+        ?var1 a ?var2 .
+        ?var2 qry:hasTimeseries ?ts .
+        ?ts qry:hasDataPoint ?dp .
+        ?dp qry:hasValue ?val .
+        }
+    "#;
+    let parsed = parse_sparql_select_query(sparql).unwrap();
+    let mut preprocessor = Preprocessor::new();
+    let (preprocessed_query, has_constraint) = preprocessor.preprocess(&parsed);
+    let rewriter = StaticQueryRewriter::new_with_table_annotations(&has_constraint, true);
+    let (static_rewrites_map, _, _) = rewriter.rewrite_query(preprocessed_query);
+    assert_eq!(static_rewrites_map.len(), 1);
+    let static_rewrite = static_rewrites_map.get(&Context::new()).unwrap();
+    let expected_str = r#"
+    SELECT ?var1 ?var2 ?ts_datatype_0 ?ts_table_0 ?ts_external_id_0 WHERE {
+     ?var1 <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> ?var2 .
+     ?ts <https://github.com/magbak/chrontext#hasExternalId> ?ts_external_id_0 .
+     ?ts <https://github.com/magbak/chrontext#hasDatatype> ?ts_datatype_0 .
+     ?var2 <https://github.com/magbak/chrontext#hasTimeseries> ?ts .
+      OPTIONAL { ?ts <https://github.com/magbak/chrontext#hasTable> ?ts_table_0 } }"#;
+    let expected_query = Query::parse(expected_str, None).unwrap();
+    assert_eq!(static_rewrite, &expected_query);
+}
+
 #[test]
 fn test_filtered_query() {
     let sparql = r#"
@@ -388,7 +417,6 @@ fn test_fix_dropped_triple() {
             ]),
         )),
         datatype_variable: Some(Variable::new_unchecked("ts_datatype_0")),
-        datatype: None,
         timestamp_variable: Some(VariableInContext::new(
             Variable::new_unchecked("t"),
             Context::from_path(vec![
@@ -397,7 +425,7 @@ fn test_fix_dropped_triple() {
                 PathEntry::BGP,
             ]),
         )),
-        ids: None,
+        ..BasicTimeSeriesQuery::new_empty()
     }];
     assert_eq!(time_series_queries, expected_time_series_queries);
 }
@@ -466,7 +494,6 @@ fn test_property_path_expression() {
                 ]),
             )),
             datatype_variable: Some(Variable::new_unchecked("ts_datatype_0")),
-            datatype: None,
             timestamp_variable: Some(VariableInContext::new(
                 Variable::new_unchecked("t"),
                 Context::from_path(vec![
@@ -475,7 +502,7 @@ fn test_property_path_expression() {
                     PathEntry::BGP,
                 ]),
             )),
-            ids: None,
+            ..BasicTimeSeriesQuery::new_empty()
         },
         BasicTimeSeriesQuery {
             identifier_variable: Some(Variable::new_unchecked("ts_external_id_1")),
@@ -504,7 +531,6 @@ fn test_property_path_expression() {
                 ]),
             )),
             datatype_variable: Some(Variable::new_unchecked("ts_datatype_1")),
-            datatype: None,
             timestamp_variable: Some(VariableInContext::new(
                 Variable::new_unchecked("t"),
                 Context::from_path(vec![
@@ -513,7 +539,7 @@ fn test_property_path_expression() {
                     PathEntry::BGP,
                 ]),
             )),
-            ids: None,
+            ..BasicTimeSeriesQuery::new_empty()
         },
     ];
     assert_eq!(time_series_queries, expected_time_series_queries);
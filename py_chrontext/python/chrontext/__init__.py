@@ -1,2 +1,2 @@
-from .chrontext import Engine, ArrowFlightSQLDatabase, TimeSeriesTable, OPCUAHistoryRead
+from .chrontext import Engine, ArrowFlightSQLDatabase, TimeSeriesTable, OPCUAHistoryRead, OPCUASecurityConfig
 
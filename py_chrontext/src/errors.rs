@@ -2,6 +2,7 @@ use oxrdf::IriParseError;
 use thiserror::Error;
 
 use chrontext::timeseries_database::arrow_flight_sql_database::ArrowFlightSQLError as RustArrowFlightSQLError;
+use chrontext::timeseries_database::opcua_history_read::OPCUAHistoryReadError as RustOPCUAHistoryReadError;
 use pyo3::{create_exception, exceptions::PyException, prelude::*};
 
 #[derive(Error, Debug)]
@@ -9,13 +10,25 @@ pub enum PyQueryError {
     #[error(transparent)]
     ArrowFlightSQLError(#[from] RustArrowFlightSQLError),
     #[error(transparent)]
+    OPCUAHistoryReadError(#[from] RustOPCUAHistoryReadError),
+    #[error(transparent)]
     DatatypeIRIParseError(#[from] IriParseError),
     #[error(transparent)]
     QueryExecutionError(Box<dyn std::error::Error>),
     #[error("Missing time series database")]
     MissingTimeSeriesDatabaseError,
     #[error("Time series database already defined")]
-    TimeSeriesDatabaseAlreadyDefined
+    TimeSeriesDatabaseAlreadyDefined,
+    #[error("Invalid OPC UA security configuration: {0}")]
+    OPCUASecurityConfigError(String),
+    #[error("Invalid SQL dialect: {0}")]
+    SqlDialectError(String),
+    #[error("Invalid partition scheme: {0}")]
+    PartitionSchemeError(String),
+    #[error("Invalid timestamp encoding: {0}")]
+    TimestampEncodingError(String),
+    #[error("Invalid wide table: {0}")]
+    WideTableError(String),
 }
 
 impl std::convert::From<PyQueryError> for PyErr {
@@ -24,6 +37,9 @@ impl std::convert::From<PyQueryError> for PyErr {
             PyQueryError::ArrowFlightSQLError(err) => {
                 ArrowFlightSQLError::new_err(format!("{}", err))
             }
+            PyQueryError::OPCUAHistoryReadError(err) => {
+                OPCUAHistoryReadError::new_err(format!("{}", err))
+            }
             PyQueryError::DatatypeIRIParseError(err) => {
                 DatatypeIRIParseError::new_err(format!("{}", err))
             }
@@ -36,12 +52,25 @@ impl std::convert::From<PyQueryError> for PyErr {
             PyQueryError::TimeSeriesDatabaseAlreadyDefined => {
                 TimeSeriesDatabaseAlreadyDefinedError::new_err("")
             }
+            PyQueryError::OPCUASecurityConfigError(err) => {
+                OPCUASecurityConfigError::new_err(err)
+            }
+            PyQueryError::SqlDialectError(err) => SqlDialectError::new_err(err),
+            PyQueryError::PartitionSchemeError(err) => PartitionSchemeError::new_err(err),
+            PyQueryError::TimestampEncodingError(err) => TimestampEncodingError::new_err(err),
+            PyQueryError::WideTableError(err) => WideTableError::new_err(err),
         }
     }
 }
 
 create_exception!(exceptions, ArrowFlightSQLError, PyException);
+create_exception!(exceptions, OPCUAHistoryReadError, PyException);
 create_exception!(exceptions, DatatypeIRIParseError, PyException);
 create_exception!(exceptions, QueryExecutionError, PyException);
 create_exception!(exceptions, MissingTimeSeriesDatabaseError, PyException);
 create_exception!(exceptions, TimeSeriesDatabaseAlreadyDefinedError, PyException);
+create_exception!(exceptions, OPCUASecurityConfigError, PyException);
+create_exception!(exceptions, SqlDialectError, PyException);
+create_exception!(exceptions, PartitionSchemeError, PyException);
+create_exception!(exceptions, TimestampEncodingError, PyException);
+create_exception!(exceptions, WideTableError, PyException);
@@ -2,17 +2,29 @@ pub mod errors;
 
 use crate::errors::PyQueryError;
 use arrow_python_utils::to_python::to_py_df;
-use chrontext::timeseries_database::arrow_flight_sql_database::ArrowFlightSQLDatabase as RustArrowFlightSQLDatabase;
-use chrontext::timeseries_database::opcua_history_read::OPCUAHistoryRead as RustOPCUAHistoryRead;
+use chrontext::timeseries_database::arrow_flight_sql_database::{
+    ArrowFlightSQLConfig, ArrowFlightSQLDatabase as RustArrowFlightSQLDatabase,
+};
+use chrontext::timeseries_database::opcua_history_read::{
+    OPCUAHistoryRead as RustOPCUAHistoryRead, OPCUASecurityConfig as RustOPCUASecurityConfig,
+};
+use chrontext::timeseries_database::timeseries_sql_rewrite::sql_dialect::SqlDialect;
+use chrontext::timeseries_database::timeseries_sql_rewrite::partition_scheme::{
+    PartitionColumn, PartitionKind, PartitionScheme,
+};
+use chrontext::timeseries_database::timeseries_sql_rewrite::timestamp_encoding::TimestampEncoding;
 use chrontext::timeseries_database::timeseries_sql_rewrite::TimeSeriesTable as RustTimeSeriesTable;
 use chrontext::engine::Engine as RustEngine;
-use chrontext::pushdown_setting::{PushdownSetting, all_pushdowns};
 use log::debug;
 use oxrdf::vocab::{rdf, xsd};
-use oxrdf::{IriParseError, Literal, NamedNode, Variable};
+use opcua_client::prelude::{IdentityToken, MessageSecurityMode, SecurityPolicy};
+use oxrdf::{Literal, NamedNode, Variable};
 use pyo3::prelude::*;
 use spargebra::term::{NamedNodePattern, TermPattern, TriplePattern};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use tokio::runtime::{Builder, Runtime};
 
 #[pyclass(unsendable)]
@@ -35,23 +47,49 @@ impl Engine {
         if self.engine.is_some() {
             return Err(PyQueryError::TimeSeriesDatabaseAlreadyDefined.into());
         }
-        let endpoint = format!("http://{}:{}", &db.host, &db.port);
+        let use_tls = db.tls.unwrap_or(false);
+        let scheme = if use_tls { "https" } else { "http" };
+        let endpoint = format!("{}://{}:{}", scheme, &db.host, &db.port);
+        let mut config = ArrowFlightSQLConfig {
+            use_tls,
+            root_certificate_path: db.root_certificate_path.clone(),
+            tls_domain_name: db.tls_domain_name.clone(),
+            request_timeout: db.request_timeout_seconds.map(Duration::from_secs_f64),
+            ..Default::default()
+        };
+        if let Some(connect_timeout_seconds) = db.connect_timeout_seconds {
+            config.connect_timeout = Some(Duration::from_secs_f64(connect_timeout_seconds));
+        }
+        if let Some(max_retries) = db.max_retries {
+            config.max_retries = max_retries;
+        }
+        if let Some(retry_backoff_seconds) = db.retry_backoff_seconds {
+            config.retry_backoff = Duration::from_secs_f64(retry_backoff_seconds);
+        }
+        let sql_dialect = if let Some(d) = &db.sql_dialect {
+            SqlDialect::from_str(d)
+                .map_err(|e| PyQueryError::SqlDialectError(e.to_string()))?
+        } else {
+            SqlDialect::default()
+        };
         let mut new_tables = vec![];
         for t in &db.tables {
-            new_tables.push(t.to_rust_table().map_err(PyQueryError::from)?);
+            new_tables.push(t.to_rust_table()?);
         }
 
         let afsqldb_result = Runtime::new()
             .unwrap()
-            .block_on(RustArrowFlightSQLDatabase::new(
+            .block_on(RustArrowFlightSQLDatabase::new_with_config(
                 &endpoint,
                 &db.username,
                 &db.password,
                 new_tables,
+                config,
             ));
-        let db = afsqldb_result.map_err(PyQueryError::from)?;
+        let mut db = afsqldb_result.map_err(PyQueryError::from)?;
+        db.set_sql_dialect(sql_dialect);
         self.engine = Some(RustEngine::new(
-                    all_pushdowns(), Box::new(db), self.endpoint.clone()
+                    Box::new(db), self.endpoint.clone()
                 ));
         Ok(())
     }
@@ -60,9 +98,16 @@ impl Engine {
         if self.engine.is_some() {
             return Err(PyQueryError::TimeSeriesDatabaseAlreadyDefined.into());
         }
-        let actual_db = RustOPCUAHistoryRead::new(&db.endpoint, db.namespace);
+        let security_config = if let Some(security_config) = &db.security_config {
+            security_config.to_rust_security_config()?
+        } else {
+            RustOPCUASecurityConfig::default()
+        };
+        let actual_db =
+            RustOPCUAHistoryRead::new_with_security_config(&db.endpoint, db.namespace, security_config)
+                .map_err(PyQueryError::from)?;
         self.engine = Some(RustEngine::new(
-                    [PushdownSetting::GroupBy].into(), Box::new(actual_db), self.endpoint.clone()
+                    Box::new(actual_db), self.endpoint.clone()
                 ));
         Ok(())
     }
@@ -110,6 +155,14 @@ pub struct ArrowFlightSQLDatabase {
     username: String,
     password: String,
     tables: Vec<TimeSeriesTable>,
+    sql_dialect: Option<String>,
+    tls: Option<bool>,
+    root_certificate_path: Option<String>,
+    connect_timeout_seconds: Option<f64>,
+    request_timeout_seconds: Option<f64>,
+    max_retries: Option<u32>,
+    tls_domain_name: Option<String>,
+    retry_backoff_seconds: Option<f64>,
 }
 
 #[pymethods]
@@ -121,6 +174,14 @@ impl ArrowFlightSQLDatabase {
         username: String,
         password: String,
         tables: Vec<TimeSeriesTable>,
+        sql_dialect: Option<String>,
+        tls: Option<bool>,
+        root_certificate_path: Option<String>,
+        connect_timeout_seconds: Option<f64>,
+        request_timeout_seconds: Option<f64>,
+        max_retries: Option<u32>,
+        tls_domain_name: Option<String>,
+        retry_backoff_seconds: Option<f64>,
     ) -> ArrowFlightSQLDatabase {
         ArrowFlightSQLDatabase {
             username,
@@ -128,6 +189,14 @@ impl ArrowFlightSQLDatabase {
             host,
             port,
             tables,
+            sql_dialect,
+            tls,
+            root_certificate_path,
+            connect_timeout_seconds,
+            request_timeout_seconds,
+            max_retries,
+            tls_domain_name,
+            retry_backoff_seconds,
         }
     }
 }
@@ -137,19 +206,143 @@ impl ArrowFlightSQLDatabase {
 pub struct OPCUAHistoryRead {
     namespace: u16,
     endpoint: String,
+    security_config: Option<OPCUASecurityConfig>,
 }
 
 #[pymethods]
 impl OPCUAHistoryRead {
     #[new]
-    pub fn new(endpoint: String, namespace: u16) -> OPCUAHistoryRead {
+    pub fn new(
+        endpoint: String,
+        namespace: u16,
+        security_config: Option<OPCUASecurityConfig>,
+    ) -> OPCUAHistoryRead {
         OPCUAHistoryRead {
             namespace,
             endpoint,
+            security_config,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct OPCUASecurityConfig {
+    security_policy: Option<String>,
+    message_security_mode: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    user_certificate_path: Option<String>,
+    user_private_key_path: Option<String>,
+    pki_dir: Option<String>,
+    certificate_path: Option<String>,
+    private_key_path: Option<String>,
+    trusted_server_certificates: Option<Vec<String>>,
+    trust_server_certs: Option<bool>,
+}
+
+#[pymethods]
+impl OPCUASecurityConfig {
+    #[new]
+    pub fn new(
+        security_policy: Option<String>,
+        message_security_mode: Option<String>,
+        username: Option<String>,
+        password: Option<String>,
+        user_certificate_path: Option<String>,
+        user_private_key_path: Option<String>,
+        pki_dir: Option<String>,
+        certificate_path: Option<String>,
+        private_key_path: Option<String>,
+        trusted_server_certificates: Option<Vec<String>>,
+        trust_server_certs: Option<bool>,
+    ) -> OPCUASecurityConfig {
+        OPCUASecurityConfig {
+            security_policy,
+            message_security_mode,
+            username,
+            password,
+            user_certificate_path,
+            user_private_key_path,
+            pki_dir,
+            certificate_path,
+            private_key_path,
+            trusted_server_certificates,
+            trust_server_certs,
         }
     }
 }
 
+impl OPCUASecurityConfig {
+    fn to_rust_security_config(&self) -> Result<RustOPCUASecurityConfig, PyQueryError> {
+        let default = RustOPCUASecurityConfig::default();
+        let security_policy = if let Some(p) = &self.security_policy {
+            match SecurityPolicy::from_str(p) {
+                Ok(SecurityPolicy::Unknown) | Err(_) => {
+                    return Err(PyQueryError::OPCUASecurityConfigError(format!(
+                        "Unknown security policy {}",
+                        p
+                    )))
+                }
+                Ok(policy) => policy,
+            }
+        } else {
+            default.security_policy
+        };
+        let message_security_mode = if let Some(m) = &self.message_security_mode {
+            match m.as_str() {
+                "None" => MessageSecurityMode::None,
+                "Sign" => MessageSecurityMode::Sign,
+                "SignAndEncrypt" => MessageSecurityMode::SignAndEncrypt,
+                _ => {
+                    return Err(PyQueryError::OPCUASecurityConfigError(format!(
+                        "Unknown message security mode {}, should be one of None, Sign or SignAndEncrypt",
+                        m
+                    )))
+                }
+            }
+        } else {
+            default.message_security_mode
+        };
+        let identity_token = match (
+            &self.username,
+            &self.password,
+            &self.user_certificate_path,
+            &self.user_private_key_path,
+        ) {
+            (None, None, None, None) => IdentityToken::Anonymous,
+            (Some(username), Some(password), None, None) => {
+                IdentityToken::UserName(username.clone(), password.clone())
+            }
+            (None, None, Some(cert), Some(key)) => {
+                IdentityToken::X509(PathBuf::from(cert), PathBuf::from(key))
+            }
+            _ => {
+                return Err(PyQueryError::OPCUASecurityConfigError(
+                    "Specify either username and password or user certificate and private key"
+                        .to_string(),
+                ))
+            }
+        };
+        Ok(RustOPCUASecurityConfig {
+            security_policy,
+            message_security_mode,
+            identity_token,
+            pki_dir: self.pki_dir.as_ref().map(PathBuf::from),
+            certificate_path: self.certificate_path.as_ref().map(PathBuf::from),
+            private_key_path: self.private_key_path.as_ref().map(PathBuf::from),
+            trusted_server_certificates: self
+                .trusted_server_certificates
+                .as_ref()
+                .map(|v| v.iter().map(PathBuf::from).collect())
+                .unwrap_or_default(),
+            trust_server_certs: self
+                .trust_server_certs
+                .unwrap_or(default.trust_server_certs),
+        })
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 pub struct TimeSeriesTable {
@@ -162,6 +355,13 @@ pub struct TimeSeriesTable {
     pub year_column: Option<String>,
     pub month_column: Option<String>,
     pub day_column: Option<String>,
+    pub quality_column: Option<String>,
+    pub iri: Option<String>,
+    pub identifier_prefixes: Option<Vec<String>>,
+    pub value_columns: Option<HashMap<String, String>>,
+    pub identifier_columns: Option<Vec<String>>,
+    pub partition_scheme: Option<Vec<(String, String)>>,
+    pub timestamp_encoding: Option<String>,
 }
 
 #[pymethods]
@@ -177,8 +377,23 @@ impl TimeSeriesTable {
         year_column: Option<String>,
         month_column: Option<String>,
         day_column: Option<String>,
-    ) -> TimeSeriesTable {
-        TimeSeriesTable {
+        quality_column: Option<String>,
+        iri: Option<String>,
+        identifier_prefixes: Option<Vec<String>>,
+        value_columns: Option<HashMap<String, String>>,
+        identifier_columns: Option<Vec<String>>,
+        partition_scheme: Option<Vec<(String, String)>>,
+        timestamp_encoding: Option<String>,
+    ) -> PyResult<TimeSeriesTable> {
+        //A wide table without value columns has no time series to query
+        if value_columns.as_ref().map_or(false, |x| x.is_empty()) {
+            return Err(PyQueryError::WideTableError(format!(
+                "{} has no value columns",
+                time_series_table
+            ))
+            .into());
+        }
+        Ok(TimeSeriesTable {
             schema,
             time_series_table,
             value_column,
@@ -187,23 +402,58 @@ impl TimeSeriesTable {
             value_datatype,
             year_column,
             month_column,
-            day_column
-        }
+            day_column,
+            quality_column,
+            iri,
+            identifier_prefixes,
+            value_columns,
+            identifier_columns,
+            partition_scheme,
+            timestamp_encoding,
+        })
     }
 }
 
 impl TimeSeriesTable {
-    fn to_rust_table(&self) -> Result<RustTimeSeriesTable, IriParseError> {
+    fn to_rust_table(&self) -> Result<RustTimeSeriesTable, PyQueryError> {
+        let partition_scheme = if let Some(columns) = &self.partition_scheme {
+            let mut partition_columns = vec![];
+            for (kind, column) in columns {
+                partition_columns.push(PartitionColumn {
+                    kind: PartitionKind::from_str(kind)
+                        .map_err(|e| PyQueryError::PartitionSchemeError(e.to_string()))?,
+                    column: column.clone(),
+                });
+            }
+            Some(PartitionScheme::new(partition_columns))
+        } else {
+            None
+        };
+        let timestamp_encoding = if let Some(e) = &self.timestamp_encoding {
+            Some(
+                TimestampEncoding::from_str(e)
+                    .map_err(|x| PyQueryError::TimestampEncodingError(x.to_string()))?,
+            )
+        } else {
+            None
+        };
         Ok(RustTimeSeriesTable {
+            iri: self.iri.clone(),
             schema: self.schema.clone(),
             time_series_table: self.time_series_table.clone(),
             value_column: self.value_column.clone(),
             timestamp_column: self.timestamp_column.clone(),
+            timestamp_encoding,
             identifier_column: self.identifier_column.clone(),
+            identifier_columns: self.identifier_columns.clone(),
             value_datatype: NamedNode::new(&self.value_datatype)?,
             year_column: self.year_column.clone(),
             month_column: self.month_column.clone(),
-            day_column: self.day_column.clone()
+            day_column: self.day_column.clone(),
+            partition_scheme,
+            quality_column: self.quality_column.clone(),
+            identifier_prefixes: self.identifier_prefixes.clone(),
+            value_columns: self.value_columns.clone(),
         })
     }
 }
@@ -214,5 +464,6 @@ fn chrontext(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<TimeSeriesTable>()?;
     m.add_class::<ArrowFlightSQLDatabase>()?;
     m.add_class::<OPCUAHistoryRead>()?;
+    m.add_class::<OPCUASecurityConfig>()?;
     Ok(())
 }